@@ -13,7 +13,7 @@ use bdk_core::bitcoin;
 use bdk_core::{BlockId, CheckPoint};
 use bitcoin::{
     bip158::{self, BlockFilter},
-    Block, BlockHash, ScriptBuf,
+    Block, BlockHash, OutPoint, ScriptBuf, TxOut,
 };
 use bitcoincore_rpc;
 use bitcoincore_rpc::RpcApi;
@@ -69,6 +69,17 @@ impl<'c, C: RpcApi> FilterIter<'c, C> {
         self.spks.push(spk);
     }
 
+    /// Fetch the previous outputs at `outpoints`, for example the ones spent by the transactions
+    /// of a matching block.
+    ///
+    /// See [`fetch_prevouts`](crate::fetch_prevouts) for the requirements on the node.
+    pub fn fetch_prevouts(
+        &self,
+        outpoints: impl IntoIterator<Item = OutPoint>,
+    ) -> Result<Vec<(OutPoint, TxOut)>, Error> {
+        Ok(crate::fetch_prevouts(self.client, outpoints)?)
+    }
+
     /// Get the next filter and increment the current best height.
     ///
     /// Returns `Ok(None)` when the stop height is exceeded.
@@ -9,8 +9,9 @@
 //! mempool.
 #![warn(missing_docs)]
 
-use bdk_core::{BlockId, CheckPoint};
-use bitcoin::{block::Header, Block, BlockHash, Transaction};
+use bdk_core::collections::{hash_map::Entry, HashMap};
+use bdk_core::{BlockId, CheckPoint, FeeEstimator};
+use bitcoin::{block::Header, Block, BlockHash, FeeRate, OutPoint, Transaction, TxOut, Txid};
 use bitcoincore_rpc::bitcoincore_rpc_json;
 
 pub mod bip158;
@@ -142,6 +143,42 @@ impl<'c, C: bitcoincore_rpc::RpcApi> Emitter<'c, C> {
         Ok(poll(self, |hash| self.client.get_block(hash))?
             .map(|(checkpoint, block)| BlockEvent { block, checkpoint }))
     }
+
+    /// Fetch the previous outputs at `outpoints`, for example the ones spent by the transactions of
+    /// an emitted block.
+    ///
+    /// Indexers which need the outputs spent by a transaction to decide whether it is relevant,
+    /// like the silent payments index of `bdk_wallet`, must be given them before the block is
+    /// applied. See [`fetch_prevouts`] for the requirements on the node.
+    pub fn fetch_prevouts(
+        &self,
+        outpoints: impl IntoIterator<Item = OutPoint>,
+    ) -> Result<Vec<(OutPoint, TxOut)>, bitcoincore_rpc::Error> {
+        fetch_prevouts(self.client, outpoints)
+    }
+}
+
+/// Fetch the previous outputs at `outpoints` from the transactions creating them.
+///
+/// The transactions are fetched with the `getrawtransaction` RPC method, so the node must maintain
+/// a transaction index (`-txindex`) unless they are in the mempool. Outpoints of transactions that
+/// don't have the output are skipped.
+pub fn fetch_prevouts<C: bitcoincore_rpc::RpcApi>(
+    client: &C,
+    outpoints: impl IntoIterator<Item = OutPoint>,
+) -> Result<Vec<(OutPoint, TxOut)>, bitcoincore_rpc::Error> {
+    let mut txs = HashMap::<Txid, Transaction>::new();
+    let mut prevouts = Vec::new();
+    for outpoint in outpoints {
+        let tx = match txs.entry(outpoint.txid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(client.get_raw_transaction(&outpoint.txid, None)?),
+        };
+        if let Some(txout) = tx.output.get(outpoint.vout as usize) {
+            prevouts.push((outpoint, txout.clone()));
+        }
+    }
+    Ok(prevouts)
 }
 
 /// A newly emitted block from [`Emitter`].
@@ -187,6 +224,35 @@ impl<B> BlockEvent<B> {
     }
 }
 
+/// [`FeeEstimator`] backed by the `estimatesmartfee` RPC method of `bitcoind`.
+#[derive(Debug, Clone, Copy)]
+pub struct RpcFeeEstimator<'c, C> {
+    client: &'c C,
+}
+
+impl<'c, C: bitcoincore_rpc::RpcApi> RpcFeeEstimator<'c, C> {
+    /// Construct a new [`RpcFeeEstimator`].
+    pub fn new(client: &'c C) -> Self {
+        Self { client }
+    }
+}
+
+impl<C: bitcoincore_rpc::RpcApi> FeeEstimator for RpcFeeEstimator<'_, C> {
+    type Error = bitcoincore_rpc::Error;
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error> {
+        // `fee_rate` is missing if the node has no estimate for this target.
+        let res = self.client.estimate_smart_fee(target_blocks, None)?;
+        Ok(res.fee_rate.map(fee_rate_from_per_kvb))
+    }
+}
+
+/// Convert a fee rate in BTC/kvB, as returned by `estimatesmartfee`, rounding up to the next
+/// sat/kwu.
+fn fee_rate_from_per_kvb(per_kvb: bitcoin::Amount) -> FeeRate {
+    FeeRate::from_sat_per_kwu((per_kvb.to_sat() + 3) / 4)
+}
+
 enum PollResponse {
     Block(bitcoincore_rpc_json::GetBlockResult),
     NoMoreBlocks,
@@ -329,3 +395,32 @@ impl BitcoindRpcErrorExt for bitcoincore_rpc::Error {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::fee_rate_from_per_kvb;
+    use bitcoin::{Amount, FeeRate};
+
+    #[test]
+    fn test_fee_rate_from_per_kvb() {
+        // (BTC/kvB, sat/kwu)
+        let cases = [
+            ("0", 0),
+            ("0.00001", 250),
+            ("0.00001001", 251),
+            ("0.00001004", 251),
+            ("0.0002", 5_000),
+            ("0.00012345", 3_087),
+            ("0.01", 250_000),
+        ];
+        for (btc_per_kvb, sat_per_kwu) in cases {
+            let per_kvb = Amount::from_str_in(btc_per_kvb, bitcoin::Denomination::Bitcoin).unwrap();
+            assert_eq!(
+                fee_rate_from_per_kvb(per_kvb),
+                FeeRate::from_sat_per_kwu(sat_per_kwu),
+                "{} BTC/kvB",
+                btc_per_kvb
+            );
+        }
+    }
+}
@@ -464,6 +464,35 @@ fn mempool_avoids_re_emission() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Ensure [`Emitter::fetch_prevouts`] returns the outputs at the given outpoints, skipping the
+/// ones that don't exist.
+#[test]
+fn fetch_prevouts() -> anyhow::Result<()> {
+    let env = TestEnv::new()?;
+    let emitter = Emitter::new(env.rpc_client(), env.make_checkpoint_tip(), 0);
+    let addr = env
+        .rpc_client()
+        .get_new_address(None, None)?
+        .assume_checked();
+    env.mine_blocks(101, None)?;
+
+    // the transaction is in the mempool, so no transaction index is needed
+    let txid = env.send(&addr, Amount::from_sat(10_000))?;
+    let tx = env.rpc_client().get_raw_transaction(&txid, None)?;
+    let outpoints = (0..=tx.output.len() as u32).map(|vout| OutPoint::new(txid, vout));
+    let prevouts = emitter.fetch_prevouts(outpoints)?;
+    assert_eq!(
+        prevouts,
+        tx.output
+            .iter()
+            .enumerate()
+            .map(|(vout, txout)| (OutPoint::new(txid, vout as u32), txout.clone()))
+            .collect::<Vec<_>>()
+    );
+
+    Ok(())
+}
+
 /// Ensure mempool tx is still re-emitted if [`Emitter`] has not reached the tx's introduction
 /// height.
 ///
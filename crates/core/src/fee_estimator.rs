@@ -0,0 +1,37 @@
+use crate::collections::BTreeMap;
+use bitcoin::FeeRate;
+
+/// Trait for sources of fee rate estimates, such as a node or an indexing server.
+pub trait FeeEstimator {
+    /// The error returned when the estimate cannot be fetched.
+    type Error;
+
+    /// Estimate the fee rate a transaction needs to pay to be confirmed within `target_blocks`
+    /// blocks.
+    ///
+    /// Returns `Ok(None)` if the source has no estimate for this target, for example because it
+    /// has not seen enough blocks yet.
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error>;
+}
+
+impl<E: FeeEstimator + ?Sized> FeeEstimator for &E {
+    type Error = E::Error;
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error> {
+        (**self).estimate_fee_rate(target_blocks)
+    }
+}
+
+/// Fee rate estimates fetched in advance, indexed by confirmation target.
+///
+/// The estimate for a target is the one of the highest target that is not above it.
+impl FeeEstimator for BTreeMap<u16, FeeRate> {
+    type Error = core::convert::Infallible;
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error> {
+        Ok(self
+            .range(..=target_blocks)
+            .next_back()
+            .map(|(_, fee_rate)| *fee_rate))
+    }
+}
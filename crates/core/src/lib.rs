@@ -71,4 +71,7 @@ pub use tx_update::*;
 mod merge;
 pub use merge::*;
 
+mod fee_estimator;
+pub use fee_estimator::*;
+
 pub mod spk_client;
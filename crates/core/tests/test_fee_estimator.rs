@@ -0,0 +1,18 @@
+use bdk_core::{bitcoin::FeeRate, collections::BTreeMap, FeeEstimator};
+
+#[test]
+fn test_estimates_map() {
+    let estimates: BTreeMap<u16, FeeRate> = [
+        (1, FeeRate::from_sat_per_vb_u32(20)),
+        (6, FeeRate::from_sat_per_vb_u32(10)),
+        (144, FeeRate::from_sat_per_vb_u32(2)),
+    ]
+    .into();
+
+    let estimate = |target| estimates.estimate_fee_rate(target).unwrap();
+    assert_eq!(estimate(0), None);
+    assert_eq!(estimate(1), Some(FeeRate::from_sat_per_vb_u32(20)));
+    assert_eq!(estimate(5), Some(FeeRate::from_sat_per_vb_u32(20)));
+    assert_eq!(estimate(6), Some(FeeRate::from_sat_per_vb_u32(10)));
+    assert_eq!(estimate(1008), Some(FeeRate::from_sat_per_vb_u32(2)));
+}
@@ -1,8 +1,8 @@
 use bdk_core::{
-    bitcoin::{block::Header, BlockHash, OutPoint, ScriptBuf, Transaction, Txid},
+    bitcoin::{block::Header, BlockHash, FeeRate, OutPoint, ScriptBuf, Transaction, Txid},
     collections::{BTreeMap, HashMap},
     spk_client::{FullScanRequest, FullScanResponse, SyncRequest, SyncResponse},
-    BlockId, CheckPoint, ConfirmationBlockTime, TxUpdate,
+    BlockId, CheckPoint, ConfirmationBlockTime, FeeEstimator, TxUpdate,
 };
 use electrum_client::{ElectrumApi, Error, HeaderNotification};
 use std::{
@@ -444,6 +444,28 @@ impl<E: ElectrumApi> BdkElectrumClient<E> {
     }
 }
 
+/// Estimates fee rates with the `blockchain.estimatefee` method of the Electrum server.
+impl<E: ElectrumApi> FeeEstimator for BdkElectrumClient<E> {
+    type Error = Error;
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error> {
+        let btc_per_kvb = self.inner.estimate_fee(target_blocks as usize)?;
+        Ok(fee_rate_from_btc_per_kvb(btc_per_kvb))
+    }
+}
+
+/// Convert a fee rate in BTC/kvB, as returned by `blockchain.estimatefee`, rounding up to the next
+/// sat/kwu.
+///
+/// Returns `None` if the server has no estimate, which it signals with -1.
+fn fee_rate_from_btc_per_kvb(btc_per_kvb: f64) -> Option<FeeRate> {
+    if !btc_per_kvb.is_finite() || btc_per_kvb < 0.0 {
+        return None;
+    }
+    let sat_per_kvb = (btc_per_kvb * 100_000_000.0).round() as u64;
+    Some(FeeRate::from_sat_per_kwu((sat_per_kvb + 3) / 4))
+}
+
 /// Return a [`CheckPoint`] of the latest tip, that connects with `prev_tip`. The latest blocks are
 /// fetched to construct checkpoint updates with the proper [`BlockHash`] in case of re-org.
 fn fetch_tip_and_latest_blocks(
@@ -542,8 +564,11 @@ fn chain_update(
 
 #[cfg(test)]
 mod test {
-    use crate::{bdk_electrum_client::TxUpdate, BdkElectrumClient};
-    use bdk_chain::bitcoin::{OutPoint, Transaction, TxIn};
+    use crate::{
+        bdk_electrum_client::{fee_rate_from_btc_per_kvb, TxUpdate},
+        BdkElectrumClient,
+    };
+    use bdk_chain::bitcoin::{FeeRate, OutPoint, Transaction, TxIn};
     use bdk_core::collections::BTreeMap;
     use bdk_testenv::{utils::new_tx, TestEnv};
     use std::sync::Arc;
@@ -580,4 +605,28 @@ mod test {
         // Ensure that the txouts are empty.
         assert_eq!(tx_update.txouts, BTreeMap::default());
     }
+
+    #[test]
+    fn test_fee_rate_from_btc_per_kvb() {
+        // (BTC/kvB, sat/kwu)
+        let cases = [
+            (-1.0, None),
+            (f64::NAN, None),
+            (0.0, Some(0)),
+            (0.00001, Some(250)),
+            (0.00001001, Some(251)),
+            (0.00001004, Some(251)),
+            (0.0002, Some(5_000)),
+            (0.00012345, Some(3_087)),
+            (0.01, Some(250_000)),
+        ];
+        for (btc_per_kvb, sat_per_kwu) in cases {
+            assert_eq!(
+                fee_rate_from_btc_per_kvb(btc_per_kvb),
+                sat_per_kwu.map(FeeRate::from_sat_per_kwu),
+                "{} BTC/kvB",
+                btc_per_kvb
+            );
+        }
+    }
 }
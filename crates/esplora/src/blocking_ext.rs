@@ -1,13 +1,13 @@
 use bdk_core::collections::{BTreeMap, BTreeSet, HashSet};
 use bdk_core::spk_client::{FullScanRequest, FullScanResponse, SyncRequest, SyncResponse};
 use bdk_core::{
-    bitcoin::{BlockHash, OutPoint, ScriptBuf, Txid},
-    BlockId, CheckPoint, ConfirmationBlockTime, Indexed, TxUpdate,
+    bitcoin::{BlockHash, FeeRate, OutPoint, ScriptBuf, Txid},
+    BlockId, CheckPoint, ConfirmationBlockTime, FeeEstimator, Indexed, TxUpdate,
 };
 use esplora_client::{OutputStatus, Tx};
 use std::thread::JoinHandle;
 
-use crate::{insert_anchor_from_status, insert_prevouts};
+use crate::{convert_fee_estimates, insert_anchor_from_status, insert_prevouts};
 
 /// [`esplora_client::Error`]
 pub type Error = Box<esplora_client::Error>;
@@ -149,6 +149,34 @@ impl EsploraExt for esplora_client::BlockingClient {
     }
 }
 
+/// [`FeeEstimator`] backed by the `/fee-estimates` endpoint of an Esplora server.
+///
+/// Every estimate fetches all the estimates of the server. To estimate several targets with one
+/// request, fetch them with [`esplora_client::BlockingClient::get_fee_estimates`] and
+/// [`convert_fee_estimates`] instead.
+#[derive(Debug, Clone, Copy)]
+pub struct EsploraFeeEstimator<'a> {
+    client: &'a esplora_client::BlockingClient,
+}
+
+impl<'a> EsploraFeeEstimator<'a> {
+    /// Construct a new [`EsploraFeeEstimator`] from a blocking client.
+    pub fn new(client: &'a esplora_client::BlockingClient) -> Self {
+        Self { client }
+    }
+}
+
+impl FeeEstimator for EsploraFeeEstimator<'_> {
+    type Error = Error;
+
+    fn estimate_fee_rate(&self, target_blocks: u16) -> Result<Option<FeeRate>, Self::Error> {
+        let estimates = convert_fee_estimates(self.client.get_fee_estimates()?);
+        Ok(estimates
+            .estimate_fee_rate(target_blocks)
+            .unwrap_or_else(|never| match never {}))
+    }
+}
+
 /// Fetch latest blocks from Esplora in an atomic call.
 ///
 /// We want to do this before fetching transactions and anchors as we cannot fetch latest blocks AND
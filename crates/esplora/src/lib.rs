@@ -19,10 +19,18 @@
 //! Just like how [`EsploraExt`] extends the functionality of an
 //! [`esplora_client::BlockingClient`], [`EsploraAsyncExt`] is the async version which extends
 //! [`esplora_client::AsyncClient`].
+//!
+//! # Fee estimation
+//!
+//! [`EsploraFeeEstimator`] implements [`FeeEstimator`](bdk_core::FeeEstimator) with the
+//! `/fee-estimates` endpoint of a blocking client. With the async client, the estimates can be
+//! fetched in advance and converted with [`convert_fee_estimates`].
 
-use bdk_core::bitcoin::{Amount, OutPoint, TxOut, Txid};
+use bdk_core::bitcoin::{Amount, FeeRate, OutPoint, TxOut, Txid};
+use bdk_core::collections::BTreeMap;
 use bdk_core::{BlockId, ConfirmationBlockTime, TxUpdate};
 use esplora_client::TxStatus;
+use std::collections::HashMap;
 
 pub use esplora_client;
 
@@ -36,6 +44,21 @@ mod async_ext;
 #[cfg(feature = "async")]
 pub use async_ext::*;
 
+/// Convert the fee estimates returned by Esplora, in sat/vB by confirmation target, into fee rates.
+///
+/// Fee rates are rounded up to the next sat/kwu. The returned map implements
+/// [`FeeEstimator`](bdk_core::FeeEstimator).
+pub fn convert_fee_estimates(estimates: HashMap<u16, f64>) -> BTreeMap<u16, FeeRate> {
+    estimates
+        .into_iter()
+        .filter(|(_, sat_per_vb)| sat_per_vb.is_finite() && *sat_per_vb >= 0.0)
+        .map(|(target, sat_per_vb)| {
+            let sat_per_kvb = (sat_per_vb * 1_000.0).round() as u64;
+            (target, FeeRate::from_sat_per_kwu((sat_per_kvb + 3) / 4))
+        })
+        .collect()
+}
+
 fn insert_anchor_from_status(
     update: &mut TxUpdate<ConfirmationBlockTime>,
     txid: Txid,
@@ -75,3 +98,42 @@ fn insert_prevouts(
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::convert_fee_estimates;
+    use bdk_core::bitcoin::FeeRate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_convert_fee_estimates() {
+        // (target, sat/vB, sat/kwu)
+        let cases = [
+            (1, 0.0, Some(0)),
+            (2, 0.001, Some(1)),
+            (3, 1.0, Some(250)),
+            (4, 1.001, Some(251)),
+            (5, 1.004, Some(251)),
+            (6, 20.0, Some(5_000)),
+            (7, 12.345, Some(3_087)),
+            (8, 1_000.0, Some(250_000)),
+            (9, -1.0, None),
+            (10, f64::NAN, None),
+            (11, f64::INFINITY, None),
+        ];
+        let estimates = cases
+            .iter()
+            .map(|&(target, sat_per_vb, _)| (target, sat_per_vb))
+            .collect::<HashMap<u16, f64>>();
+        let converted = convert_fee_estimates(estimates);
+
+        for (target, sat_per_vb, sat_per_kwu) in cases {
+            assert_eq!(
+                converted.get(&target).copied(),
+                sat_per_kwu.map(FeeRate::from_sat_per_kwu),
+                "{} sat/vB",
+                sat_per_vb
+            );
+        }
+    }
+}
@@ -43,6 +43,12 @@ pub enum Error {
     Hex(bitcoin::hex::HexToBytesError),
     /// The provided wallet descriptors are identical
     ExternalAndInternalAreTheSame,
+    /// The provided descriptor is already used by another keychain of the wallet
+    DescriptorAlreadyInUse,
+    /// The keychain is already assigned to a different descriptor
+    KeychainAlreadyAssigned,
+    /// Error in a `musig()` key expression
+    Musig(crate::wallet::signer::musig::MusigError),
 }
 
 impl From<crate::keys::KeyError> for Error {
@@ -83,6 +89,16 @@ impl fmt::Display for Error {
             Self::ExternalAndInternalAreTheSame => {
                 write!(f, "External and internal descriptors are the same")
             }
+            Self::DescriptorAlreadyInUse => {
+                write!(f, "The descriptor is already used by another keychain")
+            }
+            Self::KeychainAlreadyAssigned => {
+                write!(
+                    f,
+                    "The keychain is already assigned to a different descriptor"
+                )
+            }
+            Self::Musig(err) => write!(f, "MuSig2 error: {}", err),
         }
     }
 }
@@ -120,6 +136,12 @@ impl From<bitcoin::hex::HexToBytesError> for Error {
     }
 }
 
+impl From<crate::wallet::signer::musig::MusigError> for Error {
+    fn from(err: crate::wallet::signer::musig::MusigError) -> Self {
+        Error::Musig(err)
+    }
+}
+
 impl From<crate::descriptor::policy::PolicyError> for Error {
     fn from(err: crate::descriptor::policy::PolicyError) -> Self {
         Error::Policy(err)
@@ -46,6 +46,7 @@ pub use self::error::Error as DescriptorError;
 pub use self::policy::Policy;
 use self::template::DescriptorTemplateOut;
 use crate::keys::{IntoDescriptorKey, KeyError};
+use crate::wallet::signer::musig::expand_musig_keys;
 use crate::wallet::signer::SignersContainer;
 use crate::wallet::utils::SecpCtx;
 
@@ -95,8 +96,9 @@ impl IntoWalletDescriptor for &str {
             }
             None => self,
         };
+        let descriptor = expand_musig_keys(secp, descriptor, network.into())?;
 
-        ExtendedDescriptor::parse_descriptor(secp, descriptor)?
+        ExtendedDescriptor::parse_descriptor(secp, &descriptor)?
             .into_wallet_descriptor(secp, network)
     }
 }
@@ -99,6 +99,15 @@ impl PkOrF {
             }
         }
     }
+
+    // The id of the signer of the key, computed like `signer_id`
+    fn signer_id(&self) -> SignerId {
+        match self {
+            PkOrF::Pubkey(pk) => pk.to_pubkeyhash(SigType::Ecdsa).into(),
+            PkOrF::XOnlyPubkey(pk) => pk.to_pubkeyhash(SigType::Ecdsa).into(),
+            PkOrF::Fingerprint(fingerprint) => (*fingerprint).into(),
+        }
+    }
 }
 
 /// An item that needs to be satisfied
@@ -196,7 +205,7 @@ fn combinations(vec: &[usize], size: usize) -> Vec<Vec<usize>> {
             for (new_index, val) in vec.iter().skip(index + 1).enumerate() {
                 let mut cloned = vals.clone();
                 cloned.push(*val);
-                queue.push_front((new_index, cloned));
+                queue.push_front((index + 1 + new_index, cloned));
             }
         }
     }
@@ -444,6 +453,52 @@ pub struct Policy {
     pub contribution: Satisfaction,
 }
 
+/// A way to satisfy a spending policy, returned by [`Policy::spending_paths`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SpendingPath {
+    /// The policy path selecting this way to satisfy the policy, to be passed to
+    /// [`TxBuilder::policy_path`](crate::wallet::tx_builder::TxBuilder::policy_path)
+    pub policy_path: BTreeMap<String, Vec<usize>>,
+    /// The keys that must sign
+    pub keys: Vec<PkOrF>,
+    /// The hash preimages that must be revealed
+    pub preimages: Vec<SatisfiableItem>,
+    /// The timelocks that must be reached
+    pub condition: Condition,
+}
+
+impl SpendingPath {
+    /// Whether the signatures of `keys` are enough to satisfy the path, not considering its
+    /// preimages and timelocks
+    pub fn is_signed_by(&self, keys: &[PkOrF]) -> bool {
+        self.keys.iter().all(|key| keys.contains(key))
+    }
+
+    /// Whether `signers` have a signer for each of the keys of the path
+    pub(crate) fn is_signed_by_signers(&self, signers: &SignersContainer) -> bool {
+        self.keys
+            .iter()
+            .all(|key| signers.find(key.signer_id()).is_some())
+    }
+
+    // Returns the path satisfying both `self` and `other`, if their timelocks are compatible
+    fn merge(mut self, other: SpendingPath) -> Option<SpendingPath> {
+        self.condition = self.condition.merge(&other.condition).ok()?;
+        self.policy_path.extend(other.policy_path);
+        for key in other.keys {
+            if !self.keys.contains(&key) {
+                self.keys.push(key);
+            }
+        }
+        for preimage in other.preimages {
+            if !self.preimages.contains(&preimage) {
+                self.preimages.push(preimage);
+            }
+        }
+        Some(self)
+    }
+}
+
 /// An extra condition that must be satisfied but that is out of control of the user
 /// TODO: use `bitcoin::LockTime` and `bitcoin::Sequence`
 #[derive(Hash, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Default, Serialize)]
@@ -725,6 +780,76 @@ impl Policy {
             _ => Ok(Condition::default()),
         }
     }
+
+    /// Return every way to satisfy the policy, with the keys, hash preimages and timelocks each
+    /// of them requires
+    ///
+    /// The combinations of items whose timelocks mix units are left out since they can't be
+    /// satisfied. Note that the number of paths grows with the number of combinations of the
+    /// items of every threshold in the policy.
+    pub fn spending_paths(&self) -> Vec<SpendingPath> {
+        match &self.item {
+            SatisfiableItem::EcdsaSignature(key) | SatisfiableItem::SchnorrSignature(key) => {
+                vec![SpendingPath {
+                    keys: vec![key.clone()],
+                    ..Default::default()
+                }]
+            }
+            SatisfiableItem::Sha256Preimage { .. }
+            | SatisfiableItem::Hash256Preimage { .. }
+            | SatisfiableItem::Ripemd160Preimage { .. }
+            | SatisfiableItem::Hash160Preimage { .. } => vec![SpendingPath {
+                preimages: vec![self.item.clone()],
+                ..Default::default()
+            }],
+            SatisfiableItem::AbsoluteTimelock { value } => vec![SpendingPath {
+                condition: Condition {
+                    csv: None,
+                    timelock: Some(*value),
+                },
+                ..Default::default()
+            }],
+            SatisfiableItem::RelativeTimelock { value } => vec![SpendingPath {
+                condition: Condition {
+                    csv: Some((*value).into()),
+                    timelock: None,
+                },
+                ..Default::default()
+            }],
+            SatisfiableItem::Multisig { keys, threshold } => {
+                combinations(&(0..keys.len()).collect::<Vec<_>>(), *threshold)
+                    .into_iter()
+                    .map(|indexes| SpendingPath {
+                        keys: indexes.iter().map(|i| keys[*i].clone()).collect(),
+                        policy_path: vec![(self.id.clone(), indexes)].into_iter().collect(),
+                        ..Default::default()
+                    })
+                    .collect()
+            }
+            SatisfiableItem::Thresh { items, threshold } => {
+                let items_paths = items.iter().map(Policy::spending_paths).collect::<Vec<_>>();
+                combinations(&(0..items.len()).collect::<Vec<_>>(), *threshold)
+                    .into_iter()
+                    .flat_map(|indexes| {
+                        let selected = SpendingPath {
+                            policy_path: vec![(self.id.clone(), indexes.clone())]
+                                .into_iter()
+                                .collect(),
+                            ..Default::default()
+                        };
+                        // every combination of the paths of the selected items
+                        mix(indexes.iter().map(|i| items_paths[*i].clone()).collect())
+                            .into_iter()
+                            .filter_map(move |paths| {
+                                paths
+                                    .into_iter()
+                                    .try_fold(selected.clone(), SpendingPath::merge)
+                            })
+                    })
+                    .collect()
+            }
+        }
+    }
 }
 
 impl From<SatisfiableItem> for Policy {
@@ -1563,6 +1688,114 @@ mod test {
         assert_eq!(out_of_range, Err(PolicyError::IndexOutOfRange(5)));
     }
 
+    #[test]
+    fn test_combinations() {
+        assert_eq!(
+            combinations(&[0, 1, 2, 3], 3),
+            vec![vec![0, 2, 3], vec![0, 1, 3], vec![0, 1, 2], vec![1, 2, 3]]
+        );
+    }
+
+    // the combinations of three or more items used to repeat some items, e.g. [0, 2, 2], so some
+    // of the conditions of a threshold were keyed by paths that `get_condition` rejects
+    #[test]
+    fn test_thresh_conditions_paths() {
+        let secp = Secp256k1::new();
+
+        let (prvkey0, _pubkey0, _fingerprint0) = setup_keys(TPRV0_STR, PATH, &secp);
+        let (prvkey1, _pubkey1, _fingerprint1) = setup_keys(TPRV1_STR, PATH, &secp);
+        let (prvkey2, _pubkey2, _fingerprint2) = setup_keys(TPRV0_STR, "m/0", &secp);
+        let sequence = 50;
+        #[rustfmt::skip]
+        let desc = descriptor!(wsh(thresh(
+            3,
+            pk(prvkey0),
+            s:pk(prvkey1),
+            s:pk(prvkey2),
+            s:l:n:older(sequence)
+        )))
+        .unwrap();
+
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+
+        let conditions = match &policy.contribution {
+            Satisfaction::PartialComplete { conditions, .. } => conditions,
+            _ => panic!("all the items can be satisfied"),
+        };
+        for (items, item_conditions) in conditions {
+            let path = vec![(policy.id.clone(), items.clone())]
+                .into_iter()
+                .collect();
+            let condition = policy.get_condition(&path).unwrap();
+            assert_eq!(item_conditions.iter().collect::<Vec<_>>(), vec![&condition]);
+            assert_eq!(condition.csv.is_some(), items.contains(&3));
+        }
+        assert_eq!(
+            conditions.keys().cloned().collect::<Vec<_>>(),
+            vec![vec![0, 1, 2], vec![0, 1, 3], vec![0, 2, 3], vec![1, 2, 3]]
+        );
+    }
+
+    #[test]
+    fn test_spending_paths() {
+        let secp = Secp256k1::new();
+
+        let fingerprint0 = bip32::Xpriv::from_str(TPRV0_STR)
+            .unwrap()
+            .fingerprint(&secp);
+        let fingerprint1 = bip32::Xpriv::from_str(TPRV1_STR)
+            .unwrap()
+            .fingerprint(&secp);
+        let sequence = 50;
+        let desc = format!(
+            "wsh(thresh(2,pk({}/0/*),s:pk({}/0/*),snl:older({})))",
+            TPRV0_STR, TPRV1_STR, sequence
+        );
+
+        let (wallet_desc, keymap) = desc
+            .into_wallet_descriptor(&secp, Network::Testnet)
+            .unwrap();
+        let signers_container = Arc::new(SignersContainer::build(keymap, &wallet_desc, &secp));
+        let policy = wallet_desc
+            .extract_policy(&signers_container, BuildSatisfaction::None, &secp)
+            .unwrap()
+            .unwrap();
+
+        let paths = policy.spending_paths();
+        assert_eq!(paths.len(), 3);
+
+        let key0 = PkOrF::Fingerprint(fingerprint0);
+        let key1 = PkOrF::Fingerprint(fingerprint1);
+        let signed_by_key0 = paths
+            .iter()
+            .filter(|path| path.is_signed_by(core::slice::from_ref(&key0)))
+            .collect::<Vec<_>>();
+        assert_eq!(signed_by_key0.len(), 1);
+        assert_eq!(signed_by_key0[0].condition.csv, Some(Sequence(sequence)));
+        assert_eq!(
+            signed_by_key0[0].policy_path,
+            vec![(policy.id.clone(), vec![0, 2])].into_iter().collect()
+        );
+
+        let both_keys = paths
+            .iter()
+            .find(|path| path.keys == vec![key0.clone(), key1.clone()])
+            .unwrap();
+        assert!(both_keys.condition.is_null());
+
+        // the policy path of every spending path is valid
+        for path in &paths {
+            assert_eq!(policy.get_condition(&path.policy_path), Ok(path.condition));
+        }
+    }
+
     const ALICE_TPRV_STR:&str = "tprv8ZgxMBicQKsPf6T5X327efHnvJDr45Xnb8W4JifNWtEoqXu9MRYS4v1oYe6DFcMVETxy5w3bqpubYRqvcVTqovG1LifFcVUuJcbwJwrhYzP";
     const BOB_TPRV_STR:&str = "tprv8ZgxMBicQKsPeinZ155cJAn117KYhbaN6MV3WeG6sWhxWzcvX1eg1awd4C9GpUN1ncLEM2rzEvunAg3GizdZD4QPPCkisTz99tXXB4wZArp";
     const CAROL_TPRV_STR:&str = "tprv8ZgxMBicQKsPdC3CicFifuLCEyVVdXVUNYorxUWj3iGZ6nimnLAYAY9SYB7ib8rKzRxrCKFcEytCt6szwd2GHnGPRCBLAEAoSVDefSNk4Bt";
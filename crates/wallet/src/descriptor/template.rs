@@ -535,10 +535,15 @@ macro_rules! expand_make_bipxx {
                         derivation_path.push(bip32::ChildNumber::from_hardened_idx(1)?);
                     }
                 }
-                derivation_path.push(bip32::ChildNumber::from_hardened_idx(0)?);
+                // custom keychains use their id as the account number
+                let account = match keychain {
+                    KeychainKind::Custom(id) => id,
+                    _ => 0,
+                };
+                derivation_path.push(bip32::ChildNumber::from_hardened_idx(account)?);
 
                 match keychain {
-                    KeychainKind::External => {
+                    KeychainKind::External | KeychainKind::Custom(_) => {
                         derivation_path.push(bip32::ChildNumber::from_normal_idx(0)?)
                     }
                     KeychainKind::Internal => {
@@ -560,6 +565,8 @@ macro_rules! expand_make_bipxx {
                 let derivation_path: bip32::DerivationPath = match keychain {
                     KeychainKind::External => vec![bip32::ChildNumber::from_normal_idx(0)?].into(),
                     KeychainKind::Internal => vec![bip32::ChildNumber::from_normal_idx(1)?].into(),
+                    // the account is fixed by the public key
+                    KeychainKind::Custom(_) => return Err(DescriptorError::InvalidHdKeyPath),
                 };
 
                 let source_path = bip32::DerivationPath::from(vec![
@@ -17,6 +17,10 @@ use bitcoin::FeeRate;
 use bitcoin::Psbt;
 use bitcoin::TxOut;
 
+pub mod v2;
+
+pub use v2::PsbtV2;
+
 // TODO upstream the functions here to `rust-bitcoin`?
 
 /// Trait to add functions to extract utxos and calculate fees.
@@ -68,3 +72,19 @@ impl PsbtUtils for Psbt {
         fee_amount.map(|fee| fee / weight)
     }
 }
+
+/// A PSBT which can be signed and finalized by the [`Wallet`](crate::Wallet): either a version 0
+/// [`Psbt`] or a [`PsbtV2`].
+pub trait SignablePsbt {
+    /// Return the PSBT as a version 0 PSBT, whose inputs are signed and finalized.
+    fn as_v0_mut(&mut self) -> &mut Psbt;
+
+    /// Update the PSBT once its inputs are signed, before they are finalized.
+    fn signed(&mut self) {}
+}
+
+impl SignablePsbt for Psbt {
+    fn as_v0_mut(&mut self) -> &mut Psbt {
+        self
+    }
+}
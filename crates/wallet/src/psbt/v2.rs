@@ -0,0 +1,1074 @@
+//! PSBT version 2
+//!
+//! This module implements the version 2 of PSBTs, defined in [BIP370]. Unlike version 0 PSBTs,
+//! which carry the unsigned transaction, version 2 PSBTs store the fields of the transaction in
+//! the maps of its inputs and outputs: this lets constructors add inputs and outputs to a PSBT
+//! after its creation, as long as its [`TxModifiable`] flags allow it.
+//!
+//! [`PsbtV2`] keeps the inputs and outputs as a version 0 [`Psbt`], so it can be signed and
+//! finalized by the [`Wallet`] like any other PSBT, and converted from and to version 0 PSBTs.
+//!
+//! [BIP370]: https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki
+//! [`Wallet`]: crate::Wallet
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::base64::prelude::{Engine as _, BASE64_STANDARD};
+use bitcoin::consensus::encode::{deserialize, deserialize_partial, serialize, VarInt};
+use bitcoin::psbt::{self, Psbt};
+use bitcoin::{
+    absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+};
+
+use super::SignablePsbt;
+
+const MAGIC: &[u8] = b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+const PSBT_GLOBAL_VERSION: u8 = 0xFB;
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0E;
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0F;
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// The key-value pairs of a map of a PSBT
+type Map = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// The flags telling constructors which parts of a [`PsbtV2`] can be modified.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct TxModifiable {
+    /// Inputs can be added
+    pub inputs: bool,
+    /// Outputs can be added
+    pub outputs: bool,
+    /// An input is signed with `SIGHASH_SINGLE`, so inputs and outputs must be added in pairs,
+    /// see [`PsbtV2::add_input_output`]
+    pub sighash_single: bool,
+}
+
+impl TxModifiable {
+    fn from_byte(byte: u8) -> Self {
+        TxModifiable {
+            inputs: byte & 0x01 != 0,
+            outputs: byte & 0x02 != 0,
+            sighash_single: byte & 0x04 != 0,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        self.inputs as u8 | (self.outputs as u8) << 1 | (self.sighash_single as u8) << 2
+    }
+}
+
+/// The minimum locktime required by an input of a [`PsbtV2`].
+///
+/// An input can require both a time and a height, in which case the locktime of the transaction
+/// can be of either type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RequiredLockTime {
+    /// The minimum time-based locktime
+    pub time: Option<absolute::Time>,
+    /// The minimum height-based locktime
+    pub height: Option<absolute::Height>,
+}
+
+impl RequiredLockTime {
+    fn is_none(&self) -> bool {
+        self.time.is_none() && self.height.is_none()
+    }
+}
+
+/// A PSBT version 2, see the [module](self) documentation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsbtV2 {
+    /// The inputs and outputs, with the locktime of the transaction computed from the fallback
+    /// and the required locktimes
+    psbt: Psbt,
+    fallback_lock_time: absolute::LockTime,
+    required_lock_times: Vec<RequiredLockTime>,
+    modifiable: TxModifiable,
+}
+
+impl PsbtV2 {
+    /// Create an empty PSBT, whose inputs and outputs can be added by constructors.
+    ///
+    /// The locktime of the transaction is `fallback_lock_time`, unless some inputs require a
+    /// locktime. Returns an error if `version` is lower than 2, which BIP370 doesn't allow.
+    pub fn new(
+        version: transaction::Version,
+        fallback_lock_time: absolute::LockTime,
+    ) -> Result<Self, PsbtV2Error> {
+        if version < transaction::Version::TWO {
+            return Err(PsbtV2Error::InvalidTxVersion(version));
+        }
+        let tx = Transaction {
+            version,
+            lock_time: fallback_lock_time,
+            input: Vec::new(),
+            output: Vec::new(),
+        };
+        Ok(PsbtV2 {
+            psbt: Psbt::from_unsigned_tx(tx).expect("the transaction is empty"),
+            fallback_lock_time,
+            required_lock_times: Vec::new(),
+            modifiable: TxModifiable {
+                inputs: true,
+                outputs: true,
+                sighash_single: false,
+            },
+        })
+    }
+
+    /// Return the version of the transaction.
+    pub fn tx_version(&self) -> transaction::Version {
+        self.psbt.unsigned_tx.version
+    }
+
+    /// Return the locktime of the transaction when no input requires a locktime.
+    pub fn fallback_lock_time(&self) -> absolute::LockTime {
+        self.fallback_lock_time
+    }
+
+    /// Return the locktime of the transaction.
+    pub fn lock_time(&self) -> absolute::LockTime {
+        self.psbt.unsigned_tx.lock_time
+    }
+
+    /// Return the flags telling which parts of the PSBT can be modified.
+    pub fn modifiable(&self) -> TxModifiable {
+        self.modifiable
+    }
+
+    /// Set the flags telling which parts of the PSBT can be modified.
+    pub fn set_modifiable(&mut self, modifiable: TxModifiable) {
+        self.modifiable = modifiable;
+    }
+
+    /// Return the locktime required by the input `index`.
+    pub fn required_lock_time(&self, index: usize) -> Option<RequiredLockTime> {
+        self.required_lock_times.get(index).copied()
+    }
+
+    /// Return the unsigned transaction.
+    pub fn unsigned_tx(&self) -> &Transaction {
+        &self.psbt.unsigned_tx
+    }
+
+    /// Return the inputs.
+    pub fn inputs(&self) -> &[psbt::Input] {
+        &self.psbt.inputs
+    }
+
+    /// Return the outputs.
+    pub fn outputs(&self) -> &[psbt::Output] {
+        &self.psbt.outputs
+    }
+
+    /// Return the PSBT as a version 0 PSBT.
+    pub fn as_psbt(&self) -> &Psbt {
+        &self.psbt
+    }
+
+    /// Convert the PSBT to a version 0 PSBT.
+    pub fn into_psbt(self) -> Psbt {
+        self.psbt
+    }
+
+    /// Add an input spending `previous_output`, as a constructor.
+    ///
+    /// The input is added after the existing ones. Returns an error if the inputs can't be
+    /// modified, if the input would change the locktime of a signed transaction, or if an input is
+    /// signed with `SIGHASH_SINGLE`, in which case [`PsbtV2::add_input_output`] must be used.
+    pub fn add_input(
+        &mut self,
+        previous_output: OutPoint,
+        sequence: Sequence,
+        input: psbt::Input,
+        required_lock_time: RequiredLockTime,
+    ) -> Result<(), PsbtV2Error> {
+        if self.modifiable.sighash_single {
+            return Err(PsbtV2Error::UnpairedModification);
+        }
+        self.push_input(previous_output, sequence, input, required_lock_time)
+    }
+
+    /// Add the output `txout`, as a constructor.
+    ///
+    /// The output is added after the existing ones. Returns an error if the outputs can't be
+    /// modified, or if an input is signed with `SIGHASH_SINGLE`, in which case
+    /// [`PsbtV2::add_input_output`] must be used.
+    pub fn add_output(&mut self, txout: TxOut, output: psbt::Output) -> Result<(), PsbtV2Error> {
+        if self.modifiable.sighash_single {
+            return Err(PsbtV2Error::UnpairedModification);
+        }
+        self.push_output(txout, output)
+    }
+
+    /// Add an input spending `previous_output` and the output `txout` at the same index, as a
+    /// constructor.
+    ///
+    /// This keeps the pairing of the inputs signed with `SIGHASH_SINGLE` with their outputs, so
+    /// it is allowed when [`TxModifiable::sighash_single`] is set. Returns an error if the inputs
+    /// or the outputs can't be modified, if the PSBT doesn't have as many inputs as outputs, or if
+    /// the input would change the locktime of a signed transaction.
+    pub fn add_input_output(
+        &mut self,
+        previous_output: OutPoint,
+        sequence: Sequence,
+        input: psbt::Input,
+        required_lock_time: RequiredLockTime,
+        txout: TxOut,
+        output: psbt::Output,
+    ) -> Result<(), PsbtV2Error> {
+        if !self.modifiable.outputs {
+            return Err(PsbtV2Error::OutputsNotModifiable);
+        }
+        if self.psbt.inputs.len() != self.psbt.outputs.len() {
+            return Err(PsbtV2Error::UnpairedModification);
+        }
+        self.push_input(previous_output, sequence, input, required_lock_time)?;
+        self.push_output(txout, output)
+    }
+
+    fn push_input(
+        &mut self,
+        previous_output: OutPoint,
+        sequence: Sequence,
+        input: psbt::Input,
+        required_lock_time: RequiredLockTime,
+    ) -> Result<(), PsbtV2Error> {
+        if !self.modifiable.inputs {
+            return Err(PsbtV2Error::InputsNotModifiable);
+        }
+        if self
+            .psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .any(|txin| txin.previous_output == previous_output)
+        {
+            return Err(PsbtV2Error::DuplicateInput(previous_output));
+        }
+        let mut required_lock_times = self.required_lock_times.clone();
+        required_lock_times.push(required_lock_time);
+        let lock_time = compute_lock_time(self.fallback_lock_time, &required_lock_times)?;
+        if lock_time != self.lock_time() && self.psbt.inputs.iter().any(is_signed) {
+            return Err(PsbtV2Error::LockTimeChange);
+        }
+
+        self.psbt.unsigned_tx.input.push(TxIn {
+            previous_output,
+            sequence,
+            ..Default::default()
+        });
+        self.psbt.unsigned_tx.lock_time = lock_time;
+        self.psbt.inputs.push(input);
+        self.required_lock_times = required_lock_times;
+        Ok(())
+    }
+
+    fn push_output(&mut self, txout: TxOut, output: psbt::Output) -> Result<(), PsbtV2Error> {
+        if !self.modifiable.outputs {
+            return Err(PsbtV2Error::OutputsNotModifiable);
+        }
+        self.psbt.unsigned_tx.output.push(txout);
+        self.psbt.outputs.push(output);
+        Ok(())
+    }
+
+    /// Serialize the PSBT in the binary format.
+    pub fn serialize(&self) -> Vec<u8> {
+        let v0 = self.psbt.serialize();
+        let mut bytes = &v0[MAGIC.len()..];
+        let tx = &self.psbt.unsigned_tx;
+
+        let mut global = read_map(&mut bytes).expect("the version 0 PSBT is valid");
+        global.retain(|(key, _)| {
+            key.as_slice() != [PSBT_GLOBAL_UNSIGNED_TX] && key.as_slice() != [PSBT_GLOBAL_VERSION]
+        });
+        global.push(pair(PSBT_GLOBAL_TX_VERSION, tx.version.0.to_le_bytes()));
+        if self.fallback_lock_time != absolute::LockTime::ZERO {
+            global.push(pair(
+                PSBT_GLOBAL_FALLBACK_LOCKTIME,
+                self.fallback_lock_time.to_consensus_u32().to_le_bytes(),
+            ));
+        }
+        global.push(pair(
+            PSBT_GLOBAL_INPUT_COUNT,
+            serialize(&VarInt(tx.input.len() as u64)),
+        ));
+        global.push(pair(
+            PSBT_GLOBAL_OUTPUT_COUNT,
+            serialize(&VarInt(tx.output.len() as u64)),
+        ));
+        global.push(pair(PSBT_GLOBAL_TX_MODIFIABLE, [self.modifiable.to_byte()]));
+        global.push(pair(PSBT_GLOBAL_VERSION, 2u32.to_le_bytes()));
+
+        let mut maps = vec![global];
+        for (txin, required) in tx.input.iter().zip(&self.required_lock_times) {
+            let mut map = read_map(&mut bytes).expect("the version 0 PSBT is valid");
+            map.push(pair(
+                PSBT_IN_PREVIOUS_TXID,
+                serialize(&txin.previous_output.txid),
+            ));
+            map.push(pair(
+                PSBT_IN_OUTPUT_INDEX,
+                txin.previous_output.vout.to_le_bytes(),
+            ));
+            map.push(pair(
+                PSBT_IN_SEQUENCE,
+                txin.sequence.to_consensus_u32().to_le_bytes(),
+            ));
+            if let Some(time) = required.time {
+                map.push(pair(
+                    PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                    time.to_consensus_u32().to_le_bytes(),
+                ));
+            }
+            if let Some(height) = required.height {
+                map.push(pair(
+                    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                    height.to_consensus_u32().to_le_bytes(),
+                ));
+            }
+            maps.push(map);
+        }
+        for txout in &tx.output {
+            let mut map = read_map(&mut bytes).expect("the version 0 PSBT is valid");
+            map.push(pair(PSBT_OUT_AMOUNT, txout.value.to_sat().to_le_bytes()));
+            map.push(pair(PSBT_OUT_SCRIPT, txout.script_pubkey.to_bytes()));
+            maps.push(map);
+        }
+
+        let mut buf = MAGIC.to_vec();
+        for mut map in maps {
+            map.sort();
+            write_map(&mut buf, &map);
+        }
+        buf
+    }
+
+    /// Deserialize a PSBT from the binary format.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, PsbtV2Error> {
+        let mut bytes = bytes
+            .strip_prefix(MAGIC)
+            .ok_or(PsbtV2Error::InvalidFormat)?;
+
+        let mut global = read_map(&mut bytes)?;
+        let version = take(&mut global, PSBT_GLOBAL_VERSION)
+            .map(|value| read_u32(&value, "version"))
+            .transpose()?
+            .unwrap_or(0);
+        if version != 2 {
+            return Err(PsbtV2Error::UnsupportedVersion(version));
+        }
+        if take(&mut global, PSBT_GLOBAL_UNSIGNED_TX).is_some() {
+            return Err(PsbtV2Error::InvalidField("unsigned transaction"));
+        }
+        let tx_version = take(&mut global, PSBT_GLOBAL_TX_VERSION)
+            .ok_or(PsbtV2Error::MissingField("transaction version"))
+            .and_then(|value| read_u32(&value, "transaction version"))
+            .map(|version| transaction::Version(version as i32))?;
+        if tx_version < transaction::Version::TWO {
+            return Err(PsbtV2Error::InvalidTxVersion(tx_version));
+        }
+        let fallback_lock_time = take(&mut global, PSBT_GLOBAL_FALLBACK_LOCKTIME)
+            .map(|value| read_u32(&value, "fallback locktime"))
+            .transpose()?
+            .map(absolute::LockTime::from_consensus)
+            .unwrap_or(absolute::LockTime::ZERO);
+        let input_count = take(&mut global, PSBT_GLOBAL_INPUT_COUNT)
+            .ok_or(PsbtV2Error::MissingField("input count"))
+            .and_then(|value| read_count(&value, "input count"))?;
+        let output_count = take(&mut global, PSBT_GLOBAL_OUTPUT_COUNT)
+            .ok_or(PsbtV2Error::MissingField("output count"))
+            .and_then(|value| read_count(&value, "output count"))?;
+        let modifiable = match take(&mut global, PSBT_GLOBAL_TX_MODIFIABLE).as_deref() {
+            Some([byte]) => TxModifiable::from_byte(*byte),
+            Some(_) => return Err(PsbtV2Error::InvalidField("transaction modifiable flags")),
+            None => TxModifiable::default(),
+        };
+
+        let mut input_maps = Vec::new();
+        let mut txins = Vec::new();
+        let mut required_lock_times = Vec::new();
+        for _ in 0..input_count {
+            let mut map = read_map(&mut bytes)?;
+            let txid = take(&mut map, PSBT_IN_PREVIOUS_TXID)
+                .ok_or(PsbtV2Error::MissingField("previous txid"))
+                .and_then(|value| {
+                    deserialize::<Txid>(&value)
+                        .map_err(|_| PsbtV2Error::InvalidField("previous txid"))
+                })?;
+            let vout = take(&mut map, PSBT_IN_OUTPUT_INDEX)
+                .ok_or(PsbtV2Error::MissingField("output index"))
+                .and_then(|value| read_u32(&value, "output index"))?;
+            let sequence = take(&mut map, PSBT_IN_SEQUENCE)
+                .map(|value| read_u32(&value, "sequence"))
+                .transpose()?
+                .map(Sequence)
+                .unwrap_or(Sequence::MAX);
+            let time = take(&mut map, PSBT_IN_REQUIRED_TIME_LOCKTIME)
+                .map(|value| {
+                    read_u32(&value, "required time locktime").and_then(|time| {
+                        absolute::Time::from_consensus(time)
+                            .map_err(|_| PsbtV2Error::InvalidField("required time locktime"))
+                    })
+                })
+                .transpose()?;
+            let height = take(&mut map, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)
+                .map(|value| {
+                    read_u32(&value, "required height locktime").and_then(|height| {
+                        absolute::Height::from_consensus(height)
+                            .map_err(|_| PsbtV2Error::InvalidField("required height locktime"))
+                    })
+                })
+                .transpose()?;
+            txins.push(TxIn {
+                previous_output: OutPoint::new(txid, vout),
+                sequence,
+                ..Default::default()
+            });
+            required_lock_times.push(RequiredLockTime { time, height });
+            input_maps.push(map);
+        }
+
+        let mut output_maps = Vec::new();
+        let mut txouts = Vec::new();
+        for _ in 0..output_count {
+            let mut map = read_map(&mut bytes)?;
+            let value = take(&mut map, PSBT_OUT_AMOUNT)
+                .ok_or(PsbtV2Error::MissingField("amount"))
+                .and_then(|value| {
+                    <[u8; 8]>::try_from(value.as_slice())
+                        .ok()
+                        .map(i64::from_le_bytes)
+                        .and_then(|amount| u64::try_from(amount).ok())
+                        .ok_or(PsbtV2Error::InvalidField("amount"))
+                })?;
+            let script_pubkey =
+                take(&mut map, PSBT_OUT_SCRIPT).ok_or(PsbtV2Error::MissingField("script"))?;
+            txouts.push(TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::from_bytes(script_pubkey),
+            });
+            output_maps.push(map);
+        }
+        if !bytes.is_empty() {
+            return Err(PsbtV2Error::InvalidFormat);
+        }
+
+        // Parse the maps as a version 0 PSBT. A transaction without inputs can't be serialized
+        // unambiguously, so a placeholder input is used in that case.
+        let no_inputs = txins.is_empty();
+        if no_inputs {
+            txins.push(TxIn::default());
+            input_maps.push(Map::new());
+        }
+        let tx = Transaction {
+            version: tx_version,
+            lock_time: compute_lock_time(fallback_lock_time, &required_lock_times)?,
+            input: txins,
+            output: txouts,
+        };
+        global.push(pair(PSBT_GLOBAL_UNSIGNED_TX, serialize(&tx)));
+        let mut v0 = MAGIC.to_vec();
+        for map in core::iter::once(&global)
+            .chain(&input_maps)
+            .chain(&output_maps)
+        {
+            write_map(&mut v0, map);
+        }
+        let mut psbt = Psbt::deserialize(&v0).map_err(PsbtV2Error::Psbt)?;
+        if no_inputs {
+            psbt.unsigned_tx.input.clear();
+            psbt.inputs.clear();
+        }
+
+        Ok(PsbtV2 {
+            psbt,
+            fallback_lock_time,
+            required_lock_times,
+            modifiable,
+        })
+    }
+}
+
+impl TryFrom<Psbt> for PsbtV2 {
+    type Error = PsbtV2Error;
+
+    /// Convert a version 0 PSBT, whose locktime becomes the fallback locktime.
+    ///
+    /// No input requires a locktime, and the PSBT can't be modified. Returns an error if the
+    /// version of the transaction is lower than 2.
+    fn try_from(mut psbt: Psbt) -> Result<Self, Self::Error> {
+        if psbt.unsigned_tx.version < transaction::Version::TWO {
+            return Err(PsbtV2Error::InvalidTxVersion(psbt.unsigned_tx.version));
+        }
+        psbt.version = 0;
+        Ok(PsbtV2 {
+            fallback_lock_time: psbt.unsigned_tx.lock_time,
+            required_lock_times: vec![RequiredLockTime::default(); psbt.inputs.len()],
+            modifiable: TxModifiable::default(),
+            psbt,
+        })
+    }
+}
+
+impl From<PsbtV2> for Psbt {
+    fn from(psbt: PsbtV2) -> Self {
+        psbt.into_psbt()
+    }
+}
+
+impl SignablePsbt for PsbtV2 {
+    fn as_v0_mut(&mut self) -> &mut Psbt {
+        &mut self.psbt
+    }
+
+    fn signed(&mut self) {
+        // signatures committing to all the inputs or outputs prevent their modification
+        for input in &self.psbt.inputs {
+            let sighash_types = input
+                .partial_sigs
+                .values()
+                .map(|sig| sig.sighash_type.to_u32())
+                .chain(
+                    input
+                        .tap_key_sig
+                        .iter()
+                        .chain(input.tap_script_sigs.values())
+                        .map(|sig| sig.sighash_type as u32),
+                );
+            for sighash_type in sighash_types {
+                // without SIGHASH_ANYONECANPAY
+                if sighash_type & 0x80 == 0 {
+                    self.modifiable.inputs = false;
+                }
+                match sighash_type & 0x1f {
+                    // SIGHASH_NONE
+                    0x02 => {}
+                    // SIGHASH_SINGLE
+                    0x03 => self.modifiable.sighash_single = true,
+                    // SIGHASH_ALL, or SIGHASH_DEFAULT for taproot
+                    _ => self.modifiable.outputs = false,
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for PsbtV2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", BASE64_STANDARD.encode(self.serialize()))
+    }
+}
+
+impl FromStr for PsbtV2 {
+    type Err = PsbtV2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = BASE64_STANDARD
+            .decode(s)
+            .map_err(|_| PsbtV2Error::InvalidFormat)?;
+        Self::deserialize(&bytes)
+    }
+}
+
+/// Whether the input has signatures.
+fn is_signed(input: &psbt::Input) -> bool {
+    !input.partial_sigs.is_empty()
+        || input.tap_key_sig.is_some()
+        || !input.tap_script_sigs.is_empty()
+        || input.final_script_sig.is_some()
+        || input.final_script_witness.is_some()
+}
+
+/// Compute the locktime of the transaction from the locktimes required by its inputs, as
+/// specified by BIP370.
+fn compute_lock_time(
+    fallback: absolute::LockTime,
+    required_lock_times: &[RequiredLockTime],
+) -> Result<absolute::LockTime, PsbtV2Error> {
+    let required_lock_times = required_lock_times
+        .iter()
+        .filter(|required| !required.is_none())
+        .collect::<Vec<_>>();
+    if required_lock_times.is_empty() {
+        return Ok(fallback);
+    }
+    // a height-based locktime is preferred when the inputs allow both types
+    if required_lock_times.iter().all(|r| r.height.is_some()) {
+        let height = required_lock_times
+            .iter()
+            .filter_map(|r| r.height)
+            .max_by_key(|height| height.to_consensus_u32())
+            .expect("not empty");
+        return Ok(absolute::LockTime::Blocks(height));
+    }
+    if required_lock_times.iter().all(|r| r.time.is_some()) {
+        let time = required_lock_times
+            .iter()
+            .filter_map(|r| r.time)
+            .max_by_key(|time| time.to_consensus_u32())
+            .expect("not empty");
+        return Ok(absolute::LockTime::Seconds(time));
+    }
+    Err(PsbtV2Error::LockTimeConflict)
+}
+
+fn pair(key_type: u8, value: impl AsRef<[u8]>) -> (Vec<u8>, Vec<u8>) {
+    (vec![key_type], value.as_ref().to_vec())
+}
+
+/// Remove the pair of `key_type` without key data from `map`, returning its value.
+fn take(map: &mut Map, key_type: u8) -> Option<Vec<u8>> {
+    let index = map
+        .iter()
+        .position(|(key, _)| key.as_slice() == [key_type])?;
+    Some(map.remove(index).1)
+}
+
+fn read_u32(value: &[u8], field: &'static str) -> Result<u32, PsbtV2Error> {
+    <[u8; 4]>::try_from(value)
+        .map(u32::from_le_bytes)
+        .map_err(|_| PsbtV2Error::InvalidField(field))
+}
+
+fn read_count(value: &[u8], field: &'static str) -> Result<u64, PsbtV2Error> {
+    deserialize::<VarInt>(value)
+        .map(|count| count.0)
+        .map_err(|_| PsbtV2Error::InvalidField(field))
+}
+
+fn read_bytes(bytes: &mut &[u8]) -> Result<Vec<u8>, PsbtV2Error> {
+    let (VarInt(len), consumed) =
+        deserialize_partial::<VarInt>(bytes).map_err(|_| PsbtV2Error::InvalidFormat)?;
+    let rest = &bytes[consumed..];
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= rest.len())
+        .ok_or(PsbtV2Error::InvalidFormat)?;
+    let (value, rest) = rest.split_at(len);
+    *bytes = rest;
+    Ok(value.to_vec())
+}
+
+/// Read a map, up to its separator.
+fn read_map(bytes: &mut &[u8]) -> Result<Map, PsbtV2Error> {
+    let mut map = Map::new();
+    loop {
+        let key = read_bytes(bytes)?;
+        if key.is_empty() {
+            return Ok(map);
+        }
+        if map.iter().any(|(k, _)| *k == key) {
+            return Err(PsbtV2Error::DuplicateKey);
+        }
+        let value = read_bytes(bytes)?;
+        map.push((key, value));
+    }
+}
+
+fn write_map(buf: &mut Vec<u8>, map: &Map) {
+    for (key, value) in map {
+        for bytes in [key, value] {
+            buf.extend(serialize(&VarInt(bytes.len() as u64)));
+            buf.extend(bytes);
+        }
+    }
+    buf.push(0x00);
+}
+
+/// Errors related to version 2 PSBTs.
+#[derive(Debug)]
+pub enum PsbtV2Error {
+    /// The PSBT is not correctly encoded.
+    InvalidFormat,
+    /// The PSBT is not a version 2 PSBT.
+    UnsupportedVersion(u32),
+    /// A key is repeated in a map.
+    DuplicateKey,
+    /// A field required by version 2 PSBTs is missing.
+    MissingField(&'static str),
+    /// A field is invalid.
+    InvalidField(&'static str),
+    /// The version of the transaction is lower than 2.
+    InvalidTxVersion(transaction::Version),
+    /// The inputs require incompatible locktimes.
+    LockTimeConflict,
+    /// The inputs of the PSBT can't be modified.
+    InputsNotModifiable,
+    /// The outputs of the PSBT can't be modified.
+    OutputsNotModifiable,
+    /// The input would change the locktime of the transaction, invalidating its signatures.
+    LockTimeChange,
+    /// The outpoint is already spent by an input.
+    DuplicateInput(OutPoint),
+    /// An input is signed with `SIGHASH_SINGLE`, so an input and an output must be added at the
+    /// same index.
+    UnpairedModification,
+    /// The inputs or outputs are invalid.
+    Psbt(psbt::Error),
+}
+
+impl fmt::Display for PsbtV2Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "Invalid PSBT encoding"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported PSBT version: {}", version)
+            }
+            Self::DuplicateKey => write!(f, "Duplicate key in PSBT map"),
+            Self::MissingField(field) => write!(f, "Missing PSBT field: {}", field),
+            Self::InvalidField(field) => write!(f, "Invalid PSBT field: {}", field),
+            Self::InvalidTxVersion(version) => write!(
+                f,
+                "Invalid transaction version {}, at least 2 is required",
+                version
+            ),
+            Self::LockTimeConflict => write!(f, "The inputs require incompatible locktimes"),
+            Self::InputsNotModifiable => write!(f, "The inputs of the PSBT can't be modified"),
+            Self::OutputsNotModifiable => write!(f, "The outputs of the PSBT can't be modified"),
+            Self::LockTimeChange => write!(
+                f,
+                "The input would change the locktime of a signed transaction"
+            ),
+            Self::DuplicateInput(outpoint) => write!(f, "Duplicate input: {}", outpoint),
+            Self::UnpairedModification => write!(
+                f,
+                "An input is signed with SIGHASH_SINGLE, inputs and outputs must be added in pairs"
+            ),
+            Self::Psbt(err) => write!(f, "Invalid PSBT: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PsbtV2Error {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn required(time: Option<u32>, height: Option<u32>) -> RequiredLockTime {
+        RequiredLockTime {
+            time: time.map(|time| absolute::Time::from_consensus(time).unwrap()),
+            height: height.map(|height| absolute::Height::from_consensus(height).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_compute_lock_time() {
+        let fallback = absolute::LockTime::from_consensus(100);
+        assert_eq!(compute_lock_time(fallback, &[]).unwrap(), fallback);
+        assert_eq!(
+            compute_lock_time(fallback, &[required(None, None)]).unwrap(),
+            fallback
+        );
+        assert_eq!(
+            compute_lock_time(
+                fallback,
+                &[
+                    required(None, Some(10_000)),
+                    required(Some(1_657_000_000), Some(20_000)),
+                    required(None, None)
+                ]
+            )
+            .unwrap(),
+            absolute::LockTime::from_consensus(20_000)
+        );
+        assert_eq!(
+            compute_lock_time(
+                fallback,
+                &[
+                    required(Some(1_657_000_000), None),
+                    required(Some(1_658_000_000), Some(20_000)),
+                ]
+            )
+            .unwrap(),
+            absolute::LockTime::from_consensus(1_658_000_000)
+        );
+        assert!(matches!(
+            compute_lock_time(
+                fallback,
+                &[
+                    required(Some(1_657_000_000), None),
+                    required(None, Some(20_000)),
+                ]
+            ),
+            Err(PsbtV2Error::LockTimeConflict)
+        ));
+    }
+
+    #[test]
+    fn test_empty_psbt() {
+        let psbt = PsbtV2::new(
+            transaction::Version::TWO,
+            absolute::LockTime::from_consensus(100),
+        )
+        .unwrap();
+        let decoded = PsbtV2::from_str(&psbt.to_string()).unwrap();
+        assert_eq!(decoded, psbt);
+        assert_eq!(decoded.lock_time(), absolute::LockTime::from_consensus(100));
+        assert!(decoded.modifiable().inputs && decoded.modifiable().outputs);
+
+        // version 0 PSBTs are rejected
+        let v0 = psbt.into_psbt();
+        assert!(matches!(
+            PsbtV2::deserialize(&v0.serialize()),
+            Err(PsbtV2Error::UnsupportedVersion(0))
+        ));
+
+        assert!(matches!(
+            PsbtV2::new(transaction::Version::ONE, absolute::LockTime::ZERO),
+            Err(PsbtV2Error::InvalidTxVersion(transaction::Version::ONE))
+        ));
+    }
+
+    fn set(map: &mut Map, key_type: u8, value: impl AsRef<[u8]>) {
+        take(map, key_type);
+        map.push(pair(key_type, value));
+    }
+
+    fn remove(map: &mut Map, key_type: u8) {
+        take(map, key_type).unwrap();
+    }
+
+    /// Return the maps of a PSBT with an input and two outputs, with only the fields required by
+    /// BIP370.
+    fn bip370_maps() -> (Map, Vec<Map>, Vec<Map>) {
+        let global = vec![
+            pair(PSBT_GLOBAL_TX_VERSION, 2u32.to_le_bytes()),
+            pair(PSBT_GLOBAL_INPUT_COUNT, [1]),
+            pair(PSBT_GLOBAL_OUTPUT_COUNT, [2]),
+            pair(PSBT_GLOBAL_TX_MODIFIABLE, [0]),
+            pair(PSBT_GLOBAL_VERSION, 2u32.to_le_bytes()),
+        ];
+        let txid = "0b0ad921419c1c8719735d72dc739f9ea9e0638d1fe4c1eef0f9944084815fc8"
+            .parse::<Txid>()
+            .unwrap();
+        let input = vec![
+            pair(PSBT_IN_PREVIOUS_TXID, serialize(&txid)),
+            pair(PSBT_IN_OUTPUT_INDEX, 1u32.to_le_bytes()),
+        ];
+        let output = |amount: u64, script: &str| {
+            vec![
+                pair(PSBT_OUT_AMOUNT, amount.to_le_bytes()),
+                pair(
+                    PSBT_OUT_SCRIPT,
+                    ScriptBuf::from_hex(script).unwrap().to_bytes(),
+                ),
+            ]
+        };
+        let outputs = vec![
+            output(149_990_000, "0014d85c2b71d0060b09c9886aeb815e50991dda124d"),
+            output(100_000_000, "001400aea9a2e5f0f876a588df5546e8742d1d87008f"),
+        ];
+        (global, vec![input], outputs)
+    }
+
+    fn bip370_psbt(global: &Map, inputs: &[Map], outputs: &[Map]) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        for map in core::iter::once(global).chain(inputs).chain(outputs) {
+            let mut map = map.clone();
+            map.sort();
+            write_map(&mut buf, &map);
+        }
+        buf
+    }
+
+    #[test]
+    fn test_bip370_invalid_vectors() {
+        type Change = fn(&mut Map, &mut Vec<Map>, &mut Vec<Map>);
+        let vectors: [(Change, PsbtV2Error); 12] = [
+            (
+                |global, _, _| set(global, PSBT_GLOBAL_UNSIGNED_TX, [0]),
+                PsbtV2Error::InvalidField("unsigned transaction"),
+            ),
+            (
+                |global, _, _| remove(global, PSBT_GLOBAL_TX_VERSION),
+                PsbtV2Error::MissingField("transaction version"),
+            ),
+            (
+                |global, _, _| set(global, PSBT_GLOBAL_TX_VERSION, 1u32.to_le_bytes()),
+                PsbtV2Error::InvalidTxVersion(transaction::Version::ONE),
+            ),
+            (
+                |global, _, _| remove(global, PSBT_GLOBAL_INPUT_COUNT),
+                PsbtV2Error::MissingField("input count"),
+            ),
+            (
+                |global, _, _| remove(global, PSBT_GLOBAL_OUTPUT_COUNT),
+                PsbtV2Error::MissingField("output count"),
+            ),
+            (
+                |_, inputs, _| remove(&mut inputs[0], PSBT_IN_PREVIOUS_TXID),
+                PsbtV2Error::MissingField("previous txid"),
+            ),
+            (
+                |_, inputs, _| remove(&mut inputs[0], PSBT_IN_OUTPUT_INDEX),
+                PsbtV2Error::MissingField("output index"),
+            ),
+            (
+                |_, _, outputs| remove(&mut outputs[0], PSBT_OUT_AMOUNT),
+                PsbtV2Error::MissingField("amount"),
+            ),
+            (
+                |_, _, outputs| remove(&mut outputs[1], PSBT_OUT_SCRIPT),
+                PsbtV2Error::MissingField("script"),
+            ),
+            // the required time locktime is a height
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                        499_999_999u32.to_le_bytes(),
+                    )
+                },
+                PsbtV2Error::InvalidField("required time locktime"),
+            ),
+            // the required height locktime is a time
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                        500_000_000u32.to_le_bytes(),
+                    )
+                },
+                PsbtV2Error::InvalidField("required height locktime"),
+            ),
+            // the inputs require locktimes of different types
+            (
+                |global, inputs, _| {
+                    set(global, PSBT_GLOBAL_INPUT_COUNT, [2]);
+                    let mut other = inputs[0].clone();
+                    set(&mut other, PSBT_IN_OUTPUT_INDEX, 2u32.to_le_bytes());
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                        10_000u32.to_le_bytes(),
+                    );
+                    set(
+                        &mut other,
+                        PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                        1_657_048_460u32.to_le_bytes(),
+                    );
+                    inputs.push(other);
+                },
+                PsbtV2Error::LockTimeConflict,
+            ),
+        ];
+        for (change, expected) in vectors {
+            let (mut global, mut inputs, mut outputs) = bip370_maps();
+            change(&mut global, &mut inputs, &mut outputs);
+            let err = PsbtV2::deserialize(&bip370_psbt(&global, &inputs, &outputs)).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string());
+        }
+    }
+    #[test]
+    fn test_bip370_valid_vectors() {
+        type Change = fn(&mut Map, &mut Vec<Map>, &mut Vec<Map>);
+        let vectors: [(Change, absolute::LockTime, TxModifiable); 7] = [
+            (
+                |_, _, _| {},
+                absolute::LockTime::ZERO,
+                TxModifiable::default(),
+            ),
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_SEQUENCE,
+                        0xfffffffeu32.to_le_bytes(),
+                    )
+                },
+                absolute::LockTime::ZERO,
+                TxModifiable::default(),
+            ),
+            (
+                |global, _, _| {
+                    set(
+                        global,
+                        PSBT_GLOBAL_FALLBACK_LOCKTIME,
+                        1_000u32.to_le_bytes(),
+                    )
+                },
+                absolute::LockTime::from_consensus(1_000),
+                TxModifiable::default(),
+            ),
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                        1_657_048_460u32.to_le_bytes(),
+                    )
+                },
+                absolute::LockTime::from_consensus(1_657_048_460),
+                TxModifiable::default(),
+            ),
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                        10_000u32.to_le_bytes(),
+                    )
+                },
+                absolute::LockTime::from_consensus(10_000),
+                TxModifiable::default(),
+            ),
+            // a height-based locktime is used when both types are allowed
+            (
+                |_, inputs, _| {
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                        1_657_048_460u32.to_le_bytes(),
+                    );
+                    set(
+                        &mut inputs[0],
+                        PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                        10_000u32.to_le_bytes(),
+                    );
+                },
+                absolute::LockTime::from_consensus(10_000),
+                TxModifiable::default(),
+            ),
+            (
+                |global, _, _| set(global, PSBT_GLOBAL_TX_MODIFIABLE, [0x07]),
+                absolute::LockTime::ZERO,
+                TxModifiable {
+                    inputs: true,
+                    outputs: true,
+                    sighash_single: true,
+                },
+            ),
+        ];
+        for (change, lock_time, modifiable) in vectors {
+            let (mut global, mut inputs, mut outputs) = bip370_maps();
+            change(&mut global, &mut inputs, &mut outputs);
+            let bytes = bip370_psbt(&global, &inputs, &outputs);
+            let psbt = PsbtV2::deserialize(&bytes).unwrap();
+            assert_eq!(psbt.lock_time(), lock_time);
+            assert_eq!(psbt.modifiable(), modifiable);
+            assert_eq!(psbt.unsigned_tx().input.len(), 1);
+            assert_eq!(psbt.unsigned_tx().output.len(), 2);
+            assert_eq!(PsbtV2::deserialize(&psbt.serialize()).unwrap(), psbt);
+        }
+    }
+}
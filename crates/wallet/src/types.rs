@@ -10,10 +10,12 @@
 // licenses.
 
 use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 use chain::{ChainPosition, ConfirmationBlockTime};
 use core::convert::AsRef;
 
-use bitcoin::transaction::{OutPoint, Sequence, TxOut};
+use bitcoin::transaction::{OutPoint, Sequence, Transaction, TxOut};
 use bitcoin::{psbt, Weight};
 
 use serde::{Deserialize, Serialize};
@@ -22,26 +24,63 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum KeychainKind {
     /// External keychain, used for deriving recipient addresses.
-    External = 0,
+    External,
     /// Internal keychain, used for deriving change addresses.
-    Internal = 1,
+    Internal,
+    /// Additional receive keychain identified by a user-chosen number, for example an account
+    /// index.
+    ///
+    /// Custom keychains are added with [`CreateParams::keychain`] or [`Wallet::add_keychain`].
+    /// Outputs received on them are treated like outputs of the [`External`] keychain.
+    ///
+    /// When used with the BIP44/49/84/86 [templates](crate::template) the id is used as the
+    /// account number. The public key templates cannot derive custom keychains.
+    ///
+    /// [`CreateParams::keychain`]: crate::CreateParams::keychain
+    /// [`Wallet::add_keychain`]: crate::Wallet::add_keychain
+    /// [`External`]: KeychainKind::External
+    Custom(u32),
 }
 
 impl KeychainKind {
     /// Return [`KeychainKind`] as a byte
+    ///
+    /// All [`Custom`](KeychainKind::Custom) keychains share the same byte: use
+    /// [`KeychainKind::to_bytes`] to tell them apart.
     pub fn as_byte(&self) -> u8 {
         match self {
             KeychainKind::External => b'e',
             KeychainKind::Internal => b'i',
+            KeychainKind::Custom(_) => b'c',
+        }
+    }
+
+    /// Return [`KeychainKind`] as bytes, unique for every keychain.
+    ///
+    /// This is the byte of [`KeychainKind::as_byte`], followed for a
+    /// [`Custom`](KeychainKind::Custom) keychain by its id in big-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.as_byte()];
+        if let KeychainKind::Custom(id) = self {
+            bytes.extend_from_slice(&id.to_be_bytes());
         }
+        bytes
+    }
+
+    /// Whether this is the [`Internal`](KeychainKind::Internal) (change) keychain.
+    pub fn is_change(&self) -> bool {
+        matches!(self, KeychainKind::Internal)
     }
 }
 
+/// All [`Custom`](KeychainKind::Custom) keychains share the same bytes: use
+/// [`KeychainKind::to_bytes`] to tell them apart.
 impl AsRef<[u8]> for KeychainKind {
     fn as_ref(&self) -> &[u8] {
         match self {
             KeychainKind::External => b"e",
             KeychainKind::Internal => b"i",
+            KeychainKind::Custom(_) => b"c",
         }
     }
 }
@@ -63,6 +102,55 @@ pub struct LocalOutput {
     pub derivation_index: u32,
     /// The position of the output in the blockchain.
     pub chain_position: ChainPosition<ConfirmationBlockTime>,
+    /// The lock preventing this UTXO from being selected, if any.
+    ///
+    /// See [`Wallet::lock_utxo`](crate::Wallet::lock_utxo).
+    #[serde(default)]
+    pub lock: Option<UtxoLock>,
+}
+
+/// A lock placed on a UTXO to keep it out of coin selection.
+///
+/// Locks are created with [`Wallet::lock_utxo`] and are persisted with the wallet.
+///
+/// [`Wallet::lock_utxo`]: crate::Wallet::lock_utxo
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UtxoLock {
+    /// Why the UTXO is locked, for example "dust attack".
+    pub reason: String,
+    /// Block height at which the lock expires, or `None` if it never expires.
+    pub until_height: Option<u32>,
+}
+
+impl UtxoLock {
+    /// Whether the lock is still in effect when the chain tip is at `height`.
+    pub fn is_active(&self, height: u32) -> bool {
+        self.until_height
+            .map_or(true, |until_height| height < until_height)
+    }
+}
+
+/// A transaction built by the wallet that has not been broadcast yet.
+///
+/// The inputs of a pending spend are reserved: they are not selected when building other
+/// transactions until the pending spend is released or expires. Pending spends are recorded with
+/// [`Wallet::insert_pending_spend`] and are persisted with the wallet.
+///
+/// [`Wallet::insert_pending_spend`]: crate::Wallet::insert_pending_spend
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PendingSpend {
+    /// The unsigned transaction.
+    pub tx: Transaction,
+    /// Block height at which the reservation expires, or `None` if it never expires.
+    pub until_height: Option<u32>,
+}
+
+impl PendingSpend {
+    /// Whether the reservation is still in effect when the chain tip is at `height`.
+    pub fn is_active(&self, height: u32) -> bool {
+        self.until_height
+            .map_or(true, |until_height| height < until_height)
+    }
 }
 
 /// A [`Utxo`] with its `satisfaction_weight`.
@@ -0,0 +1,191 @@
+//! PSBT analysis
+//!
+//! This module implements the report returned by [`Wallet::analyze_psbt`], which describes how far
+//! a PSBT is from being finalized, similar to the `analyzepsbt` RPC of Bitcoin Core: for every
+//! input it lists the keys that already signed, how much the signatures satisfy the spending
+//! policy and which timelocks prevent the input from being finalized; for the whole transaction
+//! it estimates the final weight and fee rate.
+//!
+//! ## Example
+//!
+//! ```
+//! # use core::str::FromStr;
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # use bdk_wallet::descriptor::policy::Satisfaction;
+//! # let mut wallet = doctest_wallet!();
+//! # let to_address = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt").unwrap().assume_checked();
+//! let mut tx_builder = wallet.build_tx();
+//! tx_builder.add_recipient(to_address.script_pubkey(), Amount::from_sat(50_000));
+//! let mut psbt = tx_builder.finish()?;
+//!
+//! let analysis = wallet.analyze_psbt(&psbt)?;
+//! let input = &analysis.inputs[0];
+//! assert!(input.signed_by.is_empty());
+//! assert_eq!(input.policy.as_ref().unwrap().satisfaction, Satisfaction::None);
+//! assert!(analysis.estimated_fee_rate.is_some());
+//!
+//! wallet.sign(&mut psbt, SignOptions::default())?;
+//! let analysis = wallet.analyze_psbt(&psbt)?;
+//! assert!(analysis.inputs[0].is_final);
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [`Wallet::analyze_psbt`]: crate::Wallet::analyze_psbt
+
+use alloc::vec::Vec;
+
+use bitcoin::psbt::Input;
+use bitcoin::{Amount, FeeRate, TxOut, Weight};
+use serde::Serialize;
+
+use crate::descriptor::policy::{Condition, PkOrF, Policy, Satisfaction, SatisfiableItem};
+use crate::types::KeychainKind;
+
+/// Analysis of a PSBT, returned by [`Wallet::analyze_psbt`](crate::Wallet::analyze_psbt)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsbtAnalysis {
+    /// Analysis of every input of the PSBT
+    pub inputs: Vec<InputAnalysis>,
+    /// Estimated weight of the transaction once all the inputs are finalized
+    ///
+    /// This is `None` if an input is neither finalized nor spending one of the wallet's scripts.
+    pub estimated_weight: Option<Weight>,
+    /// Fee of the transaction, `None` if the previous output of an input is unknown
+    pub fee: Option<Amount>,
+    /// Estimated fee rate of the transaction once all the inputs are finalized
+    pub estimated_fee_rate: Option<FeeRate>,
+}
+
+impl PsbtAnalysis {
+    /// Whether every input is finalized
+    pub fn is_final(&self) -> bool {
+        self.inputs.iter().all(|input| input.is_final)
+    }
+}
+
+/// Analysis of an input of a PSBT
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InputAnalysis {
+    /// The keychain of the spent script, `None` if it doesn't belong to the wallet
+    pub keychain: Option<KeychainKind>,
+    /// Whether the previous output is known, either from the PSBT or from the wallet
+    pub has_utxo: bool,
+    /// Whether the input is finalized
+    pub is_final: bool,
+    /// The keys that already signed the input
+    ///
+    /// Keys whose derivation is in the PSBT are reported by the fingerprint of their origin.
+    pub signed_by: Vec<PkOrF>,
+    /// The spending policy of the wallet's descriptor, whose [`satisfaction`] describes which
+    /// branches are satisfied by the signatures in the PSBT and the timelocks reached by the
+    /// transaction
+    ///
+    /// This is `None` if the input doesn't spend one of the wallet's scripts or if it is already
+    /// finalized.
+    ///
+    /// [`satisfaction`]: Policy::satisfaction
+    pub policy: Option<Policy>,
+    /// The timelocks of the policy that are not satisfied yet, if the signatures in the PSBT are
+    /// not enough to finalize the input
+    pub pending_timelocks: Vec<Condition>,
+    /// Estimated weight of the input once finalized, `None` if it can't be computed
+    pub satisfaction_weight: Option<Weight>,
+}
+
+impl InputAnalysis {
+    /// Whether the input is finalized or the signatures in the PSBT are enough to finalize it
+    pub fn is_satisfied(&self) -> bool {
+        self.is_final
+            || self
+                .policy
+                .as_ref()
+                .map(|policy| is_satisfied(&policy.satisfaction))
+                .unwrap_or(false)
+    }
+}
+
+// The previous outputs and the estimated final weight of a PSBT
+#[derive(Debug, Default)]
+pub(crate) struct PsbtEstimate {
+    pub(crate) utxos: Vec<Option<TxOut>>,
+    pub(crate) derivations: Vec<Option<(KeychainKind, u32)>>,
+    pub(crate) satisfaction_weights: Vec<Option<Weight>>,
+    pub(crate) weight: Option<Weight>,
+    pub(crate) fee: Option<Amount>,
+}
+
+impl PsbtEstimate {
+    pub(crate) fn fee_rate(&self) -> Option<FeeRate> {
+        self.fee.zip(self.weight).map(|(fee, weight)| fee / weight)
+    }
+}
+
+pub(crate) fn is_satisfied(satisfaction: &Satisfaction) -> bool {
+    matches!(
+        satisfaction,
+        Satisfaction::Complete { .. } | Satisfaction::PartialComplete { .. }
+    )
+}
+
+pub(crate) fn is_final(input: &Input) -> bool {
+    input.final_script_sig.is_some() || input.final_script_witness.is_some()
+}
+
+// Returns the keys that signed `input`, identified by their origin when it's known
+pub(crate) fn signed_by(input: &Input) -> Vec<PkOrF> {
+    let ecdsa = input.partial_sigs.keys().map(|pk| {
+        input
+            .bip32_derivation
+            .get(&pk.inner)
+            .map(|(fingerprint, _)| PkOrF::Fingerprint(*fingerprint))
+            .unwrap_or(PkOrF::Pubkey(*pk))
+    });
+    let schnorr = input
+        .tap_key_sig
+        .and(input.tap_internal_key)
+        .into_iter()
+        .chain(input.tap_script_sigs.keys().map(|(pk, _)| *pk))
+        .map(|pk| {
+            input
+                .tap_key_origins
+                .get(&pk)
+                .map(|(_, (fingerprint, _))| PkOrF::Fingerprint(*fingerprint))
+                .unwrap_or(PkOrF::XOnlyPubkey(pk))
+        });
+
+    let mut signed_by = Vec::new();
+    for key in ecdsa.chain(schnorr) {
+        if !signed_by.contains(&key) {
+            signed_by.push(key);
+        }
+    }
+    signed_by
+}
+
+// Returns the timelocks of `policy` which are not satisfied
+pub(crate) fn pending_timelocks(policy: &Policy) -> Vec<Condition> {
+    let mut timelocks = Vec::new();
+    let mut queue = vec![policy];
+    while let Some(policy) = queue.pop() {
+        let condition = match &policy.item {
+            SatisfiableItem::AbsoluteTimelock { value } => Condition {
+                timelock: Some(*value),
+                csv: None,
+            },
+            SatisfiableItem::RelativeTimelock { value } => Condition {
+                timelock: None,
+                csv: Some((*value).into()),
+            },
+            SatisfiableItem::Thresh { items, .. } => {
+                queue.extend(items.iter().rev());
+                continue;
+            }
+            _ => continue,
+        };
+        if !is_satisfied(&policy.satisfaction) {
+            timelocks.push(condition);
+        }
+    }
+    timelocks
+}
@@ -3,10 +3,17 @@ use bdk_chain::{
 };
 use miniscript::{Descriptor, DescriptorPublicKey};
 
+use crate::collections::BTreeMap;
+use crate::labels::{Label, LabelRef};
+use crate::{PendingSpend, UtxoLock};
+
 type IndexedTxGraphChangeSet =
     indexed_tx_graph::ChangeSet<ConfirmationBlockTime, keychain_txout::ChangeSet>;
 
 /// A changeset for [`Wallet`](crate::Wallet).
+///
+/// The fields after `indexer` weren't persisted by earlier releases: see [`LegacyChangeSet`] to
+/// read a `bdk_file_store` created by one.
 #[derive(Default, Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct ChangeSet {
     /// Descriptor for recipient addresses.
@@ -21,6 +28,18 @@ pub struct ChangeSet {
     pub tx_graph: tx_graph::ChangeSet<ConfirmationBlockTime>,
     /// Changes to [`KeychainTxOutIndex`](keychain_txout::KeychainTxOutIndex).
     pub indexer: keychain_txout::ChangeSet,
+    /// Descriptors of [`KeychainKind::Custom`](crate::KeychainKind::Custom) keychains.
+    #[serde(default)]
+    pub custom_descriptors: BTreeMap<u32, Descriptor<DescriptorPublicKey>>,
+    /// Changes to the wallet's BIP329 labels, where `None` means the label was removed.
+    #[serde(default)]
+    pub labels: BTreeMap<LabelRef, Option<Label>>,
+    /// Changes to the wallet's UTXO locks, where `None` means the UTXO was unlocked.
+    #[serde(default)]
+    pub locked_utxos: BTreeMap<bitcoin::OutPoint, Option<UtxoLock>>,
+    /// Changes to the wallet's pending spends, where `None` means the pending spend was released.
+    #[serde(default)]
+    pub pending_spends: BTreeMap<bitcoin::Txid, Option<PendingSpend>>,
 }
 
 impl Merge for ChangeSet {
@@ -52,6 +71,19 @@ impl Merge for ChangeSet {
         Merge::merge(&mut self.local_chain, other.local_chain);
         Merge::merge(&mut self.tx_graph, other.tx_graph);
         Merge::merge(&mut self.indexer, other.indexer);
+        for (keychain, descriptor) in other.custom_descriptors {
+            debug_assert!(
+                self.custom_descriptors
+                    .get(&keychain)
+                    .map_or(true, |existing| *existing == descriptor),
+                "custom descriptor must never change"
+            );
+            self.custom_descriptors.insert(keychain, descriptor);
+        }
+        // labels set or removed later take precedence
+        self.labels.extend(other.labels);
+        self.locked_utxos.extend(other.locked_utxos);
+        self.pending_spends.extend(other.pending_spends);
     }
 
     fn is_empty(&self) -> bool {
@@ -61,6 +93,10 @@ impl Merge for ChangeSet {
             && self.local_chain.is_empty()
             && self.tx_graph.is_empty()
             && self.indexer.is_empty()
+            && self.custom_descriptors.is_empty()
+            && self.labels.is_empty()
+            && self.locked_utxos.is_empty()
+            && self.pending_spends.is_empty()
     }
 }
 
@@ -70,6 +106,14 @@ impl ChangeSet {
     pub const WALLET_SCHEMA_NAME: &'static str = "bdk_wallet";
     /// Name of table to store wallet descriptors and network.
     pub const WALLET_TABLE_NAME: &'static str = "bdk_wallet";
+    /// Name of table to store descriptors of custom keychains.
+    pub const KEYCHAINS_TABLE_NAME: &'static str = "bdk_wallet_keychains";
+    /// Name of table to store BIP329 labels.
+    pub const LABELS_TABLE_NAME: &'static str = "bdk_wallet_labels";
+    /// Name of table to store UTXO locks.
+    pub const LOCKED_UTXOS_TABLE_NAME: &'static str = "bdk_wallet_locked_utxos";
+    /// Name of table to store pending spends.
+    pub const PENDING_SPENDS_TABLE_NAME: &'static str = "bdk_wallet_pending_spends";
 
     /// Get v0 sqlite [ChangeSet] schema
     pub fn schema_v0() -> alloc::string::String {
@@ -84,12 +128,78 @@ impl ChangeSet {
         )
     }
 
+    /// Get v1 sqlite [ChangeSet] schema
+    ///
+    /// Adds a table for the descriptors of custom keychains.
+    pub fn schema_v1() -> alloc::string::String {
+        format!(
+            "CREATE TABLE {} ( \
+                keychain INTEGER PRIMARY KEY NOT NULL, \
+                descriptor TEXT NOT NULL \
+                ) STRICT;",
+            Self::KEYCHAINS_TABLE_NAME,
+        )
+    }
+
+    /// Get v2 sqlite [ChangeSet] schema
+    ///
+    /// Adds a table for BIP329 labels.
+    pub fn schema_v2() -> alloc::string::String {
+        format!(
+            "CREATE TABLE {} ( \
+                type TEXT NOT NULL, \
+                ref TEXT NOT NULL, \
+                label TEXT NOT NULL, \
+                origin TEXT, \
+                spendable INTEGER, \
+                PRIMARY KEY (type, ref) \
+                ) STRICT;",
+            Self::LABELS_TABLE_NAME,
+        )
+    }
+
+    /// Get v3 sqlite [ChangeSet] schema
+    ///
+    /// Adds a table for UTXO locks.
+    pub fn schema_v3() -> alloc::string::String {
+        format!(
+            "CREATE TABLE {} ( \
+                txid TEXT NOT NULL, \
+                vout INTEGER NOT NULL, \
+                reason TEXT NOT NULL, \
+                until_height INTEGER, \
+                PRIMARY KEY (txid, vout) \
+                ) STRICT;",
+            Self::LOCKED_UTXOS_TABLE_NAME,
+        )
+    }
+
+    /// Get v4 sqlite [ChangeSet] schema
+    ///
+    /// Adds a table for pending spends.
+    pub fn schema_v4() -> alloc::string::String {
+        format!(
+            "CREATE TABLE {} ( \
+                txid TEXT PRIMARY KEY NOT NULL, \
+                tx BLOB NOT NULL, \
+                until_height INTEGER \
+                ) STRICT;",
+            Self::PENDING_SPENDS_TABLE_NAME,
+        )
+    }
+
     /// Initialize sqlite tables for wallet tables.
     pub fn init_sqlite_tables(db_tx: &chain::rusqlite::Transaction) -> chain::rusqlite::Result<()> {
         crate::rusqlite_impl::migrate_schema(
             db_tx,
             Self::WALLET_SCHEMA_NAME,
-            &[&Self::schema_v0()],
+            &[
+                &Self::schema_v0(),
+                &Self::schema_v1(),
+                &Self::schema_v2(),
+                &Self::schema_v3(),
+                &Self::schema_v4(),
+            ],
         )?;
 
         bdk_chain::local_chain::ChangeSet::init_sqlite_tables(db_tx)?;
@@ -101,6 +211,7 @@ impl ChangeSet {
 
     /// Recover a [`ChangeSet`] from sqlite database.
     pub fn from_sqlite(db_tx: &chain::rusqlite::Transaction) -> chain::rusqlite::Result<Self> {
+        use alloc::string::{String, ToString};
         use chain::rusqlite::OptionalExtension;
         use chain::Impl;
 
@@ -127,6 +238,91 @@ impl ChangeSet {
             changeset.network = network.map(Impl::into_inner);
         }
 
+        let mut keychains_statement = db_tx.prepare(&format!(
+            "SELECT keychain, descriptor FROM {}",
+            Self::KEYCHAINS_TABLE_NAME,
+        ))?;
+        let row_iter = keychains_statement.query_map([], |row| {
+            Ok((
+                row.get::<_, u32>("keychain")?,
+                row.get::<_, Impl<Descriptor<DescriptorPublicKey>>>("descriptor")?,
+            ))
+        })?;
+        for row in row_iter {
+            let (keychain, Impl(descriptor)) = row?;
+            changeset.custom_descriptors.insert(keychain, descriptor);
+        }
+
+        let mut labels_statement = db_tx.prepare(&format!(
+            "SELECT type, ref, label, origin, spendable FROM {}",
+            Self::LABELS_TABLE_NAME,
+        ))?;
+        let row_iter = labels_statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>("type")?,
+                row.get::<_, String>("ref")?,
+                Label {
+                    label: row.get("label")?,
+                    origin: row.get("origin")?,
+                    spendable: row.get("spendable")?,
+                },
+            ))
+        })?;
+        for row in row_iter {
+            let (label_type, reference, label) = row?;
+            let label_type = label_type
+                .parse()
+                .map_err(|err: crate::labels::LabelError| {
+                    chain::rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        chain::rusqlite::types::Type::Text,
+                        err.to_string().into(),
+                    )
+                })?;
+            changeset
+                .labels
+                .insert(LabelRef::new(label_type, reference), Some(label));
+        }
+
+        let mut locks_statement = db_tx.prepare(&format!(
+            "SELECT txid, vout, reason, until_height FROM {}",
+            Self::LOCKED_UTXOS_TABLE_NAME,
+        ))?;
+        let row_iter = locks_statement.query_map([], |row| {
+            Ok((
+                row.get::<_, Impl<bitcoin::Txid>>("txid")?,
+                row.get::<_, u32>("vout")?,
+                UtxoLock {
+                    reason: row.get("reason")?,
+                    until_height: row.get("until_height")?,
+                },
+            ))
+        })?;
+        for row in row_iter {
+            let (Impl(txid), vout, lock) = row?;
+            changeset
+                .locked_utxos
+                .insert(bitcoin::OutPoint::new(txid, vout), Some(lock));
+        }
+
+        let mut pending_spends_statement = db_tx.prepare(&format!(
+            "SELECT txid, tx, until_height FROM {}",
+            Self::PENDING_SPENDS_TABLE_NAME,
+        ))?;
+        let row_iter = pending_spends_statement.query_map([], |row| {
+            Ok((
+                row.get::<_, Impl<bitcoin::Txid>>("txid")?,
+                row.get::<_, Impl<bitcoin::Transaction>>("tx")?,
+                row.get::<_, Option<u32>>("until_height")?,
+            ))
+        })?;
+        for row in row_iter {
+            let (Impl(txid), Impl(tx), until_height) = row?;
+            changeset
+                .pending_spends
+                .insert(txid, Some(PendingSpend { tx, until_height }));
+        }
+
         changeset.local_chain = local_chain::ChangeSet::from_sqlite(db_tx)?;
         changeset.tx_graph = tx_graph::ChangeSet::<_>::from_sqlite(db_tx)?;
         changeset.indexer = keychain_txout::ChangeSet::from_sqlite(db_tx)?;
@@ -139,6 +335,7 @@ impl ChangeSet {
         &self,
         db_tx: &chain::rusqlite::Transaction,
     ) -> chain::rusqlite::Result<()> {
+        use alloc::string::ToString;
         use chain::rusqlite::named_params;
         use chain::Impl;
 
@@ -175,6 +372,85 @@ impl ChangeSet {
             })?;
         }
 
+        let mut keychain_statement = db_tx.prepare_cached(&format!(
+            "INSERT OR IGNORE INTO {}(keychain, descriptor) VALUES(:keychain, :descriptor)",
+            Self::KEYCHAINS_TABLE_NAME,
+        ))?;
+        for (&keychain, descriptor) in &self.custom_descriptors {
+            keychain_statement.execute(named_params! {
+                ":keychain": keychain,
+                ":descriptor": Impl(descriptor.clone()),
+            })?;
+        }
+
+        let mut label_statement = db_tx.prepare_cached(&format!(
+            "INSERT INTO {}(type, ref, label, origin, spendable) VALUES(:type, :ref, :label, :origin, :spendable) ON CONFLICT(type, ref) DO UPDATE SET label=:label, origin=:origin, spendable=:spendable",
+            Self::LABELS_TABLE_NAME,
+        ))?;
+        let mut remove_label_statement = db_tx.prepare_cached(&format!(
+            "DELETE FROM {} WHERE type=:type AND ref=:ref",
+            Self::LABELS_TABLE_NAME,
+        ))?;
+        for (label_ref, label) in &self.labels {
+            match label {
+                Some(label) => label_statement.execute(named_params! {
+                    ":type": label_ref.label_type().to_string(),
+                    ":ref": label_ref.reference(),
+                    ":label": label.label,
+                    ":origin": label.origin,
+                    ":spendable": label.spendable,
+                })?,
+                None => remove_label_statement.execute(named_params! {
+                    ":type": label_ref.label_type().to_string(),
+                    ":ref": label_ref.reference(),
+                })?,
+            };
+        }
+
+        let mut lock_statement = db_tx.prepare_cached(&format!(
+            "INSERT INTO {}(txid, vout, reason, until_height) VALUES(:txid, :vout, :reason, :until_height) ON CONFLICT(txid, vout) DO UPDATE SET reason=:reason, until_height=:until_height",
+            Self::LOCKED_UTXOS_TABLE_NAME,
+        ))?;
+        let mut unlock_statement = db_tx.prepare_cached(&format!(
+            "DELETE FROM {} WHERE txid=:txid AND vout=:vout",
+            Self::LOCKED_UTXOS_TABLE_NAME,
+        ))?;
+        for (outpoint, lock) in &self.locked_utxos {
+            match lock {
+                Some(lock) => lock_statement.execute(named_params! {
+                    ":txid": Impl(outpoint.txid),
+                    ":vout": outpoint.vout,
+                    ":reason": lock.reason,
+                    ":until_height": lock.until_height,
+                })?,
+                None => unlock_statement.execute(named_params! {
+                    ":txid": Impl(outpoint.txid),
+                    ":vout": outpoint.vout,
+                })?,
+            };
+        }
+
+        let mut pending_spend_statement = db_tx.prepare_cached(&format!(
+            "REPLACE INTO {}(txid, tx, until_height) VALUES(:txid, :tx, :until_height)",
+            Self::PENDING_SPENDS_TABLE_NAME,
+        ))?;
+        let mut release_statement = db_tx.prepare_cached(&format!(
+            "DELETE FROM {} WHERE txid=:txid",
+            Self::PENDING_SPENDS_TABLE_NAME,
+        ))?;
+        for (txid, pending_spend) in &self.pending_spends {
+            match pending_spend {
+                Some(pending_spend) => pending_spend_statement.execute(named_params! {
+                    ":txid": Impl(*txid),
+                    ":tx": Impl(pending_spend.tx.clone()),
+                    ":until_height": pending_spend.until_height,
+                })?,
+                None => release_statement.execute(named_params! {
+                    ":txid": Impl(*txid),
+                })?,
+            };
+        }
+
         self.local_chain.persist_to_sqlite(db_tx)?;
         self.tx_graph.persist_to_sqlite(db_tx)?;
         self.indexer.persist_to_sqlite(db_tx)?;
@@ -218,3 +494,66 @@ impl From<keychain_txout::ChangeSet> for ChangeSet {
         }
     }
 }
+
+/// A [`ChangeSet`] as persisted by `bdk_file_store` before custom keychains, labels, UTXO locks and
+/// pending spends were added.
+///
+/// `bdk_file_store` encodes changesets with bincode, which can't skip missing fields, so the current
+/// [`ChangeSet`] can't be read from a store created by an earlier release. Such a store can be
+/// migrated by opening it as a `Store<LegacyChangeSet>`, and appending its aggregate changeset,
+/// converted with [`From`], to a new `Store<ChangeSet>`.
+#[derive(Default, Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LegacyChangeSet {
+    /// Descriptor for recipient addresses.
+    pub descriptor: Option<Descriptor<DescriptorPublicKey>>,
+    /// Descriptor for change addresses.
+    pub change_descriptor: Option<Descriptor<DescriptorPublicKey>>,
+    /// Stores the network type of the transaction data.
+    pub network: Option<bitcoin::Network>,
+    /// Changes to the [`LocalChain`](local_chain::LocalChain).
+    pub local_chain: local_chain::ChangeSet,
+    /// Changes to [`TxGraph`](tx_graph::TxGraph).
+    pub tx_graph: tx_graph::ChangeSet<ConfirmationBlockTime>,
+    /// Changes to [`KeychainTxOutIndex`](keychain_txout::KeychainTxOutIndex).
+    pub indexer: keychain_txout::ChangeSet,
+}
+
+impl Merge for LegacyChangeSet {
+    fn merge(&mut self, other: Self) {
+        if other.descriptor.is_some() {
+            self.descriptor = other.descriptor;
+        }
+        if other.change_descriptor.is_some() {
+            self.change_descriptor = other.change_descriptor;
+        }
+        if other.network.is_some() {
+            self.network = other.network;
+        }
+        Merge::merge(&mut self.local_chain, other.local_chain);
+        Merge::merge(&mut self.tx_graph, other.tx_graph);
+        Merge::merge(&mut self.indexer, other.indexer);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.descriptor.is_none()
+            && self.change_descriptor.is_none()
+            && self.network.is_none()
+            && self.local_chain.is_empty()
+            && self.tx_graph.is_empty()
+            && self.indexer.is_empty()
+    }
+}
+
+impl From<LegacyChangeSet> for ChangeSet {
+    fn from(legacy: LegacyChangeSet) -> Self {
+        Self {
+            descriptor: legacy.descriptor,
+            change_descriptor: legacy.change_descriptor,
+            network: legacy.network,
+            local_chain: legacy.local_chain,
+            tx_graph: legacy.tx_graph,
+            indexer: legacy.indexer,
+            ..Default::default()
+        }
+    }
+}
@@ -48,6 +48,7 @@
 //!             drain_script,
 //!             rand: _,
 //!             avoid_partial_spends,
+//!             long_term_fee_rate: _,
 //!         } = params;
 //!         let mut selected_amount = Amount::ZERO;
 //!         let mut additional_weight = Weight::ZERO;
@@ -83,6 +84,7 @@
 //!             selected: all_utxos_selected,
 //!             fee_amount: additional_fees,
 //!             excess,
+//!             bump_fee: Amount::ZERO,
 //!         })
 //!     }
 //! }
@@ -127,6 +129,15 @@ use super::utils::shuffle_slice;
 /// overridden
 pub type DefaultCoinSelectionAlgorithm = BranchAndBoundCoinSelection<SingleRandomDraw>;
 
+/// Default long-term fee rate used to compute the [waste](waste) of a selection
+///
+/// This is 10 sat/vB, the same as Bitcoin Core's default `-consolidatefeerate`.
+pub const DEFAULT_LONG_TERM_FEE_RATE: FeeRate = FeeRate::from_sat_per_kwu(2_500);
+
+// Satisfaction weight of a P2WPKH input: signature len (1WU) + signature and sighash (72WU) +
+// pubkey len (1WU) + pubkey (33WU)
+const P2WPKH_SATISFACTION_WEIGHT: Weight = Weight::from_wu(1 + 72 + 1 + 33);
+
 /// Wallet's UTXO set is not enough to cover recipient's requested plus fee.
 ///
 /// This is thrown by [`CoinSelectionAlgorithm`].
@@ -181,6 +192,12 @@ pub struct CoinSelectionResult {
     pub fee_amount: Amount,
     /// Remaining amount after deducing fees and outgoing outputs
     pub excess: Excess,
+    /// Extra fee paid to bring the unconfirmed ancestors of the selected utxos up to the target
+    /// fee rate
+    ///
+    /// Coin selection algorithms should leave this at zero, it is filled in by the wallet when
+    /// the ancestors need to be bumped.
+    pub bump_fee: Amount,
 }
 
 impl CoinSelectionResult {
@@ -218,6 +235,25 @@ pub struct CoinSelectionParams<'a, R: RngCore> {
     pub rand: &'a mut R,
     /// - `avoid_partial_spends`: if true, the algorithm should try to avoid partial spends
     pub avoid_partial_spends: bool,
+    /// - `long_term_fee_rate`: the fee rate expected to be paid to spend the selected utxos in the
+    ///   future, used to compute the [waste](waste) of a selection
+    pub long_term_fee_rate: FeeRate,
+}
+
+impl<R: RngCore> CoinSelectionParams<'_, R> {
+    // Clone the params, reborrowing the random number generator.
+    fn reborrow(&mut self) -> CoinSelectionParams<'_, R> {
+        CoinSelectionParams {
+            required_utxos: self.required_utxos.clone(),
+            optional_utxos: self.optional_utxos.clone(),
+            fee_rate: self.fee_rate,
+            target_amount: self.target_amount,
+            drain_script: self.drain_script,
+            rand: &mut *self.rand,
+            avoid_partial_spends: self.avoid_partial_spends,
+            long_term_fee_rate: self.long_term_fee_rate,
+        }
+    }
 }
 
 /// Trait for generalized coin selection algorithms
@@ -292,6 +328,7 @@ impl CoinSelectionAlgorithm for LargestFirstCoinSelection {
             drain_script,
             rand: _,
             avoid_partial_spends,
+            long_term_fee_rate: _,
         } = params;
         let required_utxo_group =
             group_utxos_if_applies(required_utxos.clone(), avoid_partial_spends);
@@ -337,6 +374,7 @@ impl CoinSelectionAlgorithm for OldestFirstCoinSelection {
             drain_script,
             rand: _,
             avoid_partial_spends,
+            long_term_fee_rate: _,
         } = params;
         let required_utxo_group =
             group_utxos_if_applies(required_utxos.clone(), avoid_partial_spends);
@@ -367,10 +405,7 @@ impl CoinSelectionAlgorithm for OldestFirstCoinSelection {
 /// - `fee_rate`: required fee rate for the current selection
 /// - `drain_script`: script to consider change creation
 pub fn decide_change(remaining_amount: Amount, fee_rate: FeeRate, drain_script: &Script) -> Excess {
-    // drain_output_len = size(len(script_pubkey)) + len(script_pubkey) + size(output_value)
-    let drain_output_len = serialize(drain_script).len() + 8usize;
-    let change_fee =
-        fee_rate * Weight::from_vb(drain_output_len as u64).expect("overflow occurred");
+    let change_fee = change_fee(fee_rate, drain_script);
     let drain_val = remaining_amount.checked_sub(change_fee).unwrap_or_default();
 
     if drain_val.is_dust(drain_script) {
@@ -388,6 +423,55 @@ pub fn decide_change(remaining_amount: Amount, fee_rate: FeeRate, drain_script:
     }
 }
 
+// The fee for adding a change output with `drain_script` to the transaction.
+fn change_fee(fee_rate: FeeRate, drain_script: &Script) -> Amount {
+    // drain_output_len = size(len(script_pubkey)) + len(script_pubkey) + size(output_value)
+    let drain_output_len = serialize(drain_script).len() + 8usize;
+    fee_rate * Weight::from_vb(drain_output_len as u64).expect("overflow occurred")
+}
+
+// The weight of an input spending a utxo with the given satisfaction weight.
+fn input_weight(satisfaction_weight: Weight) -> Weight {
+    TxIn::default()
+        .segwit_weight()
+        .checked_add(satisfaction_weight)
+        .expect("`Weight` addition should not cause an integer overflow")
+}
+
+/// Calculate the waste of a selection, as defined by Bitcoin Core
+///
+/// The waste is the fee paid for spending the `selected` utxos now at `fee_rate` rather than
+/// later at `long_term_fee_rate`, plus the cost of the change output if there is one, or else
+/// the excess dropped to fees. The cost of the change output is the fee for creating it now and
+/// spending it later, where `change_spend_weight` is its satisfaction weight.
+///
+/// Among the selections paying for the same transaction, the one with the lowest waste is the
+/// cheapest in the long term. The waste is negative when consolidating utxos at a fee rate lower
+/// than the long-term one.
+pub fn waste(
+    selected: &[WeightedUtxo],
+    excess: &Excess,
+    fee_rate: FeeRate,
+    long_term_fee_rate: FeeRate,
+    change_spend_weight: Weight,
+) -> SignedAmount {
+    let signed = |amount: Amount| amount.to_signed().expect("signed amount");
+    let inputs_waste = selected
+        .iter()
+        .map(|weighted_utxo| {
+            let weight = input_weight(weighted_utxo.satisfaction_weight);
+            signed(fee_rate * weight) - signed(long_term_fee_rate * weight)
+        })
+        .sum::<SignedAmount>();
+    let excess_waste = match excess {
+        Excess::Change { fee, .. } => *fee + long_term_fee_rate * input_weight(change_spend_weight),
+        Excess::NoChange {
+            remaining_amount, ..
+        } => *remaining_amount,
+    };
+    inputs_waste + signed(excess_waste)
+}
+
 fn select_sorted_utxos(
     utxos: impl Iterator<Item = (bool, Vec<WeightedUtxo>)>,
     fee_rate: FeeRate,
@@ -434,6 +518,7 @@ fn select_sorted_utxos(
         selected,
         fee_amount,
         excess,
+        bump_fee: Amount::ZERO,
     })
 }
 
@@ -449,11 +534,7 @@ struct OutputGroup {
 
 impl OutputGroup {
     fn new(weighted_utxo: WeightedUtxo, fee_rate: FeeRate) -> Self {
-        let fee = fee_rate
-            * TxIn::default()
-                .segwit_weight()
-                .checked_add(weighted_utxo.satisfaction_weight)
-                .expect("`Weight` addition should not cause an integer overflow");
+        let fee = fee_rate * input_weight(weighted_utxo.satisfaction_weight);
         let effective_value = weighted_utxo
             .utxo
             .txout()
@@ -524,6 +605,7 @@ impl<Cs: CoinSelectionAlgorithm> CoinSelectionAlgorithm for BranchAndBoundCoinSe
             drain_script,
             rand: _,
             avoid_partial_spends,
+            long_term_fee_rate,
         } = params;
         let required_utxo_group =
             group_utxos_if_applies(required_utxos.clone(), avoid_partial_spends);
@@ -641,6 +723,7 @@ impl<Cs: CoinSelectionAlgorithm> CoinSelectionAlgorithm for BranchAndBoundCoinSe
                     drain_script,
                     rand: params.rand,
                     avoid_partial_spends,
+                    long_term_fee_rate,
                 };
                 self.fallback_algorithm.coin_select(params)
             }
@@ -804,6 +887,7 @@ impl CoinSelectionAlgorithm for SingleRandomDraw {
             drain_script,
             rand,
             avoid_partial_spends,
+            long_term_fee_rate: _,
         } = params;
         let required_utxo_group = group_utxos_if_applies(required_utxos, avoid_partial_spends);
         let mut optional_utxos_group = group_utxos_if_applies(optional_utxos, avoid_partial_spends);
@@ -822,6 +906,500 @@ impl CoinSelectionAlgorithm for SingleRandomDraw {
     }
 }
 
+/// Knapsack coin selection
+///
+/// Adapted from Bitcoin Core's knapsack solver. Looks for utxos matching the target exactly,
+/// or else exceeding it by at least `min_change`, by combining the utxos smaller than that at
+/// random. The result is compared with the smallest single utxo covering the target, which is
+/// preferred when it doesn't leave more change.
+#[derive(Debug, Clone, Copy)]
+pub struct KnapsackCoinSelection {
+    min_change: Amount,
+}
+
+impl Default for KnapsackCoinSelection {
+    fn default() -> Self {
+        Self {
+            // Bitcoin Core's lower bound for the change target
+            min_change: Amount::from_sat(50_000),
+        }
+    }
+}
+
+impl KnapsackCoinSelection {
+    /// Create new instance with the `min_change` to aim for when the target can't be matched
+    /// exactly.
+    pub fn new(min_change: Amount) -> Self {
+        Self { min_change }
+    }
+}
+
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+impl CoinSelectionAlgorithm for KnapsackCoinSelection {
+    fn coin_select<R: RngCore>(
+        &self,
+        params: CoinSelectionParams<'_, R>,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        let CoinSelectionParams {
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+            rand,
+            avoid_partial_spends,
+            long_term_fee_rate: _,
+        } = params;
+        let required_ogs = output_groups(required_utxos, avoid_partial_spends, fee_rate);
+        let mut optional_ogs = output_groups(optional_utxos, avoid_partial_spends, fee_rate);
+        optional_ogs.retain(|group| group_value(group).is_positive());
+        check_funds(&required_ogs, &optional_ogs, target_amount)?;
+
+        let signed_target_amount = target_amount.to_signed().expect("signed amount");
+        let required_value = required_ogs
+            .iter()
+            .map(|g| group_value(g))
+            .sum::<SignedAmount>();
+        let target = signed_target_amount - required_value;
+        let min_change = self.min_change.to_signed().expect("signed amount");
+
+        let selected = if !target.is_positive() {
+            vec![]
+        } else {
+            shuffle_slice(&mut optional_ogs, rand);
+
+            // Look for an exact match, while collecting the utxos smaller than the target plus
+            // the minimum change and the smallest utxo larger than that.
+            let mut exact_match = None;
+            let mut lowest_larger: Option<(usize, SignedAmount)> = None;
+            let mut applicable = Vec::new();
+            let mut total_lower = SignedAmount::ZERO;
+            for (i, group) in optional_ogs.iter().enumerate() {
+                let value = group_value(group);
+                if value == target {
+                    exact_match = Some(i);
+                    break;
+                } else if value < target + min_change {
+                    applicable.push((i, value));
+                    total_lower += value;
+                } else if lowest_larger.map_or(true, |(_, lowest)| value < lowest) {
+                    lowest_larger = Some((i, value));
+                }
+            }
+
+            if let Some(i) = exact_match {
+                vec![i]
+            } else if total_lower == target {
+                applicable.into_iter().map(|(i, _)| i).collect()
+            } else if total_lower < target {
+                // `check_funds` guarantees there is a larger utxo in this case
+                vec![lowest_larger.expect("larger utxo must exist").0]
+            } else {
+                applicable.sort_unstable_by_key(|(_, value)| core::cmp::Reverse(*value));
+                let values = applicable.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+                let (mut best, mut best_value) =
+                    approximate_best_subset(&values, total_lower, target, rand);
+                if best_value != target && total_lower >= target + min_change {
+                    (best, best_value) =
+                        approximate_best_subset(&values, total_lower, target + min_change, rand);
+                }
+
+                match lowest_larger {
+                    // Prefer the larger utxo if the subset leaves less than the minimum change,
+                    // or if it's smaller than the subset.
+                    Some((i, value))
+                        if (best_value != target && best_value < target + min_change)
+                            || value <= best_value =>
+                    {
+                        vec![i]
+                    }
+                    _ => applicable
+                        .into_iter()
+                        .zip(best)
+                        .filter_map(|((i, _), is_in_best)| is_in_best.then_some(i))
+                        .collect(),
+                }
+            }
+        };
+
+        let selected_ogs = take_groups(optional_ogs, &selected);
+        let selected_value = selected_ogs
+            .iter()
+            .map(|g| group_value(g))
+            .sum::<SignedAmount>();
+        let remaining_amount = (required_value + selected_value - signed_target_amount)
+            .to_unsigned()
+            .expect("remaining amount can't be negative");
+        let excess = decide_change(remaining_amount, fee_rate, drain_script);
+
+        Ok(calculate_cs_result(selected_ogs, required_ogs, excess))
+    }
+}
+
+// Find a subset of `values`, sorted from largest to smallest, whose sum is at least `target`
+// and as close to it as possible, by including values at random and then filling up
+// deterministically. Returns which values are included and their sum.
+fn approximate_best_subset<R: RngCore>(
+    values: &[SignedAmount],
+    total_lower: SignedAmount,
+    target: SignedAmount,
+    rand: &mut R,
+) -> (Vec<bool>, SignedAmount) {
+    let mut best = vec![true; values.len()];
+    let mut best_value = total_lower;
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = SignedAmount::ZERO;
+        let mut reached_target = false;
+        for pass in 0..2 {
+            if reached_target {
+                break;
+            }
+            for (i, value) in values.iter().enumerate() {
+                // The first pass includes values at random, the second pass includes those left
+                // out until the target is reached.
+                let include = if pass == 0 {
+                    rand.next_u32() & 1 == 1
+                } else {
+                    !included[i]
+                };
+                if include {
+                    total += *value;
+                    included[i] = true;
+                    if total >= target {
+                        reached_target = true;
+                        if total < best_value {
+                            best_value = total;
+                            best.clone_from(&included);
+                        }
+                        total -= *value;
+                        included[i] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    (best, best_value)
+}
+
+/// CoinGrinder coin selection
+///
+/// Adapted from Bitcoin Core's CoinGrinder. Finds the selection with the lowest weight that
+/// pays for the target and a change output, which minimizes the fee paid now at the expense of
+/// leaving more utxos to be spent later. This is useful when the fee rate is high.
+///
+/// Unlike other algorithms, CoinGrinder always creates change: it fails if the utxos can't pay
+/// for the target plus a change output that is not dust.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CoinGrinderCoinSelection;
+
+impl CoinSelectionAlgorithm for CoinGrinderCoinSelection {
+    fn coin_select<R: RngCore>(
+        &self,
+        params: CoinSelectionParams<'_, R>,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        let CoinSelectionParams {
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script,
+            rand: _,
+            avoid_partial_spends,
+            long_term_fee_rate: _,
+        } = params;
+        let required_ogs = output_groups(required_utxos, avoid_partial_spends, fee_rate);
+        let mut optional_ogs = output_groups(optional_utxos, avoid_partial_spends, fee_rate);
+        optional_ogs.retain(|group| group_value(group).is_positive());
+
+        // The change output must be paid for and not be dust
+        let change_target = change_fee(fee_rate, drain_script) + drain_script.minimal_non_dust();
+        check_funds(&required_ogs, &optional_ogs, target_amount + change_target)?;
+
+        let signed_target_amount = target_amount.to_signed().expect("signed amount");
+        let required_value = required_ogs
+            .iter()
+            .map(|g| group_value(g))
+            .sum::<SignedAmount>();
+        let target = signed_target_amount + change_target.to_signed().expect("signed amount")
+            - required_value;
+
+        let selected = if !target.is_positive() {
+            vec![]
+        } else {
+            // Explore the largest utxos first, and the lightest among those of the same value.
+            optional_ogs.sort_unstable_by_key(|group| {
+                (core::cmp::Reverse(group_value(group)), group_weight(group))
+            });
+            let values = optional_ogs
+                .iter()
+                .map(|g| group_value(g))
+                .collect::<Vec<_>>();
+            let weights = optional_ogs
+                .iter()
+                .map(|g| group_weight(g))
+                .collect::<Vec<_>>();
+            // The search gives up after `BNB_TOTAL_TRIES`, fall back to the largest utxos then
+            grind(&values, &weights, target).unwrap_or_else(|| largest_first(&values, target))
+        };
+
+        let selected_ogs = take_groups(optional_ogs, &selected);
+        let selected_value = selected_ogs
+            .iter()
+            .map(|g| group_value(g))
+            .sum::<SignedAmount>();
+        let remaining_amount = (required_value + selected_value - signed_target_amount)
+            .to_unsigned()
+            .expect("remaining amount can't be negative");
+        let excess = decide_change(remaining_amount, fee_rate, drain_script);
+
+        Ok(calculate_cs_result(selected_ogs, required_ogs, excess))
+    }
+}
+
+// Depth first search for the lightest selection of `values` reaching `target`. Returns the
+// indexes of the selected values, or `None` if no selection is found within `BNB_TOTAL_TRIES`.
+fn grind(values: &[SignedAmount], weights: &[Weight], target: SignedAmount) -> Option<Vec<usize>> {
+    // lookahead[i] is the sum of the values from i onwards
+    let mut lookahead = vec![SignedAmount::ZERO; values.len() + 1];
+    for i in (0..values.len()).rev() {
+        lookahead[i] = lookahead[i + 1] + values[i];
+    }
+
+    let mut selection = Vec::new();
+    let mut curr_value = SignedAmount::ZERO;
+    let mut curr_weight = Weight::ZERO;
+    let mut best: Option<(Vec<usize>, Weight, SignedAmount)> = None;
+    // The next utxo to try to include
+    let mut next = 0;
+
+    for _ in 0..BNB_TOTAL_TRIES {
+        if next < values.len() && curr_value + lookahead[next] >= target {
+            // Inclusion branch first
+            selection.push(next);
+            curr_value += values[next];
+            curr_weight += weights[next];
+
+            let over_weight = best.as_ref().map_or(false, |(_, best_weight, best_value)| {
+                curr_weight > *best_weight
+                    || (curr_weight == *best_weight && curr_value >= *best_value)
+            });
+            if !over_weight && curr_value >= target {
+                best = Some((selection.clone(), curr_weight, curr_value));
+            }
+            // Adding more utxos only makes the selection heavier, try omitting this one instead.
+            if over_weight || curr_value >= target {
+                selection.pop();
+                curr_value -= values[next];
+                curr_weight -= weights[next];
+            }
+            next += 1;
+        } else {
+            // The target can't be reached on this branch, omit the last included utxo.
+            match selection.pop() {
+                Some(last) => {
+                    curr_value -= values[last];
+                    curr_weight -= weights[last];
+                    next = last + 1;
+                }
+                None => break,
+            }
+        }
+    }
+
+    best.map(|(selection, _, _)| selection)
+}
+
+// The indexes of the first `values` reaching `target`.
+fn largest_first(values: &[SignedAmount], target: SignedAmount) -> Vec<usize> {
+    let mut curr_value = SignedAmount::ZERO;
+    (0..values.len())
+        .take_while(|&i| {
+            let reached = curr_value >= target;
+            curr_value += values[i];
+            !reached
+        })
+        .collect()
+}
+
+/// Coin selection minimizing the [waste](waste) of the selection
+///
+/// Like Bitcoin Core, this runs [`BranchAndBoundCoinSelection`], [`KnapsackCoinSelection`],
+/// [`SingleRandomDraw`] and, when the fee rate is more than three times the long-term fee rate,
+/// [`CoinGrinderCoinSelection`], then picks the result with the lowest waste. Ties are broken in
+/// favor of the result spending more utxos.
+#[derive(Debug, Clone)]
+pub struct LowestWasteCoinSelection {
+    size_of_change: u64,
+    change_spend_weight: Weight,
+    knapsack: KnapsackCoinSelection,
+}
+
+impl Default for LowestWasteCoinSelection {
+    fn default() -> Self {
+        Self {
+            // P2WPKH cost of change -> value (8 bytes) + script len (1 bytes) + script (22 bytes)
+            size_of_change: 8 + 1 + 22,
+            change_spend_weight: P2WPKH_SATISFACTION_WEIGHT,
+            knapsack: KnapsackCoinSelection::default(),
+        }
+    }
+}
+
+impl LowestWasteCoinSelection {
+    /// Create new instance with the `size_of_change` output and the satisfaction weight of the
+    /// change when it is spent, `change_spend_weight`.
+    pub fn new(size_of_change: u64, change_spend_weight: Weight) -> Self {
+        Self {
+            size_of_change,
+            change_spend_weight,
+            knapsack: KnapsackCoinSelection::default(),
+        }
+    }
+}
+
+impl CoinSelectionAlgorithm for LowestWasteCoinSelection {
+    fn coin_select<R: RngCore>(
+        &self,
+        mut params: CoinSelectionParams<'_, R>,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        // Single random draw finds a selection whenever there are enough funds, so its error is
+        // the one to report.
+        let srd = SingleRandomDraw.coin_select(params.reborrow())?;
+        let mut results = vec![
+            BranchAndBoundCoinSelection::new(self.size_of_change, NoFallback)
+                .coin_select(params.reborrow()),
+            self.knapsack.coin_select(params.reborrow()),
+            Ok(srd),
+        ];
+        if params.fee_rate.to_sat_per_kwu()
+            > params.long_term_fee_rate.to_sat_per_kwu().saturating_mul(3)
+        {
+            results.push(CoinGrinderCoinSelection.coin_select(params.reborrow()));
+        }
+
+        let satisfaction_weights = params
+            .required_utxos
+            .iter()
+            .chain(&params.optional_utxos)
+            .map(|wu| (wu.utxo.outpoint(), wu.satisfaction_weight))
+            .collect::<HashMap<_, _>>();
+        let mut best: Option<(SignedAmount, CoinSelectionResult)> = None;
+        for result in results.into_iter().flatten() {
+            let selected = result
+                .selected
+                .iter()
+                .map(|utxo| WeightedUtxo {
+                    satisfaction_weight: satisfaction_weights[&utxo.outpoint()],
+                    utxo: utxo.clone(),
+                })
+                .collect::<Vec<_>>();
+            let waste = waste(
+                &selected,
+                &result.excess,
+                params.fee_rate,
+                params.long_term_fee_rate,
+                self.change_spend_weight,
+            );
+            let is_better = best.as_ref().map_or(true, |(best_waste, best_result)| {
+                waste < *best_waste
+                    || (waste == *best_waste && selected.len() > best_result.selected.len())
+            });
+            if is_better {
+                best = Some((waste, result));
+            }
+        }
+
+        Ok(best.expect("single random draw succeeded").1)
+    }
+}
+
+// Used to run branch and bound on its own, the error is never returned to the caller.
+#[derive(Debug)]
+struct NoFallback;
+
+impl CoinSelectionAlgorithm for NoFallback {
+    fn coin_select<R: RngCore>(
+        &self,
+        params: CoinSelectionParams<'_, R>,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        Err(InsufficientFunds {
+            needed: params.target_amount,
+            available: Amount::ZERO,
+        })
+    }
+}
+
+// Group the utxos if needed and compute their fee and effective value.
+fn output_groups(
+    utxos: Vec<WeightedUtxo>,
+    avoid_partial_spends: bool,
+    fee_rate: FeeRate,
+) -> Vec<Vec<OutputGroup>> {
+    group_utxos_if_applies(utxos, avoid_partial_spends)
+        .into_iter()
+        .map(|group| {
+            group
+                .into_iter()
+                .map(|weighted_utxo| OutputGroup::new(weighted_utxo, fee_rate))
+                .collect()
+        })
+        .collect()
+}
+
+fn group_value(group: &[OutputGroup]) -> SignedAmount {
+    group.iter().map(|og| og.effective_value).sum()
+}
+
+fn group_weight(group: &[OutputGroup]) -> Weight {
+    group
+        .iter()
+        .map(|og| input_weight(og.weighted_utxo.satisfaction_weight))
+        .sum()
+}
+
+// Take the groups at the given indexes.
+fn take_groups(groups: Vec<Vec<OutputGroup>>, indexes: &[usize]) -> Vec<Vec<OutputGroup>> {
+    groups
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, group)| indexes.contains(&i).then_some(group))
+        .collect()
+}
+
+// Check that the effective value of the groups can pay for `target_amount`.
+fn check_funds(
+    required_ogs: &[Vec<OutputGroup>],
+    optional_ogs: &[Vec<OutputGroup>],
+    target_amount: Amount,
+) -> Result<(), InsufficientFunds> {
+    let total_value = required_ogs
+        .iter()
+        .chain(optional_ogs)
+        .map(|g| group_value(g))
+        .sum::<SignedAmount>();
+    if total_value >= target_amount.to_signed().expect("signed amount") {
+        return Ok(());
+    }
+    let (fees, value) = required_ogs
+        .iter()
+        .chain(optional_ogs)
+        .flatten()
+        .fold((Amount::ZERO, Amount::ZERO), |(fees, value), og| {
+            (fees + og.fee, value + og.weighted_utxo.utxo.txout().value)
+        });
+    Err(InsufficientFunds {
+        needed: target_amount + fees,
+        available: value,
+    })
+}
+
 fn calculate_cs_result(
     mut selected_utxos: Vec<Vec<OutputGroup>>,
     mut required_utxos: Vec<Vec<OutputGroup>>,
@@ -843,6 +1421,7 @@ fn calculate_cs_result(
         selected,
         fee_amount,
         excess,
+        bump_fee: Amount::ZERO,
     }
 }
 
@@ -943,6 +1522,7 @@ mod test {
                 },
                 keychain: KeychainKind::External,
                 is_spent: false,
+                lock: None,
                 derivation_index: 42,
                 chain_position,
             }),
@@ -982,6 +1562,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: rng.next_u32(),
                     chain_position: if rng.gen_bool(0.5) {
                         ChainPosition::Confirmed {
@@ -1019,6 +1600,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -1046,6 +1628,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -1064,6 +1647,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -1082,6 +1666,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -1100,6 +1685,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -1140,6 +1726,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1163,6 +1750,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1186,6 +1774,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1208,6 +1797,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut thread_rng(),
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
         assert!(matches!(result, Err(InsufficientFunds { .. })));
     }
@@ -1226,6 +1816,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut thread_rng(),
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
         assert!(matches!(result, Err(InsufficientFunds { .. })));
     }
@@ -1245,6 +1836,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1268,6 +1860,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1291,6 +1884,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1313,6 +1907,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut thread_rng(),
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
         assert!(matches!(result, Err(InsufficientFunds { .. })));
     }
@@ -1333,6 +1928,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut thread_rng(),
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
         assert!(matches!(result, Err(InsufficientFunds { .. })));
     }
@@ -1354,6 +1950,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1377,6 +1974,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1402,6 +2000,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1427,6 +2026,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut thread_rng(),
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
 
         assert!(
@@ -1456,6 +2056,7 @@ mod test {
             drain_script: &drain_script,
             rand: &mut rng,
             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
         });
 
         assert!(matches!(result, Err(InsufficientFunds {needed, available})
@@ -1500,6 +2101,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1523,6 +2125,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             },
         );
 
@@ -1544,6 +2147,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             },
         );
         assert!(matches!(result, Err(InsufficientFunds { .. })));
@@ -1566,6 +2170,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
 
@@ -1596,6 +2201,7 @@ mod test {
                     drain_script: &drain_script,
                     rand: &mut thread_rng(),
                     avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                    long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
                 })
                 .unwrap();
             assert_eq!(result.selected_amount(), target_amount);
@@ -1767,6 +2373,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             },
         );
 
@@ -1797,6 +2404,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             },
         );
 
@@ -1823,6 +2431,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             },
         );
 
@@ -1855,6 +2464,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut thread_rng(),
                 avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .unwrap();
         assert_eq!(res.selected_amount(), Amount::from_sat(200_000));
@@ -1873,6 +2483,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 0,
                     chain_position: ChainPosition::Confirmed {
                         anchor: ConfirmationBlockTime {
@@ -2010,6 +2621,7 @@ mod test {
                             drain_script: &drain_script,
                             rand: &mut thread_rng(),
                             avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                            long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
                         },
                     )
                 }
@@ -2022,6 +2634,7 @@ mod test {
                         drain_script: &drain_script,
                         rand: &mut thread_rng(),
                         avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                        long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
                     })
                 }
                 CoinSelectionAlgo::LargestFirst => {
@@ -2033,6 +2646,7 @@ mod test {
                         drain_script: &drain_script,
                         rand: &mut thread_rng(),
                         avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+                        long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
                     })
                 }
             };
@@ -2115,6 +2729,7 @@ mod test {
                     },
                     keychain: KeychainKind::External,
                     is_spent: false,
+                    lock: None,
                     derivation_index: 42,
                     chain_position: ChainPosition::Unconfirmed { last_seen: Some(0) },
                 }),
@@ -2183,6 +2798,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: false, // grouping disabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed without grouping");
         // Without grouping, the algorithm picks one UTXO—the one with the highest value.
@@ -2210,6 +2826,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: true, // grouping enabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed with grouping");
         // With grouping enabled, each address is treated as a group.
@@ -2257,6 +2874,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: false, // grouping disabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed without grouping (OldestFirst)");
         // Expect the highest-value individual coin is chosen (here 1.0 btc).
@@ -2281,6 +2899,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: true, // grouping enabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed with grouping (OldestFirst)");
         // With grouping enabled, one group (either A’s or B’s) is used: both outputs (1.0+0.5).
@@ -2325,6 +2944,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: false, // grouping disabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed without grouping (BnB)");
         // Expect exactly one UTXO selected. However, due to the fallback randomness
@@ -2351,6 +2971,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: true, // grouping enabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed with grouping (BnB)");
         // With grouping, each address is treated as a group.
@@ -2395,6 +3016,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: false, // grouping disabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed without grouping (RandomDraw)");
         // Expect that exactly one UTXO is picked.
@@ -2422,6 +3044,7 @@ mod test {
                 drain_script: &drain_script,
                 rand: &mut rng,
                 avoid_partial_spends: true, // grouping enabled
+                long_term_fee_rate: DEFAULT_LONG_TERM_FEE_RATE,
             })
             .expect("coin selection should succeed with grouping (RandomDraw)");
         // With grouping enabled, the algorithm should select both UTXOs from one address.
@@ -2444,4 +3067,324 @@ mod test {
             );
         }
     }
+
+    fn select<Cs: CoinSelectionAlgorithm>(
+        coin_selection: Cs,
+        required_utxos: Vec<WeightedUtxo>,
+        optional_utxos: Vec<WeightedUtxo>,
+        fee_rate: FeeRate,
+        long_term_fee_rate: FeeRate,
+        target_amount: Amount,
+    ) -> Result<CoinSelectionResult, InsufficientFunds> {
+        coin_selection.coin_select(CoinSelectionParams {
+            required_utxos,
+            optional_utxos,
+            fee_rate,
+            target_amount,
+            drain_script: &ScriptBuf::default(),
+            rand: &mut StdRng::from_seed([0; 32]),
+            avoid_partial_spends: DO_NOT_AVOID_PARTIAL_SPENDS,
+            long_term_fee_rate,
+        })
+    }
+
+    #[test]
+    fn test_waste() {
+        let utxos = vec![confirmed_utxo(Amount::from_sat(100_000), 0, 1, 0)];
+        let fee_rate = FeeRate::from_sat_per_vb_u32(10);
+        let long_term_fee_rate = FeeRate::from_sat_per_vb_u32(5);
+        let change_spend_weight = P2WPKH_SATISFACTION_WEIGHT;
+        let no_change = Excess::NoChange {
+            dust_threshold: Amount::from_sat(294),
+            remaining_amount: Amount::from_sat(100),
+            change_fee: Amount::from_sat(310),
+        };
+        let change = Excess::Change {
+            amount: Amount::from_sat(50_000),
+            fee: Amount::from_sat(310),
+        };
+
+        // the input weighs 272 wu, so spending it now costs 680 sats instead of 340 sats later
+        assert_eq!(
+            waste(
+                &utxos,
+                &no_change,
+                fee_rate,
+                long_term_fee_rate,
+                change_spend_weight
+            ),
+            SignedAmount::from_sat(340 + 100)
+        );
+        // spending the change output later costs 340 sats too
+        assert_eq!(
+            waste(
+                &utxos,
+                &change,
+                fee_rate,
+                long_term_fee_rate,
+                change_spend_weight
+            ),
+            SignedAmount::from_sat(340 + 310 + 340)
+        );
+        // consolidating below the long-term fee rate has a negative waste
+        assert_eq!(
+            waste(
+                &utxos,
+                &no_change,
+                long_term_fee_rate,
+                fee_rate,
+                change_spend_weight
+            ),
+            SignedAmount::from_sat(-340 + 100)
+        );
+    }
+
+    #[test]
+    fn test_knapsack_exact_match() {
+        let utxos = generate_same_value_utxos(Amount::from_sat(100_000), 10);
+        let fee_rate = FeeRate::from_sat_per_vb_u32(1);
+        let target_amount = calc_target_amount(&utxos[..3], fee_rate);
+
+        let result = select(
+            KnapsackCoinSelection::default(),
+            vec![],
+            utxos,
+            fee_rate,
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+
+        assert_eq!(result.selected.len(), 3);
+        assert_matches!(
+            result.excess,
+            Excess::NoChange {
+                remaining_amount: Amount::ZERO,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn test_knapsack_lowest_larger() {
+        // the 100_000 sat utxo is not enough, so the 200_000 sat one is used alone
+        let target_amount = Amount::from_sat(100_000) + FEE_AMOUNT;
+
+        let result = select(
+            KnapsackCoinSelection::default(),
+            vec![],
+            get_test_utxos(),
+            FeeRate::from_sat_per_vb_u32(1),
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+
+        assert_eq!(result.selected.len(), 1);
+        assert_eq!(result.selected_amount(), Amount::from_sat(200_000));
+    }
+
+    #[test]
+    fn test_knapsack_min_change() {
+        // two utxos would leave less change than the minimum, so three are used
+        let utxos = generate_same_value_utxos(Amount::from_sat(100_000), 10);
+        let target_amount = Amount::from_sat(150_000);
+
+        let result = select(
+            KnapsackCoinSelection::new(Amount::from_sat(100_000)),
+            vec![],
+            utxos,
+            FeeRate::from_sat_per_vb_u32(1),
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+
+        assert_eq!(result.selected.len(), 3);
+        assert_matches!(result.excess, Excess::Change { .. });
+    }
+
+    #[test]
+    fn test_knapsack_required_are_enough() {
+        let target_amount = Amount::from_sat(20_000) + FEE_AMOUNT;
+
+        let result = select(
+            KnapsackCoinSelection::default(),
+            get_test_utxos(),
+            generate_same_value_utxos(Amount::from_sat(100_000), 10),
+            FeeRate::from_sat_per_vb_u32(1),
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+
+        assert_eq!(result.selected.len(), 3);
+        assert_eq!(result.selected_amount(), Amount::from_sat(300_010));
+    }
+
+    #[test]
+    fn test_knapsack_insufficient_funds() {
+        let result = select(
+            KnapsackCoinSelection::default(),
+            vec![],
+            get_test_utxos(),
+            FeeRate::from_sat_per_vb_u32(1),
+            DEFAULT_LONG_TERM_FEE_RATE,
+            Amount::from_sat(500_000),
+        );
+        assert_matches!(result, Err(InsufficientFunds { .. }));
+    }
+
+    #[test]
+    fn test_coin_grinder_lightest_selection() {
+        // a large utxo that is expensive to spend and two smaller cheap ones
+        let mut heavy = confirmed_utxo(Amount::from_sat(150_000), 0, 1, 0);
+        heavy.satisfaction_weight = Weight::from_wu(2_000);
+        let light_a = confirmed_utxo(Amount::from_sat(80_000), 1, 1, 0);
+        let light_b = confirmed_utxo(Amount::from_sat(80_000), 2, 1, 0);
+        let utxos = vec![heavy, light_a.clone(), light_b.clone()];
+        let fee_rate = FeeRate::from_sat_per_vb_u32(50);
+        let target_amount = Amount::from_sat(100_000);
+
+        let result = select(
+            CoinGrinderCoinSelection,
+            vec![],
+            utxos.clone(),
+            fee_rate,
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+        let selected = result
+            .selected
+            .iter()
+            .map(|u| u.outpoint())
+            .collect::<Vec<_>>();
+        assert_eq!(selected.len(), 2);
+        assert!(selected.contains(&light_a.utxo.outpoint()));
+        assert!(selected.contains(&light_b.utxo.outpoint()));
+        assert_matches!(result.excess, Excess::Change { .. });
+
+        // largest first picks the heavy utxo
+        let result = select(
+            LargestFirstCoinSelection,
+            vec![],
+            utxos,
+            fee_rate,
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+        assert_eq!(result.selected.len(), 1);
+    }
+
+    #[test]
+    fn test_coin_grinder_needs_change() {
+        let utxos = generate_same_value_utxos(Amount::from_sat(100_000), 3);
+        let fee_rate = FeeRate::from_sat_per_vb_u32(1);
+        // enough to pay the target but not a change output
+        let target_amount = calc_target_amount(&utxos, fee_rate);
+
+        let result = select(
+            CoinGrinderCoinSelection,
+            vec![],
+            utxos,
+            fee_rate,
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        );
+        assert_matches!(result, Err(InsufficientFunds { .. }));
+    }
+
+    #[test]
+    fn test_grind_unreachable_target() {
+        let values = [SignedAmount::from_sat(1_000), SignedAmount::from_sat(2_000)];
+        let weights = [Weight::from_wu(100), Weight::from_wu(100)];
+
+        assert_eq!(grind(&[], &[], SignedAmount::from_sat(1)), None);
+        assert_eq!(
+            grind(&values, &weights, SignedAmount::from_sat(5_000)),
+            None
+        );
+        assert_eq!(
+            grind(&values, &weights, SignedAmount::from_sat(2_500)),
+            Some(vec![0, 1])
+        );
+        assert_eq!(largest_first(&values, SignedAmount::from_sat(500)), vec![0]);
+        assert_eq!(
+            largest_first(&values, SignedAmount::from_sat(2_500)),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_lowest_waste_exact_match() {
+        let utxos = generate_same_value_utxos(Amount::from_sat(100_000), 10);
+        let fee_rate = FeeRate::from_sat_per_vb_u32(1);
+        let target_amount = calc_target_amount(&utxos[..3], fee_rate);
+
+        let result = select(
+            LowestWasteCoinSelection::default(),
+            vec![],
+            utxos,
+            fee_rate,
+            DEFAULT_LONG_TERM_FEE_RATE,
+            target_amount,
+        )
+        .unwrap();
+
+        assert_eq!(result.selected.len(), 3);
+        assert_matches!(
+            result.excess,
+            Excess::NoChange {
+                remaining_amount: Amount::ZERO,
+                ..
+            }
+        );
+    }
+
+    #[test]
+    fn test_lowest_waste_depends_on_long_term_fee_rate() {
+        let utxos = generate_same_value_utxos(Amount::from_sat(100_000), 10);
+        let target_amount = Amount::from_sat(150_000);
+        let low_fee_rate = FeeRate::from_sat_per_vb_u32(1);
+        let high_fee_rate = FeeRate::from_sat_per_vb_u32(50);
+
+        // spend more utxos now if it is cheaper than spending them later
+        let result = select(
+            LowestWasteCoinSelection::default(),
+            vec![],
+            utxos.clone(),
+            low_fee_rate,
+            high_fee_rate,
+            target_amount,
+        )
+        .unwrap();
+        assert!(result.selected.len() > 2);
+
+        // and as few as possible otherwise
+        let result = select(
+            LowestWasteCoinSelection::default(),
+            vec![],
+            utxos,
+            high_fee_rate,
+            low_fee_rate,
+            target_amount,
+        )
+        .unwrap();
+        assert_eq!(result.selected.len(), 2);
+    }
+
+    #[test]
+    fn test_lowest_waste_insufficient_funds() {
+        let result = select(
+            LowestWasteCoinSelection::default(),
+            vec![],
+            get_test_utxos(),
+            FeeRate::from_sat_per_vb_u32(1),
+            DEFAULT_LONG_TERM_FEE_RATE,
+            Amount::from_sat(500_000),
+        );
+        assert_matches!(result, Err(InsufficientFunds { .. }));
+    }
 }
@@ -13,10 +13,13 @@
 
 use crate::descriptor::policy::PolicyError;
 use crate::descriptor::DescriptorError;
+use crate::psbt::v2::PsbtV2Error;
 use crate::wallet::coin_selection;
+use crate::wallet::silent_payments::SilentPaymentError;
 use crate::{descriptor, KeychainKind};
 use alloc::string::String;
-use bitcoin::{absolute, psbt, Amount, OutPoint, Sequence, Txid};
+use bdk_chain::tx_graph::CalculateFeeError;
+use bitcoin::{absolute, psbt, Amount, OutPoint, ScriptBuf, Sequence, Txid};
 use core::fmt;
 
 /// Errors returned by miniscript when updating inconsistent PSBTs
@@ -102,8 +105,19 @@ pub enum CreateTxError {
     UnknownUtxo,
     /// Missing non_witness_utxo on foreign utxo for given `OutPoint`
     MissingNonWitnessUtxo(OutPoint),
+    /// The assets given to the [`TxBuilder`] can't satisfy the spending policy of a manually
+    /// selected utxo
+    ///
+    /// [`TxBuilder`]: crate::wallet::tx_builder::TxBuilder
+    UnsatisfiableUtxo(OutPoint),
     /// Miniscript PSBT error
     MiniscriptPsbt(MiniscriptPsbtError),
+    /// Error computing the outputs of silent payment recipients
+    SilentPayment(SilentPaymentError),
+    /// The transaction can't be built as a version 2 PSBT
+    PsbtV2(PsbtV2Error),
+    /// A batch replacement doesn't pay an output of the original transaction to this script
+    RemovedOutput(ScriptBuf),
 }
 
 impl fmt::Display for CreateTxError {
@@ -165,12 +179,30 @@ impl fmt::Display for CreateTxError {
             CreateTxError::UnknownUtxo => {
                 write!(f, "UTXO not found in the internal database")
             }
+            CreateTxError::UnsatisfiableUtxo(outpoint) => {
+                write!(
+                    f,
+                    "The assets can't satisfy the spending policy of {}",
+                    outpoint
+                )
+            }
             CreateTxError::MissingNonWitnessUtxo(outpoint) => {
                 write!(f, "Missing non_witness_utxo on foreign utxo {}", outpoint)
             }
             CreateTxError::MiniscriptPsbt(err) => {
                 write!(f, "Miniscript PSBT error: {}", err)
             }
+            CreateTxError::SilentPayment(err) => {
+                write!(f, "Silent payment error: {}", err)
+            }
+            CreateTxError::PsbtV2(err) => write!(f, "PSBT version 2 error: {}", err),
+            CreateTxError::RemovedOutput(script_pubkey) => {
+                write!(
+                    f,
+                    "The replacement removes the original output to {}",
+                    script_pubkey
+                )
+            }
         }
     }
 }
@@ -193,6 +225,18 @@ impl From<MiniscriptPsbtError> for CreateTxError {
     }
 }
 
+impl From<SilentPaymentError> for CreateTxError {
+    fn from(err: SilentPaymentError) -> Self {
+        CreateTxError::SilentPayment(err)
+    }
+}
+
+impl From<PsbtV2Error> for CreateTxError {
+    fn from(err: PsbtV2Error) -> Self {
+        CreateTxError::PsbtV2(err)
+    }
+}
+
 impl From<psbt::Error> for CreateTxError {
     fn from(err: psbt::Error) -> Self {
         CreateTxError::Psbt(err)
@@ -253,3 +297,90 @@ impl fmt::Display for BuildFeeBumpError {
 
 #[cfg(feature = "std")]
 impl std::error::Error for BuildFeeBumpError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::build_cpfp`]
+///
+/// [`Wallet::build_cpfp`]: super::Wallet::build_cpfp
+pub enum BuildCpfpError {
+    /// Thrown when the parent tx is not found in the internal database
+    TransactionNotFound(Txid),
+    /// Happens when trying to bump a parent transaction that is already confirmed
+    TransactionConfirmed(Txid),
+    /// The fee of the parent transaction can't be calculated, for example because the previous
+    /// output of one of its inputs is missing
+    CalculateFee(CalculateFeeError),
+    /// The parent transaction has no unspent output that belongs to the wallet
+    NoSpendableOutput(Txid),
+}
+
+impl fmt::Display for BuildCpfpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TransactionNotFound(txid) => {
+                write!(
+                    f,
+                    "Transaction not found in the internal database with txid: {}",
+                    txid
+                )
+            }
+            Self::TransactionConfirmed(txid) => {
+                write!(f, "Transaction already confirmed with txid: {}", txid)
+            }
+            Self::CalculateFee(err) => {
+                write!(
+                    f,
+                    "Cannot calculate the fee of the parent transaction: {}",
+                    err
+                )
+            }
+            Self::NoSpendableOutput(txid) => {
+                write!(
+                    f,
+                    "Transaction has no unspent output owned by the wallet with txid: {}",
+                    txid
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BuildCpfpError {}
+
+#[derive(Debug)]
+/// Error returned from [`Wallet::build_timelock_refresh`]
+///
+/// [`Wallet::build_timelock_refresh`]: super::Wallet::build_timelock_refresh
+pub enum BuildTimelockRefreshError {
+    /// Error while extracting the spending policy of the wallet's descriptors
+    Descriptor(DescriptorError),
+    /// None of the wallet's UTXOs that aren't locked or reserved has a relative timelock expiring
+    /// in the given number of blocks
+    NoExpiringUtxos,
+    /// The descriptor of the keychain has no spending path without timelocks
+    NoPrimaryPath(KeychainKind),
+}
+
+impl fmt::Display for BuildTimelockRefreshError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Descriptor(err) => err.fmt(f),
+            Self::NoExpiringUtxos => write!(f, "No UTXO has a relative timelock about to expire"),
+            Self::NoPrimaryPath(keychain) => write!(
+                f,
+                "The descriptor of the {:?} keychain has no spending path without timelocks",
+                keychain
+            ),
+        }
+    }
+}
+
+impl From<DescriptorError> for BuildTimelockRefreshError {
+    fn from(err: DescriptorError) -> Self {
+        BuildTimelockRefreshError::Descriptor(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BuildTimelockRefreshError {}
@@ -0,0 +1,419 @@
+//! Wallet labels
+//!
+//! This module implements the labels of [BIP329], which can be attached to transactions,
+//! addresses, public keys, inputs, outputs and extended public keys. Labels are stored in the
+//! wallet's [`ChangeSet`](crate::ChangeSet) and can be exchanged with other wallets in the BIP329
+//! JSON Lines format.
+//!
+//! ## Example
+//!
+//! ```
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # use bdk_wallet::labels::LabelRef;
+//! # let mut wallet = doctest_wallet!();
+//! let address = wallet.reveal_next_address(KeychainKind::External).address;
+//! wallet.set_label(LabelRef::addr(&address), "donations");
+//! assert_eq!(
+//!     wallet.get_label(&LabelRef::addr(&address)).unwrap().label,
+//!     "donations"
+//! );
+//!
+//! let exported = wallet.export_labels();
+//! wallet.import_labels(&exported)?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [BIP329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::bip32::Xpub;
+use bitcoin::{Address, Network, OutPoint, PublicKey, Txid};
+use serde::{Deserialize, Serialize};
+
+/// The type of item a label refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelType {
+    /// A transaction, referenced by its txid.
+    Tx,
+    /// An address.
+    Addr,
+    /// A public key, in hex.
+    Pubkey,
+    /// A transaction input, referenced by the outpoint it spends.
+    Input,
+    /// A transaction output, referenced by its outpoint.
+    Output,
+    /// An extended public key.
+    Xpub,
+}
+
+impl fmt::Display for LabelType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LabelType::Tx => "tx",
+            LabelType::Addr => "addr",
+            LabelType::Pubkey => "pubkey",
+            LabelType::Input => "input",
+            LabelType::Output => "output",
+            LabelType::Xpub => "xpub",
+        };
+        f.write_str(s)
+    }
+}
+
+impl FromStr for LabelType {
+    type Err = LabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tx" => Ok(LabelType::Tx),
+            "addr" => Ok(LabelType::Addr),
+            "pubkey" => Ok(LabelType::Pubkey),
+            "input" => Ok(LabelType::Input),
+            "output" => Ok(LabelType::Output),
+            "xpub" => Ok(LabelType::Xpub),
+            _ => Err(LabelError::UnknownType(s.to_string())),
+        }
+    }
+}
+
+/// Reference to the item a label is attached to.
+///
+/// The reference is kept in the string form used by BIP329, so labels can be exported without
+/// further context. It is displayed and serialized as `<type>:<ref>`, so that it can be used
+/// as a map key in any serde format.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct LabelRef {
+    label_type: LabelType,
+    reference: String,
+}
+
+impl LabelRef {
+    /// Reference a transaction.
+    pub fn tx(txid: Txid) -> Self {
+        Self::new(LabelType::Tx, txid.to_string())
+    }
+
+    /// Reference an address.
+    pub fn addr(address: &Address) -> Self {
+        Self::new(LabelType::Addr, address.to_string())
+    }
+
+    /// Reference a public key.
+    pub fn pubkey(pubkey: &PublicKey) -> Self {
+        Self::new(LabelType::Pubkey, pubkey.to_string())
+    }
+
+    /// Reference the input spending `outpoint`.
+    pub fn input(outpoint: OutPoint) -> Self {
+        Self::new(LabelType::Input, outpoint.to_string())
+    }
+
+    /// Reference the output at `outpoint`.
+    pub fn output(outpoint: OutPoint) -> Self {
+        Self::new(LabelType::Output, outpoint.to_string())
+    }
+
+    /// Reference an extended public key.
+    pub fn xpub(xpub: &Xpub) -> Self {
+        Self::new(LabelType::Xpub, xpub.to_string())
+    }
+
+    /// Parse a BIP329 `type` and `ref` pair, checking that addresses belong to `network`.
+    pub fn from_bip329(
+        label_type: LabelType,
+        reference: &str,
+        network: Network,
+    ) -> Result<Self, LabelError> {
+        let invalid = || LabelError::InvalidRef {
+            label_type,
+            reference: reference.to_string(),
+        };
+        Ok(match label_type {
+            LabelType::Tx => Self::tx(Txid::from_str(reference).map_err(|_| invalid())?),
+            LabelType::Addr => {
+                let address = Address::<NetworkUnchecked>::from_str(reference)
+                    .map_err(|_| invalid())?
+                    .require_network(network)
+                    .map_err(|_| invalid())?;
+                Self::addr(&address)
+            }
+            LabelType::Pubkey => {
+                Self::pubkey(&PublicKey::from_str(reference).map_err(|_| invalid())?)
+            }
+            LabelType::Input => Self::input(OutPoint::from_str(reference).map_err(|_| invalid())?),
+            LabelType::Output => {
+                Self::output(OutPoint::from_str(reference).map_err(|_| invalid())?)
+            }
+            LabelType::Xpub => Self::xpub(&Xpub::from_str(reference).map_err(|_| invalid())?),
+        })
+    }
+
+    pub(crate) fn new(label_type: LabelType, reference: String) -> Self {
+        Self {
+            label_type,
+            reference,
+        }
+    }
+
+    /// The type of the referenced item.
+    pub fn label_type(&self) -> LabelType {
+        self.label_type
+    }
+
+    /// The BIP329 `ref` of the referenced item.
+    pub fn reference(&self) -> &str {
+        &self.reference
+    }
+}
+
+impl fmt::Display for LabelRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.label_type, self.reference)
+    }
+}
+
+impl FromStr for LabelRef {
+    type Err = LabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (label_type, reference) = s
+            .split_once(':')
+            .ok_or_else(|| LabelError::UnknownType(s.to_string()))?;
+        Ok(Self::new(label_type.parse()?, reference.to_string()))
+    }
+}
+
+impl From<LabelRef> for String {
+    fn from(label_ref: LabelRef) -> Self {
+        label_ref.to_string()
+    }
+}
+
+impl TryFrom<String> for LabelRef {
+    type Error = LabelError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// A label attached to a [`LabelRef`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Label {
+    /// The label text.
+    pub label: String,
+    /// Optional key origin of the referenced item, in the abbreviated descriptor form of BIP329,
+    /// e.g. `wpkh([d34db33f/84'/0'/0'])`.
+    pub origin: Option<String>,
+    /// Whether an output should be spent, only meaningful for [`LabelType::Output`].
+    pub spendable: Option<bool>,
+}
+
+impl From<&str> for Label {
+    fn from(label: &str) -> Self {
+        Self::from(label.to_string())
+    }
+}
+
+impl From<String> for Label {
+    fn from(label: String) -> Self {
+        Self {
+            label,
+            ..Default::default()
+        }
+    }
+}
+
+/// A single line of a BIP329 export.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    #[serde(rename = "type")]
+    label_type: LabelType,
+    #[serde(rename = "ref")]
+    reference: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    origin: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spendable: Option<bool>,
+}
+
+/// Encode labels in the BIP329 JSON Lines format.
+pub(crate) fn to_jsonl<'a>(labels: impl IntoIterator<Item = (&'a LabelRef, &'a Label)>) -> String {
+    let mut jsonl = String::new();
+    for (label_ref, label) in labels {
+        let record = Record {
+            label_type: label_ref.label_type,
+            reference: label_ref.reference.clone(),
+            label: label.label.clone(),
+            origin: label.origin.clone(),
+            spendable: label.spendable,
+        };
+        jsonl.push_str(&serde_json::to_string(&record).expect("must serialize"));
+        jsonl.push('\n');
+    }
+    jsonl
+}
+
+/// Decode labels from the BIP329 JSON Lines format, skipping empty lines.
+pub(crate) fn from_jsonl(
+    jsonl: &str,
+    network: Network,
+) -> Result<alloc::vec::Vec<(LabelRef, Label)>, LabelError> {
+    jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            let record = serde_json::from_str::<Record>(line).map_err(|err| LabelError::Json {
+                line: index + 1,
+                error: err.to_string(),
+            })?;
+            let label_ref = LabelRef::from_bip329(record.label_type, &record.reference, network)?;
+            let label = Label {
+                label: record.label,
+                origin: record.origin,
+                spendable: record.spendable,
+            };
+            Ok((label_ref, label))
+        })
+        .collect()
+}
+
+/// Errors that can happen when importing labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LabelError {
+    /// A line is not a valid BIP329 record.
+    Json {
+        /// Line number, starting at 1.
+        line: usize,
+        /// The error returned by the JSON parser.
+        error: String,
+    },
+    /// The label type is unknown.
+    UnknownType(String),
+    /// The reference is not valid for its type, or is an address of another network.
+    InvalidRef {
+        /// The type of the label.
+        label_type: LabelType,
+        /// The invalid reference.
+        reference: String,
+    },
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json { line, error } => write!(f, "Invalid label on line {}: {}", line, error),
+            Self::UnknownType(label_type) => write!(f, "Unknown label type: {}", label_type),
+            Self::InvalidRef {
+                label_type,
+                reference,
+            } => write!(
+                f,
+                "Invalid reference for a {} label: {}",
+                label_type, reference
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LabelError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TXID: &str = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+    const ADDRESS: &str = "bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c";
+
+    #[test]
+    fn test_jsonl_roundtrip() {
+        let jsonl = format!(
+            "{}\n{}\n\n{}\n",
+            r#"{"type":"tx","ref":"f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd","label":"Transaction","origin":"wpkh([d34db33f/84'/0'/0'])"}"#,
+            r#"{"type":"addr","ref":"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c","label":"Address"}"#,
+            r#"{"type":"output","ref":"f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:1","label":"Output","spendable":false}"#,
+        );
+        let labels = from_jsonl(&jsonl, Network::Bitcoin).unwrap();
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels[0].0, LabelRef::tx(Txid::from_str(TXID).unwrap()));
+        assert_eq!(
+            labels[0].1.origin.as_deref(),
+            Some("wpkh([d34db33f/84'/0'/0'])")
+        );
+        assert_eq!(labels[1].0.label_type(), LabelType::Addr);
+        assert_eq!(labels[1].0.reference(), ADDRESS);
+        assert_eq!(labels[2].1.spendable, Some(false));
+
+        let exported = to_jsonl(labels.iter().map(|(r, l)| (r, l)));
+        assert_eq!(exported, jsonl.replace("\n\n", "\n"));
+    }
+
+    #[test]
+    fn test_jsonl_unknown_fields() {
+        // newer revisions of BIP329 add optional fields, which are ignored
+        let jsonl = r#"{"type":"tx","ref":"f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd","label":"Transaction","height":800000,"time":"2023-07-19T08:38:05Z"}"#;
+        let labels = from_jsonl(jsonl, Network::Bitcoin).unwrap();
+        assert_eq!(labels[0].1, Label::from("Transaction"));
+    }
+
+    #[test]
+    fn test_jsonl_errors() {
+        assert_eq!(
+            from_jsonl("{\"type\":\"tx\"}", Network::Bitcoin)
+                .unwrap_err()
+                .to_string(),
+            "Invalid label on line 1: missing field `ref` at line 1 column 13"
+        );
+        assert_eq!(
+            from_jsonl(r#"{"type":"tx","ref":"abc","label":"x"}"#, Network::Bitcoin),
+            Err(LabelError::InvalidRef {
+                label_type: LabelType::Tx,
+                reference: "abc".to_string(),
+            })
+        );
+        // addresses must belong to the wallet's network
+        let jsonl = format!(r#"{{"type":"addr","ref":"{}","label":"x"}}"#, ADDRESS);
+        assert!(matches!(
+            from_jsonl(&jsonl, Network::Testnet),
+            Err(LabelError::InvalidRef {
+                label_type: LabelType::Addr,
+                ..
+            })
+        ));
+        assert!(matches!(
+            from_jsonl(r#"{"type":"utxo","ref":"x","label":"x"}"#, Network::Bitcoin),
+            Err(LabelError::Json { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_label_ref_as_map_key() {
+        let label_ref = LabelRef::output(OutPoint::new(Txid::from_str(TXID).unwrap(), 1));
+        assert_eq!(label_ref.to_string(), format!("output:{}:1", TXID));
+        assert_eq!(label_ref.to_string().parse(), Ok(label_ref.clone()));
+        assert_eq!(
+            "utxo:abc".parse::<LabelRef>(),
+            Err(LabelError::UnknownType("utxo".to_string()))
+        );
+
+        let labels: crate::collections::BTreeMap<_, _> =
+            [(label_ref, Some(Label::from("Output")))].into();
+        let json = serde_json::to_string(&labels).unwrap();
+        assert_eq!(
+            serde_json::from_str::<crate::collections::BTreeMap<_, _>>(&json).unwrap(),
+            labels
+        );
+    }
+}
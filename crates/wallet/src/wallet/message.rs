@@ -0,0 +1,345 @@
+//! Generic message signing
+//!
+//! This module implements [BIP322] message signatures, which prove the control of an address by
+//! signing a virtual transaction spending from it: the `to_spend` transaction pays the address and
+//! commits to the message, and the `to_sign` transaction spends it. Since signing `to_sign` is the
+//! same as signing any other transaction, any descriptor the wallet can spend from can sign
+//! messages, including multisig and taproot ones: see [`Wallet::sign_message`].
+//!
+//! A signature can be encoded in three formats, see [`SignatureFormat`]. Signatures are verified
+//! by running the spending conditions of the address with the miniscript interpreter, so only
+//! addresses whose scripts are miniscript can be verified.
+//!
+//! [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+//! [`Wallet::sign_message`]: crate::Wallet::sign_message
+
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::base64::prelude::{Engine as _, BASE64_STANDARD};
+use bitcoin::consensus::encode::{deserialize, serialize};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::{Secp256k1, Verification};
+use bitcoin::sighash::{EcdsaSighashType, Prevouts, TapSighashType};
+use bitcoin::sign_message::{self, signed_msg_hash};
+use bitcoin::{
+    absolute, transaction, Address, Amount, OutPoint, Script, ScriptBuf, Sequence, Transaction,
+    TxIn, TxOut, Txid, Witness,
+};
+use miniscript::interpreter::{Interpreter, KeySigPair, SatisfiedConstraint};
+
+use super::signer::SignerError;
+
+const MESSAGE_TAG: &str = "BIP0322-signed-message";
+
+/// The formats of message signatures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureFormat {
+    /// The witness of the `to_sign` transaction, only for segwit addresses that don't need a
+    /// script sig: P2WPKH, P2WSH and P2TR.
+    Simple,
+    /// The whole `to_sign` transaction, for any address.
+    Full,
+    /// The signature of the `signmessage` RPC of Bitcoin Core, only for P2PKH addresses.
+    Legacy,
+}
+
+/// A message signature, in one of the [`SignatureFormat`]s.
+///
+/// It is encoded in base64, and the format is detected when it's parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageSignature {
+    /// The witness of the `to_sign` transaction
+    Simple(Witness),
+    /// The `to_sign` transaction
+    Full(Transaction),
+    /// A legacy signature
+    Legacy(sign_message::MessageSignature),
+}
+
+impl MessageSignature {
+    /// Return the format of the signature.
+    pub fn format(&self) -> SignatureFormat {
+        match self {
+            Self::Simple(_) => SignatureFormat::Simple,
+            Self::Full(_) => SignatureFormat::Full,
+            Self::Legacy(_) => SignatureFormat::Legacy,
+        }
+    }
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = match self {
+            Self::Simple(witness) => serialize(witness),
+            Self::Full(tx) => serialize(tx),
+            Self::Legacy(signature) => signature.serialize().to_vec(),
+        };
+        write!(f, "{}", BASE64_STANDARD.encode(bytes))
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = MessageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = BASE64_STANDARD
+            .decode(s)
+            .map_err(|_| MessageError::InvalidEncoding)?;
+        if let Ok(tx) = deserialize::<Transaction>(&bytes) {
+            return Ok(Self::Full(tx));
+        }
+        if let Ok(witness) = deserialize::<Witness>(&bytes) {
+            return Ok(Self::Simple(witness));
+        }
+        sign_message::MessageSignature::from_slice(&bytes)
+            .map(Self::Legacy)
+            .map_err(|_| MessageError::InvalidEncoding)
+    }
+}
+
+/// Compute the tagged hash of `message` committed to by the `to_spend` transaction.
+pub fn message_hash(message: &str) -> sha256::Hash {
+    let tag = sha256::Hash::hash(MESSAGE_TAG.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+/// Build the `to_spend` transaction of `message`, paying `script_pubkey`.
+pub fn to_spend(script_pubkey: &Script, message: &str) -> Transaction {
+    let script_sig = Builder::new()
+        .push_int(0)
+        .push_slice(message_hash(message).to_byte_array())
+        .into_script();
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 0xFFFFFFFF),
+            script_sig,
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ScriptBuf::from(script_pubkey),
+        }],
+    }
+}
+
+/// Build the unsigned `to_sign` transaction spending `to_spend`.
+pub fn to_sign(to_spend: &Transaction) -> Transaction {
+    Transaction {
+        version: transaction::Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+/// Verify the `signature` of `message` for `address`.
+///
+/// Returns `false` if the signature is invalid, and an error if its format can't be used with the
+/// address.
+pub fn verify_message<C: Verification>(
+    secp: &Secp256k1<C>,
+    address: &Address,
+    message: &str,
+    signature: &MessageSignature,
+) -> Result<bool, MessageError> {
+    let script_pubkey = address.script_pubkey();
+    let to_spend = to_spend(&script_pubkey, message);
+    let to_sign = match signature {
+        MessageSignature::Legacy(signature) => {
+            if !script_pubkey.is_p2pkh() {
+                return Err(MessageError::UnsupportedFormat);
+            }
+            return Ok(signature
+                .is_signed_by_address(secp, address, signed_msg_hash(message))
+                .unwrap_or(false));
+        }
+        MessageSignature::Simple(witness) => {
+            if !is_simple(&script_pubkey) {
+                return Err(MessageError::UnsupportedFormat);
+            }
+            let mut to_sign = to_sign(&to_spend);
+            to_sign.input[0].witness = witness.clone();
+            to_sign
+        }
+        MessageSignature::Full(to_sign) => to_sign.clone(),
+    };
+
+    let expected = self::to_sign(&to_spend);
+    if to_sign.input.len() != 1
+        || to_sign.input[0].previous_output != expected.input[0].previous_output
+        || to_sign.output != expected.output
+    {
+        return Ok(false);
+    }
+    Ok(satisfies(secp, &to_sign, 0, &to_spend.output))
+}
+
+/// Whether the input `input_index` of `tx` satisfies the script pubkey of its previous output,
+/// according to the miniscript interpreter, with signatures committing to the whole transaction.
+pub(crate) fn satisfies<C: Verification>(
+    secp: &Secp256k1<C>,
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+) -> bool {
+    let txin = &tx.input[input_index];
+    let interpreter = match Interpreter::from_txdata(
+        &prevouts[input_index].script_pubkey,
+        &txin.script_sig,
+        &txin.witness,
+        txin.sequence,
+        tx.lock_time,
+    ) {
+        Ok(interpreter) => interpreter,
+        Err(_) => return false,
+    };
+    let prevouts = Prevouts::All(prevouts);
+    let satisfied = interpreter
+        .iter(secp, tx, input_index, &prevouts)
+        .all(|constraint| match constraint {
+            Ok(SatisfiedConstraint::PublicKey { key_sig })
+            | Ok(SatisfiedConstraint::PublicKeyHash { key_sig, .. }) => match key_sig {
+                KeySigPair::Ecdsa(_, signature) => signature.sighash_type == EcdsaSighashType::All,
+                KeySigPair::Schnorr(_, signature) => matches!(
+                    signature.sighash_type,
+                    TapSighashType::All | TapSighashType::Default
+                ),
+            },
+            Ok(_) => true,
+            Err(_) => false,
+        });
+    satisfied
+}
+
+/// Whether signatures for `script_pubkey` can be in the [`SignatureFormat::Simple`] format.
+pub(crate) fn is_simple(script_pubkey: &Script) -> bool {
+    script_pubkey.is_witness_program()
+}
+
+/// Errors related to message signatures.
+#[derive(Debug)]
+pub enum MessageError {
+    /// The address doesn't belong to the wallet.
+    UnknownAddress(String),
+    /// The signature format can't be used with the address.
+    UnsupportedFormat,
+    /// None of the signers of the wallet has the key to sign a legacy signature.
+    MissingKey,
+    /// The signers of the wallet couldn't complete the signature.
+    Incomplete,
+    /// Error while signing the `to_sign` transaction.
+    Signer(SignerError),
+    /// The signature is not valid base64, or not a signature in any format.
+    InvalidEncoding,
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownAddress(address) => {
+                write!(f, "Address {} doesn't belong to the wallet", address)
+            }
+            Self::UnsupportedFormat => {
+                write!(f, "The signature format can't be used with the address")
+            }
+            Self::MissingKey => write!(f, "Missing the private key of the address"),
+            Self::Incomplete => write!(f, "The message signature is incomplete"),
+            Self::Signer(err) => write!(f, "Signer error: {}", err),
+            Self::InvalidEncoding => write!(f, "Invalid message signature encoding"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MessageError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+
+    // test vectors from BIP322
+    const ADDRESS: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+
+    fn address() -> Address {
+        ADDRESS
+            .parse::<Address<_>>()
+            .unwrap()
+            .require_network(bitcoin::Network::Bitcoin)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            message_hash("").to_string(),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            message_hash("Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_virtual_transactions() {
+        let script_pubkey = address().script_pubkey();
+        for (message, to_spend_txid, to_sign_txid) in [
+            (
+                "",
+                "c5680aa69bb8d860bf82d4e9cd3504b55dde018de765a91bb566283c545a99a7",
+                "1e9654e951a5ba44c8604c4de6c67fd78a27e81dcadcfe1edf638ba3aaebaed6",
+            ),
+            (
+                "Hello World",
+                "b79d196740ad5217771c1098fc4a4b51e0535c32236c71f1ea4d61a2d603352b",
+                "88737ae86f2077145f93cc4b153ae9a1cb8d56afa511988c149c5c8c9d93bddf",
+            ),
+        ] {
+            let to_spend = to_spend(&script_pubkey, message);
+            assert_eq!(to_spend.compute_txid().to_string(), to_spend_txid);
+            assert_eq!(to_sign(&to_spend).compute_txid().to_string(), to_sign_txid);
+        }
+    }
+
+    #[test]
+    fn test_verify_message() {
+        let secp = Secp256k1::verification_only();
+        let address = address();
+        let signature: MessageSignature = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=".parse().unwrap();
+        assert_eq!(signature.format(), SignatureFormat::Simple);
+        assert!(verify_message(&secp, &address, "Hello World", &signature).unwrap());
+        assert!(!verify_message(&secp, &address, "", &signature).unwrap());
+
+        let signature: MessageSignature = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=".parse().unwrap();
+        assert!(verify_message(&secp, &address, "", &signature).unwrap());
+        assert_eq!(
+            signature.to_string(),
+            "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI="
+        );
+
+        assert!(matches!(
+            "not base64!".parse::<MessageSignature>(),
+            Err(MessageError::InvalidEncoding)
+        ));
+    }
+}
@@ -32,53 +32,82 @@ use bdk_chain::{
     },
     tx_graph::{CalculateFeeError, CanonicalTx, TxGraph, TxUpdate},
     BlockId, ChainPosition, ConfirmationBlockTime, DescriptorExt, FullTxOut, Indexed,
-    IndexedTxGraph, Indexer, Merge,
+    IndexedTxGraph, Indexer, Merge, TxPosInBlock,
 };
 use bitcoin::{
     absolute,
     consensus::encode::serialize,
     constants::{genesis_block, COINBASE_MATURITY},
+    hashes::Hash as _,
+    key::TweakedPublicKey,
     psbt,
-    secp256k1::Secp256k1,
+    secp256k1::{Message, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey},
     sighash::{EcdsaSighashType, TapSighashType},
-    transaction, Address, Amount, Block, BlockHash, FeeRate, Network, OutPoint, Psbt, ScriptBuf,
-    Sequence, Transaction, TxOut, Txid, Weight, Witness,
+    sign_message::{self, signed_msg_hash},
+    transaction, Address, Amount, Block, BlockHash, FeeRate, Network, NetworkKind, OutPoint, Psbt,
+    ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Weight, Witness,
 };
 use miniscript::{
     descriptor::KeyMap,
+    plan::{Assets, Plan},
     psbt::{PsbtExt, PsbtInputExt, PsbtInputSatisfier},
+    DefiniteDescriptorKey, ForEachKey, Satisfier,
 };
 use rand_core::RngCore;
 
+pub mod analyze;
 mod changeset;
 pub mod coin_selection;
 pub mod error;
 pub mod export;
+pub mod labels;
+pub mod message;
 mod params;
+pub mod payjoin;
 mod persisted;
+pub(crate) mod plan;
+pub mod reserves;
 pub mod signer;
+pub mod silent_payments;
+pub mod spendability;
 pub mod tx_builder;
 pub(crate) mod utils;
 
-use crate::collections::{BTreeMap, HashMap, HashSet};
+use crate::collections::{btree_map, BTreeMap, HashMap, HashSet};
 use crate::descriptor::{
-    check_wallet_descriptor, error::Error as DescriptorError, policy::BuildSatisfaction,
+    check_wallet_descriptor,
+    error::Error as DescriptorError,
+    policy::{BuildSatisfaction, Condition, SpendingPath},
     DerivedDescriptor, DescriptorMeta, ExtendedDescriptor, ExtractPolicy, IntoWalletDescriptor,
     Policy, XKeyUtils,
 };
-use crate::psbt::PsbtUtils;
+use crate::labels::{Label, LabelError, LabelRef};
+use crate::psbt::{PsbtUtils, SignablePsbt};
 use crate::types::*;
 use crate::wallet::{
+    analyze::{InputAnalysis, PsbtAnalysis, PsbtEstimate},
     coin_selection::{DefaultCoinSelectionAlgorithm, Excess, InsufficientFunds},
-    error::{BuildFeeBumpError, CreateTxError, MiniscriptPsbtError},
-    signer::{SignOptions, SignerError, SignerOrdering, SignersContainer, TransactionSigner},
+    error::{
+        BuildCpfpError, BuildFeeBumpError, BuildTimelockRefreshError, CreateTxError,
+        MiniscriptPsbtError,
+    },
+    message::{MessageError, MessageSignature, SignatureFormat},
+    reserves::{ProofBuilder, ProofError},
+    signer::{
+        policy::{InputSummary, OutputSummary, PsbtSummary, SigningPolicy, SigningPolicyError},
+        SignOptions, SignerError, SignerOrdering, SignersContainer, TransactionSigner,
+    },
+    silent_payments::{
+        SilentPaymentAddress, SilentPaymentError, SilentPaymentIndex, SilentPaymentOutput,
+    },
+    spendability::{PathSpendability, ProjectedBalance, UtxoSpendability},
     tx_builder::{FeePolicy, TxBuilder, TxParams},
     utils::{check_nsequence_rbf, After, Older, SecpCtx},
 };
 
 // re-exports
 pub use bdk_chain::Balance;
-pub use changeset::ChangeSet;
+pub use changeset::{ChangeSet, LegacyChangeSet};
 pub use params::*;
 pub use persisted::*;
 pub use utils::IsDust;
@@ -102,11 +131,18 @@ pub use utils::IsDust;
 /// [`take_staged`]: Wallet::take_staged
 #[derive(Debug)]
 pub struct Wallet {
-    signers: Arc<SignersContainer>,
-    change_signers: Arc<SignersContainer>,
+    signers: BTreeMap<KeychainKind, Arc<SignersContainer>>,
     chain: LocalChain,
     indexed_graph: IndexedTxGraph<ConfirmationBlockTime, KeychainTxOutIndex<KeychainKind>>,
     stage: ChangeSet,
+    labels: BTreeMap<LabelRef, Label>,
+    locked_utxos: BTreeMap<OutPoint, UtxoLock>,
+    pending_spends: BTreeMap<Txid, PendingSpend>,
+    silent_payments: Option<SilentPaymentIndex>,
+    // where the transactions waiting for their previous outputs to be scanned for silent payments
+    // were seen, to apply them once they are found to pay us
+    silent_payment_positions: HashMap<Txid, ChainPosition<ConfirmationBlockTime>>,
+    signing_policies: Vec<Arc<dyn SigningPolicy>>,
     network: Network,
     secp: SecpCtx,
 }
@@ -379,28 +415,53 @@ impl Wallet {
         check_wallet_descriptor(&descriptor)?;
         descriptor_keymap.extend(params.descriptor_keymap);
 
-        let signers = Arc::new(SignersContainer::build(
-            descriptor_keymap,
-            &descriptor,
-            &secp,
-        ));
+        let mut signers = BTreeMap::new();
+        signers.insert(
+            KeychainKind::External,
+            Arc::new(SignersContainer::build(
+                descriptor_keymap,
+                &descriptor,
+                &secp,
+            )),
+        );
 
-        let (change_descriptor, change_signers) = match params.change_descriptor {
+        let change_descriptor = match params.change_descriptor {
             Some(make_desc) => {
                 let (change_descriptor, mut internal_keymap) = make_desc(&secp, network)?;
                 check_wallet_descriptor(&change_descriptor)?;
                 internal_keymap.extend(params.change_descriptor_keymap);
-                let change_signers = Arc::new(SignersContainer::build(
-                    internal_keymap,
-                    &change_descriptor,
-                    &secp,
-                ));
-                (Some(change_descriptor), change_signers)
+                signers.insert(
+                    KeychainKind::Internal,
+                    Arc::new(SignersContainer::build(
+                        internal_keymap,
+                        &change_descriptor,
+                        &secp,
+                    )),
+                );
+                Some(change_descriptor)
             }
-            None => (None, Arc::new(SignersContainer::new())),
+            None => None,
         };
 
-        let index = create_indexer(descriptor, change_descriptor, params.lookahead)?;
+        let mut custom_keymaps = params.custom_keymaps;
+        let mut custom_descriptors = BTreeMap::new();
+        for (id, make_desc) in params.custom_descriptors {
+            let (custom_descriptor, mut keymap) = make_desc(&secp, network)?;
+            check_wallet_descriptor(&custom_descriptor)?;
+            keymap.extend(custom_keymaps.remove(&id).unwrap_or_default());
+            signers.insert(
+                KeychainKind::Custom(id),
+                Arc::new(SignersContainer::build(keymap, &custom_descriptor, &secp)),
+            );
+            custom_descriptors.insert(id, custom_descriptor);
+        }
+
+        let index = create_indexer(
+            descriptor,
+            change_descriptor,
+            custom_descriptors.clone(),
+            params.lookahead,
+        )?;
 
         let descriptor = index.get_descriptor(KeychainKind::External).cloned();
         let change_descriptor = index.get_descriptor(KeychainKind::Internal).cloned();
@@ -410,19 +471,28 @@ impl Wallet {
         let stage = ChangeSet {
             descriptor,
             change_descriptor,
+            custom_descriptors,
             local_chain: chain_changeset,
             tx_graph: indexed_graph_changeset.tx_graph,
             indexer: indexed_graph_changeset.indexer,
             network: Some(network),
+            labels: BTreeMap::new(),
+            locked_utxos: BTreeMap::new(),
+            pending_spends: BTreeMap::new(),
         };
 
         Ok(Wallet {
             signers,
-            change_signers,
             network,
             chain,
             indexed_graph,
             stage,
+            labels: BTreeMap::new(),
+            locked_utxos: BTreeMap::new(),
+            pending_spends: BTreeMap::new(),
+            silent_payments: None,
+            silent_payment_positions: HashMap::new(),
+            signing_policies: Vec::new(),
             secp,
         })
     }
@@ -537,7 +607,11 @@ impl Wallet {
                 }));
             }
         }
-        let signers = Arc::new(SignersContainer::build(external_keymap, &descriptor, &secp));
+        let mut signers = BTreeMap::new();
+        signers.insert(
+            KeychainKind::External,
+            Arc::new(SignersContainer::build(external_keymap, &descriptor, &secp)),
+        );
 
         let mut change_descriptor = None;
         let mut internal_keymap = params.change_descriptor_keymap;
@@ -590,30 +664,108 @@ impl Wallet {
             },
         }
 
-        let change_signers = match change_descriptor {
-            Some(ref change_descriptor) => Arc::new(SignersContainer::build(
-                internal_keymap,
-                change_descriptor,
-                &secp,
-            )),
-            None => Arc::new(SignersContainer::new()),
-        };
+        if let Some(ref change_descriptor) = change_descriptor {
+            signers.insert(
+                KeychainKind::Internal,
+                Arc::new(SignersContainer::build(
+                    internal_keymap,
+                    change_descriptor,
+                    &secp,
+                )),
+            );
+        }
+
+        let mut check_custom_descriptors = params.check_custom_descriptors;
+        let mut custom_keymaps = params.custom_keymaps;
+        for (&id, desc) in &changeset.custom_descriptors {
+            check_wallet_descriptor(desc).map_err(LoadError::Descriptor)?;
+            let mut keymap = custom_keymaps.remove(&id).unwrap_or_default();
+            match check_custom_descriptors.remove(&id) {
+                // nothing expected
+                None => {}
+                // expected none for existing
+                Some(None) => {
+                    return Err(LoadError::Mismatch(LoadMismatch::Descriptor {
+                        keychain: KeychainKind::Custom(id),
+                        loaded: Some(desc.clone()),
+                        expected: None,
+                    }))
+                }
+                // parameters must match
+                Some(Some(make_desc)) => {
+                    let (exp_desc, exp_keymap) =
+                        make_desc(&secp, network).map_err(LoadError::Descriptor)?;
+                    if desc.descriptor_id() != exp_desc.descriptor_id() {
+                        return Err(LoadError::Mismatch(LoadMismatch::Descriptor {
+                            keychain: KeychainKind::Custom(id),
+                            loaded: Some(desc.clone()),
+                            expected: Some(exp_desc),
+                        }));
+                    }
+                    if params.extract_keys {
+                        keymap.extend(exp_keymap);
+                    }
+                }
+            }
+            signers.insert(
+                KeychainKind::Custom(id),
+                Arc::new(SignersContainer::build(keymap, desc, &secp)),
+            );
+        }
+        // expected desc but none loaded
+        if let Some((id, make_desc)) = check_custom_descriptors
+            .into_iter()
+            .find_map(|(id, expected)| Some((id, expected?)))
+        {
+            let (exp_desc, _) = make_desc(&secp, network).map_err(LoadError::Descriptor)?;
+            return Err(LoadError::Mismatch(LoadMismatch::Descriptor {
+                keychain: KeychainKind::Custom(id),
+                loaded: None,
+                expected: Some(exp_desc),
+            }));
+        }
 
-        let index = create_indexer(descriptor, change_descriptor, params.lookahead)
-            .map_err(LoadError::Descriptor)?;
+        let index = create_indexer(
+            descriptor,
+            change_descriptor,
+            changeset.custom_descriptors,
+            params.lookahead,
+        )
+        .map_err(LoadError::Descriptor)?;
 
         let mut indexed_graph = IndexedTxGraph::new(index);
         indexed_graph.apply_changeset(changeset.indexer.into());
         indexed_graph.apply_changeset(changeset.tx_graph.into());
 
+        let labels = changeset
+            .labels
+            .into_iter()
+            .filter_map(|(label_ref, label)| Some((label_ref, label?)))
+            .collect();
+        let locked_utxos = changeset
+            .locked_utxos
+            .into_iter()
+            .filter_map(|(outpoint, lock)| Some((outpoint, lock?)))
+            .collect();
+        let pending_spends = changeset
+            .pending_spends
+            .into_iter()
+            .filter_map(|(txid, pending_spend)| Some((txid, pending_spend?)))
+            .collect();
+
         let stage = ChangeSet::default();
 
         Ok(Some(Wallet {
             signers,
-            change_signers,
             chain,
             indexed_graph,
             stage,
+            labels,
+            locked_utxos,
+            pending_spends,
+            silent_payments: None,
+            silent_payment_positions: HashMap::new(),
+            signing_policies: Vec::new(),
             network,
             secp,
         }))
@@ -636,7 +788,9 @@ impl Wallet {
     /// # Panics
     ///
     /// This panics when the caller requests for an address of derivation index greater than the
-    /// [BIP32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki) max index.
+    /// [BIP32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki) max index, or
+    /// if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet (see
+    /// [`Wallet::keychains`]).
     pub fn peek_address(&self, keychain: KeychainKind, mut index: u32) -> AddressInfo {
         let keychain = self.map_keychain(keychain);
         let mut spk_iter = self
@@ -683,6 +837,11 @@ impl Wallet {
     /// println!("Next address: {}", next_address.address);
     /// # Ok::<(), anyhow::Error>(())
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
     pub fn reveal_next_address(&mut self, keychain: KeychainKind) -> AddressInfo {
         let keychain = self.map_keychain(keychain);
         let index = &mut self.indexed_graph.index;
@@ -711,6 +870,11 @@ impl Wallet {
     ///
     /// **WARNING**: To avoid address reuse you must persist the changes resulting from one or more
     /// calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
     pub fn reveal_addresses_to(
         &mut self,
         keychain: KeychainKind,
@@ -740,6 +904,11 @@ impl Wallet {
     ///
     /// **WARNING**: To avoid address reuse you must persist the changes resulting from one or more
     /// calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
     pub fn next_unused_address(&mut self, keychain: KeychainKind) -> AddressInfo {
         let keychain = self.map_keychain(keychain);
         let index = &mut self.indexed_graph.index;
@@ -819,7 +988,120 @@ impl Wallet {
                 self.chain.tip().block_id(),
                 self.indexed_graph.index.outpoints().iter().cloned(),
             )
-            .map(|((k, i), full_txo)| new_local_utxo(k, i, full_txo))
+            .map(|((k, i), full_txo)| {
+                let lock = self.active_utxo_lock(full_txo.outpoint);
+                new_local_utxo(k, i, full_txo, lock)
+            })
+    }
+
+    /// Return the spending paths of every unspent output of this wallet, with the height or time
+    /// at which each of them becomes spendable
+    ///
+    /// The paths are computed from the spending policy of the output's descriptor (see
+    /// [`Policy::spending_paths`]), and their timelocks from the position of the output in the
+    /// chain: the relative timelocks of unconfirmed outputs can't be projected yet, and neither
+    /// can relative time-based timelocks (see [`SpendableAt`]).
+    ///
+    /// [`SpendableAt`]: spendability::SpendableAt
+    ///
+    /// See the [`spendability`] module for an example.
+    pub fn list_spendability(&self) -> Result<Vec<UtxoSpendability>, DescriptorError> {
+        let mut keychain_paths = BTreeMap::new();
+        for keychain in self.keychains().map(|(keychain, _)| keychain) {
+            let paths = self
+                .policies(keychain)?
+                .map(|policy| policy.spending_paths())
+                .unwrap_or_default();
+            keychain_paths.insert(keychain, paths);
+        }
+
+        Ok(self
+            .list_unspent()
+            .map(|utxo| {
+                let paths = keychain_paths[&utxo.keychain]
+                    .iter()
+                    .map(|path| PathSpendability {
+                        path: path.clone(),
+                        spendable_at: spendability::spendable_at(path, &utxo.chain_position),
+                    })
+                    .collect();
+                UtxoSpendability { utxo, paths }
+            })
+            .collect())
+    }
+
+    /// Return the balance of the outputs spendable at `height` and median time past `time`
+    /// through the spending paths accepted by `path_filter`
+    ///
+    /// The outputs with at least one accepted path are either spendable, if the timelocks of one
+    /// of the paths are reached, or locked. For instance, the balance of an inheritance wallet
+    /// that the heir can spend at a given height is given by a filter accepting the paths signed
+    /// by the heir's key (see [`SpendingPath::is_signed_by`]).
+    ///
+    /// See the [`spendability`] module for an example.
+    ///
+    /// [`SpendingPath::is_signed_by`]: crate::descriptor::policy::SpendingPath::is_signed_by
+    pub fn projected_balance<F>(
+        &self,
+        height: u32,
+        time: u64,
+        mut path_filter: F,
+    ) -> Result<ProjectedBalance, DescriptorError>
+    where
+        F: FnMut(&SpendingPath) -> bool,
+    {
+        let mut balance = ProjectedBalance::default();
+        for utxo in self.list_spendability()? {
+            let mut paths = utxo
+                .paths
+                .iter()
+                .filter(|path| path_filter(&path.path))
+                .peekable();
+            if paths.peek().is_none() {
+                continue;
+            }
+            if paths.any(|path| path.is_spendable(height, time)) {
+                balance.spendable += utxo.utxo.txout.value;
+            } else {
+                balance.locked += utxo.utxo.txout.value;
+            }
+        }
+        Ok(balance)
+    }
+
+    /// Return the unspent outputs with a spending path whose relative timelock expires in at most
+    /// `blocks` blocks from the current chain tip
+    ///
+    /// These are the outputs that the keys of the paths without timelocks must spend again before
+    /// the timelock expires, for instance to prevent the recovery key of a vault or the heir of an
+    /// inheritance wallet from spending them. The outputs whose timelock already expired are
+    /// returned too. Only height-based relative timelocks are considered, and unconfirmed outputs
+    /// are not returned as their timelock didn't start yet.
+    ///
+    /// See [`Wallet::build_timelock_refresh`] to spend them again.
+    pub fn list_expiring_utxos(
+        &self,
+        blocks: u32,
+    ) -> Result<Vec<UtxoSpendability>, DescriptorError> {
+        let deadline = self.chain.tip().height().saturating_add(blocks);
+        Ok(self
+            .list_spendability()?
+            .into_iter()
+            .filter(|utxo| {
+                utxo.paths.iter().any(|path| {
+                    let is_height_based = path
+                        .path
+                        .condition
+                        .csv
+                        .map_or(false, |csv| csv.is_height_locked());
+                    is_height_based
+                        && path
+                            .spendable_at
+                            .and_then(|spendable_at| spendable_at.height)
+                            .map_or(false, |height| height <= deadline)
+                })
+            })
+            .collect())
     }
 
     /// List all relevant outputs (includes both spent and unspent, confirmed and unconfirmed).
@@ -833,7 +1115,10 @@ impl Wallet {
                 self.chain.tip().block_id(),
                 self.indexed_graph.index.outpoints().iter().cloned(),
             )
-            .map(|((k, i), full_txo)| new_local_utxo(k, i, full_txo))
+            .map(|((k, i), full_txo)| {
+                let lock = self.active_utxo_lock(full_txo.outpoint);
+                new_local_utxo(k, i, full_txo, lock)
+            })
     }
 
     /// Get all the checkpoints the wallet is currently storing indexed by height.
@@ -864,6 +1149,11 @@ impl Wallet {
     ///
     /// See [`all_unbounded_spk_iters`] for more documentation
     ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
+    ///
     /// [`all_unbounded_spk_iters`]: Self::all_unbounded_spk_iters
     pub fn unbounded_spk_iter(
         &self,
@@ -886,7 +1176,10 @@ impl Wallet {
                 self.chain.tip().block_id(),
                 core::iter::once(((), op)),
             )
-            .map(|(_, full_txo)| new_local_utxo(keychain, index, full_txo))
+            .map(|(_, full_txo)| {
+                let lock = self.active_utxo_lock(op);
+                new_local_utxo(keychain, index, full_txo, lock)
+            })
             .next()
     }
 
@@ -912,6 +1205,420 @@ impl Wallet {
         self.stage.merge(additions.into());
     }
 
+    /// Start looking for outputs paid to the [silent payment](silent_payments) address with the
+    /// given scan and spend keys.
+    ///
+    /// The transactions already in the wallet are scanned again. From now on, the transactions
+    /// applied to the wallet are scanned too, which requires the previous outputs they spend:
+    /// insert them with [`Wallet::insert_silent_payment_prevouts`], before or after applying the
+    /// transactions. The transactions paying the address are kept with their previous outputs.
+    ///
+    /// The scan and spend keys are not persisted: this method must be called again after loading
+    /// the wallet, which finds the outputs received before from the transactions kept. The
+    /// transactions still waiting for their previous outputs are not kept either, so insert them
+    /// before closing the wallet.
+    ///
+    /// The outputs found are part of the [`balance`](Self::balance) of the wallet, are listed by
+    /// [`Wallet::list_silent_payment_unspent`] and are selected to fund transactions. To sign for
+    /// them, add a [`SilentPaymentSigner`](silent_payments::SilentPaymentSigner) with the secret
+    /// spend key to the signers of the wallet.
+    pub fn scan_silent_payments(&mut self, scan_key: SecretKey, spend_key: PublicKey) {
+        let mut index = SilentPaymentIndex::new(scan_key, spend_key);
+        let graph = self.indexed_graph.graph();
+        index.insert_prevouts(
+            graph
+                .all_txouts()
+                .map(|(outpoint, txout)| (outpoint, txout.clone())),
+        );
+        for tx_node in graph.full_txs() {
+            index.index_tx(&tx_node.tx);
+        }
+        self.silent_payments = Some(index);
+    }
+
+    /// The silent payment address scanned by the wallet, if any.
+    ///
+    /// See [`Wallet::scan_silent_payments`].
+    pub fn silent_payment_address(&self) -> Option<SilentPaymentAddress> {
+        self.silent_payments
+            .as_ref()
+            .map(|index| index.address(NetworkKind::from(self.network)))
+    }
+
+    /// Get a reference to the [`SilentPaymentIndex`] of the wallet, if any.
+    ///
+    /// See [`Wallet::scan_silent_payments`].
+    pub fn silent_payment_index(&self) -> Option<&SilentPaymentIndex> {
+        self.silent_payments.as_ref()
+    }
+
+    /// Insert the previous outputs spent by transactions to scan for silent payments.
+    ///
+    /// The transactions applied to the wallet before all their previous outputs were inserted are
+    /// scanned again, see [`SilentPaymentIndex::pending_txs`]. Use
+    /// [`SilentPaymentIndex::missing_prevouts`] to find the ones to fetch from the chain source.
+    pub fn insert_silent_payment_prevouts(
+        &mut self,
+        prevouts: impl IntoIterator<Item = (OutPoint, TxOut)>,
+    ) {
+        let index = match &mut self.silent_payments {
+            Some(index) => index,
+            None => return,
+        };
+        index.insert_prevouts(prevouts);
+        let ready = index
+            .pending_txs()
+            .filter(|tx| index.missing_prevouts(tx).is_empty())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for tx in ready {
+            let txid = tx.compute_txid();
+            let position = self.silent_payment_positions.remove(&txid);
+            let mut changeset = match self.index_silent_payments(&tx) {
+                Some(changeset) => changeset,
+                None => continue,
+            };
+            changeset.merge(self.indexed_graph.insert_tx(tx).into());
+            match position {
+                Some(ChainPosition::Confirmed { anchor, .. }) => {
+                    changeset.merge(self.indexed_graph.insert_anchor(txid, anchor).into());
+                }
+                Some(ChainPosition::Unconfirmed {
+                    last_seen: Some(last_seen),
+                }) => {
+                    changeset.merge(self.indexed_graph.insert_seen_at(txid, last_seen).into());
+                }
+                _ => {}
+            }
+            self.stage.merge(changeset);
+        }
+        self.silent_payment_positions
+            .retain(|txid, _| match &self.silent_payments {
+                Some(index) => index.is_pending(*txid),
+                None => false,
+            });
+    }
+
+    /// Return the list of unspent outputs received with silent payments.
+    ///
+    /// See [`Wallet::scan_silent_payments`].
+    pub fn list_silent_payment_unspent(&self) -> impl Iterator<Item = SilentPaymentOutput> + '_ {
+        self.indexed_graph
+            .graph()
+            .filter_chain_unspents(
+                &self.chain,
+                self.chain.tip().block_id(),
+                self.silent_payments
+                    .iter()
+                    .flat_map(|index| index.outpoints().iter().cloned()),
+            )
+            .map(|(tweak, full_txo)| SilentPaymentOutput {
+                outpoint: full_txo.outpoint,
+                txout: full_txo.txout,
+                tweak,
+                is_spent: full_txo.spent_by.is_some(),
+                chain_position: full_txo.chain_position,
+            })
+    }
+
+    /// Scan `tx` for silent payments, returning the changes if it pays or spends outputs received
+    /// with them.
+    ///
+    /// The previous outputs of the transactions paying us are inserted in the graph, so that the
+    /// outputs can be found again after loading the wallet.
+    fn index_silent_payments(&mut self, tx: &Transaction) -> Option<ChangeSet> {
+        let index = self.silent_payments.as_mut()?;
+        let prevouts = tx
+            .input
+            .iter()
+            .filter_map(|txin| {
+                let txout = index.prevout(txin.previous_output)?;
+                Some((txin.previous_output, txout.clone()))
+            })
+            .collect::<Vec<_>>();
+        let found = index.index_tx(tx);
+        if !index.is_tx_relevant(tx) {
+            return None;
+        }
+
+        let mut changeset = ChangeSet::default();
+        if !found.is_empty() {
+            for (outpoint, txout) in prevouts {
+                changeset.merge(self.indexed_graph.insert_txout(outpoint, txout).into());
+            }
+        }
+        Some(changeset)
+    }
+
+    /// Keep the position of `tx` if it waits for its previous outputs to be scanned for silent
+    /// payments, as it isn't inserted in the graph until it is found to pay us.
+    fn record_silent_payment_position(
+        &mut self,
+        tx: &Transaction,
+        position: ChainPosition<ConfirmationBlockTime>,
+    ) {
+        let txid = tx.compute_txid();
+        let is_pending = match &self.silent_payments {
+            Some(index) => index.is_pending(txid),
+            None => false,
+        };
+        if is_pending {
+            self.silent_payment_positions.insert(txid, position);
+        }
+    }
+
+    /// The [`WeightedUtxo`] of an unspent `output` received with silent payments.
+    pub(crate) fn silent_payment_weighted_utxo(&self, output: SilentPaymentOutput) -> WeightedUtxo {
+        let outpoint = output.outpoint;
+        let psbt_input = psbt::Input {
+            witness_utxo: Some(output.txout),
+            non_witness_utxo: self
+                .indexed_graph
+                .graph()
+                .get_tx(outpoint.txid)
+                .map(|tx| tx.as_ref().clone()),
+            proprietary: [(silent_payments::psbt_tweak_key(), output.tweak.to_vec())].into(),
+            ..Default::default()
+        };
+        WeightedUtxo {
+            // the outputs are spent with the key path, like a `tr()` descriptor without a tree
+            satisfaction_weight: Weight::from_wu(1 + 65),
+            utxo: Utxo::Foreign {
+                outpoint,
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                psbt_input: Box::new(psbt_input),
+            },
+        }
+    }
+
+    /// Attach a [BIP329] label to a transaction, address, public key, input, output or xpub.
+    ///
+    /// Any existing label for `label_ref` is replaced. You must persist the changes resulting
+    /// from this method if you need the label to be reloaded after closing the wallet.
+    ///
+    /// [BIP329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+    pub fn set_label(&mut self, label_ref: LabelRef, label: impl Into<Label>) {
+        let label = label.into();
+        self.labels.insert(label_ref.clone(), label.clone());
+        self.stage.merge(ChangeSet {
+            labels: [(label_ref, Some(label))].into(),
+            ..Default::default()
+        });
+    }
+
+    /// Remove the label attached to `label_ref`, returning it if there was one.
+    pub fn remove_label(&mut self, label_ref: &LabelRef) -> Option<Label> {
+        let label = self.labels.remove(label_ref)?;
+        self.stage.merge(ChangeSet {
+            labels: [(label_ref.clone(), None)].into(),
+            ..Default::default()
+        });
+        Some(label)
+    }
+
+    /// Get the label attached to `label_ref`.
+    pub fn get_label(&self, label_ref: &LabelRef) -> Option<&Label> {
+        self.labels.get(label_ref)
+    }
+
+    /// Iterate over all labels of the wallet, ordered by [`LabelRef`].
+    pub fn labels(&self) -> impl Iterator<Item = (&LabelRef, &Label)> {
+        self.labels.iter()
+    }
+
+    /// Export all labels of the wallet in the [BIP329] JSON Lines format.
+    ///
+    /// [BIP329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+    pub fn export_labels(&self) -> String {
+        labels::to_jsonl(&self.labels)
+    }
+
+    /// Import labels in the [BIP329] JSON Lines format, returning the number of labels imported.
+    ///
+    /// Imported labels replace existing labels with the same reference. The import is atomic:
+    /// if any line fails to parse no label is changed.
+    ///
+    /// [BIP329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+    pub fn import_labels(&mut self, jsonl: &str) -> Result<usize, LabelError> {
+        let records = labels::from_jsonl(jsonl, self.network)?;
+        let count = records.len();
+        for (label_ref, label) in records {
+            self.set_label(label_ref, label);
+        }
+        Ok(count)
+    }
+
+    /// Lock the UTXO at `outpoint` so that it is not selected when building transactions.
+    ///
+    /// Unlike [`TxBuilder::unspendable`], the lock applies to every transaction built by the
+    /// wallet until it is removed with [`unlock_utxo`] or the chain tip reaches `until_height`.
+    /// Locking an already locked UTXO replaces its lock. A locked UTXO can still be spent by
+    /// adding it explicitly with [`TxBuilder::add_utxo`].
+    ///
+    /// You must persist the changes resulting from this method if you need the lock to be
+    /// reloaded after closing the wallet.
+    ///
+    /// [`unlock_utxo`]: Self::unlock_utxo
+    pub fn lock_utxo(
+        &mut self,
+        outpoint: OutPoint,
+        reason: impl Into<String>,
+        until_height: Option<u32>,
+    ) {
+        let lock = UtxoLock {
+            reason: reason.into(),
+            until_height,
+        };
+        self.locked_utxos.insert(outpoint, lock.clone());
+        self.stage.merge(ChangeSet {
+            locked_utxos: [(outpoint, Some(lock))].into(),
+            ..Default::default()
+        });
+    }
+
+    /// Remove the lock on the UTXO at `outpoint`, returning it if there was one.
+    pub fn unlock_utxo(&mut self, outpoint: OutPoint) -> Option<UtxoLock> {
+        let lock = self.locked_utxos.remove(&outpoint)?;
+        self.stage.merge(ChangeSet {
+            locked_utxos: [(outpoint, None)].into(),
+            ..Default::default()
+        });
+        Some(lock)
+    }
+
+    /// Iterate over the UTXO locks that are in effect at the current chain tip.
+    pub fn locked_utxos(&self) -> impl Iterator<Item = (OutPoint, &UtxoLock)> {
+        let height = self.chain.tip().height();
+        self.locked_utxos
+            .iter()
+            .filter(move |(_, lock)| lock.is_active(height))
+            .map(|(outpoint, lock)| (*outpoint, lock))
+    }
+
+    fn active_utxo_lock(&self, outpoint: OutPoint) -> Option<UtxoLock> {
+        self.locked_utxos
+            .get(&outpoint)
+            .filter(|lock| lock.is_active(self.chain.tip().height()))
+            .cloned()
+    }
+
+    /// Record `psbt` as a pending spend, reserving its inputs until it is broadcast, released or
+    /// the chain tip reaches `until_height`.
+    ///
+    /// Reserved inputs are not selected when building other transactions, so that building
+    /// several transactions before broadcasting any of them doesn't spend the same UTXOs twice. A
+    /// reserved UTXO can still be spent by adding it explicitly with [`TxBuilder::add_utxo`].
+    ///
+    /// The reservation ends once one of the inputs is spent by a canonical transaction of the
+    /// wallet, e.g. after inserting the signed transaction with [`apply_unconfirmed_txs`] or when
+    /// syncing. This also covers transactions whose txid changes once signed and replacements of
+    /// the transaction. To abandon the transaction use [`cancel_tx`] or [`release_pending_spend`].
+    /// The pending spends that ended are removed when the wallet applies new chain data.
+    ///
+    /// Transactions can also be recorded as they are created with [`TxBuilder::reserve_inputs`].
+    ///
+    /// You must persist the changes resulting from this method if you need the reservation to be
+    /// reloaded after closing the wallet.
+    ///
+    /// Returns the txid of the unsigned transaction.
+    ///
+    /// [`apply_unconfirmed_txs`]: Self::apply_unconfirmed_txs
+    /// [`cancel_tx`]: Self::cancel_tx
+    /// [`release_pending_spend`]: Self::release_pending_spend
+    pub fn insert_pending_spend(&mut self, psbt: &Psbt, until_height: Option<u32>) -> Txid {
+        let pending_spend = PendingSpend {
+            tx: psbt.unsigned_tx.clone(),
+            until_height,
+        };
+        let txid = pending_spend.tx.compute_txid();
+        self.pending_spends.insert(txid, pending_spend.clone());
+        self.stage.merge(ChangeSet {
+            pending_spends: [(txid, Some(pending_spend))].into(),
+            ..Default::default()
+        });
+        txid
+    }
+
+    /// Release the pending spend with the given txid, making its inputs available for coin
+    /// selection again. Returns the pending spend if there was one.
+    pub fn release_pending_spend(&mut self, txid: Txid) -> Option<PendingSpend> {
+        let pending_spend = self.pending_spends.remove(&txid)?;
+        self.stage.merge(ChangeSet {
+            pending_spends: [(txid, None)].into(),
+            ..Default::default()
+        });
+        Some(pending_spend)
+    }
+
+    /// Iterate over the pending spends that are still reserving their inputs.
+    pub fn pending_spends(&self) -> impl Iterator<Item = (Txid, &PendingSpend)> {
+        let ended = self.ended_pending_spends();
+        self.pending_spends
+            .iter()
+            .filter(move |(txid, _)| !ended.contains(*txid))
+            .map(|(txid, pending_spend)| (*txid, pending_spend))
+    }
+
+    // The txids of the pending spends that expired, or with an input spent by a canonical
+    // transaction: the pending spend itself once signed, a replacement or a conflicting one
+    fn ended_pending_spends(&self) -> HashSet<Txid> {
+        let height = self.chain.tip().height();
+        let graph = self.indexed_graph.graph();
+        let mut ended = HashSet::new();
+        let mut spent = Vec::new();
+        for (txid, pending_spend) in &self.pending_spends {
+            if !pending_spend.is_active(height) {
+                ended.insert(*txid);
+                continue;
+            }
+            let spenders = pending_spend
+                .tx
+                .input
+                .iter()
+                .flat_map(|txin| graph.outspends(txin.previous_output))
+                .copied()
+                .collect::<Vec<_>>();
+            if !spenders.is_empty() {
+                spent.push((*txid, spenders));
+            }
+        }
+
+        if !spent.is_empty() {
+            let canonical_txids = graph
+                .list_canonical_txs(&self.chain, self.chain.tip().block_id())
+                .map(|tx| tx.tx_node.txid)
+                .collect::<HashSet<_>>();
+            ended.extend(
+                spent
+                    .into_iter()
+                    .filter(|(_, spenders)| {
+                        spenders.iter().any(|txid| canonical_txids.contains(txid))
+                    })
+                    .map(|(txid, _)| txid),
+            );
+        }
+        ended
+    }
+
+    // The outpoints spent by the pending spends still reserving their inputs
+    fn reserved_outpoints(&self) -> HashSet<OutPoint> {
+        self.pending_spends()
+            .flat_map(|(_, pending_spend)| &pending_spend.tx.input)
+            .map(|txin| txin.previous_output)
+            .collect()
+    }
+
+    // Remove the pending spends that ended
+    fn prune_pending_spends(&mut self) {
+        if self.pending_spends.is_empty() {
+            return;
+        }
+        for txid in self.ended_pending_spends() {
+            self.release_pending_spend(txid);
+        }
+    }
+
     /// Calculates the fee of a given transaction. Returns [`Amount::ZERO`] if `tx` is a coinbase transaction.
     ///
     /// To calculate the fee for a [`Transaction`] with inputs not owned by this wallet you must
@@ -1110,15 +1817,89 @@ impl Wallet {
     /// Return the balance, separated into available, trusted-pending, untrusted-pending and immature
     /// values.
     pub fn balance(&self) -> Balance {
-        self.indexed_graph.graph().balance(
+        let keychain_balance = self.indexed_graph.graph().balance(
             &self.chain,
             self.chain.tip().block_id(),
             self.indexed_graph.index.outpoints().iter().cloned(),
             |&(k, _), _| k == KeychainKind::Internal,
+        );
+        keychain_balance + self.silent_payment_balance()
+    }
+
+    /// Return the balance of the outputs received with silent payments, see
+    /// [`Wallet::scan_silent_payments`].
+    ///
+    /// Unconfirmed outputs are never trusted.
+    pub fn silent_payment_balance(&self) -> Balance {
+        self.indexed_graph.graph().balance(
+            &self.chain,
+            self.chain.tip().block_id(),
+            self.silent_payments
+                .iter()
+                .flat_map(|index| index.outpoints().iter().cloned()),
+            |_, _| false,
         )
     }
 
-    /// Add an external signer
+    /// Return the balance of a single `keychain`, separated into available, trusted-pending,
+    /// untrusted-pending and immature values.
+    ///
+    /// Only outputs received on the given `keychain` are counted, so the balances of all keychains
+    /// and the [`Wallet::silent_payment_balance`] add up to [`Wallet::balance`].
+    pub fn keychain_balance(&self, keychain: KeychainKind) -> Balance {
+        self.indexed_graph.graph().balance(
+            &self.chain,
+            self.chain.tip().block_id(),
+            self.indexed_graph
+                .index
+                .keychain_outpoints(keychain)
+                .map(|(i, op)| ((keychain, i), op)),
+            |&(k, _), _| k == KeychainKind::Internal,
+        )
+    }
+
+    /// Add a [`KeychainKind::Custom`] keychain with the given `id` and `descriptor` to the wallet.
+    ///
+    /// Returns `Ok(true)` if the keychain was added, or `Ok(false)` if the wallet already had the
+    /// same descriptor for this keychain. Any secret keys in `descriptor` are used to build the
+    /// keychain's signers. See also [`CreateParams::keychain`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DescriptorError::KeychainAlreadyAssigned`] if the keychain already has a
+    /// different descriptor, and [`DescriptorError::DescriptorAlreadyInUse`] if `descriptor` is
+    /// already used by another keychain.
+    ///
+    /// **WARNING**: You must persist the changes resulting from this method, otherwise the
+    /// keychain will be missing when the wallet is loaded again.
+    pub fn add_keychain<D: IntoWalletDescriptor>(
+        &mut self,
+        id: u32,
+        descriptor: D,
+    ) -> Result<bool, DescriptorError> {
+        let keychain = KeychainKind::Custom(id);
+        let (descriptor, keymap) = descriptor.into_wallet_descriptor(&self.secp, self.network)?;
+        check_wallet_descriptor(&descriptor)?;
+
+        let inserted = self
+            .indexed_graph
+            .index
+            .insert_descriptor(keychain, descriptor.clone())
+            .map_err(map_insert_descriptor_error)?;
+        if inserted {
+            self.signers.insert(
+                keychain,
+                Arc::new(SignersContainer::build(keymap, &descriptor, &self.secp)),
+            );
+            self.stage.merge(ChangeSet {
+                custom_descriptors: [(id, descriptor)].into(),
+                ..Default::default()
+            });
+        }
+        Ok(inserted)
+    }
+
+    /// Add an external signer
     ///
     /// See [the `signer` module](signer) for an example.
     pub fn add_signer(
@@ -1127,24 +1908,27 @@ impl Wallet {
         ordering: SignerOrdering,
         signer: Arc<dyn TransactionSigner>,
     ) {
-        let signers = match keychain {
-            KeychainKind::External => Arc::make_mut(&mut self.signers),
-            KeychainKind::Internal => Arc::make_mut(&mut self.change_signers),
-        };
+        let signers = Arc::make_mut(self.signers.entry(keychain).or_default());
 
         signers.add_external(signer.id(&self.secp), ordering, signer);
     }
 
+    /// Add a signing policy to the wallet
+    ///
+    /// Every signing policy checks the PSBTs passed to [`Wallet::sign`] before any signature is
+    /// produced, and can refuse to sign them. See the [`signer::policy`] module for the built-in
+    /// policies and an example.
+    pub fn add_signing_policy(&mut self, policy: Arc<dyn SigningPolicy>) {
+        self.signing_policies.push(policy);
+    }
+
     /// Set the keymap for a given keychain.
     ///
     /// Note this does nothing if the given keychain has no descriptor because we won't
     /// know the context (segwit, taproot, etc) in which to create signatures.
     pub fn set_keymap(&mut self, keychain: KeychainKind, keymap: KeyMap) {
-        let wallet_signers = match keychain {
-            KeychainKind::External => Arc::make_mut(&mut self.signers),
-            KeychainKind::Internal => Arc::make_mut(&mut self.change_signers),
-        };
         if let Some(descriptor) = self.indexed_graph.index.get_descriptor(keychain) {
+            let wallet_signers = Arc::make_mut(self.signers.entry(keychain).or_default());
             *wallet_signers = SignersContainer::build(keymap, descriptor, &self.secp)
         }
     }
@@ -1176,10 +1960,7 @@ impl Wallet {
     /// Ok::<(), Box<dyn std::error::Error>>(())
     /// ```
     pub fn get_signers(&self, keychain: KeychainKind) -> Arc<SignersContainer> {
-        match keychain {
-            KeychainKind::External => Arc::clone(&self.signers),
-            KeychainKind::Internal => Arc::clone(&self.change_signers),
-        }
+        self.signers.get(&keychain).cloned().unwrap_or_default()
     }
 
     /// Start building a transaction.
@@ -1224,65 +2005,42 @@ impl Wallet {
         params: TxParams,
         rng: &mut impl RngCore,
     ) -> Result<Psbt, CreateTxError> {
-        let keychains: BTreeMap<_, _> = self.indexed_graph.index.keychains().collect();
-        let external_descriptor = keychains.get(&KeychainKind::External).expect("must exist");
-        let internal_descriptor = keychains.get(&KeychainKind::Internal);
-
-        let external_policy = external_descriptor
-            .extract_policy(&self.signers, BuildSatisfaction::None, &self.secp)?
-            .unwrap();
-        let internal_policy = internal_descriptor
-            .map(|desc| {
-                Ok::<_, CreateTxError>(
-                    desc.extract_policy(&self.change_signers, BuildSatisfaction::None, &self.secp)?
-                        .unwrap(),
-                )
-            })
-            .transpose()?;
-
-        // The policy allows spending external outputs, but it requires a policy path that hasn't been
-        // provided
-        if params.change_policy != tx_builder::ChangeSpendPolicy::OnlyChange
-            && external_policy.requires_path()
-            && params.external_policy_path.is_none()
-        {
-            return Err(CreateTxError::SpendingPolicyRequired(
-                KeychainKind::External,
-            ));
-        };
-        // Same for the internal_policy path
-        if let Some(internal_policy) = &internal_policy {
-            if params.change_policy != tx_builder::ChangeSpendPolicy::ChangeForbidden
-                && internal_policy.requires_path()
-                && params.internal_policy_path.is_none()
-            {
-                return Err(CreateTxError::SpendingPolicyRequired(
-                    KeychainKind::Internal,
-                ));
+        let mut requirements = Condition::default();
+        // With assets, the requirements are computed from the plans of the selected UTXOs
+        let keychains = self
+            .indexed_graph
+            .index
+            .keychains()
+            .filter(|_| params.assets.is_none());
+        for (keychain, descriptor) in keychains {
+            let policy = descriptor
+                .extract_policy(
+                    &self.get_signers(keychain),
+                    BuildSatisfaction::None,
+                    &self.secp,
+                )?
+                .unwrap();
+            let policy_path = params.policy_paths.get(&keychain);
+
+            // The policy allows spending outputs of this keychain, but it requires a policy path
+            // that hasn't been provided
+            let may_spend = match params.change_policy {
+                tx_builder::ChangeSpendPolicy::ChangeAllowed => true,
+                tx_builder::ChangeSpendPolicy::OnlyChange => keychain.is_change(),
+                tx_builder::ChangeSpendPolicy::ChangeForbidden => !keychain.is_change(),
             };
-        }
-
-        let external_requirements = external_policy.get_condition(
-            params
-                .external_policy_path
-                .as_ref()
-                .unwrap_or(&BTreeMap::new()),
-        )?;
-        let internal_requirements = internal_policy
-            .map(|policy| {
-                Ok::<_, CreateTxError>(
-                    policy.get_condition(
-                        params
-                            .internal_policy_path
-                            .as_ref()
-                            .unwrap_or(&BTreeMap::new()),
-                    )?,
-                )
-            })
-            .transpose()?;
+            if policy.requires_path() && policy_path.is_none() {
+                if may_spend {
+                    return Err(CreateTxError::SpendingPolicyRequired(keychain));
+                }
+                // The outputs of this keychain aren't spent, so they don't add any requirement
+                continue;
+            }
 
-        let requirements =
-            external_requirements.merge(&internal_requirements.unwrap_or_default())?;
+            let keychain_requirements =
+                policy.get_condition(policy_path.unwrap_or(&BTreeMap::new()))?;
+            requirements = requirements.merge(&keychain_requirements)?;
+        }
 
         let version = match params.version {
             Some(transaction::Version(0)) => return Err(CreateTxError::Version0),
@@ -1303,45 +2061,7 @@ impl Wallet {
             Some(h) => h,
         };
 
-        let lock_time = match params.locktime {
-            // When no nLockTime is specified, we try to prevent fee sniping, if possible
-            None => {
-                // Fee sniping can be partially prevented by setting the timelock
-                // to current_height. If we don't know the current_height,
-                // we default to 0.
-                let fee_sniping_height = current_height;
-
-                // We choose the biggest between the required nlocktime and the fee sniping
-                // height
-                match requirements.timelock {
-                    // No requirement, just use the fee_sniping_height
-                    None => fee_sniping_height,
-                    // There's a block-based requirement, but the value is lower than the fee_sniping_height
-                    Some(value @ absolute::LockTime::Blocks(_)) if value < fee_sniping_height => {
-                        fee_sniping_height
-                    }
-                    // There's a time-based requirement or a block-based requirement greater
-                    // than the fee_sniping_height use that value
-                    Some(value) => value,
-                }
-            }
-            // Specific nLockTime required and we have no constraints, so just set to that value
-            Some(x) if requirements.timelock.is_none() => x,
-            // Specific nLockTime required and it's compatible with the constraints
-            Some(x)
-                if requirements.timelock.unwrap().is_same_unit(x)
-                    && x >= requirements.timelock.unwrap() =>
-            {
-                x
-            }
-            // Invalid nLockTime required
-            Some(x) => {
-                return Err(CreateTxError::LockTime {
-                    requested: x,
-                    required: requirements.timelock.unwrap(),
-                })
-            }
-        };
+        let lock_time = compute_lock_time(params.locktime, requirements.timelock, current_height)?;
 
         // nSequence value for inputs
         // When not explicitly specified, defaults to 0xFFFFFFFD,
@@ -1373,10 +2093,7 @@ impl Wallet {
             }
             FeePolicy::FeeRate(rate) => {
                 if let Some(previous_fee) = params.bumping_fee {
-                    let required_feerate = FeeRate::from_sat_per_kwu(
-                        previous_fee.rate.to_sat_per_kwu()
-                            + FeeRate::BROADCAST_MIN.to_sat_per_kwu(), // +1 sat/vb
-                    );
+                    let required_feerate = previous_fee.min_replacement_rate();
                     if rate < required_feerate {
                         return Err(CreateTxError::FeeRateTooLow {
                             required: required_feerate,
@@ -1398,6 +2115,20 @@ impl Wallet {
             return Err(CreateTxError::NoUtxosSelected);
         }
 
+        // A batch replacement must keep paying the recipients of the original transaction
+        let mut recipients = params.recipients.clone();
+        for kept_output in &params.kept_outputs {
+            match recipients
+                .iter()
+                .position(|recipient| recipient == kept_output)
+            {
+                Some(index) => {
+                    recipients.swap_remove(index);
+                }
+                None => return Err(CreateTxError::RemovedOutput(kept_output.0.clone())),
+            }
+        }
+
         let mut outgoing = Amount::ZERO;
         let recipients = params.recipients.iter().map(|(r, v)| (r, *v));
 
@@ -1416,6 +2147,32 @@ impl Wallet {
             outgoing += value;
         }
 
+        // silent payment outputs are computed once the inputs are selected, until then a
+        // placeholder with the same weight is used
+        let silent_payments_start = tx.output.len();
+        for (index, (address, value)) in params.silent_payment_recipients.iter().enumerate() {
+            if address.network() != NetworkKind::from(self.network) {
+                return Err(SilentPaymentError::WrongNetwork(address.to_string()).into());
+            }
+
+            let placeholder =
+                ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                    address.spend_key().x_only_public_key().0,
+                ));
+            if !params.allow_dust && value.is_dust(&placeholder) {
+                return Err(CreateTxError::OutputBelowDustLimit(
+                    silent_payments_start + index,
+                ));
+            }
+
+            tx.output.push(TxOut {
+                script_pubkey: placeholder,
+                value: *value,
+            });
+
+            outgoing += *value;
+        }
+
         fee_amount += fee_rate * tx.weight();
 
         let (required_utxos, optional_utxos) =
@@ -1448,32 +2205,183 @@ impl Wallet {
             }
         };
 
-        let (required_utxos, optional_utxos) =
+        let (mut required_utxos, mut optional_utxos) =
             coin_selection::filter_duplicates(required_utxos, optional_utxos);
 
-        let coin_selection = coin_selection
-            .coin_select(CoinSelectionParams {
-                required_utxos,
-                optional_utxos,
-                fee_rate,
-                target_amount: outgoing + fee_amount,
-                drain_script: &drain_script,
-                rand: rng,
-                avoid_partial_spends: params.avoid_partial_spends,
-            })
-            .map_err(CreateTxError::CoinSelection)?;
+        // With assets, plan the spend of every local UTXO: the ones that can't be satisfied are
+        // not selected, and the others are selected with their exact satisfaction weight
+        let mut plans = params.foreign_plans.clone();
+        if let Some(assets) = &params.assets {
+            for weighted_utxo in &mut required_utxos {
+                if let Utxo::Local(output) = &weighted_utxo.utxo {
+                    let (satisfaction_weight, plan) = self
+                        .plan_utxo(output, assets)
+                        .ok_or(CreateTxError::UnsatisfiableUtxo(output.outpoint))?;
+                    weighted_utxo.satisfaction_weight = satisfaction_weight;
+                    plans.insert(output.outpoint, plan);
+                }
+            }
+            optional_utxos.retain_mut(|weighted_utxo| match &weighted_utxo.utxo {
+                Utxo::Local(output) => match self.plan_utxo(output, assets) {
+                    Some((satisfaction_weight, plan)) => {
+                        weighted_utxo.satisfaction_weight = satisfaction_weight;
+                        plans.insert(output.outpoint, plan);
+                        true
+                    }
+                    None => false,
+                },
+                Utxo::Foreign { .. } => true,
+            });
+        }
+
+        // Spending an unconfirmed output also means paying for its unconfirmed ancestors, or the
+        // transaction will only confirm as fast as the lowest fee rate among them. The bump fee
+        // depends on the selected coins, so select again until it is covered by the target.
+        let unconfirmed_txids = if fee_rate > FeeRate::ZERO {
+            self.indexed_graph
+                .graph()
+                .list_canonical_txs(&self.chain, self.chain.tip().block_id())
+                .filter(|canon_tx| !canon_tx.chain_position.is_confirmed())
+                .map(|canon_tx| canon_tx.tx_node.txid)
+                .collect::<HashSet<Txid>>()
+        } else {
+            HashSet::new()
+        };
+        // When replacing a transaction, BIP125 rules 3 and 4 require the replacement to pay at
+        // least the original fee plus the minimum relay fee for its own size. The size is only
+        // known after coin selection, so this is handled the same way as the bump fee.
+        let satisfaction_weights = required_utxos
+            .iter()
+            .chain(&optional_utxos)
+            .map(|wu| (wu.utxo.outpoint(), wu.satisfaction_weight))
+            .collect::<HashMap<OutPoint, Weight>>();
+        let mut bump_fee = Amount::ZERO;
+        let mut replacement_fee = Amount::ZERO;
+        let coin_selection = loop {
+            let mut result = coin_selection
+                .coin_select(CoinSelectionParams {
+                    required_utxos: required_utxos.clone(),
+                    optional_utxos: optional_utxos.clone(),
+                    fee_rate,
+                    target_amount: outgoing + fee_amount + bump_fee + replacement_fee,
+                    drain_script: &drain_script,
+                    rand: rng,
+                    avoid_partial_spends: params.avoid_partial_spends,
+                    long_term_fee_rate: params
+                        .long_term_fee_rate
+                        .unwrap_or(coin_selection::DEFAULT_LONG_TERM_FEE_RATE),
+                })
+                .map_err(CreateTxError::CoinSelection)?;
+            let required_bump_fee =
+                self.ancestors_bump_fee(&result.selected, &unconfirmed_txids, fee_rate);
+            // an absolute fee is chosen by the caller and only checked against rule 3 above
+            let required_replacement_fee = match params.bumping_fee {
+                Some(previous_fee) if fee_rate > FeeRate::ZERO => {
+                    let mut replacement = tx.clone();
+                    replacement.input = result
+                        .selected
+                        .iter()
+                        .map(|utxo| bitcoin::TxIn {
+                            previous_output: utxo.outpoint(),
+                            ..Default::default()
+                        })
+                        .collect();
+                    let change = match result.excess {
+                        Excess::Change { amount, .. } => {
+                            replacement.output.push(TxOut {
+                                value: amount,
+                                script_pubkey: drain_script.clone(),
+                            });
+                            amount
+                        }
+                        Excess::NoChange { .. } => Amount::ZERO,
+                    };
+                    // The satisfaction weights are relative to an input with an empty witness, so
+                    // the weight of the finalized transaction also needs the segwit marker and
+                    // flag when any input is segwit. A foreign UTXO may be wrapped segwit, which
+                    // can't be told from its script, so it's counted as segwit.
+                    let needs_witness = result.selected.iter().any(|utxo| match utxo {
+                        Utxo::Local(local) => {
+                            let desc = self.public_descriptor(local.keychain);
+                            desc.is_witness() || desc.is_taproot()
+                        }
+                        Utxo::Foreign { .. } => true,
+                    });
+                    let mut weight = result
+                        .selected
+                        .iter()
+                        .fold(replacement.weight(), |weight, utxo| {
+                            weight + satisfaction_weights[&utxo.outpoint()]
+                        });
+                    if needs_witness {
+                        // segwit marker and flag, plus the empty witness of every input
+                        weight += Weight::from_wu(2 + replacement.input.len() as u64);
+                    }
+                    let own_fee = (result.selected_amount() - outgoing - change)
+                        .checked_sub(bump_fee)
+                        .unwrap_or_default();
+                    let min_fee = previous_fee.absolute + FeeRate::BROADCAST_MIN * weight;
+                    replacement_fee + min_fee.checked_sub(own_fee).unwrap_or_default()
+                }
+                _ => Amount::ZERO,
+            };
+            if required_bump_fee <= bump_fee && required_replacement_fee <= replacement_fee {
+                result.bump_fee = bump_fee;
+                break result;
+            }
+            bump_fee = bump_fee.max(required_bump_fee);
+            replacement_fee = required_replacement_fee;
+        };
 
         let excess = &coin_selection.excess;
+
+        // The timelocks of the plans of the selected UTXOs
+        let selected_plans = coin_selection
+            .selected
+            .iter()
+            .filter_map(|utxo| plans.get(&utxo.outpoint()));
+        for plan in selected_plans.clone() {
+            requirements = requirements.merge(&Condition {
+                timelock: plan.absolute_timelock,
+                csv: None,
+            })?;
+        }
+        if !plans.is_empty() {
+            tx.lock_time =
+                compute_lock_time(params.locktime, requirements.timelock, current_height)?;
+        }
+        if version == transaction::Version::ONE
+            && selected_plans
+                .clone()
+                .any(|plan| plan.relative_timelock.is_some())
+        {
+            return Err(CreateTxError::Version1Csv);
+        }
+
         tx.input = coin_selection
             .selected
             .iter()
-            .map(|u| bitcoin::TxIn {
-                previous_output: u.outpoint(),
-                script_sig: ScriptBuf::default(),
-                sequence: u.sequence().unwrap_or(n_sequence),
-                witness: Witness::new(),
+            .map(|u| {
+                let csv = plans
+                    .get(&u.outpoint())
+                    .and_then(|plan| plan.relative_timelock)
+                    .map(|timelock| timelock.to_sequence());
+                let sequence = match (params.sequence, csv) {
+                    (None, Some(csv)) => csv,
+                    (Some(sequence), Some(csv)) if !check_nsequence_rbf(sequence, csv) => {
+                        return Err(CreateTxError::RbfSequenceCsv { sequence, csv })
+                    }
+                    (Some(sequence), Some(_)) => sequence,
+                    (_, None) => u.sequence().unwrap_or(n_sequence),
+                };
+                Ok(bitcoin::TxIn {
+                    previous_output: u.outpoint(),
+                    script_sig: ScriptBuf::default(),
+                    sequence,
+                    witness: Witness::new(),
+                })
             })
-            .collect();
+            .collect::<Result<_, _>>()?;
 
         if tx.output.is_empty() {
             // Uh oh, our transaction has no outputs.
@@ -1481,9 +2389,12 @@ impl Wallet {
             // - We have a drain_to address and the utxos we must spend (this happens,
             // for example, when we RBF)
             // - We have a drain_to address and drain_wallet set
+            // - We drain the utxos we must spend to the change keychain (this happens,
+            // for example, when we cancel a transaction)
             // Otherwise, we don't know who we should send the funds to, and how much
             // we should send!
-            if params.drain_to.is_some() && (params.drain_wallet || !params.utxos.is_empty()) {
+            let drain = params.drain_to.is_some() || params.drain_to_change;
+            if drain && (params.drain_wallet || !params.utxos.is_empty()) {
                 if let Excess::NoChange {
                     dust_threshold,
                     remaining_amount,
@@ -1516,10 +2427,26 @@ impl Wallet {
             tx.output.push(drain_output);
         }
 
+        if !params.silent_payment_recipients.is_empty() {
+            let output_keys = self.silent_payment_output_keys(
+                &tx,
+                &coin_selection.selected,
+                &params.silent_payment_recipients,
+            )?;
+            for (txout, output_key) in tx.output[silent_payments_start..]
+                .iter_mut()
+                .zip(output_keys)
+            {
+                txout.script_pubkey = ScriptBuf::new_p2tr_tweaked(
+                    TweakedPublicKey::dangerous_assume_tweaked(output_key),
+                );
+            }
+        }
+
         // sort input/outputs according to the chosen algorithm
         params.ordering.sort_tx_with_aux_rand(&mut tx, rng);
 
-        let psbt = self.complete_transaction(tx, coin_selection.selected, params)?;
+        let psbt = self.complete_transaction(tx, coin_selection.selected, &plans, params)?;
 
         // recording changes to the change keychain
         if let (Excess::Change { .. }, Some((keychain, index))) = (excess, drain_index) {
@@ -1648,6 +2575,7 @@ impl Wallet {
                                 is_spent: true,
                                 derivation_index,
                                 chain_position,
+                                lock: None,
                             }),
                             satisfaction_weight,
                         }
@@ -1715,6 +2643,299 @@ impl Wallet {
         })
     }
 
+    /// Replace a transaction previously created with this wallet with one that also pays new
+    /// recipients.
+    ///
+    /// This works like [`Wallet::build_fee_bump`]: the returned [`TxBuilder`] spends all the
+    /// inputs of the original transaction and keeps all of its outputs, except for the change
+    /// which is recalculated. New recipients can then be added with
+    /// [`TxBuilder::add_recipient`] and coin selection will add inputs if the original ones
+    /// don't cover the extra amount. The original outputs can't be removed: if
+    /// [`TxBuilder::set_recipients`] drops any of them, [`TxBuilder::finish`] returns
+    /// [`CreateTxError::RemovedOutput`].
+    ///
+    /// The fee rate defaults to the one of the original transaction plus the minimum
+    /// incremental relay fee (1 sat/vbyte), and the absolute fee is raised if needed so that
+    /// the replacement satisfies BIP125 rules 3 and 4: it pays at least the original fee plus
+    /// the minimum relay fee for its own size.
+    ///
+    /// Returns the same errors as [`Wallet::build_fee_bump`].
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use std::str::FromStr;
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// # let mut wallet = doctest_wallet!();
+    /// # let txid: Txid = todo!();
+    /// # let new_address = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt").unwrap().assume_checked();
+    /// let mut psbt = {
+    ///     let mut builder = wallet.build_batch_replacement(txid)?;
+    ///     builder.add_recipient(new_address.script_pubkey(), Amount::from_sat(20_000));
+    ///     builder.finish()?
+    /// };
+    /// let _ = wallet.sign(&mut psbt, SignOptions::default())?;
+    /// let batch_tx = psbt.extract_tx();
+    /// // broadcast batch_tx to replace the original transaction
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn build_batch_replacement(
+        &mut self,
+        txid: Txid,
+    ) -> Result<TxBuilder<'_, DefaultCoinSelectionAlgorithm>, BuildFeeBumpError> {
+        let mut builder = self.build_fee_bump(txid)?;
+        if let Some(previous_fee) = builder.params.bumping_fee {
+            builder.params.fee_policy =
+                Some(FeePolicy::FeeRate(previous_fee.min_replacement_rate()));
+        }
+        builder.params.kept_outputs = builder.params.recipients.clone();
+        Ok(builder)
+    }
+
+    /// Build a transaction that cancels an unconfirmed transaction previously created with this
+    /// wallet.
+    ///
+    /// Unlike [`Wallet::cancel_tx`], which only updates the wallet's bookkeeping, the returned
+    /// [`TxBuilder`] creates a replacement that double-spends all the wallet's inputs of the
+    /// original transaction and sends the funds back to the change keychain. Once the replacement
+    /// is broadcast and applied to the wallet, the original transaction is conflicted and no
+    /// longer part of the wallet's history.
+    ///
+    /// The replacement pays at least `fee_rate`, and at least the minimum fee that satisfies the
+    /// BIP125 replacement rules: a fee rate higher than the original one and an absolute fee of
+    /// at least the original fee plus the minimum relay fee for the replacement's own size.
+    ///
+    /// Returns the same errors as [`Wallet::build_fee_bump`], and
+    /// [`BuildFeeBumpError::UnknownUtxo`] if none of the inputs of the transaction belong to the
+    /// wallet.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// # let mut wallet = doctest_wallet!();
+    /// # let txid: Txid = todo!();
+    /// let mut psbt = {
+    ///     let fee_rate = FeeRate::from_sat_per_vb(5).expect("valid feerate");
+    ///     let builder = wallet.build_cancellation(txid, fee_rate)?;
+    ///     builder.finish()?
+    /// };
+    /// let _ = wallet.sign(&mut psbt, SignOptions::default())?;
+    /// let cancellation_tx = psbt.extract_tx();
+    /// // broadcast cancellation_tx to replace the original transaction
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn build_cancellation(
+        &mut self,
+        txid: Txid,
+        fee_rate: FeeRate,
+    ) -> Result<TxBuilder<'_, DefaultCoinSelectionAlgorithm>, BuildFeeBumpError> {
+        let mut builder = self.build_fee_bump(txid)?;
+        let first_input = builder.params.utxos.first().map(|wu| wu.utxo.outpoint());
+
+        // we can only sign our own inputs, and double-spending any of them is enough
+        builder
+            .params
+            .utxos
+            .retain(|wu| matches!(wu.utxo, Utxo::Local(_)));
+        if builder.params.utxos.is_empty() {
+            let outpoint = first_input.expect("transactions must have inputs");
+            return Err(BuildFeeBumpError::UnknownUtxo(outpoint));
+        }
+
+        let min_fee_rate = builder
+            .params
+            .bumping_fee
+            .map(|previous_fee| previous_fee.min_replacement_rate())
+            .unwrap_or(FeeRate::BROADCAST_MIN);
+
+        let params = &mut builder.params;
+        params.recipients.clear();
+        params.drain_to_change = true;
+        params.manually_selected_only = true;
+        params.fee_policy = Some(FeePolicy::FeeRate(fee_rate.max(min_fee_rate)));
+
+        Ok(builder)
+    }
+
+    /// Build a *child pays for parent* (CPFP) transaction for an unconfirmed transaction.
+    ///
+    /// The child spends the largest unspent output of the parent that belongs to the wallet and
+    /// sends the funds back to the change keychain. Its fee is chosen so that the parent and child
+    /// (and any other unconfirmed ancestors) together reach `target_package_feerate`, taking into
+    /// account the fee already paid by the parent. If the parent alone pays at least the target
+    /// fee rate, the child only pays for itself.
+    ///
+    /// The returned [`TxBuilder`] can be further customized, e.g. to add recipients or to change
+    /// the target fee rate with [`TxBuilder::fee_rate`].
+    ///
+    /// Returns an error if the parent is not found or already confirmed, if its fee can't be
+    /// calculated because the previous output of one of its inputs is missing (see
+    /// [`Wallet::insert_txout`]), or if it doesn't have any unspent output owned by the wallet.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// # let mut wallet = doctest_wallet!();
+    /// # let parent_txid: Txid = todo!();
+    /// let mut psbt = {
+    ///     let target = FeeRate::from_sat_per_vb(10).expect("valid feerate");
+    ///     let mut builder = wallet.build_cpfp(parent_txid, target)?;
+    ///     builder.finish()?
+    /// };
+    /// let _ = wallet.sign(&mut psbt, SignOptions::default())?;
+    /// let child_tx = psbt.extract_tx();
+    /// // broadcast child_tx to speed up the confirmation of the parent
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn build_cpfp(
+        &mut self,
+        parent_txid: Txid,
+        target_package_feerate: FeeRate,
+    ) -> Result<TxBuilder<'_, DefaultCoinSelectionAlgorithm>, BuildCpfpError> {
+        let parent = self
+            .get_tx(parent_txid)
+            .ok_or(BuildCpfpError::TransactionNotFound(parent_txid))?;
+        if parent.chain_position.is_confirmed() {
+            return Err(BuildCpfpError::TransactionConfirmed(parent_txid));
+        }
+        let parent_tx = parent.tx_node.tx;
+
+        // the child can only make up for the parent's fee if we know what it is
+        self.calculate_fee(&parent_tx)
+            .map_err(BuildCpfpError::CalculateFee)?;
+
+        let utxo = (0..parent_tx.output.len() as u32)
+            .filter_map(|vout| self.get_utxo(OutPoint::new(parent_txid, vout)))
+            .max_by_key(|utxo| utxo.txout.value)
+            .ok_or(BuildCpfpError::NoSpendableOutput(parent_txid))?;
+        let satisfaction_weight = self
+            .public_descriptor(utxo.keychain)
+            .max_weight_to_satisfy()
+            .unwrap();
+
+        let params = TxParams {
+            drain_to_change: true,
+            fee_policy: Some(FeePolicy::FeeRate(target_package_feerate)),
+            utxos: vec![WeightedUtxo {
+                satisfaction_weight,
+                utxo: Utxo::Local(utxo),
+            }],
+            ..Default::default()
+        };
+
+        Ok(TxBuilder {
+            wallet: self,
+            params,
+            coin_selection: DefaultCoinSelectionAlgorithm::default(),
+        })
+    }
+
+    /// Build a transaction refreshing the relative timelocks of the wallet's UTXOs.
+    ///
+    /// The transaction spends every UTXO returned by [`Wallet::list_expiring_utxos`] with
+    /// `blocks`, through a spending path without timelocks, and sends the funds back to the change
+    /// keychain at `fee_rate`. Once it confirms, the countdown of the relative timelocks starts
+    /// again, which keeps the coins of decaying multisig, vault and inheritance wallets out of
+    /// reach of their timelocked paths. Locked UTXOs, and UTXOs reserved by a pending spend, are
+    /// left out (see [`Wallet::lock_utxo`] and [`Wallet::insert_pending_spend`]).
+    ///
+    /// If the descriptor has more than one path without timelocks, the first one whose keys all
+    /// have a signer in the wallet is used, or the first one if there is none: it can be changed
+    /// with [`TxBuilder::policy_path`]. The returned [`TxBuilder`] can be further customized, e.g.
+    /// to change the fee rate with [`TxBuilder::fee_rate`].
+    ///
+    /// Returns an error if no UTXO that isn't locked or reserved is about to expire, or if the
+    /// descriptor of one of them has no spending path without timelocks.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// # let mut wallet = doctest_wallet!();
+    /// let mut psbt = {
+    ///     // refresh the UTXOs that the recovery key could spend in the next 1000 blocks
+    ///     let fee_rate = FeeRate::from_sat_per_vb(2).expect("valid feerate");
+    ///     let mut builder = wallet.build_timelock_refresh(1_000, fee_rate)?;
+    ///     builder.finish()?
+    /// };
+    /// let _ = wallet.sign(&mut psbt, SignOptions::default())?;
+    /// let refresh_tx = psbt.extract_tx();
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn build_timelock_refresh(
+        &mut self,
+        blocks: u32,
+        fee_rate: FeeRate,
+    ) -> Result<TxBuilder<'_, DefaultCoinSelectionAlgorithm>, BuildTimelockRefreshError> {
+        // the utxos are manually selected, which would override their locks and reservations
+        let reserved = self.reserved_outpoints();
+        let mut expiring = self.list_expiring_utxos(blocks)?;
+        expiring.retain(|expiring| {
+            expiring.utxo.lock.is_none() && !reserved.contains(&expiring.utxo.outpoint)
+        });
+        if expiring.is_empty() {
+            return Err(BuildTimelockRefreshError::NoExpiringUtxos);
+        }
+
+        let mut policy_paths = BTreeMap::new();
+        let mut utxos = Vec::with_capacity(expiring.len());
+        for UtxoSpendability { utxo, paths } in expiring {
+            if let btree_map::Entry::Vacant(entry) = policy_paths.entry(utxo.keychain) {
+                let primary_paths = paths
+                    .into_iter()
+                    .map(|path| path.path)
+                    .filter(|path| path.condition.is_null())
+                    .collect::<Vec<_>>();
+                let signers = self.get_signers(utxo.keychain);
+                let primary_path = primary_paths
+                    .iter()
+                    .find(|path| path.is_signed_by_signers(&signers))
+                    .or_else(|| primary_paths.first())
+                    .ok_or(BuildTimelockRefreshError::NoPrimaryPath(utxo.keychain))?;
+                entry.insert(primary_path.policy_path.clone());
+            }
+            let satisfaction_weight = self
+                .public_descriptor(utxo.keychain)
+                .max_weight_to_satisfy()
+                .unwrap();
+            utxos.push(WeightedUtxo {
+                satisfaction_weight,
+                utxo: Utxo::Local(utxo),
+            });
+        }
+
+        // the utxos of a keychain without expiring utxos aren't spent, and don't need a policy path
+        let change_policy = if !policy_paths.contains_key(&KeychainKind::Internal) {
+            tx_builder::ChangeSpendPolicy::ChangeForbidden
+        } else if !policy_paths.contains_key(&KeychainKind::External) {
+            tx_builder::ChangeSpendPolicy::OnlyChange
+        } else {
+            tx_builder::ChangeSpendPolicy::ChangeAllowed
+        };
+
+        let params = TxParams {
+            drain_to_change: true,
+            fee_policy: Some(FeePolicy::FeeRate(fee_rate)),
+            policy_paths,
+            change_policy,
+            utxos,
+            manually_selected_only: true,
+            ..Default::default()
+        };
+
+        Ok(TxBuilder {
+            wallet: self,
+            params,
+            coin_selection: DefaultCoinSelectionAlgorithm::default(),
+        })
+    }
+
     /// Sign a transaction with all the wallet's signers, in the order specified by every signer's
     /// [`SignerOrdering`]. This function returns the `Result` type with an encapsulated `bool` that has the value true if the PSBT was finalized, or false otherwise.
     ///
@@ -1723,6 +2944,12 @@ impl Wallet {
     /// signers will follow the options, but the "software signers" (WIF keys and `xprv`) defined
     /// in this library will.
     ///
+    /// Both version 0 PSBTs and [`PsbtV2`]s can be signed, see [`SignablePsbt`]. Signing a
+    /// [`PsbtV2`] updates its [`TxModifiable`] flags.
+    ///
+    /// [`PsbtV2`]: crate::psbt::PsbtV2
+    /// [`TxModifiable`]: crate::psbt::v2::TxModifiable
+    ///
     /// ## Example
     ///
     /// ```
@@ -1742,7 +2969,122 @@ impl Wallet {
     /// let finalized = wallet.sign(&mut psbt, SignOptions::default())?;
     /// assert!(finalized, "we should have signed all the inputs");
     /// # Ok::<(),anyhow::Error>(())
-    pub fn sign(&self, psbt: &mut Psbt, sign_options: SignOptions) -> Result<bool, SignerError> {
+    pub fn sign<P: SignablePsbt>(
+        &self,
+        psbt: &mut P,
+        sign_options: SignOptions,
+    ) -> Result<bool, SignerError> {
+        if !self.signing_policies.is_empty() {
+            self.check_psbt_utxos(psbt.as_v0_mut())
+                .map_err(SignerError::Policy)?;
+            let summary = self.summarize_psbt(psbt.as_v0_mut());
+            for policy in &self.signing_policies {
+                policy.check(&summary).map_err(SignerError::Policy)?;
+            }
+        }
+
+        self.sign_inputs(psbt.as_v0_mut(), &sign_options)?;
+        psbt.signed();
+
+        // attempt to finalize
+        if sign_options.try_finalize {
+            self.finalize_psbt(psbt, sign_options)
+        } else {
+            Ok(false)
+        }
+    }
+
+    // Check that the previous outputs of the PSBT agree with each other and with the wallet's
+    // transaction graph, before they are used to compute the fee of the transaction
+    fn check_psbt_utxos(&self, psbt: &Psbt) -> Result<(), SigningPolicyError> {
+        let graph = self.indexed_graph.graph();
+        for (index, (txin, input)) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs).enumerate() {
+            let outpoint = txin.previous_output;
+            let non_witness_utxo = match &input.non_witness_utxo {
+                Some(tx) if tx.compute_txid() != outpoint.txid => {
+                    return Err(SigningPolicyError::InconsistentUtxo(index))
+                }
+                Some(tx) => Some(
+                    tx.output
+                        .get(outpoint.vout as usize)
+                        .ok_or(SigningPolicyError::InconsistentUtxo(index))?,
+                ),
+                None => None,
+            };
+            let graph_utxo = graph.get_txout(outpoint);
+
+            let mut utxos = [input.witness_utxo.as_ref(), non_witness_utxo, graph_utxo]
+                .into_iter()
+                .flatten();
+            if let Some(first) = utxos.next() {
+                if utxos.any(|utxo| utxo != first) {
+                    return Err(SigningPolicyError::InconsistentUtxo(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Build the view of `psbt` passed to the signing policies
+    fn summarize_psbt<'a>(&self, psbt: &'a Psbt) -> PsbtSummary<'a> {
+        let estimate = self.estimate_psbt(psbt);
+        let mut fingerprints = HashSet::new();
+        for (_, desc) in self.keychains() {
+            desc.for_each_key(|key| {
+                fingerprints.insert(key.master_fingerprint());
+                true
+            });
+        }
+
+        let inputs = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .zip(&psbt.inputs)
+            .enumerate()
+            .map(|(index, (txin, input))| InputSummary {
+                outpoint: txin.previous_output,
+                txout: estimate.utxos[index].clone(),
+                derivation: estimate.derivations[index],
+                sighash_type: input.sighash_type,
+            })
+            .collect();
+        let outputs = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .zip(&psbt.outputs)
+            .map(|(txout, output)| OutputSummary {
+                txout: txout.clone(),
+                derivation: self
+                    .indexed_graph
+                    .index
+                    .index_of_spk(txout.script_pubkey.clone())
+                    .copied(),
+                has_wallet_key_origin: output
+                    .bip32_derivation
+                    .values()
+                    .map(|(fingerprint, _)| fingerprint)
+                    .chain(
+                        output
+                            .tap_key_origins
+                            .values()
+                            .map(|(_, (fingerprint, _))| fingerprint),
+                    )
+                    .any(|fingerprint| fingerprints.contains(fingerprint)),
+            })
+            .collect();
+
+        PsbtSummary {
+            psbt,
+            inputs,
+            outputs,
+            fee: estimate.fee,
+            fee_rate: estimate.fee_rate(),
+        }
+    }
+
+    fn sign_inputs(&self, psbt: &mut Psbt, sign_options: &SignOptions) -> Result<(), SignerError> {
         // This adds all the PSBT metadata for the inputs, which will help us later figure out how
         // to derive our keys
         self.update_psbt_with_descriptor(psbt)
@@ -1774,42 +3116,172 @@ impl Wallet {
             return Err(SignerError::NonStandardSighash);
         }
 
-        for signer in self
-            .signers
-            .signers()
-            .iter()
-            .chain(self.change_signers.signers().iter())
-        {
-            signer.sign_transaction(psbt, &sign_options, &self.secp)?;
-        }
-
-        // attempt to finalize
-        if sign_options.try_finalize {
-            self.finalize_psbt(psbt, sign_options)
-        } else {
-            Ok(false)
+        for signer in self.signers.values().flat_map(|signers| signers.signers()) {
+            signer.sign_transaction(psbt, sign_options, &self.secp)?;
         }
+        Ok(())
     }
 
     /// Return the spending policies for the wallet's descriptor
     pub fn policies(&self, keychain: KeychainKind) -> Result<Option<Policy>, DescriptorError> {
-        let signers = match keychain {
-            KeychainKind::External => &self.signers,
-            KeychainKind::Internal => &self.change_signers,
-        };
-
         self.public_descriptor(keychain).extract_policy(
-            signers,
+            &self.get_signers(keychain),
             BuildSatisfaction::None,
             &self.secp,
         )
     }
 
+    /// Analyze a PSBT and report what is missing to finalize it
+    ///
+    /// For every input the report lists the keys that already signed it and, if it spends one of
+    /// the wallet's scripts, the spending policy whose `satisfaction` reflects the signatures in
+    /// the PSBT and the timelocks reached at the current height. The final weight and fee rate
+    /// of the transaction are estimated from the descriptors of the inputs not finalized yet.
+    ///
+    /// See the [`analyze`] module for an example.
+    pub fn analyze_psbt(&self, psbt: &Psbt) -> Result<PsbtAnalysis, DescriptorError> {
+        let current_height = self.chain.tip().height();
+        let estimate = self.estimate_psbt(psbt);
+        let mut inputs = Vec::with_capacity(psbt.inputs.len());
+
+        for (index, input) in psbt.inputs.iter().enumerate() {
+            let derivation = estimate.derivations[index];
+            let is_final = analyze::is_final(input);
+            let mut analysis = InputAnalysis {
+                keychain: derivation.map(|(keychain, _)| keychain),
+                has_utxo: estimate.utxos[index].is_some(),
+                is_final,
+                signed_by: analyze::signed_by(input),
+                policy: None,
+                pending_timelocks: Vec::new(),
+                satisfaction_weight: estimate.satisfaction_weights[index],
+            };
+
+            if let (false, Some((keychain, _))) = (is_final, derivation) {
+                // the satisfaction is computed on a PSBT with this input only, so that the
+                // signatures and timelocks of the other inputs aren't taken into account
+                let txin = &psbt.unsigned_tx.input[index];
+                let input_psbt = Psbt {
+                    unsigned_tx: Transaction {
+                        input: vec![txin.clone()],
+                        ..psbt.unsigned_tx.clone()
+                    },
+                    inputs: vec![input.clone()],
+                    ..psbt.clone()
+                };
+                let input_height = match self
+                    .get_tx(txin.previous_output.txid)
+                    .map(|tx| tx.chain_position)
+                {
+                    Some(ChainPosition::Confirmed { anchor, .. }) => anchor.block_id.height,
+                    // an unconfirmed output can't satisfy any relative timelock
+                    _ => current_height.saturating_add(1),
+                };
+                let policy = self.public_descriptor(keychain).extract_policy(
+                    &self.get_signers(keychain),
+                    BuildSatisfaction::PsbtTimelocks {
+                        psbt: &input_psbt,
+                        current_height,
+                        input_max_height: input_height,
+                    },
+                    &self.secp,
+                )?;
+                if let Some(policy) = &policy {
+                    if !analyze::is_satisfied(&policy.satisfaction) {
+                        analysis.pending_timelocks = analyze::pending_timelocks(policy);
+                    }
+                }
+                analysis.policy = policy;
+            }
+            inputs.push(analysis);
+        }
+
+        Ok(PsbtAnalysis {
+            inputs,
+            estimated_weight: estimate.weight,
+            fee: estimate.fee,
+            estimated_fee_rate: estimate.fee_rate(),
+        })
+    }
+
+    // Look up the previous outputs of the inputs of `psbt`, in the PSBT or in the wallet, and
+    // estimate the weight of the final transaction from the descriptors of the inputs which are
+    // not finalized yet
+    fn estimate_psbt(&self, psbt: &Psbt) -> PsbtEstimate {
+        let graph = self.indexed_graph.graph();
+        let mut tx = psbt.unsigned_tx.clone();
+        let mut estimate = PsbtEstimate::default();
+        let mut total_satisfaction_weight = Some(Weight::ZERO);
+        let mut needs_witness = false;
+
+        for (index, (input, txin)) in psbt.inputs.iter().zip(&mut tx.input).enumerate() {
+            // the wallet's own data can't be forged by the creator of the PSBT
+            let utxo = graph
+                .get_txout(txin.previous_output)
+                .cloned()
+                .or_else(|| psbt.get_utxo_for(index));
+            let derivation = utxo.as_ref().and_then(|utxo| {
+                self.indexed_graph
+                    .index
+                    .index_of_spk(utxo.script_pubkey.clone())
+                    .copied()
+            });
+
+            let satisfaction_weight = if analyze::is_final(input) {
+                txin.script_sig = input.final_script_sig.clone().unwrap_or_default();
+                txin.witness = input.final_script_witness.clone().unwrap_or_default();
+                Some(txin.segwit_weight() - TxIn::default().segwit_weight())
+            } else {
+                let satisfaction_weight = derivation.and_then(|(keychain, child)| {
+                    let desc = self.public_descriptor(keychain);
+                    needs_witness |= desc.is_witness() || desc.is_taproot();
+                    desc.at_derivation_index(child)
+                        .expect("child can't be hardened")
+                        .max_weight_to_satisfy()
+                        .ok()
+                });
+                total_satisfaction_weight = total_satisfaction_weight
+                    .zip(satisfaction_weight)
+                    .map(|(total, weight)| total + weight);
+                satisfaction_weight
+            };
+
+            estimate.utxos.push(utxo);
+            estimate.derivations.push(derivation);
+            estimate.satisfaction_weights.push(satisfaction_weight);
+        }
+
+        let has_witness = tx.input.iter().any(|txin| !txin.witness.is_empty());
+        estimate.weight = total_satisfaction_weight.map(|satisfaction_weight| {
+            let mut weight = tx.weight() + satisfaction_weight;
+            if needs_witness && !has_witness {
+                // segwit marker and flag, plus the empty witness of every input
+                weight += Weight::from_wu(2 + tx.input.len() as u64);
+            }
+            weight
+        });
+        estimate.fee = estimate
+            .utxos
+            .iter()
+            .map(|utxo| utxo.as_ref().map(|utxo| utxo.value))
+            .sum::<Option<Amount>>()
+            .and_then(|input_amount| {
+                let output_amount = tx.output.iter().map(|txout| txout.value).sum();
+                input_amount.checked_sub(output_amount)
+            });
+        estimate
+    }
+
     /// Returns the descriptor used to create addresses for a particular `keychain`.
     ///
     /// It's the "public" version of the wallet's descriptor, meaning a new descriptor that has
     /// the same structure but with the all secret keys replaced by their corresponding public key.
     /// This can be used to build a watch-only version of a wallet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
     pub fn public_descriptor(&self, keychain: KeychainKind) -> &ExtendedDescriptor {
         self.indexed_graph
             .index
@@ -1823,14 +3295,16 @@ impl Wallet {
     /// and [BIP371](https://github.com/bitcoin/bips/blob/master/bip-0371.mediawiki)
     /// for further information.
     ///
-    /// Returns `true` if the PSBT could be finalized, and `false` otherwise.
+    /// Returns `true` if the PSBT could be finalized, and `false` otherwise. Both version 0 PSBTs
+    /// and [`PsbtV2`](crate::psbt::PsbtV2)s can be finalized.
     ///
     /// The [`SignOptions`] can be used to tweak the behavior of the finalizer.
-    pub fn finalize_psbt(
+    pub fn finalize_psbt<P: SignablePsbt>(
         &self,
-        psbt: &mut Psbt,
+        psbt: &mut P,
         sign_options: SignOptions,
     ) -> Result<bool, SignerError> {
+        let psbt = psbt.as_v0_mut();
         let tx = &psbt.unsigned_tx;
         let chain_tip = self.chain.tip().block_id();
         let prev_txids = tx
@@ -1890,15 +3364,37 @@ impl Wallet {
 
             match desc {
                 Some(desc) => {
+                    let after = After::new(Some(current_height), false);
+                    let older = Older::new(Some(current_height), confirmation_height, false);
                     let mut tmp_input = bitcoin::TxIn::default();
-                    match desc.satisfy(
-                        &mut tmp_input,
-                        (
-                            PsbtInputSatisfier::new(psbt, n),
-                            After::new(Some(current_height), false),
-                            Older::new(Some(current_height), confirmation_height, false),
-                        ),
-                    ) {
+                    // Inputs created from a plan are satisfied with the same path, planned again
+                    // with the keys in their metadata and the timelocks of the transaction
+                    let satisfied = if plan::is_planned(psbt_input) {
+                        desc.plan(&plan::input_assets(psbt, n))
+                            .ok()
+                            .filter(|plan| {
+                                let satisfier: &dyn Satisfier<DefiniteDescriptorKey> =
+                                    &(after, older);
+                                plan.absolute_timelock
+                                    .map_or(true, |timelock| satisfier.check_after(timelock))
+                                    && plan
+                                        .relative_timelock
+                                        .map_or(true, |timelock| satisfier.check_older(timelock))
+                            })
+                            .and_then(|plan| plan.satisfy(&PsbtInputSatisfier::new(psbt, n)).ok())
+                            .map(|(witness, script_sig)| {
+                                tmp_input.witness = Witness::from_slice(&witness);
+                                tmp_input.script_sig = script_sig;
+                            })
+                            .ok_or(())
+                    } else {
+                        desc.satisfy(
+                            &mut tmp_input,
+                            (PsbtInputSatisfier::new(psbt, n), after, older),
+                        )
+                        .map_err(|_| ())
+                    };
+                    match satisfied {
                         Ok(_) => {
                             // Set the UTXO fields, final script_sig and witness
                             // and clear everything else.
@@ -1919,7 +3415,26 @@ impl Wallet {
                         Err(_) => finished = false,
                     }
                 }
-                None => finished = false,
+                // The outputs received with silent payments are finalized if they are spent with
+                // the taproot key path, other outputs without a descriptor are left as they are
+                None => match (
+                    psbt_input.tap_key_sig,
+                    self.silent_payments
+                        .as_ref()
+                        .and_then(|index| index.inner().txout(input.previous_output)),
+                ) {
+                    (Some(signature), Some(_)) => {
+                        let psbt_input = psbt
+                            .inputs
+                            .get_mut(n)
+                            .ok_or(SignerError::InputIndexOutOfRange)?;
+                        let original = mem::take(psbt_input);
+                        psbt_input.non_witness_utxo = original.non_witness_utxo;
+                        psbt_input.witness_utxo = original.witness_utxo;
+                        psbt_input.final_script_witness = Some(Witness::p2tr_key_spend(&signature));
+                    }
+                    _ => finished = false,
+                },
             }
         }
 
@@ -1934,6 +3449,134 @@ impl Wallet {
         Ok(finished)
     }
 
+    /// Sign `message` with the key of `address`, following [BIP322].
+    ///
+    /// The address must belong to the wallet. Except for the [`SignatureFormat::Legacy`] format,
+    /// the wallet's signers sign the `to_sign` virtual transaction, which is then finalized like
+    /// any other transaction: the signature is only returned if it is complete. See the [`message`]
+    /// module for more details.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// # use bdk_wallet::message::SignatureFormat;
+    /// # let mut wallet = doctest_wallet!();
+    /// let address = wallet.peek_address(KeychainKind::External, 0).address;
+    /// let signature = wallet.sign_message(&address, "Hello World", SignatureFormat::Simple)?;
+    /// assert!(wallet.verify_message(&address, "Hello World", &signature)?);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+    pub fn sign_message(
+        &self,
+        address: &Address,
+        message: &str,
+        format: SignatureFormat,
+    ) -> Result<MessageSignature, MessageError> {
+        let script_pubkey = address.script_pubkey();
+        if self
+            .indexed_graph
+            .index
+            .index_of_spk(script_pubkey.clone())
+            .is_none()
+        {
+            return Err(MessageError::UnknownAddress(address.to_string()));
+        }
+        match format {
+            SignatureFormat::Simple if !message::is_simple(&script_pubkey) => {
+                return Err(MessageError::UnsupportedFormat)
+            }
+            SignatureFormat::Legacy if !script_pubkey.is_p2pkh() => {
+                return Err(MessageError::UnsupportedFormat)
+            }
+            _ => {}
+        }
+
+        let to_spend = message::to_spend(&script_pubkey, message);
+        let mut psbt = Psbt::from_unsigned_tx(message::to_sign(&to_spend))
+            .expect("the transaction is unsigned");
+        psbt.inputs[0].witness_utxo = Some(to_spend.output[0].clone());
+        psbt.inputs[0].non_witness_utxo = Some(to_spend);
+
+        if format == SignatureFormat::Legacy {
+            self.update_psbt_with_descriptor(&mut psbt)
+                .map_err(|e| MessageError::Signer(SignerError::MiniscriptPsbt(e)))?;
+            let key = self
+                .signers
+                .values()
+                .flat_map(|signers| signers.signers())
+                .find_map(|signer| signer::input_secret_key(signer.as_ref(), &psbt, 0, &self.secp))
+                .ok_or(MessageError::MissingKey)?;
+            let digest = Message::from_digest(signed_msg_hash(message).to_byte_array());
+            let signature = self.secp.sign_ecdsa_recoverable(&digest, &key.inner);
+            return Ok(MessageSignature::Legacy(
+                sign_message::MessageSignature::new(signature, key.compressed),
+            ));
+        }
+
+        let sign_options = SignOptions {
+            trust_witness_utxo: true,
+            ..Default::default()
+        };
+        if !self
+            .sign(&mut psbt, sign_options)
+            .map_err(MessageError::Signer)?
+        {
+            return Err(MessageError::Incomplete);
+        }
+        let to_sign = psbt.extract_tx_unchecked_fee_rate();
+        Ok(match format {
+            SignatureFormat::Simple => MessageSignature::Simple(to_sign.input[0].witness.clone()),
+            _ => MessageSignature::Full(to_sign),
+        })
+    }
+
+    /// Verify the [BIP322] `signature` of `message` for `address`.
+    ///
+    /// The address doesn't need to belong to the wallet. See [`message::verify_message`].
+    ///
+    /// [BIP322]: https://github.com/bitcoin/bips/blob/master/bip-0322.mediawiki
+    pub fn verify_message(
+        &self,
+        address: &Address,
+        message: &str,
+        signature: &MessageSignature,
+    ) -> Result<bool, MessageError> {
+        message::verify_message(&self.secp, address, message, signature)
+    }
+
+    /// Start building a [BIP127] proof of reserves for `message`, spending the wallet's UTXOs.
+    ///
+    /// See the [`reserves`] module for more details.
+    ///
+    /// [BIP127]: https://github.com/bitcoin/bips/blob/master/bip-0127.mediawiki
+    pub fn build_proof(&self, message: &str) -> ProofBuilder<'_> {
+        ProofBuilder::new(self, message)
+    }
+
+    /// Verify the proof of reserves `psbt` for `message` against the wallet's transactions and
+    /// best chain, returning the amount proven at `height`.
+    ///
+    /// See [`reserves::verify_proof`].
+    pub fn verify_proof(
+        &self,
+        psbt: &Psbt,
+        message: &str,
+        height: u32,
+    ) -> Result<Amount, ProofError> {
+        reserves::verify_proof(
+            psbt,
+            message,
+            self.indexed_graph.graph(),
+            &self.chain,
+            self.chain.tip().block_id(),
+            height,
+        )
+    }
+
     /// Return the secp256k1 context used for all signing operations
     pub fn secp_ctx(&self) -> &SecpCtx {
         &self.secp
@@ -1946,6 +3589,11 @@ impl Wallet {
     }
 
     /// The index of the next address that you would get if you were to ask the wallet for a new address
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keychain` is a [`KeychainKind::Custom`] keychain that wasn't added to the wallet
+    /// (see [`Wallet::keychains`]).
     pub fn next_derivation_index(&self, keychain: KeychainKind) -> u32 {
         self.indexed_graph
             .index
@@ -1957,8 +3605,11 @@ impl Wallet {
     /// Informs the wallet that you no longer intend to broadcast a tx that was built from it.
     ///
     /// This frees up the change address used when creating the tx for use in future transactions.
-    // TODO: Make this free up reserved utxos when that's implemented
+    /// If the tx was recorded with [`Wallet::insert_pending_spend`] its inputs are released too.
+    /// If the tx was already broadcast, use [`Wallet::build_cancellation`] to replace it instead.
     pub fn cancel_tx(&mut self, tx: &Transaction) {
+        self.release_pending_spend(tx.compute_txid());
+
         let txout_index = &mut self.indexed_graph.index;
         for txout in &tx.output {
             if let Some((keychain, index)) = txout_index.index_of_spk(txout.script_pubkey.clone()) {
@@ -1991,6 +3642,54 @@ impl Wallet {
             .collect()
     }
 
+    /// Calculate the fee needed to bring the unconfirmed ancestors of `utxos`, including the
+    /// transactions creating them, up to `fee_rate`.
+    ///
+    /// The ancestors are treated as a single package, so an ancestor paying a high fee rate can
+    /// make up for one paying a low fee rate. Ancestors whose fee can't be calculated are left out
+    /// of the package: the previous outputs of the inputs of incoming payments are usually unknown,
+    /// and assuming they pay no fee would make every spend of unconfirmed coins pay for them.
+    fn ancestors_bump_fee(
+        &self,
+        utxos: &[Utxo],
+        unconfirmed_txids: &HashSet<Txid>,
+        fee_rate: FeeRate,
+    ) -> Amount {
+        let graph = self.indexed_graph.graph();
+        let mut visited = HashSet::<Txid>::new();
+        let mut package_fee = Amount::ZERO;
+        let mut package_weight = Weight::ZERO;
+
+        for utxo in utxos {
+            let txid = utxo.outpoint().txid;
+            if !unconfirmed_txids.contains(&txid) {
+                continue;
+            }
+            let tx = match graph.get_tx(txid) {
+                Some(tx) => tx,
+                None => continue,
+            };
+            let ancestors = graph.walk_ancestors(tx.clone(), |_, ancestor_tx| {
+                unconfirmed_txids
+                    .contains(&ancestor_tx.compute_txid())
+                    .then_some(ancestor_tx)
+            });
+            for tx in core::iter::once(tx).chain(ancestors) {
+                if !visited.insert(tx.compute_txid()) {
+                    continue;
+                }
+                if let Ok(fee) = graph.calculate_fee(&tx) {
+                    package_fee += fee;
+                    package_weight += tx.weight();
+                }
+            }
+        }
+
+        (fee_rate * package_weight)
+            .checked_sub(package_fee)
+            .unwrap_or_default()
+    }
+
     /// Given the options returns the list of utxos that must be used to form the
     /// transaction and any further that may be used if needed.
     fn preselect_utxos(
@@ -2016,6 +3715,7 @@ impl Wallet {
         //    must_spend <- manually selected utxos
         //    may_spend  <- all other available utxos
         let mut may_spend = self.get_available_utxos();
+        let reserved = self.reserved_outpoints();
 
         may_spend.retain(|may_spend| {
             !manually_selected
@@ -2024,8 +3724,8 @@ impl Wallet {
         });
         let mut must_spend = manually_selected;
 
-        // NOTE: we are intentionally ignoring `unspendable` here. i.e manual
-        // selection overrides unspendable.
+        // NOTE: we are intentionally ignoring `unspendable`, locked and reserved utxos here. i.e
+        // manual selection overrides unspendable, locks and reservations.
         if *manually_selected_only {
             return (must_spend, vec![]);
         }
@@ -2069,8 +3769,10 @@ impl Wallet {
 
         let mut i = 0;
         may_spend.retain(|u| {
-            let retain = (self.keychains().count() == 1 || change_policy.is_satisfied_by(&u.0))
+            let retain = (!self.has_change_keychain() || change_policy.is_satisfied_by(&u.0))
                 && !unspendable.contains(&u.0.outpoint)
+                && u.0.lock.is_none()
+                && !reserved.contains(&u.0.outpoint)
                 && satisfies_confirmed[i];
             i += 1;
             retain
@@ -2082,7 +3784,27 @@ impl Wallet {
                 satisfaction_weight,
                 utxo: Utxo::Local(local_utxo),
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        // outputs received with silent payments are never change
+        if !self.has_change_keychain()
+            || *change_policy != tx_builder::ChangeSpendPolicy::OnlyChange
+        {
+            let silent_payment_utxos = self
+                .list_silent_payment_unspent()
+                .filter(|output| {
+                    !must_spend
+                        .iter()
+                        .any(|must_spend| must_spend.utxo.outpoint() == output.outpoint)
+                        && !unspendable.contains(&output.outpoint)
+                        && self.active_utxo_lock(output.outpoint).is_none()
+                        && !reserved.contains(&output.outpoint)
+                        && (!must_only_use_confirmed_tx || output.chain_position.is_confirmed())
+                })
+                .map(|output| self.silent_payment_weighted_utxo(output))
+                .collect::<Vec<_>>();
+            may_spend.extend(silent_payment_utxos);
+        }
 
         if must_use_all_available {
             must_spend.append(&mut may_spend);
@@ -2091,10 +3813,115 @@ impl Wallet {
         (must_spend, may_spend)
     }
 
+    /// Compute the output keys of the silent payment `recipients` of `psbt` from the secret keys
+    /// of its inputs, as returned by the signers of the wallet.
+    fn silent_payment_output_keys(
+        &self,
+        tx: &Transaction,
+        selected: &[Utxo],
+        recipients: &[(SilentPaymentAddress, Amount)],
+    ) -> Result<Vec<XOnlyPublicKey>, SilentPaymentError> {
+        // the previous outputs and key origins of the inputs, to find their secret keys
+        let mut psbt = Psbt::from_unsigned_tx(tx.clone()).expect("the transaction is unsigned");
+        for (psbt_input, txin) in psbt.inputs.iter_mut().zip(&tx.input) {
+            match selected
+                .iter()
+                .find(|utxo| utxo.outpoint() == txin.previous_output)
+            {
+                Some(Utxo::Local(utxo)) => {
+                    psbt_input.witness_utxo = Some(utxo.txout.clone());
+                    let descriptor = self
+                        .public_descriptor(utxo.keychain)
+                        .at_derivation_index(utxo.derivation_index)
+                        .expect("child can't be hardened");
+                    psbt_input
+                        .update_with_descriptor_unchecked(&descriptor)
+                        .map_err(|_| SilentPaymentError::MissingInputKey(utxo.outpoint))?;
+                }
+                Some(Utxo::Foreign {
+                    psbt_input: foreign_psbt_input,
+                    ..
+                }) => {
+                    *psbt_input = foreign_psbt_input.as_ref().clone();
+                }
+                None => {}
+            }
+        }
+        let psbt = &psbt;
+
+        let mut input_keys = Vec::new();
+        for (index, txin) in psbt.unsigned_tx.input.iter().enumerate() {
+            let outpoint = txin.previous_output;
+            let script_pubkey = psbt
+                .get_utxo_for(index)
+                .ok_or(SilentPaymentError::MissingInputKey(outpoint))?
+                .script_pubkey;
+            let p2sh_p2wpkh = script_pubkey.is_p2sh()
+                && psbt.inputs[index]
+                    .redeem_script
+                    .as_ref()
+                    .map_or(false, |script| script.is_p2wpkh());
+            let eligible = script_pubkey.is_p2tr()
+                || script_pubkey.is_p2wpkh()
+                || script_pubkey.is_p2pkh()
+                || p2sh_p2wpkh;
+            if !eligible {
+                continue;
+            }
+
+            let key = self
+                .signers
+                .values()
+                .flat_map(|signers| signers.signers())
+                .find_map(|signer| {
+                    signer::input_secret_key(signer.as_ref(), psbt, index, &self.secp)
+                })
+                .ok_or(SilentPaymentError::MissingInputKey(outpoint))?;
+            // only compressed keys are eligible
+            if !key.compressed {
+                continue;
+            }
+            // the keys of taproot outputs are used with even y
+            let secret_key = match script_pubkey.is_p2tr()
+                && key.inner.x_only_public_key(&self.secp).1 == Parity::Odd
+            {
+                true => key.inner.negate(),
+                false => key.inner,
+            };
+            input_keys.push(secret_key);
+        }
+
+        let addresses = recipients
+            .iter()
+            .map(|(address, _)| *address)
+            .collect::<Vec<_>>();
+        silent_payments::create_outputs(
+            &self.secp,
+            psbt.unsigned_tx
+                .input
+                .iter()
+                .map(|txin| txin.previous_output),
+            &input_keys,
+            &addresses,
+        )
+    }
+
+    // Plans the spend of a local UTXO with the given assets, returning the plan and its
+    // satisfaction weight
+    fn plan_utxo(&self, output: &LocalOutput, assets: &Assets) -> Option<(Weight, Plan)> {
+        let descriptor = self
+            .public_descriptor(output.keychain)
+            .at_derivation_index(output.derivation_index)
+            .expect("child can't be hardened");
+        let plan = descriptor.clone().plan(assets).ok()?;
+        Some((plan::satisfaction_weight(&descriptor, &plan), plan))
+    }
+
     fn complete_transaction(
         &self,
         tx: Transaction,
         selected: Vec<Utxo>,
+        plans: &HashMap<OutPoint, Plan>,
         params: TxParams,
     ) -> Result<Psbt, CreateTxError> {
         let mut psbt = Psbt::from_unsigned_tx(tx)?;
@@ -2131,6 +3958,18 @@ impl Wallet {
             };
 
             match utxo {
+                Utxo::Local(utxo) if plans.contains_key(&utxo.outpoint) => {
+                    let plan = &plans[&utxo.outpoint];
+                    let full_input =
+                        self.get_psbt_input(utxo, params.sighash, params.only_witness_utxo)?;
+                    *psbt_input = psbt::Input {
+                        sighash_type: full_input.sighash_type,
+                        witness_utxo: full_input.witness_utxo,
+                        non_witness_utxo: full_input.non_witness_utxo,
+                        ..psbt::Input::default()
+                    };
+                    plan::update_psbt_input(psbt_input, plan);
+                }
                 Utxo::Local(utxo) => {
                     *psbt_input =
                         match self.get_psbt_input(utxo, params.sighash, params.only_witness_utxo) {
@@ -2211,6 +4050,48 @@ impl Wallet {
         Ok(psbt_input)
     }
 
+    /// Update a PSBT with the data this wallet knows about its inputs and outputs
+    ///
+    /// Every input spending one of the wallet's scripts gets its missing `witness_utxo` and
+    /// `non_witness_utxo` from the wallet's transactions, then the key origins and scripts of
+    /// the inputs and outputs belonging to the wallet are filled in from its descriptors.
+    ///
+    /// This is useful when the PSBT was created by somebody else, for instance by the
+    /// coordinator of a multisig, and lacks the data required to sign it.
+    pub fn update_psbt<P: SignablePsbt>(&self, psbt: &mut P) -> Result<(), MiniscriptPsbtError> {
+        let psbt = psbt.as_v0_mut();
+        let graph = self.indexed_graph.graph();
+        for (psbt_input, txin) in psbt.inputs.iter_mut().zip(&psbt.unsigned_tx.input) {
+            let prev_output = txin.previous_output;
+            let prev_tx = match graph.get_tx(prev_output.txid) {
+                Some(prev_tx) => prev_tx,
+                None => continue,
+            };
+            let txout = match prev_tx.output.get(prev_output.vout as usize) {
+                Some(txout) => txout,
+                None => continue,
+            };
+            let keychain = match self
+                .indexed_graph
+                .index
+                .index_of_spk(txout.script_pubkey.clone())
+            {
+                Some(&(keychain, _)) => keychain,
+                None => continue,
+            };
+
+            let desc = self.public_descriptor(keychain);
+            if (desc.is_witness() || desc.is_taproot()) && psbt_input.witness_utxo.is_none() {
+                psbt_input.witness_utxo = Some(txout.clone());
+            }
+            if !desc.is_taproot() && psbt_input.non_witness_utxo.is_none() {
+                psbt_input.non_witness_utxo = Some(prev_tx.as_ref().clone());
+            }
+        }
+
+        self.update_psbt_with_descriptor(psbt)
+    }
+
     fn update_psbt_with_descriptor(&self, psbt: &mut Psbt) -> Result<(), MiniscriptPsbtError> {
         // We need to borrow `psbt` mutably within the loops, so we have to allocate a vec for all
         // the input utxos and outputs
@@ -2225,8 +4106,12 @@ impl Wallet {
             )
             .collect::<Vec<_>>();
 
-        // Try to figure out the keychain and derivation for every input and output
+        // Try to figure out the keychain and derivation for every input and output, except for
+        // the inputs created from a plan whose metadata only describes the planned path
         for (is_input, index, out) in utxos.into_iter() {
+            if is_input && plan::is_planned(&psbt.inputs[index]) {
+                continue;
+            }
             if let Some(&(keychain, child)) =
                 self.indexed_graph.index.index_of_spk(out.script_pubkey)
             {
@@ -2303,12 +4188,15 @@ impl Wallet {
             .index
             .reveal_to_target_multi(&update.last_active_indices);
         changeset.merge(index_changeset.into());
-        changeset.merge(
-            self.indexed_graph
-                .apply_update_at(update.tx_update, Some(seen_at))
-                .into(),
-        );
+        let graph_changeset = self
+            .indexed_graph
+            .apply_update_at(update.tx_update, Some(seen_at));
+        for tx in &graph_changeset.tx_graph.txs {
+            changeset.merge(self.index_silent_payments(tx).unwrap_or_default());
+        }
+        changeset.merge(graph_changeset.into());
         self.stage.merge(changeset);
+        self.prune_pending_spends();
         Ok(())
     }
 
@@ -2404,7 +4292,39 @@ impl Wallet {
                 .apply_block_relevant(block, height)
                 .into(),
         );
+        if self.silent_payments.is_some() {
+            let block_id = BlockId {
+                hash: block.block_hash(),
+                height,
+            };
+            for (tx_pos, tx) in block.txdata.iter().enumerate() {
+                let anchor = TxPosInBlock {
+                    block,
+                    block_id,
+                    tx_pos,
+                }
+                .into();
+                let silent_payments_changeset = self.index_silent_payments(tx);
+                self.record_silent_payment_position(
+                    tx,
+                    ChainPosition::Confirmed {
+                        anchor,
+                        transitively: None,
+                    },
+                );
+                if let Some(silent_payments_changeset) = silent_payments_changeset {
+                    changeset.merge(silent_payments_changeset);
+                    changeset.merge(self.indexed_graph.insert_tx(tx.clone()).into());
+                    changeset.merge(
+                        self.indexed_graph
+                            .insert_anchor(tx.compute_txid(), anchor)
+                            .into(),
+                    );
+                }
+            }
+        }
         self.stage.merge(changeset);
+        self.prune_pending_spends();
         Ok(())
     }
 
@@ -2424,10 +4344,32 @@ impl Wallet {
         &mut self,
         unconfirmed_txs: impl IntoIterator<Item = (T, u64)>,
     ) {
+        let unconfirmed_txs = unconfirmed_txs
+            .into_iter()
+            .map(|(tx, last_seen)| (tx.into(), last_seen))
+            .collect::<Vec<(Arc<Transaction>, u64)>>();
         let indexed_graph_changeset = self
             .indexed_graph
-            .batch_insert_relevant_unconfirmed(unconfirmed_txs);
+            .batch_insert_relevant_unconfirmed(unconfirmed_txs.iter().cloned());
         self.stage.merge(indexed_graph_changeset.into());
+
+        for (tx, last_seen) in unconfirmed_txs {
+            let changeset = self.index_silent_payments(&tx);
+            self.record_silent_payment_position(
+                &tx,
+                ChainPosition::Unconfirmed {
+                    last_seen: Some(last_seen),
+                },
+            );
+            if let Some(changeset) = changeset {
+                self.stage.merge(changeset);
+                let indexed_graph_changeset = self
+                    .indexed_graph
+                    .batch_insert_unconfirmed([(tx, last_seen)]);
+                self.stage.merge(indexed_graph_changeset.into());
+            }
+        }
+        self.prune_pending_spends();
     }
 
     /// Used internally to ensure that all methods requiring a [`KeychainKind`] will use a
@@ -2435,12 +4377,20 @@ impl Wallet {
     /// with only one keychain, passing [`KeychainKind::Internal`] here will instead return
     /// [`KeychainKind::External`].
     fn map_keychain(&self, keychain: KeychainKind) -> KeychainKind {
-        if self.keychains().count() == 1 {
+        if keychain == KeychainKind::Internal && !self.has_change_keychain() {
             KeychainKind::External
         } else {
             keychain
         }
     }
+
+    /// Whether the wallet has an [`KeychainKind::Internal`] (change) keychain.
+    fn has_change_keychain(&self) -> bool {
+        self.indexed_graph
+            .index
+            .get_descriptor(KeychainKind::Internal)
+            .is_some()
+    }
 }
 
 /// Methods to construct sync/full-scan requests for spk-based chain sources.
@@ -2512,6 +4462,7 @@ fn new_local_utxo(
     keychain: KeychainKind,
     derivation_index: u32,
     full_txo: FullTxOut<ConfirmationBlockTime>,
+    lock: Option<UtxoLock>,
 ) -> LocalOutput {
     LocalOutput {
         outpoint: full_txo.outpoint,
@@ -2520,12 +4471,59 @@ fn new_local_utxo(
         chain_position: full_txo.chain_position,
         keychain,
         derivation_index,
+        lock,
     }
 }
 
+// Returns the nLockTime of a transaction, given the one requested by the caller and the one
+// required by the spending policy
+fn compute_lock_time(
+    requested: Option<absolute::LockTime>,
+    required: Option<absolute::LockTime>,
+    current_height: absolute::LockTime,
+) -> Result<absolute::LockTime, CreateTxError> {
+    let lock_time = match requested {
+        // When no nLockTime is specified, we try to prevent fee sniping, if possible
+        None => {
+            // Fee sniping can be partially prevented by setting the timelock
+            // to current_height. If we don't know the current_height,
+            // we default to 0.
+            let fee_sniping_height = current_height;
+
+            // We choose the biggest between the required nlocktime and the fee sniping
+            // height
+            match required {
+                // No requirement, just use the fee_sniping_height
+                None => fee_sniping_height,
+                // There's a block-based requirement, but the value is lower than the fee_sniping_height
+                Some(value @ absolute::LockTime::Blocks(_)) if value < fee_sniping_height => {
+                    fee_sniping_height
+                }
+                // There's a time-based requirement or a block-based requirement greater
+                // than the fee_sniping_height use that value
+                Some(value) => value,
+            }
+        }
+        // Specific nLockTime required and we have no constraints, so just set to that value
+        Some(x) if required.is_none() => x,
+        // Specific nLockTime required and it's compatible with the constraints
+        Some(x) if required.unwrap().is_same_unit(x) && x >= required.unwrap() => x,
+        // Invalid nLockTime required
+        Some(x) => {
+            return Err(CreateTxError::LockTime {
+                requested: x,
+                required: required.unwrap(),
+            })
+        }
+    };
+
+    Ok(lock_time)
+}
+
 fn create_indexer(
     descriptor: ExtendedDescriptor,
     change_descriptor: Option<ExtendedDescriptor>,
+    custom_descriptors: BTreeMap<u32, ExtendedDescriptor>,
     lookahead: u32,
 ) -> Result<KeychainTxOutIndex<KeychainKind>, DescriptorError> {
     let mut indexer = KeychainTxOutIndex::<KeychainKind>::new(lookahead);
@@ -2554,9 +4552,29 @@ fn create_indexer(
             })?);
     }
 
+    for (id, custom_descriptor) in custom_descriptors {
+        indexer
+            .insert_descriptor(KeychainKind::Custom(id), custom_descriptor)
+            .map_err(map_insert_descriptor_error)?;
+    }
+
     Ok(indexer)
 }
 
+fn map_insert_descriptor_error(
+    err: bdk_chain::indexer::keychain_txout::InsertDescriptorError<KeychainKind>,
+) -> DescriptorError {
+    use bdk_chain::indexer::keychain_txout::InsertDescriptorError;
+    match err {
+        InsertDescriptorError::DescriptorAlreadyAssigned { .. } => {
+            DescriptorError::DescriptorAlreadyInUse
+        }
+        InsertDescriptorError::KeychainAlreadyAssigned { .. } => {
+            DescriptorError::KeychainAlreadyAssigned
+        }
+    }
+}
+
 /// Transforms a [`FeeRate`] to `f64` with unit as sat/vb.
 #[macro_export]
 #[doc(hidden)]
@@ -4,6 +4,7 @@ use bitcoin::{BlockHash, Network};
 use miniscript::descriptor::KeyMap;
 
 use crate::{
+    collections::BTreeMap,
     descriptor::{DescriptorError, ExtendedDescriptor, IntoWalletDescriptor},
     utils::SecpCtx,
     AsyncWalletPersister, CreateWithPersistError, KeychainKind, LoadWithPersistError, Wallet,
@@ -36,6 +37,8 @@ pub struct CreateParams {
     pub(crate) descriptor_keymap: KeyMap,
     pub(crate) change_descriptor: Option<DescriptorToExtract>,
     pub(crate) change_descriptor_keymap: KeyMap,
+    pub(crate) custom_descriptors: BTreeMap<u32, DescriptorToExtract>,
+    pub(crate) custom_keymaps: BTreeMap<u32, KeyMap>,
     pub(crate) network: Network,
     pub(crate) genesis_hash: Option<BlockHash>,
     pub(crate) lookahead: u32,
@@ -58,6 +61,8 @@ impl CreateParams {
             descriptor_keymap: KeyMap::default(),
             change_descriptor: None,
             change_descriptor_keymap: KeyMap::default(),
+            custom_descriptors: BTreeMap::new(),
+            custom_keymaps: BTreeMap::new(),
             network: Network::Bitcoin,
             genesis_hash: None,
             lookahead: DEFAULT_LOOKAHEAD,
@@ -79,6 +84,8 @@ impl CreateParams {
             descriptor_keymap: KeyMap::default(),
             change_descriptor: Some(make_descriptor_to_extract(change_descriptor)),
             change_descriptor_keymap: KeyMap::default(),
+            custom_descriptors: BTreeMap::new(),
+            custom_keymaps: BTreeMap::new(),
             network: Network::Bitcoin,
             genesis_hash: None,
             lookahead: DEFAULT_LOOKAHEAD,
@@ -90,11 +97,29 @@ impl CreateParams {
         match keychain {
             KeychainKind::External => &mut self.descriptor_keymap,
             KeychainKind::Internal => &mut self.change_descriptor_keymap,
+            KeychainKind::Custom(id) => self.custom_keymaps.entry(id).or_default(),
         }
         .extend(keymap);
         self
     }
 
+    /// Add a [`KeychainKind::Custom`] keychain with the given `id` and `descriptor`.
+    ///
+    /// Custom keychains are receive keychains: each one reveals its own addresses and has its own
+    /// balance (see [`Wallet::keychain_balance`]), and their outputs are available for coin
+    /// selection. Change is always sent to the [`KeychainKind::Internal`] keychain.
+    ///
+    /// Adding a keychain with an `id` that was already added replaces the previous descriptor.
+    pub fn keychain<D: IntoWalletDescriptor + Send + 'static>(
+        mut self,
+        id: u32,
+        descriptor: D,
+    ) -> Self {
+        self.custom_descriptors
+            .insert(id, make_descriptor_to_extract(descriptor));
+        self
+    }
+
     /// Set `network`.
     pub fn network(mut self, network: Network) -> Self {
         self.network = network;
@@ -151,11 +176,13 @@ impl CreateParams {
 pub struct LoadParams {
     pub(crate) descriptor_keymap: KeyMap,
     pub(crate) change_descriptor_keymap: KeyMap,
+    pub(crate) custom_keymaps: BTreeMap<u32, KeyMap>,
     pub(crate) lookahead: u32,
     pub(crate) check_network: Option<Network>,
     pub(crate) check_genesis_hash: Option<BlockHash>,
     pub(crate) check_descriptor: Option<Option<DescriptorToExtract>>,
     pub(crate) check_change_descriptor: Option<Option<DescriptorToExtract>>,
+    pub(crate) check_custom_descriptors: BTreeMap<u32, Option<DescriptorToExtract>>,
     pub(crate) extract_keys: bool,
 }
 
@@ -167,11 +194,13 @@ impl LoadParams {
         Self {
             descriptor_keymap: KeyMap::default(),
             change_descriptor_keymap: KeyMap::default(),
+            custom_keymaps: BTreeMap::new(),
             lookahead: DEFAULT_LOOKAHEAD,
             check_network: None,
             check_genesis_hash: None,
             check_descriptor: None,
             check_change_descriptor: None,
+            check_custom_descriptors: BTreeMap::new(),
             extract_keys: false,
         }
     }
@@ -181,6 +210,7 @@ impl LoadParams {
         match keychain {
             KeychainKind::External => &mut self.descriptor_keymap,
             KeychainKind::Internal => &mut self.change_descriptor_keymap,
+            KeychainKind::Custom(id) => self.custom_keymaps.entry(id).or_default(),
         }
         .extend(keymap);
         self
@@ -200,6 +230,9 @@ impl LoadParams {
         match keychain {
             KeychainKind::External => self.check_descriptor = Some(expected),
             KeychainKind::Internal => self.check_change_descriptor = Some(expected),
+            KeychainKind::Custom(id) => {
+                self.check_custom_descriptors.insert(id, expected);
+            }
         }
         self
     }
@@ -0,0 +1,773 @@
+//! Payjoin
+//!
+//! This module implements the sender and receiver sides of [BIP78] payjoin, in which the receiver
+//! of a payment contributes one of its own inputs to the transaction paying it. This breaks the
+//! assumption that all the inputs of a transaction belong to the same owner.
+//!
+//! No networking is done here: the sender posts the base64 encoded [`Sender::original_psbt`] to
+//! the payjoin endpoint of the receiver, with [`Sender::query`] as query string, and the receiver
+//! answers with the base64 encoded PSBT returned by [`Receiver::propose`]. If anything goes wrong,
+//! the sender can still broadcast the original transaction.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # use bdk_wallet::payjoin::Sender;
+//! # fn post(_url: &str, _body: String) -> String { unimplemented!() }
+//! # let mut wallet = doctest_wallet!();
+//! # let payee = wallet.peek_address(KeychainKind::External, 0).script_pubkey();
+//! let mut builder = wallet.build_tx();
+//! builder.add_recipient(payee.clone(), Amount::from_sat(10_000));
+//! let psbt = builder.finish()?;
+//!
+//! let sender = Sender::new(&wallet, psbt, payee, Amount::from_sat(500))?;
+//! let url = format!("https://example.com/pj?{}", sender.query());
+//! let response = post(&url, sender.original_psbt().to_string());
+//!
+//! let proposal = response.parse::<Psbt>()?;
+//! let payjoin = sender.process_proposal(&wallet, proposal)?;
+//! let tx = payjoin.extract_tx()?;
+//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! [BIP78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin::psbt::Psbt;
+use bitcoin::{
+    Address, AddressType, Amount, FeeRate, Network, OutPoint, Script, ScriptBuf, Transaction, TxIn,
+    Weight,
+};
+use rand_core::RngCore;
+
+use super::signer::{SignOptions, SignerError};
+use super::Wallet;
+use crate::collections::HashSet;
+use crate::psbt::PsbtUtils;
+
+/// Parameters of a payjoin request, sent to the receiver in the query string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Params {
+    /// Index of the sender output the receiver may decrease to pay for the fees of its input.
+    pub additional_fee_output_index: Option<usize>,
+    /// Maximum amount the receiver may take from that output.
+    pub max_additional_fee_contribution: Amount,
+    /// Minimum fee rate of the payjoin transaction.
+    pub min_fee_rate: Option<FeeRate>,
+}
+
+impl Params {
+    /// Encode the parameters as a query string.
+    ///
+    /// Output substitution is always disabled: the receiver may increase the amount of its output
+    /// but not change its script.
+    pub fn to_query(&self) -> String {
+        let mut query = "v=1".to_string();
+        if let Some(index) = self.additional_fee_output_index {
+            query += &format!(
+                "&additionalfeeoutputindex={}&maxadditionalfeecontribution={}",
+                index,
+                self.max_additional_fee_contribution.to_sat()
+            );
+        }
+        if let Some(min_fee_rate) = self.min_fee_rate {
+            // in sat/vB, with three decimals
+            let sat_per_kwu = min_fee_rate.to_sat_per_kwu();
+            query += &format!(
+                "&minfeerate={}.{:03}",
+                sat_per_kwu / 250,
+                sat_per_kwu % 250 * 4
+            );
+        }
+        query + "&disableoutputsubstitution=true"
+    }
+
+    /// Decode the parameters from a query string. Unknown parameters are ignored.
+    pub fn from_query(query: &str) -> Result<Self, ReceiveError> {
+        let mut params = Params::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "v" if value != "1" => return Err(ReceiveError::UnsupportedVersion),
+                "additionalfeeoutputindex" => {
+                    let index = value.parse().map_err(|_| ReceiveError::InvalidParams)?;
+                    params.additional_fee_output_index = Some(index);
+                }
+                "maxadditionalfeecontribution" => {
+                    let sats = value.parse().map_err(|_| ReceiveError::InvalidParams)?;
+                    params.max_additional_fee_contribution = Amount::from_sat(sats);
+                }
+                "minfeerate" => {
+                    let sat_per_vb = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                        .ok_or(ReceiveError::InvalidParams)?;
+                    // round up to the next sat/kwu
+                    let sat_per_kwu = sat_per_vb * 250.0;
+                    let rounded = sat_per_kwu as u64;
+                    let rounded = if (rounded as f64) < sat_per_kwu {
+                        rounded + 1
+                    } else {
+                        rounded
+                    };
+                    params.min_fee_rate = Some(FeeRate::from_sat_per_kwu(rounded));
+                }
+                _ => {}
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// The sender side of a payjoin.
+#[derive(Debug, Clone)]
+pub struct Sender {
+    original: Psbt,
+    payee: ScriptBuf,
+    params: Params,
+}
+
+impl Sender {
+    /// Sign `psbt`, the original transaction paying `payee`, and prepare the payjoin request.
+    ///
+    /// The first output to the wallet is offered as the output the receiver may decrease by up to
+    /// `max_additional_fee_contribution` to pay for the fees of its input. Keypaths are removed
+    /// from the original PSBT so that it can be sent to the receiver.
+    pub fn new(
+        wallet: &Wallet,
+        mut psbt: Psbt,
+        payee: ScriptBuf,
+        max_additional_fee_contribution: Amount,
+    ) -> Result<Self, SendError> {
+        let outputs = &psbt.unsigned_tx.output;
+        if !outputs.iter().any(|txout| txout.script_pubkey == payee) {
+            return Err(SendError::MissingPayeeOutput);
+        }
+        // the outputs are found in the proposal by their script
+        if let Some(txout) = outputs.iter().enumerate().find_map(|(index, txout)| {
+            outputs[..index]
+                .iter()
+                .any(|other| other.script_pubkey == txout.script_pubkey)
+                .then_some(txout)
+        }) {
+            return Err(SendError::DuplicateOutput(txout.script_pubkey.clone()));
+        }
+        let additional_fee_output_index = outputs.iter().position(|txout| {
+            txout.script_pubkey != payee && wallet.is_mine(txout.script_pubkey.clone())
+        });
+
+        if !wallet.sign(&mut psbt, SignOptions::default())? {
+            return Err(SendError::NotFinalized);
+        }
+        psbt.xpub.clear();
+        psbt.outputs
+            .iter_mut()
+            .for_each(|output| *output = Default::default());
+
+        let max_additional_fee_contribution = match additional_fee_output_index {
+            Some(_) => max_additional_fee_contribution,
+            None => Amount::ZERO,
+        };
+        Ok(Self {
+            original: psbt,
+            payee,
+            params: Params {
+                additional_fee_output_index,
+                max_additional_fee_contribution,
+                min_fee_rate: None,
+            },
+        })
+    }
+
+    /// Request the payjoin transaction to pay at least `min_fee_rate`.
+    pub fn set_min_fee_rate(&mut self, min_fee_rate: FeeRate) {
+        self.params.min_fee_rate = Some(min_fee_rate);
+    }
+
+    /// The original PSBT, to be sent to the receiver. Its transaction can be broadcast instead of
+    /// the payjoin.
+    pub fn original_psbt(&self) -> &Psbt {
+        &self.original
+    }
+
+    /// The parameters of the payjoin request.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// The query string of the payjoin request.
+    pub fn query(&self) -> String {
+        self.params.to_query()
+    }
+
+    /// Check the payjoin `proposal` of the receiver and sign our inputs in it.
+    ///
+    /// The proposal is checked as specified by BIP78: our inputs and outputs must be unchanged and
+    /// keep their order, except for the output we offered to pay fees, and the receiver may only
+    /// take from it the fees of the inputs it added, which must not belong to us. The payjoin
+    /// transaction must pay at least the fees of the original one, and the minimum fee rate if
+    /// one was set. Returns the finalized payjoin PSBT.
+    pub fn process_proposal(&self, wallet: &Wallet, mut proposal: Psbt) -> Result<Psbt, SendError> {
+        let original_tx = &self.original.unsigned_tx;
+        let tx = &proposal.unsigned_tx;
+        if proposal.inputs.len() != tx.input.len() || proposal.outputs.len() != tx.output.len() {
+            return Err(SendError::InvalidProposal);
+        }
+        if tx.version != original_tx.version || tx.lock_time != original_tx.lock_time {
+            return Err(SendError::TxModified);
+        }
+
+        let sequence = original_tx.input[0].sequence;
+        let input_type = inputs_type(&self.original);
+        let mut receiver_weight = Weight::ZERO;
+        let mut input_value = Amount::ZERO;
+        let mut outpoints = HashSet::new();
+        let mut next_original_index = 0;
+        for (index, (txin, input)) in tx.input.iter().zip(&proposal.inputs).enumerate() {
+            let outpoint = txin.previous_output;
+            if !outpoints.insert(outpoint) {
+                return Err(SendError::InvalidProposal);
+            }
+            let has_keys_or_sigs = !input.bip32_derivation.is_empty()
+                || !input.tap_key_origins.is_empty()
+                || !input.partial_sigs.is_empty()
+                || input.tap_key_sig.is_some()
+                || !input.tap_script_sigs.is_empty();
+            let is_final = input.final_script_sig.is_some() || input.final_script_witness.is_some();
+
+            let original_index = original_tx
+                .input
+                .iter()
+                .position(|original_txin| original_txin.previous_output == outpoint);
+            match original_index {
+                Some(original_index) => {
+                    if original_index < next_original_index
+                        || txin.sequence != original_tx.input[original_index].sequence
+                        || has_keys_or_sigs
+                        || is_final
+                        || input.witness_utxo.is_some()
+                        || input.non_witness_utxo.is_some()
+                    {
+                        return Err(SendError::InvalidSenderInput(outpoint));
+                    }
+                    next_original_index = original_index + 1;
+                    let utxo = self
+                        .original
+                        .get_utxo_for(original_index)
+                        .ok_or(SendError::NotFinalized)?;
+                    input_value += utxo.value;
+                }
+                None => {
+                    let utxo = proposal
+                        .get_utxo_for(index)
+                        .ok_or(SendError::InvalidReceiverInput(outpoint))?;
+                    if wallet.is_mine(utxo.script_pubkey.clone()) {
+                        return Err(SendError::NewSenderInput(outpoint));
+                    }
+                    if txin.sequence != sequence
+                        || has_keys_or_sigs
+                        || !is_final
+                        || input_type.map_or(false, |t| Some(t) != script_type(&utxo.script_pubkey))
+                    {
+                        return Err(SendError::InvalidReceiverInput(outpoint));
+                    }
+                    receiver_weight += TxIn {
+                        script_sig: input.final_script_sig.clone().unwrap_or_default(),
+                        witness: input.final_script_witness.clone().unwrap_or_default(),
+                        ..txin.clone()
+                    }
+                    .segwit_weight();
+                    input_value += utxo.value;
+                }
+            }
+        }
+        if let Some(missing) = original_tx.input.iter().find(|original_txin| {
+            !tx.input
+                .iter()
+                .any(|txin| txin.previous_output == original_txin.previous_output)
+        }) {
+            return Err(SendError::MissingSenderInput(missing.previous_output));
+        }
+
+        let mut scripts = HashSet::new();
+        for (txout, output) in tx.output.iter().zip(&proposal.outputs) {
+            if !output.bip32_derivation.is_empty()
+                || !output.tap_key_origins.is_empty()
+                || !scripts.insert(txout.script_pubkey.clone())
+            {
+                return Err(SendError::InvalidOutput(txout.script_pubkey.clone()));
+            }
+        }
+        // the receiver may add outputs, but ours keep their order
+        let mut contribution = Amount::ZERO;
+        let mut next_position = 0;
+        for (index, original_txout) in original_tx.output.iter().enumerate() {
+            let script_pubkey = &original_txout.script_pubkey;
+            let position = tx
+                .output
+                .iter()
+                .position(|txout| &txout.script_pubkey == script_pubkey)
+                .filter(|position| *position >= next_position)
+                .ok_or_else(|| SendError::InvalidOutput(script_pubkey.clone()))?;
+            next_position = position + 1;
+            let txout = &tx.output[position];
+            let valid = if Some(index) == self.params.additional_fee_output_index {
+                contribution = original_txout
+                    .value
+                    .checked_sub(txout.value)
+                    .unwrap_or_default();
+                txout.value <= original_txout.value
+            } else if script_pubkey == &self.payee {
+                txout.value >= original_txout.value
+            } else {
+                txout.value == original_txout.value
+            };
+            if !valid {
+                return Err(SendError::InvalidOutput(script_pubkey.clone()));
+            }
+        }
+
+        let original_fee = self.original.fee().map_err(|_| SendError::NotFinalized)?;
+        let original_fee_rate = original_fee
+            / self
+                .original
+                .clone()
+                .extract_tx_unchecked_fee_rate()
+                .weight();
+        let fee = input_value
+            .checked_sub(tx.output.iter().map(|txout| txout.value).sum())
+            .ok_or(SendError::InvalidFeeContribution)?;
+        if fee < original_fee
+            || contribution > self.params.max_additional_fee_contribution
+            || contribution > fee - original_fee
+            || contribution > original_fee_rate * receiver_weight
+        {
+            return Err(SendError::InvalidFeeContribution);
+        }
+
+        for index in 0..proposal.inputs.len() {
+            let outpoint = proposal.unsigned_tx.input[index].previous_output;
+            if let Some(original_index) = original_tx
+                .input
+                .iter()
+                .position(|original_txin| original_txin.previous_output == outpoint)
+            {
+                let original_input = &self.original.inputs[original_index];
+                proposal.inputs[index].witness_utxo = original_input.witness_utxo.clone();
+                proposal.inputs[index].non_witness_utxo = original_input.non_witness_utxo.clone();
+            }
+        }
+        if !wallet.sign(&mut proposal, SignOptions::default())? {
+            return Err(SendError::NotFinalized);
+        }
+        // our signatures are only known now
+        if let Some(min_fee_rate) = self.params.min_fee_rate {
+            let weight = proposal.clone().extract_tx_unchecked_fee_rate().weight();
+            if fee / weight < min_fee_rate {
+                return Err(SendError::BelowMinFeeRate);
+            }
+        }
+        Ok(proposal)
+    }
+}
+
+/// The receiver side of a payjoin.
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    original: Psbt,
+    params: Params,
+    output_index: usize,
+}
+
+impl Receiver {
+    /// Check the `original` PSBT and the `query` string of a payjoin request received by the
+    /// wallet.
+    ///
+    /// The original PSBT must be finalized, pay to the wallet and not spend any of its outputs.
+    /// Callers should also check that its transaction can be broadcast, with
+    /// [`Receiver::original_tx`], before making a proposal: it is the fallback if the sender
+    /// doesn't broadcast the payjoin.
+    pub fn new(wallet: &Wallet, original: Psbt, query: &str) -> Result<Self, ReceiveError> {
+        let params = Params::from_query(query)?;
+        let tx = &original.unsigned_tx;
+        if tx.input.is_empty() {
+            return Err(ReceiveError::NotFinalized);
+        }
+        for (index, (txin, input)) in tx.input.iter().zip(&original.inputs).enumerate() {
+            if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+                return Err(ReceiveError::NotFinalized);
+            }
+            let utxo = original
+                .get_utxo_for(index)
+                .ok_or(ReceiveError::MissingUtxo(txin.previous_output))?;
+            if wallet.is_mine(utxo.script_pubkey) {
+                return Err(ReceiveError::OwnInput(txin.previous_output));
+            }
+        }
+        let output_index = tx
+            .output
+            .iter()
+            .position(|txout| wallet.is_mine(txout.script_pubkey.clone()))
+            .ok_or(ReceiveError::NoReceiverOutput)?;
+        if let Some(index) = params.additional_fee_output_index {
+            if index >= tx.output.len() || index == output_index {
+                return Err(ReceiveError::InvalidParams);
+            }
+        }
+        Ok(Self {
+            original,
+            params,
+            output_index,
+        })
+    }
+
+    /// The transaction of the original PSBT.
+    pub fn original_tx(&self) -> Transaction {
+        self.original.clone().extract_tx_unchecked_fee_rate()
+    }
+
+    /// The parameters of the payjoin request.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Contribute one of our UTXOs to the original transaction and return the signed proposal.
+    ///
+    /// See [`Receiver::propose_with_aux_rand`] for details.
+    #[cfg(feature = "std")]
+    pub fn propose(self, wallet: &Wallet) -> Result<Psbt, ReceiveError> {
+        self.propose_with_aux_rand(wallet, &mut bitcoin::key::rand::thread_rng())
+    }
+
+    /// Contribute one of our UTXOs to the original transaction and return the signed proposal.
+    ///
+    /// A confirmed UTXO of the same type as the inputs of the sender is picked at random, among
+    /// the ones that are not locked or reserved, and inserted at a random position. Its value is
+    /// added to our output, minus the fees of the input at the fee rate of the original
+    /// transaction, which are taken from the output offered by the sender up to the amount it
+    /// allowed. If the sender requested a minimum fee rate, our output pays the fees missing to
+    /// reach it, allowing for the signatures of the sender to be one byte longer once signed again.
+    ///
+    /// The UTXO is not reserved: use [`Wallet::insert_pending_spend`] with the proposal to avoid
+    /// spending it elsewhere until the payjoin is broadcast.
+    pub fn propose_with_aux_rand(
+        self,
+        wallet: &Wallet,
+        rng: &mut impl RngCore,
+    ) -> Result<Psbt, ReceiveError> {
+        let original_tx = self.original_tx();
+        let original_fee = self.original.fee().map_err(|_| ReceiveError::InvalidFee)?;
+        let fee_rate = original_fee / original_tx.weight();
+        let sequence = original_tx.input[0].sequence;
+        let input_type = inputs_type(&self.original);
+
+        let reserved = wallet
+            .pending_spends()
+            .flat_map(|(_, pending_spend)| &pending_spend.tx.input)
+            .map(|txin| txin.previous_output)
+            .collect::<HashSet<_>>();
+        let mut candidates = wallet
+            .get_available_utxos()
+            .into_iter()
+            .filter(|(utxo, _)| {
+                let is_coinbase = wallet
+                    .indexed_graph
+                    .graph()
+                    .get_tx(utxo.outpoint.txid)
+                    .map_or(true, |tx| tx.is_coinbase());
+                utxo.lock.is_none()
+                    && !reserved.contains(&utxo.outpoint)
+                    && utxo.chain_position.is_confirmed()
+                    && !is_coinbase
+                    && input_type
+                        .map_or(true, |t| Some(t) == script_type(&utxo.txout.script_pubkey))
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(ReceiveError::NoUtxoToContribute);
+        }
+        let (utxo, satisfaction_weight) =
+            candidates.swap_remove(rng.next_u32() as usize % candidates.len());
+        let input_index = rng.next_u32() as usize % (original_tx.input.len() + 1);
+        let psbt_input = wallet
+            .get_psbt_input(utxo.clone(), None, false)
+            .map_err(|_| ReceiveError::NoUtxoToContribute)?;
+
+        // The fees of our input depend on the size of its signature, so sign again if the
+        // estimate turns out to be wrong
+        let mut weight = TxIn::default().segwit_weight() + satisfaction_weight;
+        let mut attempts = 0;
+        let mut proposal = loop {
+            attempts += 1;
+            let mut psbt = self.original.clone();
+            let tx = &mut psbt.unsigned_tx;
+            tx.input.insert(
+                input_index,
+                TxIn {
+                    previous_output: utxo.outpoint,
+                    sequence,
+                    ..Default::default()
+                },
+            );
+            psbt.inputs.insert(input_index, psbt_input.clone());
+
+            let fee = fee_rate * weight;
+            let from_sender = match self.params.additional_fee_output_index {
+                Some(index) => fee
+                    .min(self.params.max_additional_fee_contribution)
+                    .min(tx.output[index].value),
+                None => Amount::ZERO,
+            };
+            let fee = match self.params.min_fee_rate {
+                Some(min_fee_rate) => fee.max(
+                    (min_fee_rate
+                        * (original_tx.weight()
+                            + weight
+                            + Weight::from_wu(original_tx.input.len() as u64)))
+                    .checked_sub(original_fee)
+                    .unwrap_or_default(),
+                ),
+                None => fee,
+            };
+            if let Some(index) = self.params.additional_fee_output_index {
+                tx.output[index].value -= from_sender;
+            }
+            let output = &mut tx.output[self.output_index];
+            output.value = (output.value + utxo.txout.value)
+                .checked_sub(fee - from_sender)
+                .ok_or(ReceiveError::NoUtxoToContribute)?;
+
+            wallet.sign(&mut psbt, SignOptions::default())?;
+            let input = &psbt.inputs[input_index];
+            if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+                return Err(ReceiveError::CannotSign);
+            }
+            let actual_weight = TxIn {
+                script_sig: input.final_script_sig.clone().unwrap_or_default(),
+                witness: input.final_script_witness.clone().unwrap_or_default(),
+                ..Default::default()
+            }
+            .segwit_weight();
+            if actual_weight == weight || attempts == 3 {
+                break psbt;
+            }
+            weight = actual_weight;
+        };
+
+        // Only leave the data of our input, as expected by the sender
+        for (index, input) in proposal.inputs.iter_mut().enumerate() {
+            if index != input_index {
+                *input = Default::default();
+            }
+        }
+        proposal
+            .outputs
+            .iter_mut()
+            .for_each(|output| *output = Default::default());
+        proposal.xpub.clear();
+        Ok(proposal)
+    }
+}
+
+/// The type of the script, if it is a standard one.
+fn script_type(script: &Script) -> Option<AddressType> {
+    Address::from_script(script, Network::Bitcoin)
+        .ok()?
+        .address_type()
+}
+
+/// The type of the inputs of `psbt`, if they all have the same one.
+fn inputs_type(psbt: &Psbt) -> Option<AddressType> {
+    let mut types =
+        (0..psbt.inputs.len()).map(|index| script_type(&psbt.get_utxo_for(index)?.script_pubkey));
+    let first = types.next()??;
+    types.all(|t| t == Some(first)).then_some(first)
+}
+
+/// Error returned by [`Sender`].
+#[derive(Debug)]
+pub enum SendError {
+    /// The original PSBT doesn't pay the payee.
+    MissingPayeeOutput,
+    /// The original PSBT has several outputs with the same script.
+    DuplicateOutput(ScriptBuf),
+    /// The PSBT couldn't be finalized.
+    NotFinalized,
+    /// The inputs or outputs of the proposal don't match its transaction, or it spends an output
+    /// twice.
+    InvalidProposal,
+    /// The proposal changed the version or the lock time of the transaction.
+    TxModified,
+    /// The proposal is missing one of our inputs.
+    MissingSenderInput(OutPoint),
+    /// The proposal changed one of our inputs or their order, or added signatures or data to it.
+    InvalidSenderInput(OutPoint),
+    /// The receiver added one of our UTXOs to the proposal.
+    NewSenderInput(OutPoint),
+    /// An input of the receiver isn't finalized, is missing its UTXO, or differs from ours in
+    /// sequence or type.
+    InvalidReceiverInput(OutPoint),
+    /// The proposal removed, modified or reordered one of the original outputs, added keypaths to
+    /// one, or has several outputs with the same script.
+    InvalidOutput(ScriptBuf),
+    /// The receiver decreased the fees or took from our output more than the fees of its input.
+    InvalidFeeContribution,
+    /// The fee rate of the proposal is below the minimum fee rate requested.
+    BelowMinFeeRate,
+    /// Signing failed.
+    Signer(SignerError),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingPayeeOutput => write!(f, "The PSBT doesn't pay the payee"),
+            Self::DuplicateOutput(script) => {
+                write!(f, "The PSBT pays {} more than once", script)
+            }
+            Self::NotFinalized => write!(f, "The PSBT couldn't be finalized"),
+            Self::InvalidProposal => write!(f, "The proposal is not a valid PSBT"),
+            Self::TxModified => write!(f, "The proposal changed the version or the lock time"),
+            Self::MissingSenderInput(outpoint) => {
+                write!(f, "The proposal is missing the input {}", outpoint)
+            }
+            Self::InvalidSenderInput(outpoint) => {
+                write!(f, "The proposal modified the input {}", outpoint)
+            }
+            Self::NewSenderInput(outpoint) => {
+                write!(f, "The proposal spends our output {}", outpoint)
+            }
+            Self::InvalidReceiverInput(outpoint) => {
+                write!(f, "Invalid receiver input {}", outpoint)
+            }
+            Self::InvalidOutput(script) => {
+                write!(f, "The proposal modified the output to {}", script)
+            }
+            Self::InvalidFeeContribution => write!(f, "Invalid fee contribution"),
+            Self::BelowMinFeeRate => write!(f, "The proposal is below the minimum fee rate"),
+            Self::Signer(err) => write!(f, "Signing error: {}", err),
+        }
+    }
+}
+
+impl From<SignerError> for SendError {
+    fn from(err: SignerError) -> Self {
+        SendError::Signer(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SendError {}
+
+/// Error returned by [`Receiver`].
+#[derive(Debug)]
+pub enum ReceiveError {
+    /// The payjoin version requested is not supported.
+    UnsupportedVersion,
+    /// The query parameters are invalid.
+    InvalidParams,
+    /// An input of the original PSBT is not finalized.
+    NotFinalized,
+    /// An input of the original PSBT is missing its UTXO.
+    MissingUtxo(OutPoint),
+    /// The original PSBT spends one of our outputs.
+    OwnInput(OutPoint),
+    /// The original PSBT doesn't pay to the wallet.
+    NoReceiverOutput,
+    /// The fee of the original PSBT can't be calculated.
+    InvalidFee,
+    /// The wallet has no UTXO that can be contributed.
+    NoUtxoToContribute,
+    /// The wallet couldn't finalize its input.
+    CannotSign,
+    /// Signing failed.
+    Signer(SignerError),
+}
+
+impl ReceiveError {
+    /// The BIP78 error code to return to the sender.
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            Self::UnsupportedVersion => "version-unsupported",
+            Self::NoUtxoToContribute | Self::CannotSign | Self::Signer(_) => "unavailable",
+            _ => "original-psbt-rejected",
+        }
+    }
+}
+
+impl fmt::Display for ReceiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion => write!(f, "Unsupported payjoin version"),
+            Self::InvalidParams => write!(f, "Invalid payjoin parameters"),
+            Self::NotFinalized => write!(f, "The original PSBT is not finalized"),
+            Self::MissingUtxo(outpoint) => write!(f, "Missing UTXO for input {}", outpoint),
+            Self::OwnInput(outpoint) => {
+                write!(f, "The original PSBT spends our output {}", outpoint)
+            }
+            Self::NoReceiverOutput => write!(f, "The original PSBT doesn't pay to the wallet"),
+            Self::InvalidFee => write!(f, "Invalid fee"),
+            Self::NoUtxoToContribute => write!(f, "No UTXO to contribute"),
+            Self::CannotSign => write!(f, "Cannot sign the contributed input"),
+            Self::Signer(err) => write!(f, "Signing error: {}", err),
+        }
+    }
+}
+
+impl From<SignerError> for ReceiveError {
+    fn from(err: SignerError) -> Self {
+        ReceiveError::Signer(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReceiveError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_params_query() {
+        let params = Params {
+            additional_fee_output_index: Some(1),
+            max_additional_fee_contribution: Amount::from_sat(300),
+            min_fee_rate: Some(FeeRate::from_sat_per_kwu(628)),
+        };
+        let query = params.to_query();
+        assert_eq!(
+            query,
+            "v=1&additionalfeeoutputindex=1&maxadditionalfeecontribution=300&minfeerate=2.512&disableoutputsubstitution=true"
+        );
+        assert_eq!(Params::from_query(&query).unwrap(), params);
+        assert_eq!(
+            Params::from_query("minfeerate=1.5").unwrap().min_fee_rate,
+            Some(FeeRate::from_sat_per_kwu(375))
+        );
+        assert_eq!(
+            Params::from_query("minfeerate=0.0001")
+                .unwrap()
+                .min_fee_rate,
+            Some(FeeRate::from_sat_per_kwu(1))
+        );
+        assert!(matches!(
+            Params::from_query("minfeerate=-1"),
+            Err(ReceiveError::InvalidParams)
+        ));
+        assert_eq!(Params::from_query("").unwrap(), Params::default());
+        assert!(matches!(
+            Params::from_query("v=2"),
+            Err(ReceiveError::UnsupportedVersion)
+        ));
+        assert!(matches!(
+            Params::from_query("v=1&additionalfeeoutputindex=x"),
+            Err(ReceiveError::InvalidParams)
+        ));
+    }
+}
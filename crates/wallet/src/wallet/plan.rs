@@ -0,0 +1,117 @@
+//! Spending plans
+//!
+//! Inputs created from a miniscript [`Plan`] carry in their PSBT metadata only the keys used by
+//! the plan, and are marked with a proprietary field: the metadata is not extended with the rest
+//! of the descriptor when signing, and the input is finalized by planning again with the keys and
+//! preimages of the metadata and the timelocks of the transaction, which selects the same path.
+
+use alloc::vec::Vec;
+
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{self, raw::ProprietaryKey, Psbt};
+use bitcoin::taproot::TapLeafHash;
+use bitcoin::{VarInt, Weight};
+use miniscript::descriptor::DescriptorType;
+use miniscript::hash256;
+use miniscript::plan::{Assets, CanSign, Plan, TaprootAvailableLeaves, TaprootCanSign};
+
+use crate::descriptor::DerivedDescriptor;
+
+/// The proprietary PSBT input field marking the inputs created from a [`Plan`].
+pub(crate) fn psbt_plan_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: b"bdk".to_vec(),
+        subtype: 0x01,
+        key: Vec::new(),
+    }
+}
+
+/// Whether the input was created from a [`Plan`].
+pub(crate) fn is_planned(input: &psbt::Input) -> bool {
+    input.proprietary.contains_key(&psbt_plan_key())
+}
+
+/// The satisfaction weight of the plan of `descriptor`, in the same unit as
+/// [`max_weight_to_satisfy`](miniscript::Descriptor::max_weight_to_satisfy): the weight added to
+/// an input with an empty script_sig and witness.
+pub(crate) fn satisfaction_weight(descriptor: &DerivedDescriptor, plan: &Plan) -> Weight {
+    let script_sig_weight = (plan.scriptsig_size() - 1) * 4;
+    let mut witness_size = plan.witness_size();
+    // The witness template of `wsh` descriptors doesn't include the witness script, which is
+    // pushed by `Plan::satisfy`
+    let is_wsh = matches!(
+        descriptor.desc_type(),
+        DescriptorType::Wsh
+            | DescriptorType::WshSortedMulti
+            | DescriptorType::ShWsh
+            | DescriptorType::ShWshSortedMulti
+    );
+    if let Some(witness_script) = descriptor.explicit_script().ok().filter(|_| is_wsh) {
+        let items = plan.witness_template().len();
+        witness_size = witness_size - VarInt::from(items).size()
+            + VarInt::from(items + 1).size()
+            + VarInt::from(witness_script.len()).size()
+            + witness_script.len();
+    }
+    Weight::from_wu((script_sig_weight + witness_size.saturating_sub(1)) as u64)
+}
+
+/// Add the metadata of `plan` to the PSBT input and mark it as planned.
+pub(crate) fn update_psbt_input(input: &mut psbt::Input, plan: &Plan) {
+    plan.update_psbt_input(input);
+
+    // The leaf of a script path spend isn't added to the key origins, but the signers need it
+    let leaf_hashes = input
+        .tap_scripts
+        .values()
+        .map(|(script, version)| TapLeafHash::from_script(script, *version))
+        .collect::<Vec<_>>();
+    for (origin_leaf_hashes, _) in input.tap_key_origins.values_mut() {
+        for leaf_hash in &leaf_hashes {
+            if !origin_leaf_hashes.contains(leaf_hash) {
+                origin_leaf_hashes.push(*leaf_hash);
+            }
+        }
+    }
+
+    input.proprietary.insert(psbt_plan_key(), Vec::new());
+}
+
+/// The assets to plan again the spend of a planned input: the keys and preimages of its metadata,
+/// and the timelocks of the transaction.
+pub(crate) fn input_assets(psbt: &Psbt, index: usize) -> Assets {
+    let input = &psbt.inputs[index];
+    let ecdsa_keys = input
+        .bip32_derivation
+        .values()
+        .map(|source| (source.clone(), CanSign::default()));
+    let schnorr_keys = input
+        .tap_key_origins
+        .iter()
+        .map(|(pk, (leaf_hashes, source))| {
+            let can_sign = CanSign {
+                ecdsa: false,
+                taproot: TaprootCanSign {
+                    key_spend: input.tap_internal_key == Some(*pk),
+                    script_spend: TaprootAvailableLeaves::Many(leaf_hashes.clone()),
+                    ..Default::default()
+                },
+            };
+            (source.clone(), can_sign)
+        });
+
+    let tx = &psbt.unsigned_tx;
+    Assets {
+        keys: ecdsa_keys.chain(schnorr_keys).collect(),
+        sha256_preimages: input.sha256_preimages.keys().copied().collect(),
+        hash256_preimages: input
+            .hash256_preimages
+            .keys()
+            .map(|hash| hash256::Hash::from_byte_array(hash.to_byte_array()))
+            .collect(),
+        ripemd160_preimages: input.ripemd160_preimages.keys().copied().collect(),
+        hash160_preimages: input.hash160_preimages.keys().copied().collect(),
+        absolute_timelock: Some(tx.lock_time).filter(|_| tx.is_lock_time_enabled()),
+        relative_timelock: tx.input[index].sequence.to_relative_lock_time(),
+    }
+}
@@ -0,0 +1,316 @@
+//! Proof of reserves
+//!
+//! This module implements [BIP127] proofs of reserves, which prove the control of a set of UTXOs
+//! at a given time without moving them. A proof is a PSBT spending the UTXOs, with an additional
+//! first input committing to a challenge message, and a single unspendable output: since the
+//! challenge input spends an output that doesn't exist, the transaction can never be valid, but
+//! its signatures still prove that the keys of every UTXO signed the message.
+//!
+//! Proofs are built with [`Wallet::build_proof`], signed with [`Wallet::sign`] like any other
+//! PSBT, and verified against a UTXO set with [`verify_proof`].
+//!
+//! ## Example
+//!
+//! ```
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # let mut wallet = doctest_wallet!();
+//! let message = "Proof of reserves for the audit of 2024-12-31";
+//! let mut proof = wallet.build_proof(message).finish()?;
+//! let sign_options = SignOptions {
+//!     trust_witness_utxo: true,
+//!     ..Default::default()
+//! };
+//! assert!(wallet.sign(&mut proof, sign_options)?);
+//!
+//! let height = wallet.latest_checkpoint().height();
+//! let amount = wallet.verify_proof(&proof, message, height)?;
+//! assert_eq!(amount, wallet.balance().confirmed);
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [BIP127]: https://github.com/bitcoin/bips/blob/master/bip-0127.mediawiki
+//! [`Wallet::build_proof`]: crate::Wallet::build_proof
+//! [`Wallet::sign`]: crate::Wallet::sign
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::fmt;
+
+use bdk_chain::tx_graph::TxGraph;
+use bdk_chain::{Anchor, BlockId, ChainOracle};
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+use bitcoin::opcodes::all::OP_RETURN;
+use bitcoin::psbt::Psbt;
+use bitcoin::script::Builder;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{
+    absolute, transaction, Amount, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+
+use super::error::CreateTxError;
+use super::message::satisfies;
+use super::Wallet;
+use crate::collections::{HashMap, HashSet};
+use crate::types::LocalOutput;
+
+const CHALLENGE_PREFIX: &str = "Proof-of-Reserves: ";
+
+/// Return the outpoint spent by the challenge input of the proofs for `message`.
+pub fn challenge_outpoint(message: &str) -> OutPoint {
+    let mut engine = sha256d::Hash::engine();
+    engine.input(CHALLENGE_PREFIX.as_bytes());
+    engine.input(message.as_bytes());
+    OutPoint::new(Txid::from_raw_hash(sha256d::Hash::from_engine(engine)), 0)
+}
+
+fn unspendable_script() -> ScriptBuf {
+    Builder::new().push_opcode(OP_RETURN).into_script()
+}
+
+/// A builder for proofs of reserves, created with [`Wallet::build_proof`].
+///
+/// By default the proof spends all the UTXOs of the wallet. The challenge input is signed with the
+/// key of the first of them, so the PSBT must be signed with
+/// [`SignOptions::trust_witness_utxo`] unless it is a taproot output.
+///
+/// [`SignOptions::trust_witness_utxo`]: crate::SignOptions::trust_witness_utxo
+#[derive(Debug)]
+pub struct ProofBuilder<'a> {
+    wallet: &'a Wallet,
+    message: String,
+    utxos: Vec<OutPoint>,
+    exclude_unconfirmed: bool,
+}
+
+impl<'a> ProofBuilder<'a> {
+    pub(crate) fn new(wallet: &'a Wallet, message: &str) -> Self {
+        ProofBuilder {
+            wallet,
+            message: message.into(),
+            utxos: Vec::new(),
+            exclude_unconfirmed: false,
+        }
+    }
+
+    /// Only prove the given UTXOs of the wallet, instead of all of them.
+    ///
+    /// Returns an error if one of them is not an unspent output of the wallet.
+    pub fn add_utxos(&mut self, outpoints: &[OutPoint]) -> Result<&mut Self, ProofError> {
+        if let Some(outpoint) = outpoints
+            .iter()
+            .find(|outpoint| self.wallet.get_utxo(**outpoint).is_none())
+        {
+            return Err(ProofError::UnknownUtxo(*outpoint));
+        }
+        self.utxos.extend(outpoints);
+        Ok(self)
+    }
+
+    /// Leave the unconfirmed UTXOs out of the proof.
+    pub fn exclude_unconfirmed(&mut self) -> &mut Self {
+        self.exclude_unconfirmed = true;
+        self
+    }
+
+    /// Build the unsigned proof.
+    pub fn finish(&self) -> Result<Psbt, ProofError> {
+        let mut utxos = if self.utxos.is_empty() {
+            self.wallet.list_unspent().collect::<Vec<_>>()
+        } else {
+            let mut seen = HashSet::new();
+            self.utxos
+                .iter()
+                .filter(|outpoint| seen.insert(**outpoint))
+                .map(|outpoint| {
+                    self.wallet
+                        .get_utxo(*outpoint)
+                        .ok_or(ProofError::UnknownUtxo(*outpoint))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if self.exclude_unconfirmed {
+            utxos.retain(|utxo| utxo.chain_position.is_confirmed());
+        }
+        // the challenge is signed with the key of the first UTXO, which can only be done without
+        // its previous transaction for segwit outputs
+        utxos.sort_by_key(|utxo| !utxo.txout.script_pubkey.is_witness_program());
+        let first_script_pubkey = match utxos.first() {
+            Some(utxo) => utxo.txout.script_pubkey.clone(),
+            None => return Err(ProofError::NoUtxos),
+        };
+
+        let challenge = TxIn {
+            previous_output: challenge_outpoint(&self.message),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::MAX,
+            witness: Witness::new(),
+        };
+        let tx = Transaction {
+            version: transaction::Version::ONE,
+            lock_time: absolute::LockTime::ZERO,
+            input: core::iter::once(challenge)
+                .chain(utxos.iter().map(|utxo| TxIn {
+                    previous_output: utxo.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                }))
+                .collect(),
+            output: vec![TxOut {
+                value: utxos.iter().map(|utxo| utxo.txout.value).sum(),
+                script_pubkey: unspendable_script(),
+            }],
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).expect("the transaction is unsigned");
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: Amount::ZERO,
+            script_pubkey: first_script_pubkey,
+        });
+        for (psbt_input, utxo) in psbt.inputs[1..].iter_mut().zip(utxos) {
+            *psbt_input = self.psbt_input(utxo)?;
+        }
+        Ok(psbt)
+    }
+
+    fn psbt_input(&self, utxo: LocalOutput) -> Result<bitcoin::psbt::Input, ProofError> {
+        let txout = utxo.txout.clone();
+        let mut psbt_input = self
+            .wallet
+            .get_psbt_input(utxo, None, false)
+            .map_err(ProofError::CreateTx)?;
+        // the previous transaction may not be in the wallet, while the signatures of taproot
+        // inputs commit to the previous outputs of all the inputs
+        if txout.script_pubkey.is_witness_program() {
+            psbt_input.witness_utxo = Some(txout);
+        }
+        Ok(psbt_input)
+    }
+}
+
+/// Verify the proof of reserves `psbt` for `message`, returning the proven amount.
+///
+/// The UTXOs spent by the proof are looked up in `graph`, and must be confirmed and unspent at
+/// `height` in the best chain of `chain`, whose tip is `chain_tip`. Every input of the proof must
+/// be finalized, with signatures committing to the whole transaction.
+pub fn verify_proof<A: Anchor, C: ChainOracle<Error = Infallible>>(
+    psbt: &Psbt,
+    message: &str,
+    graph: &TxGraph<A>,
+    chain: &C,
+    chain_tip: BlockId,
+    height: u32,
+) -> Result<Amount, ProofError> {
+    let tx = &psbt.unsigned_tx;
+    let challenge = challenge_outpoint(message);
+    if tx.input.first().map(|txin| txin.previous_output) != Some(challenge) {
+        return Err(ProofError::ChallengeInput);
+    }
+    let mut outpoints = Vec::with_capacity(tx.input.len() - 1);
+    let mut seen = HashSet::new();
+    for txin in &tx.input[1..] {
+        let outpoint = txin.previous_output;
+        if outpoint == challenge || !seen.insert(outpoint) {
+            return Err(ProofError::DuplicateInput(outpoint));
+        }
+        outpoints.push(outpoint);
+    }
+    if outpoints.is_empty() {
+        return Err(ProofError::NoUtxos);
+    }
+    if tx.output.len() != 1 || tx.output[0].script_pubkey != unspendable_script() {
+        return Err(ProofError::InvalidOutput);
+    }
+
+    let txouts = graph
+        .filter_chain_txouts(chain, chain_tip, outpoints.iter().map(|op| ((), *op)))
+        .map(|(_, full_txo)| (full_txo.outpoint, full_txo))
+        .collect::<HashMap<_, _>>();
+    let mut prevouts = Vec::with_capacity(tx.input.len());
+    for outpoint in &outpoints {
+        let full_txo = txouts
+            .get(outpoint)
+            .ok_or(ProofError::UnknownUtxo(*outpoint))?;
+        if !full_txo.is_confirmed_and_spendable(height) {
+            return Err(ProofError::UnavailableUtxo(*outpoint));
+        }
+        prevouts.push(full_txo.txout.clone());
+    }
+    let amount = prevouts.iter().map(|txout| txout.value).sum();
+    if tx.output[0].value != amount {
+        return Err(ProofError::InvalidOutput);
+    }
+    // the challenge input is signed with the key of the first UTXO
+    prevouts.insert(
+        0,
+        TxOut {
+            value: Amount::ZERO,
+            script_pubkey: prevouts[0].script_pubkey.clone(),
+        },
+    );
+
+    if let Some(index) = psbt
+        .inputs
+        .iter()
+        .position(|input| input.final_script_sig.is_none() && input.final_script_witness.is_none())
+    {
+        return Err(ProofError::NotFinalized(index));
+    }
+    let signed_tx = psbt.clone().extract_tx_unchecked_fee_rate();
+    let secp = Secp256k1::verification_only();
+    if let Some(index) =
+        (0..signed_tx.input.len()).find(|index| !satisfies(&secp, &signed_tx, *index, &prevouts))
+    {
+        return Err(ProofError::InvalidSignature(index));
+    }
+    Ok(amount)
+}
+
+/// Errors related to proofs of reserves.
+#[derive(Debug)]
+pub enum ProofError {
+    /// The proof doesn't spend any UTXO.
+    NoUtxos,
+    /// The UTXO is not known.
+    UnknownUtxo(OutPoint),
+    /// The UTXO is not confirmed, or already spent, at the height of the proof.
+    UnavailableUtxo(OutPoint),
+    /// The first input of the proof doesn't commit to the message.
+    ChallengeInput,
+    /// The outpoint is spent by more than one input of the proof.
+    DuplicateInput(OutPoint),
+    /// The proof must have a single unspendable output, with the amount of the UTXOs.
+    InvalidOutput,
+    /// The input is not finalized.
+    NotFinalized(usize),
+    /// The input doesn't satisfy the script of its UTXO.
+    InvalidSignature(usize),
+    /// Error while creating the inputs of the proof.
+    CreateTx(CreateTxError),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoUtxos => write!(f, "The proof doesn't spend any UTXO"),
+            Self::UnknownUtxo(outpoint) => write!(f, "Unknown UTXO: {}", outpoint),
+            Self::UnavailableUtxo(outpoint) => write!(
+                f,
+                "UTXO {} is not confirmed or already spent at the height of the proof",
+                outpoint
+            ),
+            Self::ChallengeInput => write!(f, "The first input doesn't commit to the message"),
+            Self::DuplicateInput(outpoint) => write!(f, "Duplicate input: {}", outpoint),
+            Self::InvalidOutput => write!(f, "Invalid proof output"),
+            Self::NotFinalized(index) => write!(f, "Input {} is not finalized", index),
+            Self::InvalidSignature(index) => write!(f, "Invalid signature for input {}", index),
+            Self::CreateTx(err) => write!(f, "Unable to create the proof: {}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProofError {}
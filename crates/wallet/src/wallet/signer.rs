@@ -81,6 +81,11 @@
 //! # Ok::<_, anyhow::Error>(())
 //! ```
 
+#[cfg(feature = "std")]
+pub mod hwi;
+pub mod musig;
+pub mod policy;
+
 use crate::collections::BTreeMap;
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -95,7 +100,7 @@ use bitcoin::secp256k1::Message;
 use bitcoin::sighash::{EcdsaSighashType, TapSighash, TapSighashType};
 use bitcoin::{ecdsa, psbt, sighash, taproot};
 use bitcoin::{key::TapTweak, key::XOnlyPublicKey, secp256k1};
-use bitcoin::{PrivateKey, Psbt, PublicKey};
+use bitcoin::{PrivateKey, Psbt, PublicKey, ScriptBuf};
 
 use miniscript::descriptor::{
     Descriptor, DescriptorMultiXKey, DescriptorPublicKey, DescriptorSecretKey, DescriptorXKey,
@@ -107,6 +112,7 @@ use super::utils::SecpCtx;
 use crate::descriptor::{DescriptorMeta, XKeyUtils};
 use crate::psbt::PsbtUtils;
 use crate::wallet::error::MiniscriptPsbtError;
+use musig::MusigError;
 
 /// Identifier of a signer in the `SignersContainers`. Used as a key to find the right signer among
 /// multiple of them
@@ -167,6 +173,13 @@ pub enum SignerError {
     Psbt(psbt::SignError),
     /// Miniscript PSBT error
     MiniscriptPsbt(MiniscriptPsbtError),
+    /// MuSig2 error
+    Musig(MusigError),
+    /// External signer error
+    #[cfg(feature = "std")]
+    Hwi(hwi::HwiError),
+    /// The PSBT was rejected by a [`SigningPolicy`](policy::SigningPolicy)
+    Policy(policy::SigningPolicyError),
     /// To be used only by external libraries implementing [`InputSigner`] or
     /// [`TransactionSigner`], so that they can return their own custom errors, without having to
     /// modify [`SignerError`] in BDK.
@@ -190,6 +203,10 @@ impl fmt::Display for SignerError {
             Self::SighashTaproot(err) => write!(f, "Error while computing the hash to sign a Taproot input: {}", err),
             Self::Psbt(err) => write!(f, "Error computing the sighash: {}", err),
             Self::MiniscriptPsbt(err) => write!(f, "Miniscript PSBT error: {}", err),
+            Self::Musig(err) => write!(f, "MuSig2 error: {}", err),
+            #[cfg(feature = "std")]
+            Self::Hwi(err) => write!(f, "{}", err),
+            Self::Policy(err) => write!(f, "Signing policy error: {}", err),
             Self::External(err) => write!(f, "{}", err),
         }
     }
@@ -309,24 +326,14 @@ impl SignerCommon for SignerWrapper<DescriptorXKey<Xpriv>> {
     }
 }
 
-impl InputSigner for SignerWrapper<DescriptorXKey<Xpriv>> {
-    fn sign_input(
+impl SignerWrapper<DescriptorXKey<Xpriv>> {
+    /// Derive the key of one of the keypaths of the input `input_index` of `psbt`, if any
+    fn derive_input_key(
         &self,
-        psbt: &mut Psbt,
+        psbt: &Psbt,
         input_index: usize,
-        sign_options: &SignOptions,
         secp: &SecpCtx,
-    ) -> Result<(), SignerError> {
-        if input_index >= psbt.inputs.len() {
-            return Err(SignerError::InputIndexOutOfRange);
-        }
-
-        if psbt.inputs[input_index].final_script_sig.is_some()
-            || psbt.inputs[input_index].final_script_witness.is_some()
-        {
-            return Ok(());
-        }
-
+    ) -> Result<Option<PrivateKey>, SignerError> {
         let tap_key_origins = psbt.inputs[input_index]
             .tap_key_origins
             .iter()
@@ -344,7 +351,7 @@ impl InputSigner for SignerWrapper<DescriptorXKey<Xpriv>> {
                 }
             }) {
             Some((pk, full_path)) => (pk, full_path),
-            None => return Ok(()),
+            None => return Ok(None),
         };
 
         let derived_key = match self.origin.clone() {
@@ -368,13 +375,41 @@ impl InputSigner for SignerWrapper<DescriptorXKey<Xpriv>> {
             Err(SignerError::InvalidKey)
         } else {
             // HD wallets imply compressed keys
-            let priv_key = PrivateKey {
+            Ok(Some(PrivateKey {
                 compressed: true,
                 network: self.xkey.network,
                 inner: derived_key.private_key,
-            };
+            }))
+        }
+    }
+}
+
+impl InputSigner for SignerWrapper<DescriptorXKey<Xpriv>> {
+    fn sign_input(
+        &self,
+        psbt: &mut Psbt,
+        input_index: usize,
+        sign_options: &SignOptions,
+        secp: &SecpCtx,
+    ) -> Result<(), SignerError> {
+        if input_index >= psbt.inputs.len() {
+            return Err(SignerError::InputIndexOutOfRange);
+        }
+
+        if psbt.inputs[input_index].final_script_sig.is_some()
+            || psbt.inputs[input_index].final_script_witness.is_some()
+        {
+            return Ok(());
+        }
 
-            SignerWrapper::new(priv_key, self.ctx).sign_input(psbt, input_index, sign_options, secp)
+        match self.derive_input_key(psbt, input_index, secp)? {
+            Some(priv_key) => SignerWrapper::new(priv_key, self.ctx).sign_input(
+                psbt,
+                input_index,
+                sign_options,
+                secp,
+            ),
+            None => Ok(()),
         }
     }
 }
@@ -395,6 +430,72 @@ fn multikey_to_xkeys<K: InnerXKey + Clone>(
         .collect()
 }
 
+/// Return the secret key held by `signer` for the public key spent by the input `input_index` of
+/// `psbt`
+///
+/// Only inputs spending a single key are supported: P2PKH, P2WPKH, P2SH-P2WPKH and the key path
+/// of P2TR, for which the tweaked secret key of the output is returned. This is used to compute
+/// the outputs of [silent payments](crate::silent_payments) and to sign messages with the legacy
+/// format. The key is taken from [`SignerCommon::descriptor_secret_key`], so the signers keeping
+/// their keys isolated are never used.
+pub(crate) fn input_secret_key(
+    signer: &dyn TransactionSigner,
+    psbt: &Psbt,
+    input_index: usize,
+    secp: &SecpCtx,
+) -> Option<PrivateKey> {
+    let xkeys = match signer.descriptor_secret_key()? {
+        DescriptorSecretKey::Single(single) => {
+            return single_input_secret_key(single.key, psbt, input_index, secp)
+        }
+        DescriptorSecretKey::XPrv(xkey) => vec![xkey],
+        DescriptorSecretKey::MultiXPrv(xkey) => multikey_to_xkeys(xkey),
+    };
+    xkeys.into_iter().find_map(|xkey| {
+        let key = SignerWrapper::new(xkey, SignerContext::Legacy)
+            .derive_input_key(psbt, input_index, secp)
+            .ok()??;
+        single_input_secret_key(key, psbt, input_index, secp)
+    })
+}
+
+// Return `key`, or its tweaked key for a P2TR key path spend, if it's the key spent by the input
+fn single_input_secret_key(
+    key: PrivateKey,
+    psbt: &Psbt,
+    input_index: usize,
+    secp: &SecpCtx,
+) -> Option<PrivateKey> {
+    let script_pubkey = psbt.get_utxo_for(input_index)?.script_pubkey;
+    let pubkey = PublicKey::from_private_key(secp, &key);
+
+    if script_pubkey.is_p2tr() {
+        let psbt_input = psbt.inputs.get(input_index)?;
+        if psbt_input.tap_internal_key != Some(XOnlyPublicKey::from(pubkey.inner)) {
+            return None;
+        }
+        let keypair = secp256k1::Keypair::from_secret_key(secp, &key.inner)
+            .tap_tweak(secp, psbt_input.tap_merkle_root)
+            .to_keypair();
+        let output_key = XOnlyPublicKey::from_keypair(&keypair).0;
+        (script_pubkey == ScriptBuf::new_p2tr_tweaked(output_key.dangerous_assume_tweaked()))
+            .then(|| PrivateKey::new(keypair.secret_key(), key.network))
+    } else {
+        let p2pkh = ScriptBuf::new_p2pkh(&pubkey.pubkey_hash());
+        let p2wpkh = pubkey
+            .wpubkey_hash()
+            .map(|wpkh| ScriptBuf::new_p2wpkh(&wpkh))
+            .ok();
+        let p2sh_p2wpkh = p2wpkh
+            .as_ref()
+            .map(|p2wpkh| ScriptBuf::new_p2sh(&p2wpkh.script_hash()));
+        let matches = script_pubkey == p2pkh
+            || Some(&script_pubkey) == p2wpkh.as_ref()
+            || Some(&script_pubkey) == p2sh_p2wpkh.as_ref();
+        matches.then_some(key)
+    }
+}
+
 impl SignerCommon for SignerWrapper<DescriptorMultiXKey<Xpriv>> {
     fn id(&self, secp: &SecpCtx) -> SignerId {
         SignerId::from(self.root_fingerprint(secp))
@@ -829,7 +930,7 @@ impl Default for SignOptions {
 }
 
 /// Computes the taproot sighash.
-fn compute_tap_sighash(
+pub(crate) fn compute_tap_sighash(
     psbt: &Psbt,
     input_index: usize,
     extra: Option<taproot::TapLeafHash>,
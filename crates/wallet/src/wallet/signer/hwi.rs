@@ -0,0 +1,294 @@
+//! External signers over the HWI command-line interface
+//!
+//! This module provides the [`HwiSigner`], which signs PSBTs by running an external program
+//! following the command-line contract of [HWI]: hardware wallets through HWI itself, or any other
+//! device or service exposing the same commands. The program is called with the global options
+//! `--fingerprint` and `--chain`, followed by one of these commands:
+//!
+//! - `enumerate`, returning the list of the available devices;
+//! - `getdescriptors`, returning the descriptors of the device;
+//! - `signtx <psbt>`, returning the PSBT, encoded in base64, with the signatures of the device.
+//!
+//! The result of each command is a JSON object, or an object with an `error` and a `code` if it
+//! failed.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use core::str::FromStr;
+//! # use bitcoin::bip32::Fingerprint;
+//! # use bitcoin::Network;
+//! # use bdk_wallet::signer::hwi::HwiSigner;
+//! # use bdk_wallet::signer::SignerOrdering;
+//! # use bdk_wallet::{KeychainKind, Wallet};
+//! let signer = HwiSigner::new("hwi", Fingerprint::from_str("9a6a2580")?).chain(Network::Testnet);
+//! let descriptors = signer.get_descriptors(None)?;
+//! let mut wallet = Wallet::create(
+//!     descriptors.receive[0].clone(),
+//!     descriptors.internal[0].clone(),
+//! )
+//! .network(Network::Testnet)
+//! .create_wallet_no_persist()?;
+//! wallet.add_signer(
+//!     KeychainKind::External,
+//!     SignerOrdering::default(),
+//!     Arc::new(signer),
+//! );
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [HWI]: https://github.com/bitcoin-core/HWI
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use std::ffi::OsString;
+use std::process::Command;
+
+use bitcoin::bip32::Fingerprint;
+use bitcoin::psbt::{Psbt, PsbtParseError};
+use bitcoin::Network;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::{SignOptions, SignerCommon, SignerError, SignerId, TransactionSigner};
+use crate::wallet::utils::SecpCtx;
+
+/// A device returned by the `enumerate` command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HwiDevice {
+    /// The type of the device
+    #[serde(rename = "type")]
+    pub device_type: String,
+    /// The model of the device
+    #[serde(default)]
+    pub model: String,
+    /// The path of the device
+    #[serde(default)]
+    pub path: String,
+    /// The fingerprint of the master key of the device, if it is known
+    #[serde(default)]
+    pub fingerprint: Option<Fingerprint>,
+    /// Whether the device is waiting for its PIN
+    #[serde(default)]
+    pub needs_pin_sent: bool,
+    /// Whether the device is waiting for its passphrase
+    #[serde(default)]
+    pub needs_passphrase_sent: bool,
+    /// The error encountered while connecting to the device, if any
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// The descriptors returned by the `getdescriptors` command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HwiDescriptors {
+    /// The descriptors of the receive addresses
+    pub receive: Vec<String>,
+    /// The descriptors of the change addresses
+    pub internal: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HwiFailure {
+    error: String,
+    #[serde(default)]
+    code: i64,
+}
+
+#[derive(Deserialize)]
+struct SignTxResult {
+    psbt: String,
+}
+
+/// Signs PSBTs with an external program following the HWI command-line contract.
+///
+/// The program is only run if the PSBT has an input with a key derived from the master key with
+/// the fingerprint of the signer. The signatures returned by the program are then merged into the
+/// PSBT, leaving its other fields untouched.
+#[derive(Debug, Clone)]
+pub struct HwiSigner {
+    command: OsString,
+    fingerprint: Fingerprint,
+    chain: Option<Network>,
+}
+
+impl HwiSigner {
+    /// Create a new signer running `command` for the device with the master key `fingerprint`.
+    pub fn new(command: impl Into<OsString>, fingerprint: Fingerprint) -> Self {
+        HwiSigner {
+            command: command.into(),
+            fingerprint,
+            chain: None,
+        }
+    }
+
+    /// Set the network passed to the program with the `--chain` option.
+    ///
+    /// The option is not passed by default, which lets the program use the main network.
+    pub fn chain(mut self, network: Network) -> Self {
+        self.chain = Some(network);
+        self
+    }
+
+    /// Return the fingerprint of the master key of the device.
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+
+    /// List the devices available to `command`.
+    pub fn enumerate(command: impl Into<OsString>) -> Result<Vec<HwiDevice>, HwiError> {
+        run(Command::new(command.into()).arg("enumerate"))
+    }
+
+    /// Return the descriptors of the device for the given BIP44 `account`, `0` if `None`.
+    pub fn get_descriptors(&self, account: Option<u32>) -> Result<HwiDescriptors, HwiError> {
+        let mut command = self.command();
+        command.arg("getdescriptors");
+        if let Some(account) = account {
+            command.args(["--account", &account.to_string()]);
+        }
+        run(&mut command)
+    }
+
+    /// Return a copy of `psbt` signed by the device.
+    pub fn sign_psbt(&self, psbt: &Psbt) -> Result<Psbt, HwiError> {
+        let mut command = self.command();
+        command.args(["signtx", &psbt.to_string()]);
+        let result: SignTxResult = run(&mut command)?;
+        let signed = Psbt::from_str(&result.psbt).map_err(HwiError::Psbt)?;
+        if signed.unsigned_tx != psbt.unsigned_tx || signed.inputs.len() != psbt.inputs.len() {
+            return Err(HwiError::UnexpectedPsbt);
+        }
+        Ok(signed)
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.command);
+        command.args(["--fingerprint", &self.fingerprint.to_string()]);
+        if let Some(network) = self.chain {
+            let chain = match network {
+                Network::Bitcoin => "main",
+                Network::Signet => "signet",
+                Network::Regtest => "regtest",
+                _ => "test",
+            };
+            command.args(["--chain", chain]);
+        }
+        command
+    }
+
+    /// Whether one of the inputs of `psbt` has a key derived from the master key of the device.
+    fn has_keys_for(&self, psbt: &Psbt) -> bool {
+        psbt.inputs.iter().any(|input| {
+            input
+                .bip32_derivation
+                .values()
+                .any(|(fingerprint, _)| *fingerprint == self.fingerprint)
+                || input
+                    .tap_key_origins
+                    .values()
+                    .any(|(_, (fingerprint, _))| *fingerprint == self.fingerprint)
+        })
+    }
+}
+
+/// Run `command`, parsing its output as either the result `T` or a failure.
+fn run<T: DeserializeOwned>(command: &mut Command) -> Result<T, HwiError> {
+    let output = command.output().map_err(HwiError::Io)?;
+    if let Ok(failure) = serde_json::from_slice::<HwiFailure>(&output.stdout) {
+        return Err(HwiError::Hwi {
+            code: failure.code,
+            message: failure.error,
+        });
+    }
+    if !output.status.success() {
+        return Err(HwiError::ExitStatus {
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    serde_json::from_slice(&output.stdout).map_err(HwiError::Json)
+}
+
+impl SignerCommon for HwiSigner {
+    fn id(&self, _secp: &SecpCtx) -> SignerId {
+        SignerId::from(self.fingerprint)
+    }
+}
+
+impl TransactionSigner for HwiSigner {
+    fn sign_transaction(
+        &self,
+        psbt: &mut Psbt,
+        _sign_options: &SignOptions,
+        _secp: &SecpCtx,
+    ) -> Result<(), SignerError> {
+        if !self.has_keys_for(psbt) {
+            return Ok(());
+        }
+
+        let signed = self.sign_psbt(psbt).map_err(SignerError::Hwi)?;
+        for (input, signed) in psbt.inputs.iter_mut().zip(signed.inputs) {
+            if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+                continue;
+            }
+            input.partial_sigs.extend(signed.partial_sigs);
+            input.tap_script_sigs.extend(signed.tap_script_sigs);
+            if input.tap_key_sig.is_none() {
+                input.tap_key_sig = signed.tap_key_sig;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned by the [`HwiSigner`].
+#[derive(Debug)]
+pub enum HwiError {
+    /// The program couldn't be run.
+    Io(std::io::Error),
+    /// The program returned an error.
+    Hwi {
+        /// The error code
+        code: i64,
+        /// The error message
+        message: String,
+    },
+    /// The program failed without returning an error.
+    ExitStatus {
+        /// The exit code of the program, if it wasn't terminated by a signal
+        code: Option<i32>,
+        /// The standard error of the program
+        stderr: String,
+    },
+    /// The output of the program is not the expected JSON object.
+    Json(serde_json::Error),
+    /// The PSBT returned by the program is invalid.
+    Psbt(PsbtParseError),
+    /// The PSBT returned by the program is for another transaction.
+    UnexpectedPsbt,
+}
+
+impl fmt::Display for HwiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "Unable to run the external signer: {}", err),
+            Self::Hwi { code, message } => {
+                write!(f, "External signer error {}: {}", code, message)
+            }
+            Self::ExitStatus { code, stderr } => match code {
+                Some(code) => write!(f, "External signer exited with code {}: {}", code, stderr),
+                None => write!(f, "External signer terminated by a signal: {}", stderr),
+            },
+            Self::Json(err) => write!(f, "Invalid external signer output: {}", err),
+            Self::Psbt(err) => write!(f, "Invalid PSBT returned by the external signer: {}", err),
+            Self::UnexpectedPsbt => write!(
+                f,
+                "The external signer returned a PSBT for another transaction"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HwiError {}
@@ -0,0 +1,1273 @@
+//! MuSig2 multi-signatures
+//!
+//! This module implements [BIP327] MuSig2 for taproot key path spends: the participants aggregate
+//! their public keys into a single key, and then produce together a single Schnorr signature for
+//! it, in two rounds of communication.
+//!
+//! Aggregate keys are written `musig(KEY,KEY,...)` in descriptors, as specified by [BIP390]. They
+//! can be used in `tr()` descriptors, either on their own or followed by unhardened derivation
+//! steps, which are applied to the synthetic extended public key of [BIP328]. For instance:
+//!
+//! ```text
+//! tr(musig(02...,03...)/0/*)
+//! ```
+//!
+//! Since `musig()` keys are not supported by [`miniscript`], they are replaced by the key they
+//! aggregate to when the descriptor is parsed: the synthetic extended key when followed by
+//! derivation steps, the x-only aggregate key otherwise.
+//!
+//! The [`MusigSigner`] of every participant exchanges nonces and partial signatures through the
+//! PSBT fields of [BIP373]. Each participant signs the PSBT twice: the first time to add its public
+//! nonce, and the second time, once the nonces of all participants are in the PSBT, to add its
+//! partial signature. The partial signatures are aggregated into the taproot key path signature by
+//! the first signer that finds all of them in the PSBT.
+//!
+//! [BIP327]: https://github.com/bitcoin/bips/blob/master/bip-0327.mediawiki
+//! [BIP328]: https://github.com/bitcoin/bips/blob/master/bip-0328.mediawiki
+//! [BIP373]: https://github.com/bitcoin/bips/blob/master/bip-0373.mediawiki
+//! [BIP390]: https://github.com/bitcoin/bips/blob/master/bip-0390.mediawiki
+
+// The signing session is only used by the `MusigSigner`, which requires `std`
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::bip32::{ChainCode, ChildNumber, Fingerprint, Xpub};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{
+    constants::CURVE_ORDER, schnorr, Parity, PublicKey, Scalar, Secp256k1, SecretKey, Signing,
+    Verification, XOnlyPublicKey,
+};
+use bitcoin::NetworkKind;
+use miniscript::descriptor::DescriptorPublicKey;
+use rand_core::RngCore;
+
+#[cfg(feature = "std")]
+pub use self::signer::MusigSigner;
+
+const KEYAGG_LIST_TAG: &str = "KeyAgg list";
+const KEYAGG_COEFFICIENT_TAG: &str = "KeyAgg coefficient";
+const AUX_TAG: &str = "MuSig/aux";
+const NONCE_TAG: &str = "MuSig/nonce";
+const NONCECOEF_TAG: &str = "MuSig/noncecoef";
+const CHALLENGE_TAG: &str = "BIP0340/challenge";
+
+/// The chain code of the synthetic extended public key of an aggregate key, defined by BIP328.
+const SYNTHETIC_CHAIN_CODE: [u8; 32] = [
+    0x86, 0x80, 0x87, 0xca, 0x02, 0xa6, 0xf9, 0x74, 0xc4, 0x59, 0x89, 0x24, 0xc3, 0x6b, 0x57, 0x76,
+    0x2d, 0x32, 0xcb, 0x45, 0x71, 0x71, 0x67, 0xe3, 0x00, 0x62, 0x2c, 0x71, 0x67, 0xe3, 0x89, 0x65,
+];
+
+/// The public nonce of a participant: the two points `R1` and `R2`, serialized in compressed form.
+type PublicNonce = [u8; 66];
+
+/// A MuSig2 aggregate key, made of the public keys of its participants.
+///
+/// The order of the participants matters: the same keys in another order aggregate to another
+/// key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MusigKey {
+    participants: Vec<PublicKey>,
+    aggregate_key: PublicKey,
+}
+
+impl MusigKey {
+    /// Aggregate the public keys of the `participants`.
+    ///
+    /// The participants must be distinct, as they are identified by their public key in the PSBT
+    /// fields used to sign.
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        participants: Vec<PublicKey>,
+    ) -> Result<Self, MusigError> {
+        if participants.is_empty() {
+            return Err(MusigError::NoParticipants);
+        }
+        for (i, participant) in participants.iter().enumerate() {
+            if participants[..i].contains(participant) {
+                return Err(MusigError::DuplicateParticipant(*participant));
+            }
+        }
+
+        let list_hash = list_hash(&participants);
+        let aggregate_key = participants.iter().enumerate().fold(None, |sum, (i, key)| {
+            let coefficient = coefficient(&list_hash, i, key);
+            point_add(sum, point_mul(secp, Some(*key), coefficient))
+        });
+        Ok(MusigKey {
+            aggregate_key: aggregate_key.ok_or(MusigError::InvalidAggregateKey)?,
+            participants,
+        })
+    }
+
+    /// Return the public keys of the participants.
+    pub fn participants(&self) -> &[PublicKey] {
+        &self.participants
+    }
+
+    /// Return the aggregate key, before any tweak.
+    pub fn aggregate_key(&self) -> PublicKey {
+        self.aggregate_key
+    }
+
+    /// Return the synthetic extended public key of the aggregate key, from which keys are derived
+    /// by the `musig(KEY,KEY,...)/NUM/...` expressions of descriptors.
+    pub fn synthetic_xpub(&self, network: impl Into<NetworkKind>) -> Xpub {
+        Xpub {
+            network: network.into(),
+            depth: 0,
+            parent_fingerprint: Fingerprint::from([0; 4]),
+            child_number: ChildNumber::Normal { index: 0 },
+            public_key: self.aggregate_key,
+            chain_code: ChainCode::from(SYNTHETIC_CHAIN_CODE),
+        }
+    }
+
+    /// Return the coefficient of the participant with the given public key, or `None` if it isn't
+    /// a participant.
+    fn coefficient(&self, public_key: &PublicKey) -> Option<Option<SecretKey>> {
+        let index = self.participants.iter().position(|key| key == public_key)?;
+        Some(coefficient(
+            &list_hash(&self.participants),
+            index,
+            public_key,
+        ))
+    }
+}
+
+impl fmt::Display for MusigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "musig(")?;
+        for (i, key) in self.participants.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", key)?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn list_hash(participants: &[PublicKey]) -> [u8; 32] {
+    let keys = participants
+        .iter()
+        .flat_map(|key| key.serialize())
+        .collect::<Vec<_>>();
+    tagged_hash(KEYAGG_LIST_TAG, &[&keys])
+}
+
+/// Compute the coefficient of the `index`-th participant.
+///
+/// Since the participants are distinct, the "second key" of BIP327, whose coefficient is one, is
+/// the second participant.
+fn coefficient(list_hash: &[u8; 32], index: usize, key: &PublicKey) -> Option<SecretKey> {
+    if index == 1 {
+        return Some(SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).expect("one is valid"));
+    }
+    hash_to_scalar(KEYAGG_COEFFICIENT_TAG, &[list_hash, &key.serialize()])
+}
+
+/// Replace the `musig()` expressions of `descriptor` with the keys they aggregate to.
+///
+/// Expressions followed by derivation steps are replaced with the synthetic extended public key
+/// of the aggregate key, the others with the x-only aggregate key.
+pub(crate) fn expand_musig_keys<C: Verification>(
+    secp: &Secp256k1<C>,
+    descriptor: &str,
+    network: NetworkKind,
+) -> Result<String, MusigError> {
+    const PREFIX: &str = "musig(";
+
+    if descriptor.contains(PREFIX) && !descriptor.starts_with("tr(") {
+        return Err(MusigError::NotTaproot);
+    }
+    let mut expanded = String::with_capacity(descriptor.len());
+    let mut rest = descriptor;
+    while let Some(start) = rest.find(PREFIX) {
+        expanded.push_str(&rest[..start]);
+        let keys_start = start + PREFIX.len();
+        let keys_end = match rest[keys_start..].find(')') {
+            Some(len) => keys_start + len,
+            None => return Err(MusigError::InvalidExpression(rest[start..].to_string())),
+        };
+        let participants = rest[keys_start..keys_end]
+            .split(',')
+            .map(|key| parse_participant(secp, key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = MusigKey::new(secp, participants)?;
+
+        rest = &rest[keys_end + 1..];
+        if rest.starts_with('/') {
+            expanded.push_str(&key.synthetic_xpub(network).to_string());
+        } else {
+            expanded.push_str(&key.aggregate_key.x_only_public_key().0.to_string());
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Parse a participant of a `musig()` expression, which must be a public key without wildcard.
+fn parse_participant<C: Verification>(
+    secp: &Secp256k1<C>,
+    key: &str,
+) -> Result<PublicKey, MusigError> {
+    let invalid = || MusigError::InvalidExpression(key.to_string());
+    let key = DescriptorPublicKey::from_str(key).map_err(|_| invalid())?;
+    if key.has_wildcard() || key.is_multipath() {
+        return Err(invalid());
+    }
+    let key = key
+        .at_derivation_index(0)
+        .map_err(|_| invalid())?
+        .derive_public_key(secp)
+        .map_err(|_| invalid())?;
+    Ok(key.inner)
+}
+
+// Scalars are represented by `Option<SecretKey>` and points by `Option<PublicKey>`, `None` being
+// respectively zero and the point at infinity.
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for data in data {
+        engine.input(data);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Compute a tagged hash, as an integer modulo the curve order.
+fn hash_to_scalar(tag: &str, data: &[&[u8]]) -> Option<SecretKey> {
+    let mut bytes = tagged_hash(tag, data);
+    // the curve order is above 2^255, so the hash is reduced with a single subtraction
+    if bytes >= CURVE_ORDER {
+        let mut borrow = false;
+        for (byte, order_byte) in bytes.iter_mut().zip(CURVE_ORDER).rev() {
+            let (diff, borrow1) = byte.overflowing_sub(order_byte);
+            let (diff, borrow2) = diff.overflowing_sub(borrow as u8);
+            *byte = diff;
+            borrow = borrow1 || borrow2;
+        }
+    }
+    SecretKey::from_slice(&bytes).ok()
+}
+
+fn scalar_add(a: Option<SecretKey>, b: Option<SecretKey>) -> Option<SecretKey> {
+    match (a, b) {
+        // the tweak is only invalid if the sum is zero
+        (Some(a), Some(b)) => a.add_tweak(&Scalar::from(b)).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn scalar_mul(a: Option<SecretKey>, b: Option<SecretKey>) -> Option<SecretKey> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(
+            a.mul_tweak(&Scalar::from(b))
+                .expect("the product of non-zero scalars is non-zero"),
+        ),
+        _ => None,
+    }
+}
+
+fn scalar_bytes(scalar: Option<SecretKey>) -> [u8; 32] {
+    scalar.map_or([0; 32], |scalar| scalar.secret_bytes())
+}
+
+/// Parse a scalar, which must be below the curve order.
+fn parse_scalar(bytes: &[u8]) -> Result<Option<SecretKey>, ()> {
+    if bytes.len() == 32 && bytes.iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+    SecretKey::from_slice(bytes).map(Some).map_err(|_| ())
+}
+
+fn point_add(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        // combining fails if the sum is the point at infinity
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn point_mul<C: Verification>(
+    secp: &Secp256k1<C>,
+    point: Option<PublicKey>,
+    scalar: Option<SecretKey>,
+) -> Option<PublicKey> {
+    match (point, scalar) {
+        (Some(point), Some(scalar)) => Some(
+            point
+                .mul_tweak(secp, &Scalar::from(scalar))
+                .expect("the product of a point and a non-zero scalar is a point"),
+        ),
+        _ => None,
+    }
+}
+
+fn has_even_y(point: &PublicKey) -> bool {
+    point.x_only_public_key().1 == Parity::Even
+}
+
+fn parse_public_nonce(bytes: &[u8]) -> Option<(PublicKey, PublicKey)> {
+    if bytes.len() != 66 {
+        return None;
+    }
+    Some((
+        PublicKey::from_slice(&bytes[..33]).ok()?,
+        PublicKey::from_slice(&bytes[33..]).ok()?,
+    ))
+}
+
+/// The aggregate key of a [`MusigKey`] after the tweaks applied to it: the derivation steps from
+/// its synthetic extended key, and the taproot tweak.
+#[derive(Debug, Clone, Copy)]
+struct KeyAggContext {
+    /// The tweaked aggregate key `Q`
+    output_key: PublicKey,
+    /// Whether the accumulated sign `gacc` is negative
+    negated: bool,
+    /// The accumulated tweak `tacc`
+    tweak: Option<SecretKey>,
+}
+
+impl KeyAggContext {
+    fn new(key: &MusigKey) -> Self {
+        KeyAggContext {
+            output_key: key.aggregate_key,
+            negated: false,
+            tweak: None,
+        }
+    }
+
+    /// Apply a plain tweak, as done by BIP32 derivation, or an x-only tweak, as done by taproot.
+    fn apply_tweak<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        tweak: Scalar,
+        x_only: bool,
+    ) -> Result<(), MusigError> {
+        let negate = x_only && !has_even_y(&self.output_key);
+        let output_key = if negate {
+            self.output_key.negate(secp)
+        } else {
+            self.output_key
+        };
+        self.output_key = output_key
+            .add_exp_tweak(secp, &tweak)
+            .map_err(|_| MusigError::InvalidTweak)?;
+        self.negated ^= negate;
+        let accumulated = if negate {
+            self.tweak.map(|tweak| tweak.negate())
+        } else {
+            self.tweak
+        };
+        self.tweak = scalar_add(
+            SecretKey::from_slice(&tweak.to_be_bytes()).ok(),
+            accumulated,
+        );
+        Ok(())
+    }
+
+    /// Whether the secret keys of the participants are negated to sign, i.e. `g⋅gacc = -1`.
+    fn negates_secret_keys(&self) -> bool {
+        !has_even_y(&self.output_key) ^ self.negated
+    }
+}
+
+/// The secret nonce of a participant.
+///
+/// It can't be copied, cloned or serialized, and it is consumed by [`SecretNonce::sign`], so that
+/// it is used for a single partial signature.
+struct SecretNonce {
+    k1: SecretKey,
+    k2: SecretKey,
+    public_key: PublicKey,
+}
+
+impl fmt::Debug for SecretNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretNonce")
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecretNonce {
+    /// Generate a nonce to sign `msg` for `output_key` with `secret_key`.
+    fn generate<C: Signing>(
+        secp: &Secp256k1<C>,
+        rng: &mut impl RngCore,
+        secret_key: &SecretKey,
+        output_key: &XOnlyPublicKey,
+        msg: &[u8; 32],
+    ) -> (Self, PublicNonce) {
+        let mut rand = [0; 32];
+        rng.fill_bytes(&mut rand);
+        let aux = tagged_hash(AUX_TAG, &[&rand]);
+        for ((byte, key_byte), aux_byte) in rand.iter_mut().zip(secret_key.secret_bytes()).zip(aux)
+        {
+            *byte = key_byte ^ aux_byte;
+        }
+
+        let public_key = secret_key.public_key(secp);
+        let k = |i: u8| {
+            hash_to_scalar(
+                NONCE_TAG,
+                &[
+                    &rand,
+                    &[33],
+                    &public_key.serialize(),
+                    &[32],
+                    &output_key.serialize(),
+                    &[1],
+                    &32u64.to_be_bytes(),
+                    msg,
+                    &0u32.to_be_bytes(),
+                    &[i],
+                ],
+            )
+            .expect("the probability of a zero nonce is negligible")
+        };
+        let (k1, k2) = (k(0), k(1));
+
+        let mut public_nonce = [0; 66];
+        public_nonce[..33].copy_from_slice(&k1.public_key(secp).serialize());
+        public_nonce[33..].copy_from_slice(&k2.public_key(secp).serialize());
+        (SecretNonce { k1, k2, public_key }, public_nonce)
+    }
+
+    /// Produce the partial signature of the participant with `secret_key`, consuming the nonce.
+    fn sign<C: Signing + Verification>(
+        self,
+        secp: &Secp256k1<C>,
+        secret_key: &SecretKey,
+        key: &MusigKey,
+        ctx: &KeyAggContext,
+        session: &Session,
+    ) -> Result<[u8; 32], MusigError> {
+        let public_key = secret_key.public_key(secp);
+        let coefficient = match key.coefficient(&public_key) {
+            Some(coefficient) if public_key == self.public_key => coefficient,
+            _ => return Err(MusigError::UnknownParticipant(public_key)),
+        };
+        let (k1, k2) = if has_even_y(&session.nonce) {
+            (self.k1, self.k2)
+        } else {
+            (self.k1.negate(), self.k2.negate())
+        };
+        let secret_key = if ctx.negates_secret_keys() {
+            secret_key.negate()
+        } else {
+            *secret_key
+        };
+
+        let partial_sig = scalar_add(
+            scalar_add(Some(k1), scalar_mul(session.b, Some(k2))),
+            scalar_mul(scalar_mul(session.e, coefficient), Some(secret_key)),
+        );
+        Ok(scalar_bytes(partial_sig))
+    }
+}
+
+/// The values of a signing session, common to all the participants.
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    /// The nonce coefficient `b`
+    b: Option<SecretKey>,
+    /// The final nonce `R`
+    nonce: PublicKey,
+    /// The challenge `e`
+    e: Option<SecretKey>,
+}
+
+impl Session {
+    fn new<C: Signing + Verification>(
+        secp: &Secp256k1<C>,
+        ctx: &KeyAggContext,
+        nonces: &[(PublicKey, PublicKey)],
+        msg: &[u8; 32],
+    ) -> Self {
+        let r1 = nonces
+            .iter()
+            .fold(None, |sum, (r1, _)| point_add(sum, Some(*r1)));
+        let r2 = nonces
+            .iter()
+            .fold(None, |sum, (_, r2)| point_add(sum, Some(*r2)));
+        let serialize = |point: Option<PublicKey>| point.map_or([0; 33], |point| point.serialize());
+        let output_key = ctx.output_key.x_only_public_key().0.serialize();
+
+        let b = hash_to_scalar(
+            NONCECOEF_TAG,
+            &[&serialize(r1), &serialize(r2), &output_key, msg],
+        );
+        let nonce = point_add(r1, point_mul(secp, r2, b)).unwrap_or_else(|| {
+            // the nonce is the generator if the aggregate nonce is the point at infinity
+            PublicKey::from_secret_key(
+                secp,
+                &SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).expect("one is valid"),
+            )
+        });
+        let e = hash_to_scalar(
+            CHALLENGE_TAG,
+            &[&nonce.x_only_public_key().0.serialize(), &output_key, msg],
+        );
+        Session { b, nonce, e }
+    }
+
+    /// Verify the partial signature of the participant with `public_key` and `public_nonce`.
+    fn verify<C: Signing + Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        key: &MusigKey,
+        ctx: &KeyAggContext,
+        public_key: &PublicKey,
+        public_nonce: (PublicKey, PublicKey),
+        partial_sig: Option<SecretKey>,
+    ) -> bool {
+        let coefficient = match key.coefficient(public_key) {
+            Some(coefficient) => coefficient,
+            None => return false,
+        };
+        let nonce = point_add(
+            Some(public_nonce.0),
+            point_mul(secp, Some(public_nonce.1), self.b),
+        );
+        let nonce = if has_even_y(&self.nonce) {
+            nonce
+        } else {
+            nonce.map(|nonce| nonce.negate(secp))
+        };
+        let challenge = scalar_mul(self.e, coefficient);
+        let challenge = if ctx.negates_secret_keys() {
+            challenge.map(|challenge| challenge.negate())
+        } else {
+            challenge
+        };
+        let expected = point_add(nonce, point_mul(secp, Some(*public_key), challenge));
+        partial_sig.map(|partial_sig| partial_sig.public_key(secp)) == expected
+    }
+
+    /// Aggregate the partial signatures of all the participants into a signature for the output
+    /// key.
+    fn aggregate(
+        &self,
+        ctx: &KeyAggContext,
+        partial_sigs: &[Option<SecretKey>],
+    ) -> schnorr::Signature {
+        let tweak = scalar_mul(self.e, ctx.tweak);
+        let tweak = if has_even_y(&ctx.output_key) {
+            tweak
+        } else {
+            tweak.map(|tweak| tweak.negate())
+        };
+        let s = partial_sigs
+            .iter()
+            .fold(tweak, |sum, partial_sig| scalar_add(sum, *partial_sig));
+
+        let mut signature = [0; 64];
+        signature[..32].copy_from_slice(&self.nonce.x_only_public_key().0.serialize());
+        signature[32..].copy_from_slice(&scalar_bytes(s));
+        schnorr::Signature::from_slice(&signature).expect("signatures are 64 bytes")
+    }
+}
+
+#[cfg(feature = "std")]
+mod signer {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use bitcoin::key::TweakedPublicKey;
+    use bitcoin::psbt::{raw, Psbt};
+    use bitcoin::secp256k1::Message;
+    use bitcoin::taproot::{self, TapTweakHash};
+    use bitcoin::ScriptBuf;
+
+    use super::*;
+    use crate::psbt::PsbtUtils;
+    use crate::wallet::signer::{
+        compute_tap_sighash, InputSigner, SignOptions, SignerCommon, SignerError, SignerId,
+    };
+    use crate::wallet::utils::SecpCtx;
+
+    const PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS: u8 = 0x1a;
+    const PSBT_IN_MUSIG2_PUB_NONCE: u8 = 0x1b;
+    const PSBT_IN_MUSIG2_PARTIAL_SIG: u8 = 0x1c;
+
+    /// Maximum number of secret nonces kept by a [`MusigSigner`] waiting for the second round
+    const MAX_SECRET_NONCES: usize = 64;
+
+    /// Signs the taproot key path of the inputs spending a [`MusigKey`], as one of its
+    /// participants.
+    ///
+    /// Nonces and partial signatures are exchanged with the other participants through the PSBT
+    /// fields of BIP373, so every participant has to sign the PSBT twice: see the
+    /// [module documentation](super).
+    ///
+    /// The secret nonces are generated from the thread random number generator, and never leave
+    /// the signer: each of them is dropped as soon as it is used to sign. Signing again a PSBT
+    /// holding a public nonce whose secret nonce was already used, or was generated by another
+    /// signer, fails with [`MusigError::MissingSecretNonce`] instead of reusing it.
+    ///
+    /// Secret nonces whose second round never happens are eventually dropped: a new nonce for the
+    /// same sighash replaces the previous one, and only the 64 most recent ones are kept.
+    #[derive(Debug)]
+    pub struct MusigSigner {
+        secret_key: SecretKey,
+        key: MusigKey,
+        nonces: Mutex<VecDeque<(PublicNonce, [u8; 32], SecretNonce)>>,
+    }
+
+    impl MusigSigner {
+        /// Create a new signer for the participant of `key` with `secret_key`.
+        pub fn new<C: Signing>(
+            secp: &Secp256k1<C>,
+            secret_key: SecretKey,
+            key: MusigKey,
+        ) -> Result<Self, MusigError> {
+            let public_key = secret_key.public_key(secp);
+            if !key.participants.contains(&public_key) {
+                return Err(MusigError::UnknownParticipant(public_key));
+            }
+            Ok(MusigSigner {
+                secret_key,
+                key,
+                nonces: Mutex::new(VecDeque::new()),
+            })
+        }
+
+        /// Return the aggregate key signed for.
+        pub fn key(&self) -> &MusigKey {
+            &self.key
+        }
+
+        /// Return the internal key and the context of the output key spent by the input
+        /// `input_index` of `psbt`, if its internal key is the aggregate key or is derived from it.
+        fn key_agg_context(
+            &self,
+            psbt: &Psbt,
+            input_index: usize,
+            secp: &SecpCtx,
+        ) -> Result<Option<(PublicKey, KeyAggContext)>, SignerError> {
+            let psbt_input = &psbt.inputs[input_index];
+            let internal_key = match psbt_input.tap_internal_key {
+                Some(internal_key) => internal_key,
+                None => return Ok(None),
+            };
+
+            let mut ctx = KeyAggContext::new(&self.key);
+            if internal_key != self.key.aggregate_key.x_only_public_key().0 {
+                let mut xpub = self.key.synthetic_xpub(NetworkKind::Main);
+                let path = match psbt_input.tap_key_origins.get(&internal_key) {
+                    Some((_, (fingerprint, path))) if *fingerprint == xpub.fingerprint() => path,
+                    _ => return Ok(None),
+                };
+                for child in path {
+                    let (tweak, _) = xpub
+                        .ckd_pub_tweak(*child)
+                        .map_err(|_| SignerError::InvalidKey)?;
+                    ctx.apply_tweak(secp, Scalar::from(tweak), false)
+                        .map_err(SignerError::Musig)?;
+                    xpub = xpub
+                        .ckd_pub(secp, *child)
+                        .map_err(|_| SignerError::InvalidKey)?;
+                }
+                if ctx.output_key.x_only_public_key().0 != internal_key {
+                    return Err(SignerError::InvalidKey);
+                }
+            }
+            // the BIP373 fields are keyed by the derived key, before the taproot tweak
+            let aggregate_key = ctx.output_key;
+            let tweak = TapTweakHash::from_key_and_tweak(internal_key, psbt_input.tap_merkle_root);
+            ctx.apply_tweak(secp, tweak.to_scalar(), true)
+                .map_err(SignerError::Musig)?;
+
+            let output_key =
+                TweakedPublicKey::dangerous_assume_tweaked(ctx.output_key.x_only_public_key().0);
+            match psbt.get_utxo_for(input_index) {
+                Some(txout) if txout.script_pubkey == ScriptBuf::new_p2tr_tweaked(output_key) => {
+                    Ok(Some((aggregate_key, ctx)))
+                }
+                _ => Ok(None),
+            }
+        }
+
+        /// Return the key of the `type_value` field of `participant` for `aggregate_key` in the
+        /// PSBT inputs.
+        fn field(type_value: u8, participant: &PublicKey, aggregate_key: &PublicKey) -> raw::Key {
+            let mut key = participant.serialize().to_vec();
+            key.extend(aggregate_key.serialize());
+            raw::Key { type_value, key }
+        }
+
+        /// Return the public nonces of all the participants, or `None` if some are missing.
+        fn public_nonces(
+            &self,
+            psbt_input: &bitcoin::psbt::Input,
+            aggregate_key: &PublicKey,
+        ) -> Result<Option<Vec<(PublicKey, PublicKey)>>, MusigError> {
+            let mut nonces = Vec::with_capacity(self.key.participants.len());
+            for participant in &self.key.participants {
+                let nonce = match psbt_input.unknown.get(&Self::field(
+                    PSBT_IN_MUSIG2_PUB_NONCE,
+                    participant,
+                    aggregate_key,
+                )) {
+                    Some(nonce) => nonce,
+                    None => return Ok(None),
+                };
+                nonces.push(
+                    parse_public_nonce(nonce)
+                        .ok_or(MusigError::InvalidPublicNonce(*participant))?,
+                );
+            }
+            Ok(Some(nonces))
+        }
+
+        /// Return the partial signatures of all the participants, or `None` if some are missing.
+        fn partial_sigs(
+            &self,
+            psbt_input: &bitcoin::psbt::Input,
+            aggregate_key: &PublicKey,
+        ) -> Result<Option<Vec<Option<SecretKey>>>, MusigError> {
+            let mut partial_sigs = Vec::with_capacity(self.key.participants.len());
+            for participant in &self.key.participants {
+                let partial_sig = match psbt_input.unknown.get(&Self::field(
+                    PSBT_IN_MUSIG2_PARTIAL_SIG,
+                    participant,
+                    aggregate_key,
+                )) {
+                    Some(partial_sig) => partial_sig,
+                    None => return Ok(None),
+                };
+                partial_sigs.push(
+                    parse_scalar(partial_sig)
+                        .map_err(|_| MusigError::InvalidPartialSignature(*participant))?,
+                );
+            }
+            Ok(Some(partial_sigs))
+        }
+
+        fn sign_musig_input(
+            &self,
+            psbt: &mut Psbt,
+            input_index: usize,
+            aggregate_key: PublicKey,
+            ctx: KeyAggContext,
+            secp: &SecpCtx,
+        ) -> Result<(), SignerError> {
+            let (sighash, sighash_type) = compute_tap_sighash(psbt, input_index, None)?;
+            let msg = sighash.to_byte_array();
+            let public_key = self.secret_key.public_key(secp);
+            let nonce_field = Self::field(PSBT_IN_MUSIG2_PUB_NONCE, &public_key, &aggregate_key);
+            let partial_sig_field =
+                Self::field(PSBT_IN_MUSIG2_PARTIAL_SIG, &public_key, &aggregate_key);
+            let psbt_input = &mut psbt.inputs[input_index];
+
+            psbt_input
+                .unknown
+                .entry(raw::Key {
+                    type_value: PSBT_IN_MUSIG2_PARTICIPANT_PUBKEYS,
+                    key: self.key.aggregate_key.serialize().to_vec(),
+                })
+                .or_insert_with(|| {
+                    self.key
+                        .participants
+                        .iter()
+                        .flat_map(|key| key.serialize())
+                        .collect()
+                });
+            if !psbt_input.unknown.contains_key(&nonce_field)
+                && !psbt_input.unknown.contains_key(&partial_sig_field)
+            {
+                let (secret_nonce, public_nonce) = SecretNonce::generate(
+                    secp,
+                    &mut bitcoin::key::rand::thread_rng(),
+                    &self.secret_key,
+                    &ctx.output_key.x_only_public_key().0,
+                    &msg,
+                );
+                let mut nonces = self.nonces.lock().expect("must not be poisoned");
+                nonces.retain(|(_, nonce_msg, _)| *nonce_msg != msg);
+                if nonces.len() == MAX_SECRET_NONCES {
+                    nonces.pop_front();
+                }
+                nonces.push_back((public_nonce, msg, secret_nonce));
+                psbt_input
+                    .unknown
+                    .insert(nonce_field.clone(), public_nonce.to_vec());
+            }
+
+            // the second round starts once all the participants added their nonce
+            let nonces = match self
+                .public_nonces(psbt_input, &aggregate_key)
+                .map_err(SignerError::Musig)?
+            {
+                Some(nonces) => nonces,
+                None => return Ok(()),
+            };
+            let session = Session::new(secp, &ctx, &nonces, &msg);
+            if !psbt_input.unknown.contains_key(&partial_sig_field) {
+                let public_nonce = PublicNonce::try_from(
+                    psbt_input.unknown[&nonce_field].as_slice(),
+                )
+                .map_err(|_| SignerError::Musig(MusigError::InvalidPublicNonce(public_key)))?;
+                let secret_nonce = {
+                    let mut nonces = self.nonces.lock().expect("must not be poisoned");
+                    nonces
+                        .iter()
+                        .position(|(nonce, nonce_msg, _)| {
+                            *nonce == public_nonce && *nonce_msg == msg
+                        })
+                        .and_then(|index| nonces.remove(index))
+                        .map(|(_, _, secret_nonce)| secret_nonce)
+                        .ok_or(SignerError::Musig(MusigError::MissingSecretNonce))?
+                };
+                let partial_sig = secret_nonce
+                    .sign(secp, &self.secret_key, &self.key, &ctx, &session)
+                    .map_err(SignerError::Musig)?;
+                psbt_input
+                    .unknown
+                    .insert(partial_sig_field, partial_sig.to_vec());
+            }
+
+            // aggregate the partial signatures once all the participants added theirs
+            let partial_sigs = match self
+                .partial_sigs(psbt_input, &aggregate_key)
+                .map_err(SignerError::Musig)?
+            {
+                Some(partial_sigs) => partial_sigs,
+                None => return Ok(()),
+            };
+            for ((participant, nonce), partial_sig) in
+                self.key.participants.iter().zip(nonces).zip(&partial_sigs)
+            {
+                if !session.verify(secp, &self.key, &ctx, participant, nonce, *partial_sig) {
+                    return Err(SignerError::Musig(MusigError::InvalidPartialSignature(
+                        *participant,
+                    )));
+                }
+            }
+            let signature = session.aggregate(&ctx, &partial_sigs);
+            secp.verify_schnorr(
+                &signature,
+                &Message::from(sighash),
+                &ctx.output_key.x_only_public_key().0,
+            )
+            .expect("invalid or corrupted schnorr signature");
+            psbt_input.tap_key_sig = Some(taproot::Signature {
+                signature,
+                sighash_type,
+            });
+            Ok(())
+        }
+    }
+
+    impl SignerCommon for MusigSigner {
+        fn id(&self, secp: &SecpCtx) -> SignerId {
+            let public_key = bitcoin::PublicKey::new(self.secret_key.public_key(secp));
+            SignerId::from(public_key.pubkey_hash().to_raw_hash())
+        }
+    }
+
+    impl InputSigner for MusigSigner {
+        fn sign_input(
+            &self,
+            psbt: &mut Psbt,
+            input_index: usize,
+            sign_options: &SignOptions,
+            secp: &SecpCtx,
+        ) -> Result<(), SignerError> {
+            let psbt_input = psbt
+                .inputs
+                .get(input_index)
+                .ok_or(SignerError::InputIndexOutOfRange)?;
+            if psbt_input.final_script_sig.is_some()
+                || psbt_input.final_script_witness.is_some()
+                || psbt_input.tap_key_sig.is_some()
+                || !sign_options.sign_with_tap_internal_key
+            {
+                return Ok(());
+            }
+
+            match self.key_agg_context(psbt, input_index, secp)? {
+                Some((aggregate_key, ctx)) => {
+                    self.sign_musig_input(psbt, input_index, aggregate_key, ctx, secp)
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+/// Errors related to MuSig2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MusigError {
+    /// The aggregate key has no participant.
+    NoParticipants,
+    /// The same participant appears twice in the aggregate key.
+    DuplicateParticipant(PublicKey),
+    /// The key is not a participant of the aggregate key.
+    UnknownParticipant(PublicKey),
+    /// The keys of the participants aggregate to the point at infinity.
+    InvalidAggregateKey,
+    /// Tweaking the aggregate key results in the point at infinity.
+    InvalidTweak,
+    /// The public nonce of the participant in the PSBT is invalid.
+    InvalidPublicNonce(PublicKey),
+    /// The partial signature of the participant in the PSBT is invalid.
+    InvalidPartialSignature(PublicKey),
+    /// The secret nonce of our public nonce in the PSBT is not available, because it was already
+    /// used or generated by another signer.
+    MissingSecretNonce,
+    /// Invalid `musig()` expression.
+    InvalidExpression(String),
+    /// `musig()` expressions are only supported in `tr()` descriptors.
+    NotTaproot,
+}
+
+impl fmt::Display for MusigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoParticipants => write!(f, "The aggregate key has no participant"),
+            Self::DuplicateParticipant(key) => {
+                write!(f, "Participant {} appears twice in the aggregate key", key)
+            }
+            Self::UnknownParticipant(key) => {
+                write!(f, "{} is not a participant of the aggregate key", key)
+            }
+            Self::InvalidAggregateKey => write!(f, "Invalid aggregate key"),
+            Self::InvalidTweak => write!(f, "Invalid tweak of the aggregate key"),
+            Self::InvalidPublicNonce(key) => {
+                write!(f, "Invalid public nonce of participant {}", key)
+            }
+            Self::InvalidPartialSignature(key) => {
+                write!(f, "Invalid partial signature of participant {}", key)
+            }
+            Self::MissingSecretNonce => write!(
+                f,
+                "The secret nonce is not available, it was already used or generated by another signer"
+            ),
+            Self::InvalidExpression(expression) => {
+                write!(f, "Invalid musig() expression: {}", expression)
+            }
+            Self::NotTaproot => write!(f, "musig() is only supported in tr() descriptors"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MusigError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bitcoin::hex::FromHex;
+    use bitcoin::secp256k1::{self, Message};
+
+    fn public_key(hex: &str) -> PublicKey {
+        PublicKey::from_str(hex).unwrap()
+    }
+
+    fn bip327_keys() -> [PublicKey; 3] {
+        [
+            public_key("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9"),
+            public_key("03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659"),
+            public_key("023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66"),
+        ]
+    }
+
+    #[test]
+    fn test_key_agg() {
+        let secp = Secp256k1::verification_only();
+        let [x0, x1, x2] = bip327_keys();
+        let key = MusigKey::new(&secp, vec![x0, x1, x2]).unwrap();
+        assert_eq!(
+            key.aggregate_key().x_only_public_key().0.to_string(),
+            "90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c"
+        );
+        let key = MusigKey::new(&secp, vec![x2, x1, x0]).unwrap();
+        assert_eq!(
+            key.aggregate_key().x_only_public_key().0.to_string(),
+            "6204de8b083426dc6eaf9502d27024d53fc826bf7d2012148a0575435df54b2b"
+        );
+
+        assert_eq!(
+            MusigKey::new(&secp, vec![x0, x1, x0]),
+            Err(MusigError::DuplicateParticipant(x0))
+        );
+        assert_eq!(
+            MusigKey::new(&secp, vec![]),
+            Err(MusigError::NoParticipants)
+        );
+    }
+
+    #[test]
+    fn test_expand_musig_keys() {
+        let secp = Secp256k1::verification_only();
+        let [x0, x1, x2] = bip327_keys();
+        let key = MusigKey::new(&secp, vec![x0, x1, x2]).unwrap();
+
+        assert_eq!(
+            expand_musig_keys(&secp, &format!("tr({})", key), NetworkKind::Main).unwrap(),
+            "tr(90539eede565f5d054f32cc0c220126889ed1e5d193baf15aef344fe59d4610c)"
+        );
+        let xpub = key.synthetic_xpub(NetworkKind::Test);
+        assert_eq!(
+            expand_musig_keys(&secp, &format!("tr({}/0/*)", key), NetworkKind::Test).unwrap(),
+            format!("tr({}/0/*)", xpub)
+        );
+        assert_eq!(
+            expand_musig_keys(&secp, &format!("wpkh({})", key), NetworkKind::Main),
+            Err(MusigError::NotTaproot)
+        );
+        assert!(matches!(
+            expand_musig_keys(
+                &secp,
+                &format!("tr(musig({},{}/*))", x0, x1),
+                NetworkKind::Main
+            ),
+            Err(MusigError::InvalidExpression(_))
+        ));
+    }
+
+    #[test]
+    fn test_sign() {
+        let secp = Secp256k1::new();
+        let secret_keys =
+            [[1; 32], [2; 32], [3; 32]].map(|bytes| SecretKey::from_slice(&bytes).unwrap());
+        let key = MusigKey::new(
+            &secp,
+            secret_keys.iter().map(|sk| sk.public_key(&secp)).collect(),
+        )
+        .unwrap();
+        let mut ctx = KeyAggContext::new(&key);
+        ctx.apply_tweak(&secp, Scalar::from_be_bytes([7; 32]).unwrap(), false)
+            .unwrap();
+        ctx.apply_tweak(&secp, Scalar::from_be_bytes([9; 32]).unwrap(), true)
+            .unwrap();
+        let output_key = ctx.output_key.x_only_public_key().0;
+        let msg = [42; 32];
+
+        let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) = secret_keys
+            .iter()
+            .map(|sk| SecretNonce::generate(&secp, &mut rand::thread_rng(), sk, &output_key, &msg))
+            .unzip();
+        let public_nonces = public_nonces
+            .iter()
+            .map(|nonce| parse_public_nonce(nonce).unwrap())
+            .collect::<Vec<_>>();
+        let session = Session::new(&secp, &ctx, &public_nonces, &msg);
+        let partial_sigs = secret_nonces
+            .into_iter()
+            .zip(&secret_keys)
+            .map(|(nonce, sk)| {
+                let partial_sig = nonce.sign(&secp, sk, &key, &ctx, &session).unwrap();
+                parse_scalar(&partial_sig).unwrap()
+            })
+            .collect::<Vec<_>>();
+        for ((participant, nonce), partial_sig) in key
+            .participants()
+            .iter()
+            .zip(&public_nonces)
+            .zip(&partial_sigs)
+        {
+            assert!(session.verify(&secp, &key, &ctx, participant, *nonce, *partial_sig));
+        }
+        // a partial signature doesn't verify for another participant
+        assert!(!session.verify(
+            &secp,
+            &key,
+            &ctx,
+            &key.participants()[1],
+            public_nonces[1],
+            partial_sigs[0]
+        ));
+
+        let signature = session.aggregate(&ctx, &partial_sigs);
+        secp.verify_schnorr(&signature, &Message::from_digest(msg), &output_key)
+            .unwrap();
+    }
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        Vec::<u8>::from_hex(hex).unwrap()
+    }
+
+    /// Return the secret key, secret nonce, public nonces and message of the BIP327 signing
+    /// vectors
+    fn bip327_signing_inputs() -> (SecretKey, (SecretKey, SecretKey), Vec<Vec<u8>>, [u8; 32]) {
+        let secret_key = SecretKey::from_slice(&bytes(
+            "7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671",
+        ))
+        .unwrap();
+        let secret_nonce = bytes("508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F7");
+        let secret_nonce = (
+            SecretKey::from_slice(&secret_nonce[..32]).unwrap(),
+            SecretKey::from_slice(&secret_nonce[32..]).unwrap(),
+        );
+        let public_nonces = [
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+            "0237C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0387BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+        ]
+        .map(bytes)
+        .to_vec();
+        let msg = bytes("F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF")
+            .try_into()
+            .unwrap();
+        (secret_key, secret_nonce, public_nonces, msg)
+    }
+
+    /// Sign with the secret key of the BIP327 vectors, which is the first of `public_keys`, and
+    /// check that the partial signature verifies.
+    fn sign_vector(
+        secp: &Secp256k1<secp256k1::All>,
+        public_keys: &[PublicKey],
+        key_indices: &[usize],
+        nonce_indices: &[usize],
+        tweaks: &[(Scalar, bool)],
+        expected: &str,
+    ) {
+        let (secret_key, (k1, k2), public_nonces, msg) = bip327_signing_inputs();
+        let key =
+            MusigKey::new(secp, key_indices.iter().map(|i| public_keys[*i]).collect()).unwrap();
+        let mut ctx = KeyAggContext::new(&key);
+        for (tweak, is_xonly) in tweaks {
+            ctx.apply_tweak(secp, *tweak, *is_xonly).unwrap();
+        }
+        let nonces = nonce_indices
+            .iter()
+            .map(|i| parse_public_nonce(&public_nonces[*i]).unwrap())
+            .collect::<Vec<_>>();
+        let session = Session::new(secp, &ctx, &nonces, &msg);
+        let secret_nonce = SecretNonce {
+            k1,
+            k2,
+            public_key: public_keys[0],
+        };
+        let partial_sig = secret_nonce
+            .sign(secp, &secret_key, &key, &ctx, &session)
+            .unwrap();
+        assert_eq!(partial_sig.to_vec(), bytes(expected));
+
+        let signer_index = key_indices.iter().position(|i| *i == 0).unwrap();
+        assert!(session.verify(
+            secp,
+            &key,
+            &ctx,
+            &public_keys[0],
+            nonces[signer_index],
+            parse_scalar(&partial_sig).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_sign_vectors() {
+        let secp = Secp256k1::new();
+        let public_keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661",
+        ]
+        .map(public_key);
+
+        let vectors: [(&[usize], &[usize], &str); 4] = [
+            (
+                &[0, 1, 2],
+                &[0, 1, 2],
+                "012ABBCB52B3016AC03AD82395A1A415C48B93DEF78718E62A7A90052FE224FB",
+            ),
+            (
+                &[1, 0, 2],
+                &[1, 0, 2],
+                "9FF2F7AAA856150CC8819254218D3ADEEB0535269051897724F9DB3789513A52",
+            ),
+            (
+                &[1, 2, 0],
+                &[1, 2, 0],
+                "FA23C359F6FAC4E7796BB93BC9F0532A95468C539BA20FF86D7C76ED92227900",
+            ),
+            // the aggregate nonce is the point at infinity
+            (
+                &[0, 1],
+                &[0, 3],
+                "AE386064B26105404798F75DE2EB9AF5EDA5387B064B83D049CB7C5E08879531",
+            ),
+        ];
+        for (key_indices, nonce_indices, expected) in vectors {
+            sign_vector(
+                &secp,
+                &public_keys,
+                key_indices,
+                nonce_indices,
+                &[],
+                expected,
+            );
+        }
+    }
+
+    #[test]
+    fn test_tweak_vectors() {
+        let secp = Secp256k1::new();
+        let public_keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        ]
+        .map(public_key);
+        let tweaks = [
+            "E8F791FF9225A2AF0102AFFF4A9A723D9612A682A25EBE79802B263CDFCD83BB",
+            "AE2EA797CC0FE72AC5B97B97F3C6957D7E4199A167A58EB08BCAFFDA70AC0455",
+            "F52ECBC565B3D8BEA2DFD5B75A4F457E54369809322E4120831626F290FA87E0",
+            "1969AD73CC177FA0B4FCED6DF1F7BF9907E665FDE9BA196A74FED0A3CF5AEF9D",
+        ]
+        .map(|tweak| Scalar::from_be_bytes(bytes(tweak).try_into().unwrap()).unwrap());
+
+        let vectors: [(&[bool], &str); 5] = [
+            (
+                &[true],
+                "E28A5C66E61E178C2BA19DB77B6CF9F7E2F0F56C17918CD13135E60CC848FE91",
+            ),
+            (
+                &[false],
+                "38B0767798252F21BF5702C48028B095428320F73A4B14DB1E25DE58543D2D2D",
+            ),
+            (
+                &[false, true],
+                "408A0A21C4A0F5DACAF9646AD6EB6FECD7F7A11F03ED1F48DFFF2185BC2C2408",
+            ),
+            (
+                &[false, false, true, true],
+                "45ABD206E61E3DF2EC9E264A6FEC8292141A633C28586388235541F9ADE75435",
+            ),
+            (
+                &[true, false, true, false],
+                "B255FDCAC27B40C7CE7848E2D3B7BF5EA0ED756DA81565AC804CCCA3E1D5D239",
+            ),
+        ];
+        for (is_xonly, expected) in vectors {
+            let tweaks = tweaks
+                .iter()
+                .copied()
+                .zip(is_xonly.iter().copied())
+                .collect::<Vec<_>>();
+            sign_vector(
+                &secp,
+                &public_keys,
+                &[1, 2, 0],
+                &[1, 2, 0],
+                &tweaks,
+                expected,
+            );
+        }
+    }
+}
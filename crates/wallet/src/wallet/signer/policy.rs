@@ -0,0 +1,276 @@
+//! Signing policies
+//!
+//! This module implements the checks run by [`Wallet::sign`] before producing any signature.
+//! Every [`SigningPolicy`] added with [`Wallet::add_signing_policy`] receives a [`PsbtSummary`] of
+//! the PSBT to sign, which tells which inputs and outputs belong to the wallet, the fee and the
+//! estimated fee rate of the transaction. If any of them returns an error, the PSBT is left
+//! untouched and [`Wallet::sign`] fails with [`SignerError::Policy`].
+//!
+//! The previous outputs of the PSBT are taken from the wallet's transaction graph when it knows
+//! them, and a PSBT whose `witness_utxo` disagrees with its `non_witness_utxo` or with the graph
+//! is rejected with [`SigningPolicyError::InconsistentUtxo`] before running the policies: a
+//! forged `witness_utxo` can't understate the value of the wallet's inputs to lower the fee seen
+//! by [`MaxFee`] and [`MaxFeeRate`].
+//!
+//! The following policies are built-in:
+//!
+//! - [`MaxFee`] rejects transactions paying more than a given absolute fee;
+//! - [`MaxFeeRate`] rejects transactions paying more than a given fee rate;
+//! - [`RejectForeignChange`] rejects transactions with an output that claims to be derived from
+//!   the wallet's keys, but whose script doesn't belong to the wallet. This is only a consistency
+//!   check of the PSBT metadata: an output without key origins is not checked.
+//!
+//! ## Example
+//!
+//! ```
+//! # use core::str::FromStr;
+//! # use std::sync::{Arc, Mutex};
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # use bdk_wallet::signer::SignerError;
+//! use bdk_wallet::signer::policy::{
+//!     MaxFeeRate, PsbtSummary, SigningPolicy, SigningPolicyError,
+//! };
+//!
+//! // A custom policy limiting the total amount sent to foreign scripts
+//! #[derive(Debug)]
+//! struct DailyLimit {
+//!     limit: Amount,
+//!     sent: Mutex<Amount>,
+//! }
+//!
+//! impl SigningPolicy for DailyLimit {
+//!     fn check(&self, psbt: &PsbtSummary) -> Result<(), SigningPolicyError> {
+//!         let mut sent = self.sent.lock().unwrap();
+//!         let total = *sent + psbt.foreign_amount();
+//!         if total > self.limit {
+//!             return Err(SigningPolicyError::Custom("daily limit exceeded".to_string()));
+//!         }
+//!         *sent = total;
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # let mut wallet = doctest_wallet!();
+//! # let to_address = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt").unwrap().assume_checked();
+//! wallet.add_signing_policy(Arc::new(MaxFeeRate(FeeRate::from_sat_per_vb(50).unwrap())));
+//! wallet.add_signing_policy(Arc::new(DailyLimit {
+//!     limit: Amount::from_sat(10_000),
+//!     sent: Mutex::new(Amount::ZERO),
+//! }));
+//!
+//! let mut tx_builder = wallet.build_tx();
+//! tx_builder.add_recipient(to_address.script_pubkey(), Amount::from_sat(50_000));
+//! let mut psbt = tx_builder.finish()?;
+//! assert!(matches!(
+//!     wallet.sign(&mut psbt, SignOptions::default()),
+//!     Err(SignerError::Policy(SigningPolicyError::Custom(_)))
+//! ));
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [`Wallet::sign`]: crate::Wallet::sign
+//! [`Wallet::add_signing_policy`]: crate::Wallet::add_signing_policy
+//! [`SignerError::Policy`]: super::SignerError::Policy
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::{Amount, FeeRate, OutPoint, TxOut};
+
+use crate::types::KeychainKind;
+
+/// A check run by [`Wallet::sign`](crate::Wallet::sign) before signing a PSBT
+pub trait SigningPolicy: fmt::Debug + Send + Sync {
+    /// Check whether the PSBT can be signed, returning an error to refuse signing it
+    fn check(&self, psbt: &PsbtSummary) -> Result<(), SigningPolicyError>;
+}
+
+/// A view of a PSBT, from the point of view of the wallet signing it
+#[derive(Debug, Clone)]
+pub struct PsbtSummary<'a> {
+    /// The PSBT to sign
+    pub psbt: &'a Psbt,
+    /// The inputs of the PSBT
+    pub inputs: Vec<InputSummary>,
+    /// The outputs of the PSBT
+    pub outputs: Vec<OutputSummary>,
+    /// The fee of the transaction, `None` if the previous output of an input is unknown
+    pub fee: Option<Amount>,
+    /// The estimated fee rate of the transaction once finalized, `None` if it can't be computed
+    pub fee_rate: Option<FeeRate>,
+}
+
+impl PsbtSummary<'_> {
+    /// The total amount sent to scripts that don't belong to the wallet
+    pub fn foreign_amount(&self) -> Amount {
+        self.outputs
+            .iter()
+            .filter(|output| !output.is_mine())
+            .map(|output| output.txout.value)
+            .sum()
+    }
+}
+
+/// An input of a [`PsbtSummary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSummary {
+    /// The output spent by the input
+    pub outpoint: OutPoint,
+    /// The previous output, if known by the PSBT or by the wallet
+    pub txout: Option<TxOut>,
+    /// The keychain and derivation index of the spent script, if it belongs to the wallet
+    pub derivation: Option<(KeychainKind, u32)>,
+    /// The sighash type requested for the input
+    pub sighash_type: Option<PsbtSighashType>,
+}
+
+impl InputSummary {
+    /// Whether the input spends one of the wallet's scripts
+    pub fn is_mine(&self) -> bool {
+        self.derivation.is_some()
+    }
+}
+
+/// An output of a [`PsbtSummary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputSummary {
+    /// The output
+    pub txout: TxOut,
+    /// The keychain and derivation index of the script, if it belongs to the wallet
+    pub derivation: Option<(KeychainKind, u32)>,
+    /// Whether the PSBT has a key origin for the output from one of the wallet's keys
+    pub has_wallet_key_origin: bool,
+}
+
+impl OutputSummary {
+    /// Whether the output pays one of the wallet's scripts
+    pub fn is_mine(&self) -> bool {
+        self.derivation.is_some()
+    }
+
+    /// Whether the output pays the wallet's change keychain
+    pub fn is_change(&self) -> bool {
+        matches!(self.derivation, Some((KeychainKind::Internal, _)))
+    }
+}
+
+/// Rejects transactions paying more than the given fee
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxFee(pub Amount);
+
+impl SigningPolicy for MaxFee {
+    fn check(&self, psbt: &PsbtSummary) -> Result<(), SigningPolicyError> {
+        let fee = psbt.fee.ok_or(SigningPolicyError::UnknownFee)?;
+        if fee > self.0 {
+            return Err(SigningPolicyError::FeeTooHigh { fee, max: self.0 });
+        }
+        Ok(())
+    }
+}
+
+/// Rejects transactions paying more than the given fee rate
+///
+/// The fee rate is computed from the estimated weight of the transaction once finalized, which
+/// can't be estimated for inputs spending foreign scripts that are not finalized yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxFeeRate(pub FeeRate);
+
+impl SigningPolicy for MaxFeeRate {
+    fn check(&self, psbt: &PsbtSummary) -> Result<(), SigningPolicyError> {
+        let fee_rate = psbt.fee_rate.ok_or(SigningPolicyError::UnknownFee)?;
+        if fee_rate > self.0 {
+            return Err(SigningPolicyError::FeeRateTooHigh {
+                fee_rate,
+                max: self.0,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Rejects transactions with an output that looks like the wallet's change, since the PSBT has a
+/// key origin from one of the wallet's keys for it, but whose script doesn't belong to the wallet
+///
+/// This is a consistency check of the PSBT metadata, which catches PSBTs updated with the wrong
+/// key origins. It doesn't protect against a coordinator replacing the change output of the
+/// wallet with its own: the coordinator can simply omit the key origins of the output. To detect
+/// such changes, the outputs of the PSBT must be compared with the outputs the wallet expects,
+/// for instance with a custom [`SigningPolicy`] checking [`PsbtSummary::foreign_amount`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RejectForeignChange;
+
+impl SigningPolicy for RejectForeignChange {
+    fn check(&self, psbt: &PsbtSummary) -> Result<(), SigningPolicyError> {
+        match psbt
+            .outputs
+            .iter()
+            .position(|output| output.has_wallet_key_origin && !output.is_mine())
+        {
+            Some(index) => Err(SigningPolicyError::ForeignChange(index)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Error returned by a [`SigningPolicy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SigningPolicyError {
+    /// The fee of the transaction can't be computed
+    UnknownFee,
+    /// The transaction pays more than the maximum fee
+    FeeTooHigh {
+        /// Fee of the transaction
+        fee: Amount,
+        /// Maximum fee
+        max: Amount,
+    },
+    /// The transaction pays more than the maximum fee rate
+    FeeRateTooHigh {
+        /// Estimated fee rate of the transaction
+        fee_rate: FeeRate,
+        /// Maximum fee rate
+        max: FeeRate,
+    },
+    /// The output at the given index claims to be derived from the wallet's keys but doesn't
+    /// belong to the wallet
+    ForeignChange(usize),
+    /// The previous output of the input at the given index is not the same in the PSBT's
+    /// `witness_utxo`, in its `non_witness_utxo` and in the wallet's transaction graph
+    InconsistentUtxo(usize),
+    /// To be used by custom policies to return their own errors
+    Custom(String),
+}
+
+impl fmt::Display for SigningPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFee => write!(f, "The fee of the transaction can't be computed"),
+            Self::FeeTooHigh { fee, max } => {
+                write!(f, "The fee {} is higher than the maximum {}", fee, max)
+            }
+            Self::FeeRateTooHigh { fee_rate, max } => write!(
+                f,
+                "The fee rate {} sat/vb is higher than the maximum {} sat/vb",
+                fee_rate.to_sat_per_vb_ceil(),
+                max.to_sat_per_vb_ceil()
+            ),
+            Self::ForeignChange(index) => write!(
+                f,
+                "Output {} is derived from the wallet's keys but doesn't belong to the wallet",
+                index
+            ),
+            Self::InconsistentUtxo(index) => write!(
+                f,
+                "The previous output of input {} is inconsistent with the transaction data",
+                index
+            ),
+            Self::Custom(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SigningPolicyError {}
@@ -0,0 +1,1071 @@
+//! Silent payments
+//!
+//! This module implements sending to and receiving with [BIP352] silent payment addresses. A
+//! silent payment address is a static address from which the sender derives a new taproot output
+//! for every payment, using the secret keys of the inputs of the transaction. The receiver finds
+//! its outputs by scanning the chain, and nobody else can link them to the address.
+//!
+//! Since the outputs depend on the inputs, they are computed once the coins are selected, when the
+//! transaction is finished: see [`TxBuilder::add_silent_payment_recipient`].
+//!
+//! To find the outputs paid to it, the receiver computes the tweak of every transaction from the
+//! public keys of its inputs, which requires the previous outputs they spend. The
+//! [`SilentPaymentIndex`] does so for the transactions indexed by an [`IndexedTxGraph`], once their
+//! previous outputs are inserted with [`SilentPaymentIndex::insert_prevouts`]. The outputs found
+//! are spent with the spend key tweaked by the [`SilentPaymentSigner`].
+//!
+//! [BIP352]: https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki
+//! [`TxBuilder::add_silent_payment_recipient`]: crate::TxBuilder::add_silent_payment_recipient
+//! [`IndexedTxGraph`]: bdk_chain::IndexedTxGraph
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{Bech32m, ByteIterExt, Fe32, Fe32IterExt, Hrp};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::key::TweakedPublicKey;
+use bitcoin::psbt::{raw::ProprietaryKey, Psbt};
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::{
+    Keypair, Message, Parity, PublicKey, Scalar, Secp256k1, SecretKey, Signing, Verification,
+    XOnlyPublicKey,
+};
+use bitcoin::{taproot, NetworkKind, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Txid};
+use chain::spk_txout::SpkTxOutIndex;
+use chain::{ChainPosition, ConfirmationBlockTime, Indexer, Merge};
+use serde::{Deserialize, Serialize};
+
+use super::signer::{
+    compute_tap_sighash, InputSigner, SignOptions, SignerCommon, SignerError, SignerId,
+};
+use super::utils::SecpCtx;
+use crate::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::psbt::PsbtUtils;
+
+const INPUTS_TAG: &str = "BIP0352/Inputs";
+const SHARED_SECRET_TAG: &str = "BIP0352/SharedSecret";
+
+/// The x-only public key `H` of BIP341, with no known secret key. Taproot inputs with this internal
+/// key are not eligible for silent payments.
+const NUMS_H: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// The tweak of an output received with silent payments, as a big-endian scalar.
+///
+/// The output is spent with the secret spend key of the receiver tweaked by this value.
+pub type Tweak = [u8; 32];
+
+/// A silent payment address, made of a scan key and a spend key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SilentPaymentAddress {
+    network: NetworkKind,
+    scan_key: PublicKey,
+    spend_key: PublicKey,
+}
+
+impl SilentPaymentAddress {
+    /// Create a new version 0 silent payment address.
+    pub fn new(scan_key: PublicKey, spend_key: PublicKey, network: NetworkKind) -> Self {
+        Self {
+            network,
+            scan_key,
+            spend_key,
+        }
+    }
+
+    /// The network of the address.
+    pub fn network(&self) -> NetworkKind {
+        self.network
+    }
+
+    /// The public key the receiver scans the chain with.
+    pub fn scan_key(&self) -> PublicKey {
+        self.scan_key
+    }
+
+    /// The public key the outputs paying the receiver are derived from.
+    pub fn spend_key(&self) -> PublicKey {
+        self.spend_key
+    }
+
+    fn hrp(&self) -> Hrp {
+        match self.network {
+            NetworkKind::Main => Hrp::parse_unchecked("sp"),
+            NetworkKind::Test => Hrp::parse_unchecked("tsp"),
+        }
+    }
+}
+
+impl fmt::Display for SilentPaymentAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hrp = self.hrp();
+        let chars = self
+            .scan_key
+            .serialize()
+            .into_iter()
+            .chain(self.spend_key.serialize())
+            .bytes_to_fes()
+            .with_checksum::<Bech32m>(&hrp)
+            .with_witness_version(Fe32::Q)
+            .chars();
+        for c in chars {
+            fmt::Write::write_char(f, c)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SilentPaymentAddress {
+    type Err = SilentPaymentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut checked =
+            CheckedHrpstring::new::<Bech32m>(s).map_err(|_| SilentPaymentError::InvalidAddress)?;
+        let network = match checked.hrp().as_str() {
+            "sp" => NetworkKind::Main,
+            "tsp" => NetworkKind::Test,
+            _ => return Err(SilentPaymentError::InvalidAddress),
+        };
+        let version = checked
+            .remove_witness_version()
+            .ok_or(SilentPaymentError::InvalidAddress)?
+            .to_u8();
+        let data = checked.byte_iter().collect::<Vec<u8>>();
+        // Future versions may append data, which version 0 senders ignore
+        match version {
+            0 if data.len() == 66 => {}
+            1..=30 if data.len() >= 66 => {}
+            0..=30 => return Err(SilentPaymentError::InvalidAddress),
+            _ => return Err(SilentPaymentError::UnsupportedVersion(version)),
+        }
+        let scan_key =
+            PublicKey::from_slice(&data[..33]).map_err(|_| SilentPaymentError::InvalidAddress)?;
+        let spend_key =
+            PublicKey::from_slice(&data[33..66]).map_err(|_| SilentPaymentError::InvalidAddress)?;
+        Ok(Self::new(scan_key, spend_key, network))
+    }
+}
+
+/// Compute the taproot output keys of a transaction paying `recipients`.
+///
+/// `outpoints` are the outpoints spent by all the inputs of the transaction and `input_keys` the
+/// secret keys of its inputs eligible for silent payments: P2PKH with compressed keys, P2WPKH,
+/// P2SH-P2WPKH and P2TR. Keys of P2TR inputs must be the ones of the tweaked output keys, negated
+/// if they have an odd Y coordinate.
+///
+/// Returns the output key of each recipient, in the same order.
+pub fn create_outputs<C: Signing + Verification>(
+    secp: &Secp256k1<C>,
+    outpoints: impl IntoIterator<Item = OutPoint>,
+    input_keys: &[SecretKey],
+    recipients: &[SilentPaymentAddress],
+) -> Result<Vec<XOnlyPublicKey>, SilentPaymentError> {
+    let smallest_outpoint = outpoints
+        .into_iter()
+        .map(serialize_outpoint)
+        .min()
+        .ok_or(SilentPaymentError::NoInputKeys)?;
+    let (first_key, other_keys) = input_keys
+        .split_first()
+        .ok_or(SilentPaymentError::NoInputKeys)?;
+    let input_key = other_keys.iter().try_fold(*first_key, |sum, key| {
+        sum.add_tweak(&Scalar::from(*key))
+            .map_err(|_| SilentPaymentError::InvalidTweak)
+    })?;
+
+    let input_hash = input_hash(
+        &smallest_outpoint,
+        &PublicKey::from_secret_key(secp, &input_key),
+    );
+    let tweaked_input_key = input_key
+        .mul_tweak(&input_hash)
+        .map_err(|_| SilentPaymentError::InvalidTweak)?;
+
+    let mut counters = BTreeMap::<PublicKey, u32>::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let shared_secret = recipient
+                .scan_key
+                .mul_tweak(secp, &Scalar::from(tweaked_input_key))
+                .map_err(|_| SilentPaymentError::InvalidTweak)?;
+            let k = counters.entry(recipient.scan_key).or_insert(0);
+            let t_k = output_tweak(&shared_secret, *k);
+            *k += 1;
+            let output_key = recipient
+                .spend_key
+                .add_exp_tweak(secp, &t_k)
+                .map_err(|_| SilentPaymentError::InvalidTweak)?;
+            Ok(output_key.x_only_public_key().0)
+        })
+        .collect()
+}
+
+/// Compute the `input_hash` of a transaction from its smallest outpoint and the sum of the public
+/// keys of its eligible inputs.
+fn input_hash(smallest_outpoint: &[u8; 36], input_key: &PublicKey) -> Scalar {
+    tagged_hash(INPUTS_TAG, &[smallest_outpoint, &input_key.serialize()])
+}
+
+/// Compute the tweak `t_k` of the `k`-th output paying the shared secret.
+fn output_tweak(shared_secret: &PublicKey, k: u32) -> Scalar {
+    tagged_hash(
+        SHARED_SECRET_TAG,
+        &[&shared_secret.serialize(), &k.to_be_bytes()],
+    )
+}
+
+/// Return the public key of `txin` used for silent payments, if `prevout` is eligible.
+///
+/// Eligible inputs are P2TR (unless spent with a script path whose internal key is `H`), P2WPKH,
+/// P2SH-P2WPKH and P2PKH with a compressed key.
+fn input_public_key(txin: &TxIn, prevout: &TxOut) -> Option<PublicKey> {
+    let script_pubkey = &prevout.script_pubkey;
+    let witness = txin.witness.to_vec();
+    if script_pubkey.is_p2tr() {
+        // drop the annex, if any
+        let witness = match witness.split_last() {
+            Some((last, rest)) if !rest.is_empty() && last.first() == Some(&0x50) => rest,
+            _ => &witness[..],
+        };
+        // script path spends end with a control block, starting with the internal key
+        if let Some(control_block) = witness.last().filter(|_| witness.len() > 1) {
+            if control_block.get(1..33) == Some(&NUMS_H[..]) {
+                return None;
+            }
+        }
+        let output_key = XOnlyPublicKey::from_slice(&script_pubkey.as_bytes()[2..]).ok()?;
+        return Some(output_key.public_key(Parity::Even));
+    }
+
+    let p2wpkh_key = || {
+        let key = witness.last().filter(|_| witness.len() == 2)?;
+        PublicKey::from_slice(key).ok().filter(|_| key.len() == 33)
+    };
+    if script_pubkey.is_p2wpkh() {
+        return p2wpkh_key();
+    }
+    if script_pubkey.is_p2sh() {
+        let redeem_script = match txin
+            .script_sig
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(instructions) => match instructions.as_slice() {
+                [Instruction::PushBytes(bytes)] => ScriptBuf::from(bytes.as_bytes().to_vec()),
+                _ => return None,
+            },
+            Err(_) => return None,
+        };
+        return p2wpkh_key().filter(|_| redeem_script.is_p2wpkh());
+    }
+    if script_pubkey.is_p2pkh() {
+        // the script sig can be malleated, the key is the last push matching the script pubkey
+        let pushes = txin
+            .script_sig
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) if bytes.len() == 33 => Some(bytes.as_bytes()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        return pushes.into_iter().rev().find_map(|bytes| {
+            let key = PublicKey::from_slice(bytes).ok()?;
+            let pubkey_hash = bitcoin::PublicKey::new(key).pubkey_hash();
+            (*script_pubkey == ScriptBuf::new_p2pkh(&pubkey_hash)).then_some(key)
+        });
+    }
+    None
+}
+
+fn serialize_outpoint(outpoint: OutPoint) -> [u8; 36] {
+    let mut bytes = [0; 36];
+    bytes[..32].copy_from_slice(outpoint.txid.as_byte_array());
+    bytes[32..].copy_from_slice(&outpoint.vout.to_le_bytes());
+    bytes
+}
+
+fn tagged_hash(tag: &str, data: &[&[u8]]) -> Scalar {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_ref());
+    engine.input(tag.as_ref());
+    for data in data {
+        engine.input(data);
+    }
+    // The probability of the hash not being a valid scalar is negligible
+    Scalar::from_be_bytes(sha256::Hash::from_engine(engine).to_byte_array())
+        .expect("hash must be a valid scalar")
+}
+
+/// Indexes the outputs paid to a silent payment address, for use in an [`IndexedTxGraph`].
+///
+/// Finding the outputs of a transaction requires the previous outputs spent by its inputs, which
+/// are inserted with [`insert_prevouts`]. The previous outputs still missing are returned by
+/// [`missing_prevouts`], and can be fetched from a chain source. A transaction indexed before all
+/// its previous outputs are known is kept in the [`pending_txs`], and must be indexed again once
+/// they are inserted.
+///
+/// Only the tweaks of the outputs found are kept in the [`ChangeSet`], so that they can be
+/// restored without the previous outputs.
+///
+/// [`IndexedTxGraph`]: bdk_chain::IndexedTxGraph
+/// [`insert_prevouts`]: Self::insert_prevouts
+/// [`missing_prevouts`]: Self::missing_prevouts
+/// [`pending_txs`]: Self::pending_txs
+#[derive(Debug, Clone)]
+pub struct SilentPaymentIndex {
+    scan_key: SecretKey,
+    spend_key: PublicKey,
+    prevouts: HashMap<OutPoint, TxOut>,
+    pending: HashMap<Txid, Transaction>,
+    inner: SpkTxOutIndex<Tweak>,
+    secp: SecpCtx,
+}
+
+impl SilentPaymentIndex {
+    /// Create a new index of the outputs paid to the silent payment address with the given scan
+    /// and spend keys.
+    pub fn new(scan_key: SecretKey, spend_key: PublicKey) -> Self {
+        Self {
+            scan_key,
+            spend_key,
+            prevouts: HashMap::new(),
+            pending: HashMap::new(),
+            inner: SpkTxOutIndex::default(),
+            secp: SecpCtx::new(),
+        }
+    }
+
+    /// The silent payment address of this index on `network`.
+    pub fn address(&self, network: NetworkKind) -> SilentPaymentAddress {
+        SilentPaymentAddress::new(
+            self.scan_key.public_key(&self.secp),
+            self.spend_key,
+            network,
+        )
+    }
+
+    /// Get a reference to the internal [`SpkTxOutIndex`], indexing the script pubkeys of the
+    /// outputs found by their tweak.
+    pub fn inner(&self) -> &SpkTxOutIndex<Tweak> {
+        &self.inner
+    }
+
+    /// Insert the previous outputs spent by the transactions to index.
+    ///
+    /// The previous outputs are dropped once the transaction spending them is indexed.
+    pub fn insert_prevouts(&mut self, prevouts: impl IntoIterator<Item = (OutPoint, TxOut)>) {
+        self.prevouts.extend(prevouts);
+    }
+
+    /// Get the previous output at `outpoint`, if it was inserted or is one of the outputs found.
+    pub fn prevout(&self, outpoint: OutPoint) -> Option<&TxOut> {
+        self.prevouts
+            .get(&outpoint)
+            .or_else(|| self.inner.txout(outpoint).map(|(_, txout)| txout))
+    }
+
+    /// Return the previous outputs that must be inserted to scan `tx`.
+    ///
+    /// This is empty if `tx` can't pay a silent payment address, i.e. if it is a coinbase or has
+    /// no taproot outputs.
+    pub fn missing_prevouts(&self, tx: &Transaction) -> Vec<OutPoint> {
+        if !is_candidate(tx) {
+            return Vec::new();
+        }
+        tx.input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .filter(|outpoint| self.prevout(*outpoint).is_none())
+            .collect()
+    }
+
+    /// Return the transactions that were indexed before all their previous outputs were known.
+    ///
+    /// They are scanned when indexed again, once [`missing_prevouts`](Self::missing_prevouts) is
+    /// empty for them.
+    pub fn pending_txs(&self) -> impl Iterator<Item = &Transaction> {
+        self.pending.values()
+    }
+
+    /// Whether the transaction with `txid` is waiting for its previous outputs to be scanned.
+    pub fn is_pending(&self, txid: Txid) -> bool {
+        self.pending.contains_key(&txid)
+    }
+
+    /// Get the tweak of the output with `script_pubkey`, if it was paid to this index.
+    pub fn tweak_of_spk(&self, script_pubkey: ScriptBuf) -> Option<&Tweak> {
+        self.inner.index_of_spk(script_pubkey)
+    }
+
+    /// Get the outpoints of the outputs found, alongside their tweak.
+    pub fn outpoints(&self) -> &BTreeSet<(Tweak, OutPoint)> {
+        self.inner.outpoints()
+    }
+
+    /// Find the outputs of `tx` paid to this index, returning their tweaks.
+    fn scan_outputs(&self, tx: &Transaction) -> Vec<(ScriptBuf, Tweak)> {
+        if !is_candidate(tx) {
+            return Vec::new();
+        }
+        let mut input_keys = Vec::new();
+        for txin in &tx.input {
+            let prevout = match self.prevout(txin.previous_output) {
+                Some(prevout) => prevout,
+                None => return Vec::new(),
+            };
+            // spending outputs of unknown segwit versions makes the transaction ineligible
+            if prevout
+                .script_pubkey
+                .witness_version()
+                .map_or(false, |version| version.to_num() > 1)
+            {
+                return Vec::new();
+            }
+            input_keys.extend(input_public_key(txin, prevout));
+        }
+        let input_key = match PublicKey::combine_keys(&input_keys.iter().collect::<Vec<_>>()) {
+            Ok(input_key) => input_key,
+            Err(_) => return Vec::new(),
+        };
+        let smallest_outpoint = tx
+            .input
+            .iter()
+            .map(|txin| serialize_outpoint(txin.previous_output))
+            .min()
+            .expect("candidates have inputs");
+        let shared_secret = match input_key
+            .mul_tweak(&self.secp, &input_hash(&smallest_outpoint, &input_key))
+            .and_then(|key| key.mul_tweak(&self.secp, &Scalar::from(self.scan_key)))
+        {
+            Ok(shared_secret) => shared_secret,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut found = Vec::new();
+        for k in 0.. {
+            let t_k = output_tweak(&shared_secret, k);
+            let output_key = match self.spend_key.add_exp_tweak(&self.secp, &t_k) {
+                Ok(output_key) => output_key.x_only_public_key().0,
+                Err(_) => break,
+            };
+            let script_pubkey =
+                ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(output_key));
+            if !tx
+                .output
+                .iter()
+                .any(|txout| txout.script_pubkey == script_pubkey)
+            {
+                break;
+            }
+            found.push((script_pubkey, t_k.to_be_bytes()));
+        }
+        found
+    }
+
+    fn script_pubkey_of_tweak(&self, tweak: &Tweak) -> Option<ScriptBuf> {
+        let tweak = Scalar::from_be_bytes(*tweak).ok()?;
+        let output_key = self.spend_key.add_exp_tweak(&self.secp, &tweak).ok()?;
+        Some(ScriptBuf::new_p2tr_tweaked(
+            TweakedPublicKey::dangerous_assume_tweaked(output_key.x_only_public_key().0),
+        ))
+    }
+}
+
+/// Whether `tx` can pay a silent payment address.
+fn is_candidate(tx: &Transaction) -> bool {
+    !tx.is_coinbase() && tx.output.iter().any(|txout| txout.script_pubkey.is_p2tr())
+}
+
+impl Indexer for SilentPaymentIndex {
+    type ChangeSet = ChangeSet;
+
+    fn index_txout(&mut self, outpoint: OutPoint, txout: &TxOut) -> Self::ChangeSet {
+        self.inner.scan_txout(outpoint, txout);
+        ChangeSet::default()
+    }
+
+    fn index_tx(&mut self, tx: &Transaction) -> Self::ChangeSet {
+        let txid = tx.compute_txid();
+        if !self.missing_prevouts(tx).is_empty() {
+            // outputs already found from the changeset don't need the previous outputs
+            let found = tx.output.iter().any(|txout| {
+                self.inner
+                    .index_of_spk(txout.script_pubkey.clone())
+                    .is_some()
+            });
+            if !found {
+                self.pending.insert(txid, tx.clone());
+            }
+            self.inner.scan(tx);
+            return ChangeSet::default();
+        }
+        self.pending.remove(&txid);
+
+        let mut changeset = ChangeSet::default();
+        for (script_pubkey, tweak) in self.scan_outputs(tx) {
+            if self.inner.insert_spk(tweak, script_pubkey) {
+                changeset.tweaks.insert(tweak);
+            }
+        }
+        self.inner.scan(tx);
+        for txin in &tx.input {
+            self.prevouts.remove(&txin.previous_output);
+        }
+        changeset
+    }
+
+    fn apply_changeset(&mut self, changeset: Self::ChangeSet) {
+        for tweak in changeset.tweaks {
+            if let Some(script_pubkey) = self.script_pubkey_of_tweak(&tweak) {
+                self.inner.insert_spk(tweak, script_pubkey);
+            }
+        }
+    }
+
+    fn initial_changeset(&self) -> Self::ChangeSet {
+        ChangeSet {
+            tweaks: self.inner.all_spks().keys().copied().collect(),
+        }
+    }
+
+    fn is_tx_relevant(&self, tx: &Transaction) -> bool {
+        self.inner.is_relevant(tx)
+    }
+}
+
+/// Represents updates to the outputs found by a [`SilentPaymentIndex`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    /// The tweaks of the outputs found.
+    pub tweaks: BTreeSet<Tweak>,
+}
+
+impl Merge for ChangeSet {
+    fn merge(&mut self, other: Self) {
+        self.tweaks.extend(other.tweaks);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tweaks.is_empty()
+    }
+}
+
+/// An output received with silent payments.
+///
+/// See [`Wallet::list_silent_payment_unspent`](crate::Wallet::list_silent_payment_unspent).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SilentPaymentOutput {
+    /// Reference to the transaction output
+    pub outpoint: OutPoint,
+    /// Transaction output
+    pub txout: TxOut,
+    /// The tweak of the spend key the output is paid to
+    pub tweak: Tweak,
+    /// Whether this output is spent or not
+    pub is_spent: bool,
+    /// The position of the output in the blockchain
+    pub chain_position: ChainPosition<ConfirmationBlockTime>,
+}
+
+/// The proprietary PSBT input field holding the [`Tweak`] of an output received with silent
+/// payments.
+pub(crate) fn psbt_tweak_key() -> ProprietaryKey {
+    ProprietaryKey {
+        prefix: b"bdk".to_vec(),
+        subtype: 0x00,
+        key: Vec::new(),
+    }
+}
+
+/// Signs the inputs spending outputs received with silent payments.
+///
+/// The secret key of an output is the spend key tweaked by the [`Tweak`] of the output, which the
+/// signer reads from the PSBT: inputs created by the wallet for outputs found by its
+/// [`SilentPaymentIndex`] carry it in a proprietary field. Outputs are spent with the taproot key
+/// path.
+#[derive(Debug, Clone)]
+pub struct SilentPaymentSigner {
+    spend_key: SecretKey,
+}
+
+impl SilentPaymentSigner {
+    /// Create a new signer for the outputs paid to `spend_key`.
+    pub fn new(spend_key: SecretKey) -> Self {
+        Self { spend_key }
+    }
+}
+
+impl SignerCommon for SilentPaymentSigner {
+    fn id(&self, secp: &SecpCtx) -> SignerId {
+        let spend_key = bitcoin::PublicKey::new(self.spend_key.public_key(secp));
+        SignerId::from(spend_key.pubkey_hash().to_raw_hash())
+    }
+}
+
+impl InputSigner for SilentPaymentSigner {
+    fn sign_input(
+        &self,
+        psbt: &mut Psbt,
+        input_index: usize,
+        _sign_options: &SignOptions,
+        secp: &SecpCtx,
+    ) -> Result<(), SignerError> {
+        let psbt_input = psbt
+            .inputs
+            .get(input_index)
+            .ok_or(SignerError::InputIndexOutOfRange)?;
+        if psbt_input.final_script_sig.is_some()
+            || psbt_input.final_script_witness.is_some()
+            || psbt_input.tap_key_sig.is_some()
+        {
+            return Ok(());
+        }
+        let tweak = match psbt_input.proprietary.get(&psbt_tweak_key()) {
+            Some(tweak) => <Tweak>::try_from(tweak.as_slice())
+                .ok()
+                .and_then(|tweak| Scalar::from_be_bytes(tweak).ok())
+                .ok_or(SignerError::InvalidKey)?,
+            None => return Ok(()),
+        };
+        let keypair = Keypair::from_secret_key(
+            secp,
+            &self
+                .spend_key
+                .add_tweak(&tweak)
+                .map_err(|_| SignerError::InvalidKey)?,
+        );
+        let output_key = keypair.x_only_public_key().0;
+        match psbt.get_utxo_for(input_index) {
+            Some(txout)
+                if txout.script_pubkey
+                    == ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                        output_key,
+                    )) => {}
+            // the output is paid to another spend key
+            _ => return Ok(()),
+        }
+
+        let (sighash, sighash_type) = compute_tap_sighash(psbt, input_index, None)?;
+        let msg = Message::from(sighash);
+        let signature = secp.sign_schnorr_no_aux_rand(&msg, &keypair);
+        secp.verify_schnorr(&signature, &msg, &output_key)
+            .expect("invalid or corrupted schnorr signature");
+        psbt.inputs[input_index].tap_key_sig = Some(taproot::Signature {
+            signature,
+            sighash_type,
+        });
+        Ok(())
+    }
+}
+
+/// Errors related to silent payments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SilentPaymentError {
+    /// The silent payment address is invalid.
+    InvalidAddress,
+    /// The version of the silent payment address is not supported.
+    UnsupportedVersion(u8),
+    /// The silent payment address is for another network.
+    WrongNetwork(String),
+    /// The secret key of an input eligible for silent payments is not available.
+    MissingInputKey(OutPoint),
+    /// The transaction has no input eligible for silent payments.
+    NoInputKeys,
+    /// The keys of the inputs add up to an invalid key.
+    InvalidTweak,
+}
+
+impl fmt::Display for SilentPaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress => write!(f, "Invalid silent payment address"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported silent payment address version {}", version)
+            }
+            Self::WrongNetwork(address) => {
+                write!(
+                    f,
+                    "Silent payment address {} is for another network",
+                    address
+                )
+            }
+            Self::MissingInputKey(outpoint) => {
+                write!(f, "Missing the secret key of input {}", outpoint)
+            }
+            Self::NoInputKeys => write!(f, "No input eligible for silent payments"),
+            Self::InvalidTweak => write!(f, "Invalid silent payment tweak"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SilentPaymentError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::string::ToString;
+    use bitcoin::hex::FromHex;
+
+    fn secret_key(hex: &str) -> SecretKey {
+        SecretKey::from_slice(&<Vec<u8>>::from_hex(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_address_roundtrip() {
+        let address_str = "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv";
+        let address = SilentPaymentAddress::from_str(address_str).unwrap();
+        assert_eq!(address.network(), NetworkKind::Main);
+        assert_eq!(address.to_string(), address_str);
+
+        let secp = Secp256k1::new();
+        let scan_key = PublicKey::from_secret_key(&secp, &secret_key(&"11".repeat(32)));
+        let spend_key = PublicKey::from_secret_key(&secp, &secret_key(&"22".repeat(32)));
+        let address = SilentPaymentAddress::new(scan_key, spend_key, NetworkKind::Test);
+        assert!(address.to_string().starts_with("tsp1q"));
+        assert_eq!(
+            SilentPaymentAddress::from_str(&address.to_string()),
+            Ok(address)
+        );
+        assert_eq!(
+            SilentPaymentAddress::from_str("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+            Err(SilentPaymentError::InvalidAddress)
+        );
+    }
+
+    // The secret keys of the receiver of the BIP352 test vectors
+    const SCAN_SECRET: &str = "0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c";
+    const SPEND_SECRET: &str = "9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3";
+
+    fn vector_recipient(secp: &Secp256k1<bitcoin::secp256k1::All>) -> SilentPaymentAddress {
+        let address = SilentPaymentAddress::new(
+            PublicKey::from_secret_key(secp, &secret_key(SCAN_SECRET)),
+            PublicKey::from_secret_key(secp, &secret_key(SPEND_SECRET)),
+            NetworkKind::Main,
+        );
+        assert_eq!(address.to_string(), "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv");
+        address
+    }
+
+    // Secret keys of the inputs of the BIP352 test vectors, as used by the sender
+    const INPUT_SECRET_1: &str = "eadc78165ff1f8ea94ad7cfdc54990738a4c53f6e0507b42154201b8e5dff3b1";
+    const INPUT_SECRET_2: &str = "93f5ed907ad5b2bdbbdcb5d9116ebc0a4e1f92f910d5260237fa45a9408aad16";
+    const INPUT_SECRET_3: &str = "fc8716a97a48ba9a05a98ae47b5cd201a25a7fd5d8b73c203c5f7b6b6b3b6ad7";
+
+    fn even_y(secp: &Secp256k1<bitcoin::secp256k1::All>, key: SecretKey) -> SecretKey {
+        match key.x_only_public_key(secp).1 {
+            Parity::Odd => key.negate(),
+            Parity::Even => key,
+        }
+    }
+
+    #[test]
+    fn test_create_outputs() {
+        let secp = Secp256k1::new();
+        let recipient = vector_recipient(&secp);
+        let outpoints = |outpoints: [&str; 2]| outpoints.map(|s| OutPoint::from_str(s).unwrap());
+        let output = |hex: &str| XOnlyPublicKey::from_str(hex).unwrap();
+
+        // BIP352 send vectors: (outpoints, input secret keys, expected output)
+        let vectors = [
+            // simple send: two inputs
+            (
+                outpoints([
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:0",
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0",
+                ]),
+                [secret_key(INPUT_SECRET_1), secret_key(INPUT_SECRET_2)],
+                output("3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1"),
+            ),
+            // simple send: two inputs, order reversed
+            (
+                outpoints([
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0",
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:0",
+                ]),
+                [secret_key(INPUT_SECRET_2), secret_key(INPUT_SECRET_1)],
+                output("3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1"),
+            ),
+            // simple send: two inputs from the same transaction
+            (
+                outpoints([
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:3",
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:7",
+                ]),
+                [secret_key(INPUT_SECRET_1), secret_key(INPUT_SECRET_2)],
+                output("f4c2da807f89cb1501f1a77322a895acfb93c28e08ed2724d2beb8e44539ba38"),
+            ),
+            // single recipient: taproot only inputs with even y-values
+            (
+                outpoints([
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:0",
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0",
+                ]),
+                [
+                    even_y(&secp, secret_key(INPUT_SECRET_1)),
+                    even_y(&secp, secret_key(INPUT_SECRET_3)),
+                ],
+                output("de88bea8e7ffc9ce1af30d1132f910323c505185aec8eae361670421e749a1fb"),
+            ),
+        ];
+        for (outpoints, input_keys, expected) in vectors {
+            let outputs = create_outputs(&secp, outpoints, &input_keys, &[recipient]).unwrap();
+            assert_eq!(outputs, vec![expected]);
+        }
+
+        // paying the same address twice derives two different outputs, the first one being the
+        // output of a single payment
+        let (outpoints, input_keys, expected) = &vectors[0];
+        let outputs =
+            create_outputs(&secp, *outpoints, input_keys, &[recipient, recipient]).unwrap();
+        assert_eq!(outputs[0], *expected);
+        assert_ne!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_scan_vectors() {
+        use bitcoin::script::{Builder, PushBytesBuf};
+        use bitcoin::{absolute, transaction, Amount, Witness};
+
+        let secp = Secp256k1::new();
+        let outpoint = |s: &str| OutPoint::from_str(s).unwrap();
+        let prevout = |script_pubkey: ScriptBuf| TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey,
+        };
+        let p2tr = |key: XOnlyPublicKey| {
+            ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+        };
+        let push = |bytes: &[u8]| PushBytesBuf::try_from(bytes.to_vec()).unwrap();
+        let signature = [0; 72];
+        let outpoint_1 =
+            outpoint("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:0");
+        let outpoint_2 =
+            outpoint("a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0");
+
+        // a P2PKH input and a P2SH-P2WPKH input
+        let key_1 = bitcoin::PublicKey::new(secret_key(INPUT_SECRET_1).public_key(&secp));
+        let key_2 = bitcoin::PublicKey::new(secret_key(INPUT_SECRET_2).public_key(&secp));
+        let redeem_script = ScriptBuf::new_p2wpkh(&key_2.wpubkey_hash().unwrap());
+        let legacy_inputs = vec![
+            (
+                TxIn {
+                    previous_output: outpoint_1,
+                    script_sig: Builder::new()
+                        .push_slice(push(&signature))
+                        .push_key(&key_1)
+                        .into_script(),
+                    ..Default::default()
+                },
+                prevout(ScriptBuf::new_p2pkh(&key_1.pubkey_hash())),
+            ),
+            (
+                TxIn {
+                    previous_output: outpoint_2,
+                    script_sig: Builder::new()
+                        .push_slice(push(redeem_script.as_bytes()))
+                        .into_script(),
+                    witness: Witness::from_slice(&[&signature[..], &key_2.to_bytes()]),
+                    ..Default::default()
+                },
+                prevout(ScriptBuf::new_p2sh(&redeem_script.script_hash())),
+            ),
+        ];
+
+        // two P2TR key path inputs
+        let taproot_inputs = [(outpoint_1, INPUT_SECRET_1), (outpoint_2, INPUT_SECRET_3)]
+            .map(|(outpoint, secret)| {
+                (
+                    TxIn {
+                        previous_output: outpoint,
+                        witness: Witness::from_slice(&[[0; 64]]),
+                        ..Default::default()
+                    },
+                    prevout(p2tr(secret_key(secret).x_only_public_key(&secp).0)),
+                )
+            })
+            .to_vec();
+
+        // a P2TR script path input with the NUMS internal key is not used to derive the outputs,
+        // its outpoint being greater than the other ones
+        let mut control_block = vec![0xc0];
+        control_block.extend_from_slice(&NUMS_H);
+        let nums_input = (
+            TxIn {
+                previous_output: outpoint(
+                    "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff:0",
+                ),
+                witness: Witness::from_slice(&[&[0; 64][..], &[0x51], &control_block]),
+                ..Default::default()
+            },
+            prevout(p2tr(secret_key(INPUT_SECRET_3).x_only_public_key(&secp).0)),
+        );
+
+        // BIP352 receive vectors: (inputs, expected output)
+        let output = |hex: &str| XOnlyPublicKey::from_str(hex).unwrap();
+        let simple_send =
+            output("3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1");
+        let taproot_only =
+            output("de88bea8e7ffc9ce1af30d1132f910323c505185aec8eae361670421e749a1fb");
+        let mut with_nums = legacy_inputs.clone();
+        with_nums.push(nums_input);
+        let vectors = [
+            (legacy_inputs, simple_send),
+            (with_nums, simple_send),
+            (taproot_inputs, taproot_only),
+        ];
+
+        for (inputs, expected) in vectors {
+            let mut index = SilentPaymentIndex::new(
+                secret_key(SCAN_SECRET),
+                secret_key(SPEND_SECRET).public_key(&secp),
+            );
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: absolute::LockTime::ZERO,
+                input: inputs.iter().map(|(txin, _)| txin.clone()).collect(),
+                output: vec![prevout(p2tr(expected))],
+            };
+            index.insert_prevouts(
+                inputs
+                    .into_iter()
+                    .map(|(txin, prevout)| (txin.previous_output, prevout)),
+            );
+            assert_eq!(index.index_tx(&tx).tweaks.len(), 1);
+            assert!(index.is_tx_relevant(&tx));
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        use bitcoin::{absolute, transaction, Amount, Witness};
+
+        let secp = Secp256k1::new();
+        let scan_secret = secret_key(&"11".repeat(32));
+        let spend_secret = secret_key(&"22".repeat(32));
+        let mut index = SilentPaymentIndex::new(scan_secret, spend_secret.public_key(&secp));
+        let address = index.address(NetworkKind::Test);
+
+        // a P2WPKH input and a P2TR input
+        let wpkh_secret = secret_key(&"33".repeat(32));
+        let wpkh_key = bitcoin::PublicKey::new(wpkh_secret.public_key(&secp));
+        let tr_secret = secret_key(&"44".repeat(32));
+        let (tr_key, parity) = tr_secret.x_only_public_key(&secp);
+        let tr_secret = match parity {
+            Parity::Odd => tr_secret.negate(),
+            Parity::Even => tr_secret,
+        };
+        let prevouts = [
+            (
+                OutPoint::from_str(
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:1",
+                )
+                .unwrap(),
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: ScriptBuf::new_p2wpkh(&wpkh_key.wpubkey_hash().unwrap()),
+                },
+            ),
+            (
+                OutPoint::from_str(
+                    "a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0",
+                )
+                .unwrap(),
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(
+                        TweakedPublicKey::dangerous_assume_tweaked(tr_key),
+                    ),
+                },
+            ),
+        ];
+        let output_keys = create_outputs(
+            &secp,
+            prevouts.iter().map(|(outpoint, _)| *outpoint),
+            &[wpkh_secret, tr_secret],
+            &[address, address],
+        )
+        .unwrap();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![
+                TxIn {
+                    previous_output: prevouts[0].0,
+                    witness: Witness::from_slice(&[&[0; 72][..], &wpkh_key.to_bytes()]),
+                    ..Default::default()
+                },
+                TxIn {
+                    previous_output: prevouts[1].0,
+                    witness: Witness::from_slice(&[[0; 64]]),
+                    ..Default::default()
+                },
+            ],
+            output: output_keys
+                .iter()
+                .map(|output_key| TxOut {
+                    value: Amount::from_sat(5_000),
+                    script_pubkey: ScriptBuf::new_p2tr_tweaked(
+                        TweakedPublicKey::dangerous_assume_tweaked(*output_key),
+                    ),
+                })
+                .collect(),
+        };
+
+        // the transaction can't be scanned without its previous outputs
+        assert_eq!(
+            index.missing_prevouts(&tx),
+            prevouts
+                .iter()
+                .map(|(outpoint, _)| *outpoint)
+                .collect::<Vec<_>>()
+        );
+        assert!(index.index_tx(&tx).is_empty());
+        assert!(!index.is_tx_relevant(&tx));
+        assert!(index.is_pending(tx.compute_txid()));
+
+        index.insert_prevouts(prevouts.clone());
+        assert!(index.missing_prevouts(&tx).is_empty());
+        let changeset = index.index_tx(&tx);
+        assert_eq!(index.pending_txs().count(), 0);
+        assert_eq!(changeset.tweaks.len(), 2);
+        assert!(index.is_tx_relevant(&tx));
+        assert_eq!(index.outpoints().len(), 2);
+
+        // the tweaks give the secret keys of the outputs
+        for (tweak, outpoint) in index.outpoints() {
+            let output_secret = spend_secret
+                .add_tweak(&Scalar::from_be_bytes(*tweak).unwrap())
+                .unwrap();
+            let (_, txout) = index.inner().txout(*outpoint).unwrap();
+            assert_eq!(
+                txout.script_pubkey,
+                ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                    output_secret.x_only_public_key(&secp).0
+                ))
+            );
+        }
+
+        // the outputs are found again from the changeset alone
+        let mut restored = SilentPaymentIndex::new(scan_secret, spend_secret.public_key(&secp));
+        restored.apply_changeset(changeset);
+        assert_eq!(restored.missing_prevouts(&tx).len(), 2);
+        restored.index_tx(&tx);
+        assert!(!restored.is_pending(tx.compute_txid()));
+        assert_eq!(restored.outpoints(), index.outpoints());
+        assert_eq!(restored.initial_changeset(), index.initial_changeset());
+
+        // other receivers find nothing
+        let mut other = SilentPaymentIndex::new(spend_secret, scan_secret.public_key(&secp));
+        other.insert_prevouts(prevouts);
+        assert!(other.index_tx(&tx).is_empty());
+        assert!(!other.is_tx_relevant(&tx));
+    }
+}
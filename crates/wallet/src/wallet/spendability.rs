@@ -0,0 +1,165 @@
+//! UTXO spendability
+//!
+//! This module implements the view returned by [`Wallet::list_spendability`], which tells when
+//! every UTXO of the wallet becomes spendable through each path of the spending policy of its
+//! descriptor. Coins locked in descriptors with `older()` or `after()` branches, like the ones of
+//! vault and inheritance wallets, can't be spent by every key as soon as they are received: a
+//! recovery key may only be able to spend them some blocks after their confirmation.
+//!
+//! [`Wallet::projected_balance`] sums the UTXOs that are spendable at a given height and time,
+//! through the paths selected by the caller, for instance the paths signed by a recovery key.
+//!
+//! ## Example
+//!
+//! ```
+//! # use bitcoin::*;
+//! # use bdk_wallet::*;
+//! # use bdk_wallet::test_utils::*;
+//! use bdk_wallet::descriptor::policy::PkOrF;
+//!
+//! // or(pk(A),and(pk(B),older(144))), funded by a transaction confirmed at height 2000
+//! let (wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+//! let secp = key::Secp256k1::new();
+//! let recovery_key = PrivateKey::from_wif("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8")?
+//!     .public_key(&secp);
+//! let by_recovery_key = |path: &descriptor::policy::SpendingPath| {
+//!     path.is_signed_by(&[PkOrF::Pubkey(recovery_key)])
+//! };
+//!
+//! let utxos = wallet.list_spendability()?;
+//! let recovery_path = utxos[0]
+//!     .paths
+//!     .iter()
+//!     .find(|path| by_recovery_key(&path.path))
+//!     .unwrap();
+//! assert_eq!(recovery_path.spendable_at.unwrap().height, Some(2_000 + 144));
+//!
+//! let balance = wallet.projected_balance(2_100, 0, by_recovery_key)?;
+//! assert_eq!(balance.spendable, Amount::ZERO);
+//! let balance = wallet.projected_balance(2_144, 0, by_recovery_key)?;
+//! assert_eq!(balance.locked, Amount::ZERO);
+//! # Ok::<_, anyhow::Error>(())
+//! ```
+//!
+//! [`Wallet::list_spendability`]: crate::Wallet::list_spendability
+//! [`Wallet::projected_balance`]: crate::Wallet::projected_balance
+
+use alloc::vec::Vec;
+
+use bitcoin::{absolute, relative, Amount};
+use chain::{ChainPosition, ConfirmationBlockTime};
+use serde::Serialize;
+
+use crate::descriptor::policy::SpendingPath;
+use crate::types::LocalOutput;
+
+/// When a spending path of a UTXO becomes spendable
+///
+/// The heights follow the convention of
+/// [`SignOptions::assume_height`](crate::SignOptions::assume_height): a path is spendable at the
+/// height of the chain tip from which the wallet can finalize a transaction spending through it.
+/// The times are compared with the median time past of the chain.
+///
+/// Relative time-based timelocks are counted from the median time past of the block before the
+/// one that confirmed the UTXO (see BIP68). The wallet doesn't know the times of the blocks of its
+/// chain, so the paths with such timelocks have no [`SpendableAt`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SpendableAt {
+    /// The height from which the path is spendable, `None` if it has no height-based timelock
+    pub height: Option<u32>,
+    /// The time from which the path is spendable, `None` if it has no time-based timelock
+    pub time: Option<u64>,
+}
+
+impl SpendableAt {
+    /// Whether the path is spendable at the given height and median time past
+    pub fn is_reached(&self, height: u32, time: u64) -> bool {
+        self.height.map_or(true, |h| height >= h) && self.time.map_or(true, |t| time >= t)
+    }
+}
+
+/// A spending path of a UTXO
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathSpendability {
+    /// The spending path
+    pub path: SpendingPath,
+    /// When the path becomes spendable
+    ///
+    /// This is `None` if the path has a relative timelock and the UTXO isn't confirmed yet, or if
+    /// the path has a relative time-based timelock, which can't be projected (see
+    /// [`SpendableAt`]).
+    pub spendable_at: Option<SpendableAt>,
+}
+
+impl PathSpendability {
+    /// Whether the path is spendable at the given height and median time past
+    pub fn is_spendable(&self, height: u32, time: u64) -> bool {
+        self.spendable_at
+            .map_or(false, |spendable_at| spendable_at.is_reached(height, time))
+    }
+}
+
+/// The spending paths of a UTXO, returned by
+/// [`Wallet::list_spendability`](crate::Wallet::list_spendability)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UtxoSpendability {
+    /// The UTXO
+    pub utxo: LocalOutput,
+    /// Every path of the spending policy of the UTXO's descriptor
+    pub paths: Vec<PathSpendability>,
+}
+
+/// The balance of the wallet at a given height and time, returned by
+/// [`Wallet::projected_balance`](crate::Wallet::projected_balance)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ProjectedBalance {
+    /// The UTXOs spendable through one of the selected paths
+    pub spendable: Amount,
+    /// The UTXOs with a selected path, but whose timelocks aren't reached yet
+    pub locked: Amount,
+}
+
+impl ProjectedBalance {
+    /// The sum of the spendable and locked UTXOs
+    pub fn total(&self) -> Amount {
+        self.spendable + self.locked
+    }
+}
+
+// Returns when `path` becomes spendable for an output at `chain_position`
+pub(crate) fn spendable_at(
+    path: &SpendingPath,
+    chain_position: &ChainPosition<ConfirmationBlockTime>,
+) -> Option<SpendableAt> {
+    let mut spendable_at = SpendableAt::default();
+    match path.condition.timelock {
+        Some(absolute::LockTime::Blocks(height)) => {
+            spendable_at.height = Some(height.to_consensus_u32())
+        }
+        Some(absolute::LockTime::Seconds(time)) => {
+            spendable_at.time = Some(time.to_consensus_u32().into())
+        }
+        None => {}
+    }
+
+    let relative_timelock = path
+        .condition
+        .csv
+        .and_then(|csv| csv.to_relative_lock_time());
+    if let Some(relative_timelock) = relative_timelock {
+        let anchor = match chain_position {
+            ChainPosition::Confirmed { anchor, .. } => anchor,
+            ChainPosition::Unconfirmed { .. } => return None,
+        };
+        match relative_timelock {
+            relative::LockTime::Blocks(blocks) => {
+                let height = anchor.block_id.height + u32::from(blocks.value());
+                spendable_at.height = spendable_at.height.max(Some(height));
+            }
+            // counted from the median time past of the previous block, which is unknown
+            relative::LockTime::Time(_) => return None,
+        }
+    }
+
+    Some(spendable_at)
+}
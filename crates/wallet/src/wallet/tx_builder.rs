@@ -47,12 +47,20 @@ use bitcoin::{
     absolute, transaction::Version, Amount, FeeRate, OutPoint, ScriptBuf, Sequence, Transaction,
     TxIn, TxOut, Txid, Weight,
 };
+use chain::FeeEstimator;
+use miniscript::descriptor::{ConversionError, DescriptorPublicKey};
+use miniscript::plan::{Assets, Plan};
+use miniscript::{psbt::PsbtInputExt, Descriptor};
 use rand_core::RngCore;
 
 use super::coin_selection::CoinSelectionAlgorithm;
+use super::plan;
+use super::silent_payments::SilentPaymentAddress;
 use super::utils::shuffle_slice;
 use super::{CreateTxError, Wallet};
-use crate::collections::{BTreeMap, HashSet};
+use crate::collections::{BTreeMap, HashMap, HashSet};
+#[cfg(feature = "std")]
+use crate::psbt::{v2::PsbtV2Error, PsbtV2};
 use crate::{KeychainKind, LocalOutput, Utxo, WeightedUtxo};
 
 /// A transaction builder
@@ -120,11 +128,15 @@ pub struct TxBuilder<'a, Cs> {
 #[derive(Default, Debug, Clone)]
 pub(crate) struct TxParams {
     pub(crate) recipients: Vec<(ScriptBuf, Amount)>,
+    pub(crate) silent_payment_recipients: Vec<(SilentPaymentAddress, Amount)>,
     pub(crate) drain_wallet: bool,
     pub(crate) drain_to: Option<ScriptBuf>,
+    // Without `drain_to`, allow a transaction whose only output is the change
+    pub(crate) drain_to_change: bool,
     pub(crate) fee_policy: Option<FeePolicy>,
-    pub(crate) internal_policy_path: Option<BTreeMap<String, Vec<usize>>>,
-    pub(crate) external_policy_path: Option<BTreeMap<String, Vec<usize>>>,
+    pub(crate) policy_paths: BTreeMap<KeychainKind, BTreeMap<String, Vec<usize>>>,
+    pub(crate) assets: Option<Arc<Assets>>,
+    pub(crate) foreign_plans: HashMap<OutPoint, Plan>,
     pub(crate) utxos: Vec<WeightedUtxo>,
     pub(crate) unspendable: HashSet<OutPoint>,
     pub(crate) manually_selected_only: bool,
@@ -138,9 +150,14 @@ pub(crate) struct TxParams {
     pub(crate) add_global_xpubs: bool,
     pub(crate) include_output_redeem_witness_script: bool,
     pub(crate) bumping_fee: Option<PreviousFee>,
+    // The outputs of the original transaction that a batch replacement must keep paying
+    pub(crate) kept_outputs: Vec<(ScriptBuf, Amount)>,
     pub(crate) current_height: Option<absolute::LockTime>,
     pub(crate) allow_dust: bool,
     pub(crate) avoid_partial_spends: bool,
+    pub(crate) long_term_fee_rate: Option<FeeRate>,
+    // `Some(until_height)` to record the transaction as a pending spend
+    pub(crate) pending_spend: Option<Option<u32>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -149,6 +166,15 @@ pub(crate) struct PreviousFee {
     pub rate: FeeRate,
 }
 
+impl PreviousFee {
+    /// The minimum fee rate of a transaction replacing one that paid this fee.
+    pub(crate) fn min_replacement_rate(&self) -> FeeRate {
+        FeeRate::from_sat_per_kwu(
+            self.rate.to_sat_per_kwu() + FeeRate::BROADCAST_MIN.to_sat_per_kwu(), // +1 sat/vb
+        )
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum FeePolicy {
     FeeRate(FeeRate),
@@ -178,6 +204,27 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Set the fee rate estimated by `estimator` for the transaction to be confirmed within
+    /// `target_blocks` blocks.
+    ///
+    /// If the estimator has no estimate for this target, or the estimate is below the minimum
+    /// relay fee rate, the minimum relay fee rate ([`FeeRate::BROADCAST_MIN`]) is used instead.
+    /// See [`TxBuilder::fee_rate`] for how the fee rate is applied.
+    ///
+    /// Returns an error if the estimate could not be fetched.
+    pub fn fee_target<E: FeeEstimator>(
+        &mut self,
+        target_blocks: u16,
+        estimator: &E,
+    ) -> Result<&mut Self, E::Error> {
+        let fee_rate = estimator
+            .estimate_fee_rate(target_blocks)?
+            .map_or(FeeRate::BROADCAST_MIN, |fee_rate| {
+                fee_rate.max(FeeRate::BROADCAST_MIN)
+            });
+        Ok(self.fee_rate(fee_rate))
+    }
+
     /// Set an absolute fee
     /// The fee_absolute method refers to the absolute transaction fee in [`Amount`].
     /// If anyone sets both the `fee_absolute` method and the `fee_rate` method,
@@ -259,12 +306,61 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         policy_path: BTreeMap<String, Vec<usize>>,
         keychain: KeychainKind,
     ) -> &mut Self {
-        let to_update = match keychain {
-            KeychainKind::Internal => &mut self.params.internal_policy_path,
-            KeychainKind::External => &mut self.params.external_policy_path,
-        };
+        self.params.policy_paths.insert(keychain, policy_path);
+        self
+    }
 
-        *to_update = Some(policy_path);
+    /// Set the assets available to satisfy the spending policy of the wallet's UTXOs
+    ///
+    /// This is an alternative to [`policy_path`](Self::policy_path): instead of choosing the
+    /// branches of the policy tree by their node id, the caller describes which keys, hash
+    /// preimages and timelocks are available, and for every UTXO the builder picks the cheapest
+    /// path that can be satisfied with them, using miniscript's
+    /// [`plan`](miniscript::Descriptor::plan).
+    ///
+    /// When assets are set:
+    ///
+    /// * UTXOs that can't be satisfied with the assets are not selected, and manually added UTXOs
+    ///   that can't be satisfied make the transaction creation fail with
+    ///   [`CreateTxError::UnsatisfiableUtxo`];
+    /// * coin selection uses the exact satisfaction weight of the chosen path, instead of the
+    ///   maximum weight of the descriptor;
+    /// * the `nLockTime` and `nSequence` of the transaction are set to satisfy the timelocks of
+    ///   the chosen paths;
+    /// * the PSBT inputs only contain the keys of the chosen paths, and [`Wallet::finalize_psbt`]
+    ///   finalizes them with the same paths.
+    ///
+    /// The policy paths set with [`policy_path`](Self::policy_path) are ignored.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// # use std::str::FromStr;
+    /// # use bitcoin::*;
+    /// # use bdk_wallet::*;
+    /// use bdk_wallet::miniscript::{plan::Assets, DescriptorPublicKey};
+    ///
+    /// # let to_address =
+    /// # Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+    /// #     .unwrap()
+    /// #     .assume_checked();
+    /// # let mut wallet = doctest_wallet!();
+    /// // The key that will sign the transaction
+    /// let key = DescriptorPublicKey::from_str("[73c5da0a/86'/0'/0']tpubDC3pD7UZXnsgh3EBjbtBQiB1FnLask7UHBSunZ1DPK4dCFFZoFRkgxHB8gt42FvLzx1DpxfHWxAsYaY6b643RVcGjDxXxns7wKKYnnfEcbB/0/*")?;
+    /// let assets = Assets::new().add(key);
+    ///
+    /// let mut builder = wallet.build_tx();
+    /// builder
+    ///     .add_recipient(to_address.script_pubkey(), Amount::from_sat(50_000))
+    ///     .assets(assets);
+    /// let psbt = builder.finish()?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// [`CreateTxError::UnsatisfiableUtxo`]: super::error::CreateTxError::UnsatisfiableUtxo
+    /// [`Wallet::finalize_psbt`]: super::Wallet::finalize_psbt
+    pub fn assets(&mut self, assets: Assets) -> &mut Self {
+        self.params.assets = Some(Arc::new(assets));
         self
     }
 
@@ -277,23 +373,29 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
     pub fn add_utxos(&mut self, outpoints: &[OutPoint]) -> Result<&mut Self, AddUtxoError> {
         {
             let wallet = &mut self.wallet;
+            let silent_payment_utxos = wallet
+                .list_silent_payment_unspent()
+                .map(|output| (output.outpoint, output))
+                .collect::<HashMap<_, _>>();
             let utxos = outpoints
                 .iter()
-                .map(|outpoint| {
-                    wallet
-                        .get_utxo(*outpoint)
-                        .ok_or(AddUtxoError::UnknownUtxo(*outpoint))
+                .map(|outpoint| match wallet.get_utxo(*outpoint) {
+                    Some(utxo) => {
+                        let descriptor = wallet.public_descriptor(utxo.keychain);
+                        let satisfaction_weight = descriptor.max_weight_to_satisfy().unwrap();
+                        Ok(WeightedUtxo {
+                            satisfaction_weight,
+                            utxo: Utxo::Local(utxo),
+                        })
+                    }
+                    None => silent_payment_utxos
+                        .get(outpoint)
+                        .map(|output| wallet.silent_payment_weighted_utxo(output.clone()))
+                        .ok_or(AddUtxoError::UnknownUtxo(*outpoint)),
                 })
                 .collect::<Result<Vec<_>, _>>()?;
 
-            for utxo in utxos {
-                let descriptor = wallet.public_descriptor(utxo.keychain);
-                let satisfaction_weight = descriptor.max_weight_to_satisfy().unwrap();
-                self.params.utxos.push(WeightedUtxo {
-                    satisfaction_weight,
-                    utxo: Utxo::Local(utxo),
-                });
-            }
+            self.params.utxos.extend(utxos);
         }
 
         Ok(self)
@@ -375,25 +477,89 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         satisfaction_weight: Weight,
         sequence: Sequence,
     ) -> Result<&mut Self, AddForeignUtxoError> {
-        if psbt_input.witness_utxo.is_none() {
-            match psbt_input.non_witness_utxo.as_ref() {
-                Some(tx) => {
-                    if tx.compute_txid() != outpoint.txid {
-                        return Err(AddForeignUtxoError::InvalidTxid {
-                            input_txid: tx.compute_txid(),
-                            foreign_utxo: outpoint,
-                        });
-                    }
-                    if tx.output.len() <= outpoint.vout as usize {
-                        return Err(AddForeignUtxoError::InvalidOutpoint(outpoint));
-                    }
-                }
-                None => {
-                    return Err(AddForeignUtxoError::MissingUtxo);
-                }
-            }
+        foreign_txout(outpoint, &psbt_input)?;
+
+        self.params.utxos.push(WeightedUtxo {
+            satisfaction_weight,
+            utxo: Utxo::Foreign {
+                outpoint,
+                sequence,
+                psbt_input: Box::new(psbt_input),
+            },
+        });
+
+        Ok(self)
+    }
+
+    /// Add a foreign UTXO, i.e. a UTXO not owned by this wallet, given the descriptor of its
+    /// script.
+    ///
+    /// This is like [`add_foreign_utxo`], but instead of a hand-computed satisfaction weight it
+    /// takes the `descriptor` of the foreign output, which is derived at `derivation_index` and
+    /// checked against the `script_pubkey` of the UTXO in `psbt_input`:
+    ///
+    /// * without `assets`, the satisfaction weight is the [`max_weight_to_satisfy`] of the
+    ///   descriptor, and the `psbt_input` is updated with all the scripts and key origins of the
+    ///   descriptor (`witness_script`, `redeem_script`, `tap_internal_key`, BIP32 derivations...);
+    /// * with `assets`, the cheapest path that can be satisfied with them is planned like for the
+    ///   wallet's UTXOs (see [`assets`]): the satisfaction weight is the exact weight of the path,
+    ///   the `psbt_input` is updated with the scripts and keys of the path only, and the timelocks
+    ///   of the path are applied to the transaction.
+    ///
+    /// The security considerations of [`add_foreign_utxo`] about the value of the UTXO still apply.
+    ///
+    /// # Errors
+    ///
+    /// On top of the errors of [`add_foreign_utxo`], this method fails if the descriptor can't be
+    /// derived at `derivation_index`, if its script doesn't match the UTXO or if it can't be
+    /// satisfied, with the `assets` when they are given.
+    ///
+    /// [`add_foreign_utxo`]: Self::add_foreign_utxo
+    /// [`assets`]: Self::assets
+    /// [`max_weight_to_satisfy`]: miniscript::Descriptor::max_weight_to_satisfy
+    pub fn add_foreign_utxo_with_descriptor(
+        &mut self,
+        outpoint: OutPoint,
+        mut psbt_input: psbt::Input,
+        descriptor: &Descriptor<DescriptorPublicKey>,
+        derivation_index: u32,
+        assets: Option<&Assets>,
+    ) -> Result<&mut Self, AddForeignUtxoError> {
+        let txout = foreign_txout(outpoint, &psbt_input)?;
+        let descriptor = descriptor
+            .at_derivation_index(derivation_index)
+            .map_err(AddForeignUtxoError::Descriptor)?;
+        if descriptor.script_pubkey() != txout.script_pubkey {
+            return Err(AddForeignUtxoError::DescriptorMismatch(outpoint));
         }
 
+        let (satisfaction_weight, sequence) = match assets {
+            Some(assets) => {
+                let plan = descriptor
+                    .clone()
+                    .plan(assets)
+                    .map_err(|_| AddForeignUtxoError::Unsatisfiable(outpoint))?;
+                plan::update_psbt_input(&mut psbt_input, &plan);
+                let sequence = match (plan.relative_timelock, plan.absolute_timelock) {
+                    (Some(timelock), _) => timelock.to_sequence(),
+                    (None, Some(_)) => Sequence::ENABLE_LOCKTIME_NO_RBF,
+                    (None, None) => Sequence::MAX,
+                };
+                let satisfaction_weight = plan::satisfaction_weight(&descriptor, &plan);
+                self.params.foreign_plans.insert(outpoint, plan);
+                (satisfaction_weight, sequence)
+            }
+            None => {
+                psbt_input
+                    .update_with_descriptor_unchecked(&descriptor)
+                    .map_err(AddForeignUtxoError::Descriptor)?;
+                let satisfaction_weight = descriptor
+                    .max_weight_to_satisfy()
+                    .map_err(|_| AddForeignUtxoError::Unsatisfiable(outpoint))?;
+                (satisfaction_weight, Sequence::MAX)
+            }
+        };
+
         self.params.utxos.push(WeightedUtxo {
             satisfaction_weight,
             utxo: Utxo::Foreign {
@@ -417,6 +583,17 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Record the transaction as a pending spend when it is created, reserving its inputs until
+    /// it is broadcast, released or the chain tip reaches `until_height`.
+    ///
+    /// This is the same as calling [`Wallet::insert_pending_spend`] with the PSBT returned by
+    /// [`finish`](Self::finish), so that the next transactions built by the wallet don't select
+    /// the same UTXOs.
+    pub fn reserve_inputs(&mut self, until_height: Option<u32>) -> &mut Self {
+        self.params.pending_spend = Some(until_height);
+        self
+    }
+
     /// Replace the internal list of unspendable utxos with a new list
     ///
     /// It's important to note that the "must-be-spent" utxos added with [`TxBuilder::add_utxo`]
@@ -589,7 +766,25 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Set the long-term fee rate, i.e. the fee rate you expect to pay when spending the selected
+    /// coins in the future.
+    ///
+    /// Coin selection algorithms that minimize the [waste](crate::coin_selection::waste) of a
+    /// selection spend more inputs when the current fee rate is below the long-term fee rate and
+    /// fewer inputs when it is above. Default is
+    /// [`DEFAULT_LONG_TERM_FEE_RATE`](crate::coin_selection::DEFAULT_LONG_TERM_FEE_RATE).
+    pub fn long_term_fee_rate(&mut self, long_term_fee_rate: FeeRate) -> &mut Self {
+        self.params.long_term_fee_rate = Some(long_term_fee_rate);
+        self
+    }
+
     /// Replace the recipients already added with a new list
+    ///
+    /// The outputs of the original transaction of a [batch
+    /// replacement](super::Wallet::build_batch_replacement) must still be in the list, or
+    /// [`finish`](Self::finish) returns [`CreateTxError::RemovedOutput`].
+    ///
+    /// [`CreateTxError::RemovedOutput`]: super::error::CreateTxError::RemovedOutput
     pub fn set_recipients(&mut self, recipients: Vec<(ScriptBuf, Amount)>) -> &mut Self {
         self.params.recipients = recipients;
         self
@@ -601,6 +796,27 @@ impl<'a, Cs> TxBuilder<'a, Cs> {
         self
     }
 
+    /// Add a [silent payment](crate::silent_payments) recipient to the internal list
+    ///
+    /// The taproot output paying `address` can only be computed once the inputs of the
+    /// transaction are known: it is derived when the transaction is [finished](Self::finish) from
+    /// the secret keys of the selected inputs, which must all be available to the wallet's
+    /// signers. Only P2TR key path, P2WPKH, P2SH-P2WPKH and P2PKH inputs are taken into account,
+    /// as defined by BIP352.
+    ///
+    /// Since the output script depends on the inputs, the inputs must not be changed after the
+    /// transaction is created.
+    pub fn add_silent_payment_recipient(
+        &mut self,
+        address: SilentPaymentAddress,
+        amount: Amount,
+    ) -> &mut Self {
+        self.params
+            .silent_payment_recipients
+            .push((address, amount));
+        self
+    }
+
     /// Add data as an output, using OP_RETURN
     pub fn add_data<T: AsRef<PushBytes>>(&mut self, data: &T) -> &mut Self {
         let script = ScriptBuf::new_op_return(data);
@@ -685,7 +901,35 @@ impl<Cs: CoinSelectionAlgorithm> TxBuilder<'_, Cs> {
     /// **WARNING**: To avoid change address reuse you must persist the changes resulting from one
     /// or more calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
     pub fn finish_with_aux_rand(self, rng: &mut impl RngCore) -> Result<Psbt, CreateTxError> {
-        self.wallet.create_tx(self.coin_selection, self.params, rng)
+        let pending_spend = self.params.pending_spend;
+        let psbt = self
+            .wallet
+            .create_tx(self.coin_selection, self.params, rng)?;
+        if let Some(until_height) = pending_spend {
+            self.wallet.insert_pending_spend(&psbt, until_height);
+        }
+        Ok(psbt)
+    }
+
+    /// Finish building the transaction, as a version 2 PSBT.
+    ///
+    /// Returns a new [`PsbtV2`] per [`BIP370`], whose inputs and outputs can't be modified: use
+    /// [`PsbtV2::set_modifiable`] to let other parties add theirs. Version 2 PSBTs require a
+    /// transaction version of at least 2.
+    ///
+    /// [`BIP370`]: https://github.com/bitcoin/bips/blob/master/bip-0370.mediawiki
+    ///
+    /// **WARNING**: To avoid change address reuse you must persist the changes resulting from one
+    /// or more calls to this method before closing the wallet. See [`Wallet::reveal_next_address`].
+    #[cfg(feature = "std")]
+    pub fn finish_v2(self) -> Result<PsbtV2, CreateTxError> {
+        if let Some(version) = self.params.version {
+            if version < Version::TWO {
+                return Err(PsbtV2Error::InvalidTxVersion(version).into());
+            }
+        }
+        let psbt = self.finish()?;
+        Ok(PsbtV2::try_from(psbt)?)
     }
 }
 
@@ -725,6 +969,12 @@ pub enum AddForeignUtxoError {
     InvalidOutpoint(OutPoint),
     /// Foreign utxo missing witness_utxo or non_witness_utxo
     MissingUtxo,
+    /// The descriptor of the foreign utxo can't be derived
+    Descriptor(ConversionError),
+    /// The script of the descriptor doesn't match the foreign utxo
+    DescriptorMismatch(OutPoint),
+    /// The descriptor of the foreign utxo can't be satisfied
+    Unsatisfiable(OutPoint),
 }
 
 impl fmt::Display for AddForeignUtxoError {
@@ -744,6 +994,17 @@ impl fmt::Display for AddForeignUtxoError {
                 outpoint.txid, outpoint.vout,
             ),
             Self::MissingUtxo => write!(f, "Foreign utxo missing witness_utxo or non_witness_utxo"),
+            Self::Descriptor(err) => write!(f, "Foreign utxo descriptor error: {}", err),
+            Self::DescriptorMismatch(outpoint) => write!(
+                f,
+                "The descriptor doesn't match the script of foreign utxo {}",
+                outpoint
+            ),
+            Self::Unsatisfiable(outpoint) => write!(
+                f,
+                "The descriptor of foreign utxo {} can't be satisfied",
+                outpoint
+            ),
         }
     }
 }
@@ -751,6 +1012,31 @@ impl fmt::Display for AddForeignUtxoError {
 #[cfg(feature = "std")]
 impl std::error::Error for AddForeignUtxoError {}
 
+// Returns the output spent by a foreign utxo, checking it against the outpoint
+fn foreign_txout(
+    outpoint: OutPoint,
+    psbt_input: &psbt::Input,
+) -> Result<TxOut, AddForeignUtxoError> {
+    if let Some(txout) = &psbt_input.witness_utxo {
+        return Ok(txout.clone());
+    }
+    match psbt_input.non_witness_utxo.as_ref() {
+        Some(tx) => {
+            if tx.compute_txid() != outpoint.txid {
+                return Err(AddForeignUtxoError::InvalidTxid {
+                    input_txid: tx.compute_txid(),
+                    foreign_utxo: outpoint,
+                });
+            }
+            tx.output
+                .get(outpoint.vout as usize)
+                .cloned()
+                .ok_or(AddForeignUtxoError::InvalidOutpoint(outpoint))
+        }
+        None => Err(AddForeignUtxoError::MissingUtxo),
+    }
+}
+
 type TxSort<T> = dyn (Fn(&T, &T) -> core::cmp::Ordering) + Send + Sync;
 
 /// Ordering of the transaction's inputs and outputs
@@ -826,8 +1112,8 @@ impl ChangeSpendPolicy {
     pub(crate) fn is_satisfied_by(&self, utxo: &LocalOutput) -> bool {
         match self {
             ChangeSpendPolicy::ChangeAllowed => true,
-            ChangeSpendPolicy::OnlyChange => utxo.keychain == KeychainKind::Internal,
-            ChangeSpendPolicy::ChangeForbidden => utxo.keychain == KeychainKind::External,
+            ChangeSpendPolicy::OnlyChange => utxo.keychain.is_change(),
+            ChangeSpendPolicy::ChangeForbidden => !utxo.keychain.is_change(),
         }
     }
 }
@@ -1006,6 +1292,7 @@ mod test {
                 txout: TxOut::NULL,
                 keychain: KeychainKind::External,
                 is_spent: false,
+                lock: None,
                 chain_position: chain::ChainPosition::Unconfirmed { last_seen: Some(0) },
                 derivation_index: 0,
             },
@@ -1017,6 +1304,7 @@ mod test {
                 txout: TxOut::NULL,
                 keychain: KeychainKind::Internal,
                 is_spent: false,
+                lock: None,
                 chain_position: chain::ChainPosition::Confirmed {
                     anchor: chain::ConfirmationBlockTime {
                         block_id: chain::BlockId {
@@ -1112,4 +1400,55 @@ mod test {
         builder.fee_rate(FeeRate::from_sat_per_kwu(feerate + 250));
         let _ = builder.finish().unwrap();
     }
+
+    #[test]
+    fn test_build_timelock_refresh_signable_path() {
+        use crate::descriptor::policy::PkOrF;
+        use crate::test_utils::*;
+        use bitcoin::key::Secp256k1;
+        use bitcoin::PrivateKey;
+
+        // or(pk(X),or(pk(A),and(pk(B),older(144)))), where the wallet can't sign for X
+        let secp = Secp256k1::new();
+        let [other_key, key_a] = [
+            "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW",
+            "cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu",
+        ]
+        .map(|wif| PrivateKey::from_wif(wif).unwrap().public_key(&secp));
+        let descriptor = format!(
+            "wsh(or_d(pk({}),or_d(pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu),and_v(v:pk(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(144)))))",
+            other_key
+        );
+        let (mut wallet, _) = get_funded_wallet_single(&descriptor);
+        let paths = wallet
+            .policies(KeychainKind::External)
+            .unwrap()
+            .unwrap()
+            .spending_paths();
+        let primary_path = |key| {
+            paths
+                .iter()
+                .find(|path| path.condition.is_null() && path.keys == vec![PkOrF::Pubkey(key)])
+                .unwrap()
+                .policy_path
+                .clone()
+        };
+        // the path of the key "X" comes first
+        assert_eq!(
+            paths
+                .iter()
+                .find(|path| path.condition.is_null())
+                .unwrap()
+                .policy_path,
+            primary_path(other_key)
+        );
+
+        let builder = wallet
+            .build_timelock_refresh(144, FeeRate::from_sat_per_vb_u32(5))
+            .unwrap();
+        assert_eq!(
+            builder.params.policy_paths[&KeychainKind::External],
+            primary_path(key_a)
+        );
+    }
 }
@@ -0,0 +1,150 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bdk_wallet::bitcoin::bip32::Fingerprint;
+use bdk_wallet::bitcoin::{Amount, Network, Psbt};
+use bdk_wallet::serde_json::json;
+use bdk_wallet::signer::hwi::{HwiError, HwiSigner};
+use bdk_wallet::signer::{SignOptions, SignerError, SignerOrdering};
+use bdk_wallet::test_utils::*;
+use bdk_wallet::{KeychainKind, Wallet};
+use core::str::FromStr;
+
+/// Write a script standing in for HWI in `dir`, which answers the commands for the device
+/// `fingerprint` with the given descriptors and signed PSBT. Returns the path of the script.
+fn write_stub(
+    dir: &Path,
+    fingerprint: Fingerprint,
+    descriptors: (&str, &str),
+    signed_psbt: &Psbt,
+) -> PathBuf {
+    let responses = [
+        (
+            "enumerate.json",
+            json!([{
+                "type": "stub",
+                "model": "stub",
+                "path": "/dev/stub",
+                "fingerprint": fingerprint.to_string(),
+                "needs_pin_sent": false,
+                "needs_passphrase_sent": false,
+            }]),
+        ),
+        (
+            "getdescriptors.json",
+            json!({ "receive": [descriptors.0], "internal": [descriptors.1] }),
+        ),
+        (
+            "signtx.json",
+            json!({ "psbt": signed_psbt.to_string(), "signed": true }),
+        ),
+    ];
+    for (file, response) in responses {
+        fs::write(dir.join(file), response.to_string()).unwrap();
+    }
+
+    let script = format!(
+        r#"#!/bin/sh
+dir=$(dirname "$0")
+case "$*" in
+    "enumerate") cat "$dir/enumerate.json" ;;
+    "--fingerprint {fingerprint} --chain regtest getdescriptors") cat "$dir/getdescriptors.json" ;;
+    "--fingerprint {fingerprint} --chain regtest signtx "*) cat "$dir/signtx.json" ;;
+    *)
+        echo '{{"error": "Unsupported command", "code": -13}}'
+        exit 1 ;;
+esac
+"#
+    );
+    let path = dir.join("hwi");
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+#[test]
+fn test_hwi_signer() {
+    // the device holds the private keys of the descriptors
+    let (device_descriptor, device_change_descriptor) = get_test_wpkh_and_change_desc();
+    let (device, _) = get_funded_wallet(device_descriptor, device_change_descriptor);
+    let descriptor = device.public_descriptor(KeychainKind::External).to_string();
+    let change_descriptor = device.public_descriptor(KeychainKind::Internal).to_string();
+    let origin = descriptor.find('[').unwrap() + 1;
+    let fingerprint = Fingerprint::from_str(&descriptor[origin..origin + 8]).unwrap();
+
+    let (mut wallet, _) = get_funded_wallet(&descriptor, &change_descriptor);
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+    let mut signed_psbt = psbt.clone();
+    let sign_options = SignOptions {
+        try_finalize: false,
+        ..Default::default()
+    };
+    device.sign(&mut signed_psbt, sign_options).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let command = write_stub(
+        dir.path(),
+        fingerprint,
+        (&descriptor, &change_descriptor),
+        &signed_psbt,
+    );
+
+    let devices = HwiSigner::enumerate(&command).unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].fingerprint, Some(fingerprint));
+    let signer = HwiSigner::new(&command, fingerprint).chain(Network::Regtest);
+    let descriptors = signer.get_descriptors(None).unwrap();
+    assert_eq!(descriptors.receive, vec![descriptor.clone()]);
+    assert_eq!(descriptors.internal, vec![change_descriptor.clone()]);
+
+    // the program fails for another network
+    let mut testnet_wallet = Wallet::create(descriptor.clone(), change_descriptor.clone())
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap();
+    testnet_wallet.add_signer(
+        KeychainKind::External,
+        SignerOrdering::default(),
+        Arc::new(HwiSigner::new(&command, fingerprint).chain(Network::Testnet)),
+    );
+    assert!(matches!(
+        testnet_wallet.sign(&mut psbt.clone(), SignOptions::default()),
+        Err(SignerError::Hwi(HwiError::Hwi { code: -13, .. }))
+    ));
+
+    // the program is not run for another device
+    let mut other_psbt = psbt.clone();
+    let other_device = HwiSigner::new(&command, Fingerprint::from([1; 4]));
+    assert!(other_device.sign_psbt(&other_psbt).is_err());
+    let other_wallet = {
+        let mut wallet = Wallet::create(descriptor, change_descriptor)
+            .network(Network::Regtest)
+            .create_wallet_no_persist()
+            .unwrap();
+        wallet.add_signer(
+            KeychainKind::External,
+            SignerOrdering::default(),
+            Arc::new(other_device),
+        );
+        wallet
+    };
+    assert!(!other_wallet
+        .sign(&mut other_psbt, SignOptions::default())
+        .unwrap());
+
+    wallet.add_signer(
+        KeychainKind::External,
+        SignerOrdering::default(),
+        Arc::new(signer),
+    );
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx().unwrap();
+    assert_eq!(tx.input[0].witness.len(), 2);
+}
@@ -0,0 +1,424 @@
+use bdk_wallet::bitcoin::bip32::{DerivationPath, Fingerprint};
+use bdk_wallet::bitcoin::key::TweakedPublicKey;
+use bdk_wallet::bitcoin::secp256k1::PublicKey;
+use bdk_wallet::bitcoin::transaction::Version;
+use bdk_wallet::bitcoin::{
+    Amount, FeeRate, Psbt, ScriptBuf, Sequence, TxIn, TxOut, Weight, Witness,
+};
+use bdk_wallet::payjoin::{ReceiveError, Receiver, SendError, Sender};
+use bdk_wallet::test_utils::*;
+use bdk_wallet::{KeychainKind, Wallet};
+use core::str::FromStr;
+
+/// Stand-in for the HTTP endpoint of a payjoin receiver, taking the query string and the body of
+/// the request and returning the body of the response, or the error code.
+fn payjoin_endpoint(wallet: &Wallet, query: &str, body: &str) -> Result<String, &'static str> {
+    let original = Psbt::from_str(body).map_err(|_| "original-psbt-rejected")?;
+    let receiver = Receiver::new(wallet, original, query).map_err(|e| e.error_code())?;
+    let proposal = receiver.propose(wallet).map_err(|e| e.error_code())?;
+    Ok(proposal.to_string())
+}
+
+fn payjoin_wallets() -> (Wallet, Wallet, ScriptBuf) {
+    let (sender, _) = get_funded_wallet_wpkh();
+    let (receiver, _) = get_funded_wallet_single(get_test_wpkh());
+    let payee = receiver
+        .peek_address(KeychainKind::External, 0)
+        .script_pubkey();
+    (sender, receiver, payee)
+}
+
+fn original_psbt(sender: &mut Wallet, payee: &ScriptBuf) -> Psbt {
+    let mut builder = sender.build_tx();
+    builder
+        .add_recipient(payee.clone(), Amount::from_sat(10_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(2));
+    builder.finish().unwrap()
+}
+
+#[test]
+fn test_payjoin() {
+    let (mut sender_wallet, receiver_wallet, payee) = payjoin_wallets();
+    let psbt = original_psbt(&mut sender_wallet, &payee);
+    let sender = Sender::new(&sender_wallet, psbt, payee.clone(), Amount::from_sat(1_000)).unwrap();
+    let original_tx = sender.original_psbt().clone().extract_tx().unwrap();
+    let original_fee = sender_wallet.calculate_fee(&original_tx).unwrap();
+    let change_index = sender.params().additional_fee_output_index.unwrap();
+    assert_ne!(original_tx.output[change_index].script_pubkey, payee);
+
+    let response = payjoin_endpoint(
+        &receiver_wallet,
+        &sender.query(),
+        &sender.original_psbt().to_string(),
+    )
+    .unwrap();
+    let proposal = Psbt::from_str(&response).unwrap();
+    let payjoin = sender
+        .process_proposal(&sender_wallet, proposal)
+        .unwrap()
+        .extract_tx()
+        .unwrap();
+
+    let receiver_utxo = receiver_wallet.list_unspent().next().unwrap();
+    assert_eq!(payjoin.input.len(), 2);
+    assert!(payjoin
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == receiver_utxo.outpoint));
+    assert!(original_tx.input.iter().all(|original_txin| payjoin
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == original_txin.previous_output)));
+
+    // the receiver paid for its input with our change, within the allowed contribution
+    let payee_output = payjoin
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey == payee)
+        .unwrap();
+    assert_eq!(
+        payee_output.value,
+        Amount::from_sat(10_000) + receiver_utxo.txout.value
+    );
+    let original_change = &original_tx.output[change_index];
+    let change = payjoin
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey == original_change.script_pubkey)
+        .unwrap();
+    let contribution = original_change.value - change.value;
+    assert!(contribution > Amount::ZERO && contribution <= Amount::from_sat(1_000));
+    let payjoin_fee = Amount::from_sat(50_000) + receiver_utxo.txout.value
+        - payjoin.output.iter().map(|txout| txout.value).sum();
+    assert_eq!(payjoin_fee, original_fee + contribution);
+    assert!(payjoin_fee / payjoin.weight() >= original_fee / original_tx.weight());
+}
+
+#[test]
+fn test_payjoin_sender_checks() {
+    let (mut sender_wallet, receiver_wallet, payee) = payjoin_wallets();
+    let psbt = original_psbt(&mut sender_wallet, &payee);
+    let sender = Sender::new(&sender_wallet, psbt, payee.clone(), Amount::from_sat(100)).unwrap();
+    let response = payjoin_endpoint(
+        &receiver_wallet,
+        &sender.query(),
+        &sender.original_psbt().to_string(),
+    )
+    .unwrap();
+    let proposal = Psbt::from_str(&response).unwrap();
+    let change_index = sender.params().additional_fee_output_index.unwrap();
+
+    // the outputs of the original PSBT must have different scripts
+    let mut builder = sender_wallet.build_tx();
+    builder
+        .add_recipient(payee.clone(), Amount::from_sat(10_000))
+        .add_recipient(payee.clone(), Amount::from_sat(5_000));
+    let psbt = builder.finish().unwrap();
+    assert!(matches!(
+        Sender::new(&sender_wallet, psbt, payee.clone(), Amount::from_sat(100)),
+        Err(SendError::DuplicateOutput(script)) if script == payee
+    ));
+
+    // the receiver takes more than allowed from our change
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.output[change_index].value -= Amount::from_sat(100);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidFeeContribution)
+    ));
+
+    // the receiver decreases the payment
+    let mut invalid = proposal.clone();
+    let payee_index = invalid
+        .unsigned_tx
+        .output
+        .iter()
+        .position(|txout| txout.script_pubkey == payee)
+        .unwrap();
+    invalid.unsigned_tx.output[payee_index].value = Amount::from_sat(9_000);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidOutput(script)) if script == payee
+    ));
+
+    // the receiver adds one of our UTXOs
+    let outpoint = receive_output_in_latest_block(&mut sender_wallet, 20_000);
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.input.push(TxIn {
+        previous_output: outpoint,
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        ..Default::default()
+    });
+    invalid.inputs.push(bdk_wallet::bitcoin::psbt::Input {
+        witness_utxo: Some(sender_wallet.get_utxo(outpoint).unwrap().txout),
+        final_script_witness: Some(Witness::from_slice(&[[0u8; 72]])),
+        ..Default::default()
+    });
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::NewSenderInput(op)) if op == outpoint
+    ));
+
+    // the inputs or outputs of the PSBT don't match the transaction
+    let mut invalid = proposal.clone();
+    invalid.inputs.pop();
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidProposal)
+    ));
+    let mut invalid = proposal.clone();
+    invalid.outputs.push(Default::default());
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidProposal)
+    ));
+
+    // the receiver changes the version of the transaction
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.version = Version::ONE;
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::TxModified)
+    ));
+
+    // the receiver changes the sequence of our input or finalizes it
+    let sender_index = proposal
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|txin| sender_wallet.get_utxo(txin.previous_output).is_some())
+        .unwrap();
+    let sender_outpoint = proposal.unsigned_tx.input[sender_index].previous_output;
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.input[sender_index].sequence = Sequence::MAX;
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidSenderInput(op)) if op == sender_outpoint
+    ));
+    let mut invalid = proposal.clone();
+    invalid.inputs[sender_index].final_script_witness = Some(Witness::from_slice(&[[0u8; 72]]));
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidSenderInput(op)) if op == sender_outpoint
+    ));
+
+    // the input of the receiver isn't finalized, or isn't of the same type as ours
+    let receiver_index = 1 - sender_index;
+    let receiver_outpoint = proposal.unsigned_tx.input[receiver_index].previous_output;
+    let mut invalid = proposal.clone();
+    invalid.inputs[receiver_index].final_script_witness = None;
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidReceiverInput(op)) if op == receiver_outpoint
+    ));
+    let mut invalid = proposal.clone();
+    invalid.inputs[receiver_index].witness_utxo = Some(TxOut {
+        value: Amount::from_sat(50_000),
+        script_pubkey: ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+            PublicKey::from_str(
+                "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            )
+            .unwrap()
+            .x_only_public_key()
+            .0,
+        )),
+    });
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidReceiverInput(op)) if op == receiver_outpoint
+    ));
+
+    // the receiver adds keypaths to an output
+    let mut invalid = proposal.clone();
+    invalid.outputs[payee_index].bip32_derivation.insert(
+        PublicKey::from_str("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap(),
+        (Fingerprint::default(), DerivationPath::master()),
+    );
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidOutput(script)) if script == payee
+    ));
+
+    // the receiver reorders our outputs
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.output.swap(0, 1);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidOutput(_))
+    ));
+
+    // the receiver adds an output with the same script as the payment
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.output.push(TxOut {
+        value: Amount::from_sat(1_000),
+        script_pubkey: payee.clone(),
+    });
+    invalid.outputs.push(Default::default());
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidOutput(script)) if script == payee
+    ));
+
+    // the receiver decreases the fees
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.output[payee_index].value += Amount::from_sat(1_000);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidFeeContribution)
+    ));
+
+    // the receiver removes one of our inputs
+    let mut invalid = proposal;
+    let sender_index = invalid
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|txin| sender_wallet.get_utxo(txin.previous_output).is_some())
+        .unwrap();
+    invalid.unsigned_tx.input.remove(sender_index);
+    invalid.inputs.remove(sender_index);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::MissingSenderInput(_))
+    ));
+}
+
+#[test]
+fn test_payjoin_sender_fee_checks() {
+    let (mut sender_wallet, receiver_wallet, payee) = payjoin_wallets();
+    let psbt = original_psbt(&mut sender_wallet, &payee);
+    let sender = Sender::new(&sender_wallet, psbt, payee.clone(), Amount::from_sat(5_000)).unwrap();
+    let proposal = Psbt::from_str(
+        &payjoin_endpoint(
+            &receiver_wallet,
+            &sender.query(),
+            &sender.original_psbt().to_string(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let change_index = sender.params().additional_fee_output_index.unwrap();
+
+    // the receiver takes from our change, within the allowed contribution, more than the fees of
+    // its input at the original fee rate
+    let mut invalid = proposal.clone();
+    invalid.unsigned_tx.output[change_index].value -= Amount::from_sat(1_000);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidFeeContribution)
+    ));
+
+    // the proposal is below the minimum fee rate requested
+    let mut min_fee_rate_sender = sender.clone();
+    min_fee_rate_sender.set_min_fee_rate(FeeRate::from_sat_per_vb_u32(5));
+    assert!(matches!(
+        min_fee_rate_sender.process_proposal(&sender_wallet, proposal),
+        Err(SendError::BelowMinFeeRate)
+    ));
+
+    // the receiver pays the fees missing to reach it
+    let proposal = Psbt::from_str(
+        &payjoin_endpoint(
+            &receiver_wallet,
+            &min_fee_rate_sender.query(),
+            &min_fee_rate_sender.original_psbt().to_string(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let payjoin = min_fee_rate_sender
+        .process_proposal(&sender_wallet, proposal)
+        .unwrap();
+    let fee = payjoin.fee().unwrap();
+    let weight = payjoin.extract_tx().unwrap().weight();
+    assert!(fee / weight >= FeeRate::from_sat_per_vb_u32(5));
+    assert!(fee / (weight + Weight::from_vb_unchecked(2)) < FeeRate::from_sat_per_vb_u32(5));
+}
+
+#[test]
+fn test_payjoin_sender_input_order() {
+    let (mut sender_wallet, receiver_wallet, payee) = payjoin_wallets();
+    let first = sender_wallet.list_unspent().next().unwrap().outpoint;
+    let second = receive_output_in_latest_block(&mut sender_wallet, 20_000);
+    let mut builder = sender_wallet.build_tx();
+    builder
+        .add_utxos(&[first, second])
+        .unwrap()
+        .add_recipient(payee.clone(), Amount::from_sat(10_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(2));
+    let psbt = builder.finish().unwrap();
+    let sender = Sender::new(&sender_wallet, psbt, payee, Amount::from_sat(1_000)).unwrap();
+    let proposal = Psbt::from_str(
+        &payjoin_endpoint(
+            &receiver_wallet,
+            &sender.query(),
+            &sender.original_psbt().to_string(),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert!(sender
+        .process_proposal(&sender_wallet, proposal.clone())
+        .is_ok());
+
+    // the receiver swaps our inputs
+    let sender_indexes = proposal
+        .unsigned_tx
+        .input
+        .iter()
+        .enumerate()
+        .filter(|(_, txin)| sender_wallet.get_utxo(txin.previous_output).is_some())
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let mut invalid = proposal;
+    invalid
+        .unsigned_tx
+        .input
+        .swap(sender_indexes[0], sender_indexes[1]);
+    invalid.inputs.swap(sender_indexes[0], sender_indexes[1]);
+    assert!(matches!(
+        sender.process_proposal(&sender_wallet, invalid),
+        Err(SendError::InvalidSenderInput(_))
+    ));
+}
+
+#[test]
+fn test_payjoin_receiver_checks() {
+    let (mut sender_wallet, mut receiver_wallet, payee) = payjoin_wallets();
+
+    // the original PSBT must be signed
+    let psbt = original_psbt(&mut sender_wallet, &payee);
+    assert!(matches!(
+        Receiver::new(&receiver_wallet, psbt.clone(), "v=1"),
+        Err(ReceiveError::NotFinalized)
+    ));
+    assert_eq!(
+        payjoin_endpoint(&receiver_wallet, "v=2", &psbt.to_string()),
+        Err("version-unsupported")
+    );
+
+    // the original PSBT must not spend our outputs
+    let mut builder = receiver_wallet.build_tx();
+    builder.add_recipient(payee.clone(), Amount::from_sat(10_000));
+    let mut psbt = builder.finish().unwrap();
+    receiver_wallet.sign(&mut psbt, Default::default()).unwrap();
+    assert!(matches!(
+        Receiver::new(&receiver_wallet, psbt, "v=1"),
+        Err(ReceiveError::OwnInput(_))
+    ));
+
+    // the wallet needs a spendable UTXO to contribute
+    let psbt = original_psbt(&mut sender_wallet, &payee);
+    let sender = Sender::new(&sender_wallet, psbt, payee, Amount::from_sat(1_000)).unwrap();
+    let utxo = receiver_wallet.list_unspent().next().unwrap();
+    receiver_wallet.lock_utxo(utxo.outpoint, "cold storage", None);
+    assert_eq!(
+        payjoin_endpoint(
+            &receiver_wallet,
+            &sender.query(),
+            &sender.original_psbt().to_string()
+        ),
+        Err("unavailable")
+    );
+}
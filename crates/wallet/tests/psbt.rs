@@ -1,4 +1,10 @@
-use bdk_wallet::bitcoin::{Amount, FeeRate, Psbt, TxIn};
+use assert_matches::assert_matches;
+use bdk_wallet::bitcoin::{
+    absolute, transaction, Amount, EcdsaSighashType, FeeRate, OutPoint, Psbt, Sequence, TxIn, TxOut,
+};
+use bdk_wallet::error::CreateTxError;
+use bdk_wallet::psbt::v2::{PsbtV2Error, RequiredLockTime, TxModifiable};
+use bdk_wallet::psbt::PsbtV2;
 use bdk_wallet::test_utils::*;
 use bdk_wallet::{psbt, KeychainKind, SignOptions};
 use core::str::FromStr;
@@ -221,3 +227,168 @@ fn test_psbt_multiple_internalkey_signers() {
     let verify_res = secp.verify_schnorr(&signature, &message, &xonlykey);
     assert!(verify_res.is_ok(), "The wrong internal key was used");
 }
+
+#[test]
+fn test_psbt_v2() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let send_to = wallet.peek_address(KeychainKind::External, 0);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(send_to.script_pubkey(), Amount::from_sat(10_000));
+    let mut psbt = builder.finish_v2().unwrap();
+    assert_eq!(psbt.modifiable(), TxModifiable::default());
+    assert_eq!(PsbtV2::from_str(&psbt.to_string()).unwrap(), psbt);
+
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let decoded = PsbtV2::from_str(&psbt.to_string()).unwrap();
+    assert_eq!(decoded, psbt);
+    let tx = decoded.into_psbt().extract_tx().unwrap();
+    assert_eq!(tx.compute_txid(), psbt.unsigned_tx().compute_txid());
+
+    // version 0 PSBTs are rejected
+    assert_matches!(
+        PsbtV2::from_str(PSBT_STR),
+        Err(PsbtV2Error::UnsupportedVersion(0))
+    );
+
+    // version 2 PSBTs require a transaction version of at least 2
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(send_to.script_pubkey(), Amount::from_sat(10_000))
+        .version(1);
+    assert_matches!(
+        builder.finish_v2(),
+        Err(CreateTxError::PsbtV2(PsbtV2Error::InvalidTxVersion(
+            transaction::Version::ONE
+        )))
+    );
+}
+
+#[test]
+fn test_psbt_v2_constructor() {
+    let (alice, _) = get_funded_wallet_wpkh();
+    let (bob, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    let mut psbt = PsbtV2::new(transaction::Version::TWO, absolute::LockTime::ZERO).unwrap();
+
+    // each party adds an input and an output
+    for (wallet, required_height) in [(&alice, None), (&bob, Some(100))] {
+        let utxo = wallet.list_unspent().next().unwrap();
+        let required_lock_time = RequiredLockTime {
+            time: None,
+            height: required_height.map(|height| absolute::Height::from_consensus(height).unwrap()),
+        };
+        let input = wallet.get_psbt_input(utxo.clone(), None, false).unwrap();
+        psbt.add_input(
+            utxo.outpoint,
+            Sequence::ENABLE_RBF_NO_LOCKTIME,
+            input,
+            required_lock_time,
+        )
+        .unwrap();
+        let txout = TxOut {
+            value: utxo.txout.value - Amount::from_sat(1_000),
+            script_pubkey: wallet
+                .peek_address(KeychainKind::External, 1)
+                .script_pubkey(),
+        };
+        psbt.add_output(txout, Default::default()).unwrap();
+
+        // the PSBT is sent to the next party
+        psbt = PsbtV2::from_str(&psbt.to_string()).unwrap();
+    }
+    assert_eq!(psbt.lock_time(), absolute::LockTime::from_consensus(100));
+
+    // the inputs can't require locktimes of different types
+    let required_time = RequiredLockTime {
+        time: Some(absolute::Time::from_consensus(1_700_000_000).unwrap()),
+        height: None,
+    };
+    assert_matches!(
+        psbt.clone().add_input(
+            OutPoint::null(),
+            Sequence::MAX,
+            Default::default(),
+            required_time
+        ),
+        Err(PsbtV2Error::LockTimeConflict)
+    );
+
+    assert!(!alice.sign(&mut psbt, SignOptions::default()).unwrap());
+    // the signature of alice commits to all the inputs and outputs
+    assert_eq!(psbt.modifiable(), TxModifiable::default());
+    assert_matches!(
+        psbt.add_output(TxOut::NULL, Default::default()),
+        Err(PsbtV2Error::OutputsNotModifiable)
+    );
+
+    assert!(bob.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.into_psbt().extract_tx().unwrap();
+    assert_eq!(tx.input.len(), 2);
+    assert_eq!(tx.lock_time, absolute::LockTime::from_consensus(100));
+}
+
+#[test]
+fn test_psbt_v2_sighash_single() {
+    let (wallet, _) = get_funded_wallet_wpkh();
+    let utxo = wallet.list_unspent().next().unwrap();
+    let mut psbt = PsbtV2::new(transaction::Version::TWO, absolute::LockTime::ZERO).unwrap();
+    let sighash_type = EcdsaSighashType::SinglePlusAnyoneCanPay.into();
+    let input = wallet
+        .get_psbt_input(utxo.clone(), Some(sighash_type), false)
+        .unwrap();
+    let txout = TxOut {
+        value: utxo.txout.value - Amount::from_sat(1_000),
+        script_pubkey: wallet
+            .peek_address(KeychainKind::External, 1)
+            .script_pubkey(),
+    };
+    psbt.add_input_output(
+        utxo.outpoint,
+        Sequence::ENABLE_RBF_NO_LOCKTIME,
+        input,
+        RequiredLockTime::default(),
+        txout.clone(),
+        Default::default(),
+    )
+    .unwrap();
+
+    let sign_options = SignOptions {
+        allow_all_sighashes: true,
+        ..Default::default()
+    };
+    wallet.sign(&mut psbt, sign_options).unwrap();
+    assert_eq!(
+        psbt.modifiable(),
+        TxModifiable {
+            inputs: true,
+            outputs: true,
+            sighash_single: true,
+        }
+    );
+
+    // adding an output or an input alone would break the pairing of the signed input
+    assert_matches!(
+        psbt.add_output(txout.clone(), Default::default()),
+        Err(PsbtV2Error::UnpairedModification)
+    );
+    let outpoint = OutPoint::new(utxo.outpoint.txid, 42);
+    assert_matches!(
+        psbt.add_input(
+            outpoint,
+            Sequence::MAX,
+            Default::default(),
+            RequiredLockTime::default()
+        ),
+        Err(PsbtV2Error::UnpairedModification)
+    );
+    psbt.add_input_output(
+        outpoint,
+        Sequence::MAX,
+        Default::default(),
+        RequiredLockTime::default(),
+        txout,
+        Default::default(),
+    )
+    .unwrap();
+    assert_eq!(psbt.inputs().len(), 2);
+    assert_eq!(psbt.outputs().len(), 2);
+}
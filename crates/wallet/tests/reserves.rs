@@ -0,0 +1,194 @@
+use assert_matches::assert_matches;
+use bdk_chain::{BlockId, ConfirmationBlockTime};
+use bdk_wallet::bitcoin::hashes::Hash;
+use bdk_wallet::bitcoin::{Amount, BlockHash, OutPoint, Psbt, Transaction, TxIn, TxOut};
+use bdk_wallet::reserves::{challenge_outpoint, verify_proof, ProofError};
+use bdk_wallet::test_utils::*;
+use bdk_wallet::{KeychainKind, SignOptions, Wallet};
+
+const MESSAGE: &str = "Proof of reserves";
+
+fn sign_proof(wallet: &Wallet, psbt: &mut Psbt) {
+    let sign_options = SignOptions {
+        trust_witness_utxo: true,
+        ..Default::default()
+    };
+    assert!(wallet.sign(psbt, sign_options).unwrap());
+}
+
+#[test]
+fn test_proof_of_reserves() {
+    for descriptor in [get_test_wpkh(), get_test_tr_single_sig()] {
+        let (mut wallet, _) = get_funded_wallet_single(descriptor);
+        let unconfirmed = receive_output(&mut wallet, 10_000, ReceiveTo::Mempool(100));
+        receive_output_in_latest_block(&mut wallet, 20_000);
+        let tip = wallet.latest_checkpoint();
+
+        let mut psbt = wallet.build_proof(MESSAGE).finish().unwrap();
+        assert_eq!(psbt.inputs.len(), 4);
+        assert_eq!(
+            psbt.unsigned_tx.input[0].previous_output,
+            challenge_outpoint(MESSAGE)
+        );
+        sign_proof(&wallet, &mut psbt);
+        assert_matches!(
+            wallet.verify_proof(&psbt, MESSAGE, tip.height()),
+            Err(ProofError::UnavailableUtxo(outpoint)) if outpoint == unconfirmed
+        );
+
+        let mut psbt = wallet
+            .build_proof(MESSAGE)
+            .exclude_unconfirmed()
+            .finish()
+            .unwrap();
+        sign_proof(&wallet, &mut psbt);
+        assert_eq!(
+            wallet.verify_proof(&psbt, MESSAGE, tip.height()).unwrap(),
+            Amount::from_sat(70_000)
+        );
+        assert_eq!(wallet.balance().confirmed, Amount::from_sat(70_000));
+
+        // the auditor verifies the proof against its own view of the chain
+        assert_eq!(
+            verify_proof(
+                &psbt,
+                MESSAGE,
+                wallet.tx_graph(),
+                wallet.local_chain(),
+                tip.block_id(),
+                tip.height()
+            )
+            .unwrap(),
+            Amount::from_sat(70_000)
+        );
+        assert_matches!(
+            wallet.verify_proof(&psbt, "Another message", tip.height()),
+            Err(ProofError::ChallengeInput)
+        );
+        // the UTXOs were not all confirmed yet
+        assert_matches!(
+            wallet.verify_proof(&psbt, MESSAGE, tip.height() - 1),
+            Err(ProofError::UnavailableUtxo(_))
+        );
+    }
+}
+
+#[test]
+fn test_proof_of_reserves_invalid() {
+    let (wallet, txid) = get_funded_wallet_wpkh();
+    let mut psbt = wallet.build_proof(MESSAGE).finish().unwrap();
+    assert_matches!(
+        wallet.verify_proof(&psbt, MESSAGE, 2_000),
+        Err(ProofError::NotFinalized(0))
+    );
+    sign_proof(&wallet, &mut psbt);
+    assert_eq!(
+        wallet.verify_proof(&psbt, MESSAGE, 2_000).unwrap(),
+        Amount::from_sat(50_000)
+    );
+
+    // the output must have the amount of the UTXOs
+    let mut invalid = psbt.clone();
+    invalid.unsigned_tx.output[0].value = Amount::from_sat(60_000);
+    assert_matches!(
+        wallet.verify_proof(&invalid, MESSAGE, 2_000),
+        Err(ProofError::InvalidOutput)
+    );
+
+    // the signatures commit to the message
+    let mut invalid = psbt.clone();
+    invalid.unsigned_tx.input[0].previous_output = challenge_outpoint("Another message");
+    assert_matches!(
+        wallet.verify_proof(&invalid, "Another message", 2_000),
+        Err(ProofError::InvalidSignature(0))
+    );
+
+    // an output can't be proven twice
+    let mut invalid = psbt.clone();
+    invalid
+        .unsigned_tx
+        .input
+        .push(invalid.unsigned_tx.input[1].clone());
+    invalid.inputs.push(invalid.inputs[1].clone());
+    assert_matches!(
+        wallet.verify_proof(&invalid, MESSAGE, 2_000),
+        Err(ProofError::DuplicateInput(outpoint)) if outpoint == OutPoint::new(txid, 0)
+    );
+}
+
+#[test]
+fn test_proof_of_reserves_spent_utxo() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let outpoint = OutPoint::new(txid, 0);
+    let extra = receive_output_in_latest_block(&mut wallet, 20_000);
+    let mut psbt = wallet
+        .build_proof(MESSAGE)
+        .add_utxos(&[outpoint])
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(psbt.inputs.len(), 2);
+    sign_proof(&wallet, &mut psbt);
+
+    // the UTXO is spent in a later block
+    let block_id = BlockId {
+        height: 3_000,
+        hash: BlockHash::all_zeros(),
+    };
+    insert_checkpoint(&mut wallet, block_id);
+    let spend_tx = Transaction {
+        input: vec![TxIn {
+            previous_output: outpoint,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(49_000),
+            script_pubkey: wallet
+                .peek_address(KeychainKind::External, 1)
+                .script_pubkey(),
+        }],
+        ..new_tx(0)
+    };
+    let spend_txid = spend_tx.compute_txid();
+    insert_tx(&mut wallet, spend_tx);
+    insert_anchor(
+        &mut wallet,
+        spend_txid,
+        ConfirmationBlockTime {
+            block_id,
+            confirmation_time: 300,
+        },
+    );
+
+    assert_eq!(
+        wallet.verify_proof(&psbt, MESSAGE, 2_999).unwrap(),
+        Amount::from_sat(50_000)
+    );
+    assert_matches!(
+        wallet.verify_proof(&psbt, MESSAGE, 3_000),
+        Err(ProofError::UnavailableUtxo(spent)) if spent == outpoint
+    );
+
+    // spent outputs can't be added to new proofs
+    assert_matches!(
+        wallet.build_proof(MESSAGE).add_utxos(&[extra, outpoint]),
+        Err(ProofError::UnknownUtxo(spent)) if spent == outpoint
+    );
+
+    // a proof must spend at least one UTXO
+    let unconfirmed_wallet = {
+        let mut wallet = Wallet::create_single(get_test_wpkh())
+            .network(bdk_wallet::bitcoin::Network::Regtest)
+            .create_wallet_no_persist()
+            .unwrap();
+        receive_output(&mut wallet, 10_000, ReceiveTo::Mempool(100));
+        wallet
+    };
+    assert_matches!(
+        unconfirmed_wallet
+            .build_proof(MESSAGE)
+            .exclude_unconfirmed()
+            .finish(),
+        Err(ProofError::NoUtxos)
+    );
+}
@@ -0,0 +1,266 @@
+use bdk_wallet::bitcoin::block::{Header, Version};
+use bdk_wallet::bitcoin::hashes::Hash;
+use bdk_wallet::bitcoin::key::{Secp256k1, TweakedPublicKey};
+use bdk_wallet::bitcoin::secp256k1::{schnorr, Message, SecretKey, XOnlyPublicKey};
+use bdk_wallet::bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bdk_wallet::bitcoin::{
+    psbt, taproot, Amount, Block, CompactTarget, OutPoint, ScriptBuf, Transaction, TxMerkleNode,
+    TxOut, Txid, Weight,
+};
+use bdk_wallet::signer::SignerOrdering;
+use bdk_wallet::silent_payments::SilentPaymentSigner;
+use bdk_wallet::test_utils::*;
+use bdk_wallet::{KeychainKind, SignOptions, Wallet};
+use std::sync::Arc;
+
+/// Pay the silent payment address of `receiver` from `sender`, returning the signed transaction
+/// after inserting its previous outputs in `receiver`.
+fn pay_silent_payment(sender: &mut Wallet, receiver: &mut Wallet, amount: Amount) -> Transaction {
+    let address = receiver.silent_payment_address().unwrap();
+    let mut builder = sender.build_tx();
+    builder.add_silent_payment_recipient(address, amount);
+    let mut psbt = builder.finish().unwrap();
+    assert!(sender.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx().unwrap();
+    insert_prevouts(sender, receiver, &tx);
+    tx
+}
+
+/// Insert the previous outputs of `tx`, spent from `sender`, in `receiver`.
+fn insert_prevouts(sender: &Wallet, receiver: &mut Wallet, tx: &Transaction) {
+    // fetch the previous outputs from the chain source
+    let prevouts = receiver
+        .silent_payment_index()
+        .unwrap()
+        .missing_prevouts(tx)
+        .into_iter()
+        .map(|outpoint| {
+            (
+                outpoint,
+                sender.tx_graph().get_txout(outpoint).unwrap().clone(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(prevouts.len(), tx.input.len());
+    receiver.insert_silent_payment_prevouts(prevouts);
+}
+
+fn mine_block(wallet: &mut Wallet, txdata: Vec<Transaction>) {
+    let tip = wallet.latest_checkpoint();
+    let block = Block {
+        header: Header {
+            version: Version::TWO,
+            prev_blockhash: tip.hash(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1_000_000,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        },
+        txdata,
+    };
+    wallet.apply_block(&block, tip.height() + 1).unwrap();
+}
+
+#[test]
+fn test_receive_silent_payment() {
+    let secp = Secp256k1::new();
+    let scan_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let spend_key = SecretKey::from_slice(&[2; 32]).unwrap();
+    let (mut sender, _) = get_funded_wallet_wpkh();
+    let (mut receiver, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    let keychain_balance = receiver.balance();
+    receiver.scan_silent_payments(scan_key, spend_key.public_key(&secp));
+
+    let tx = pay_silent_payment(&mut sender, &mut receiver, Amount::from_sat(25_000));
+    mine_block(&mut receiver, vec![tx.clone()]);
+
+    let outputs = receiver.list_silent_payment_unspent().collect::<Vec<_>>();
+    assert_eq!(outputs.len(), 1);
+    let output = &outputs[0];
+    assert_eq!(output.outpoint.txid, tx.compute_txid());
+    assert_eq!(output.txout.value, Amount::from_sat(25_000));
+    assert!(output.chain_position.is_confirmed());
+    assert_eq!(
+        receiver.silent_payment_balance().confirmed,
+        Amount::from_sat(25_000)
+    );
+    assert_eq!(
+        receiver.balance().total(),
+        keychain_balance.total() + Amount::from_sat(25_000)
+    );
+
+    // the output is found again after loading the wallet
+    let changeset = receiver.staged().unwrap().clone();
+    let mut loaded = Wallet::load()
+        .descriptor(KeychainKind::External, Some(get_test_tr_single_sig()))
+        .extract_keys()
+        .load_wallet_no_persist(changeset)
+        .unwrap()
+        .unwrap();
+    assert!(loaded.silent_payment_address().is_none());
+    assert_eq!(loaded.list_silent_payment_unspent().count(), 0);
+    loaded.scan_silent_payments(scan_key, spend_key.public_key(&secp));
+    assert_eq!(
+        loaded.list_silent_payment_unspent().collect::<Vec<_>>(),
+        outputs
+    );
+}
+
+#[test]
+fn test_receive_silent_payment_before_prevouts() {
+    let secp = Secp256k1::new();
+    let scan_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let spend_key = SecretKey::from_slice(&[2; 32]).unwrap();
+    let (mut sender, _) = get_funded_wallet_wpkh();
+    let (mut receiver, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    receiver.scan_silent_payments(scan_key, spend_key.public_key(&secp));
+    let address = receiver.silent_payment_address().unwrap();
+
+    let payment = |sender: &mut Wallet| {
+        let mut builder = sender.build_tx();
+        builder.add_silent_payment_recipient(address, Amount::from_sat(10_000));
+        let mut psbt = builder.finish().unwrap();
+        assert!(sender.sign(&mut psbt, SignOptions::default()).unwrap());
+        let tx = psbt.extract_tx().unwrap();
+        sender.apply_unconfirmed_txs([(tx.clone(), 0)]);
+        tx
+    };
+    let confirmed_tx = payment(&mut sender);
+    let unconfirmed_tx = payment(&mut sender);
+
+    // the transactions are applied before their previous outputs are known
+    mine_block(&mut receiver, vec![confirmed_tx.clone()]);
+    receiver.apply_unconfirmed_txs([(unconfirmed_tx.clone(), 100)]);
+    assert_eq!(receiver.list_silent_payment_unspent().count(), 0);
+    let index = receiver.silent_payment_index().unwrap();
+    assert!(index.is_pending(confirmed_tx.compute_txid()));
+    assert!(index.is_pending(unconfirmed_tx.compute_txid()));
+
+    // they are scanned again once the previous outputs are inserted
+    insert_prevouts(&sender, &mut receiver, &confirmed_tx);
+    insert_prevouts(&sender, &mut receiver, &unconfirmed_tx);
+    assert_eq!(
+        receiver
+            .silent_payment_index()
+            .unwrap()
+            .pending_txs()
+            .count(),
+        0
+    );
+    let balance = receiver.silent_payment_balance();
+    assert_eq!(balance.confirmed, Amount::from_sat(10_000));
+    assert_eq!(balance.untrusted_pending, Amount::from_sat(10_000));
+}
+
+#[test]
+fn test_spend_silent_payment() {
+    let secp = Secp256k1::new();
+    let scan_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let spend_key = SecretKey::from_slice(&[2; 32]).unwrap();
+    let (mut sender, _) = get_funded_wallet_wpkh();
+    let (mut receiver, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    receiver.scan_silent_payments(scan_key, spend_key.public_key(&secp));
+    receiver.add_signer(
+        KeychainKind::External,
+        SignerOrdering(200),
+        Arc::new(SilentPaymentSigner::new(spend_key)),
+    );
+
+    let tx = pay_silent_payment(&mut sender, &mut receiver, Amount::from_sat(25_000));
+    receiver.apply_unconfirmed_txs([(tx, 100)]);
+    let output = receiver.list_silent_payment_unspent().next().unwrap();
+    assert_eq!(
+        receiver.silent_payment_balance().untrusted_pending,
+        Amount::from_sat(25_000)
+    );
+
+    let recipient = sender
+        .reveal_next_address(KeychainKind::External)
+        .script_pubkey();
+    let mut builder = receiver.build_tx();
+    builder
+        .add_utxo(output.outpoint)
+        .unwrap()
+        .manually_selected_only()
+        .drain_to(recipient);
+    let mut psbt = builder.finish().unwrap();
+    assert!(receiver.sign(&mut psbt, SignOptions::default()).unwrap());
+    let spend_tx = psbt.extract_tx().unwrap();
+
+    // the key path signature is valid for the output key
+    let witness = &spend_tx.input[0].witness;
+    assert_eq!(witness.len(), 1);
+    let signature = taproot::Signature::from_slice(&witness[0]).unwrap();
+    let sighash = SighashCache::new(&spend_tx)
+        .taproot_key_spend_signature_hash(
+            0,
+            &Prevouts::All(core::slice::from_ref(&output.txout)),
+            signature.sighash_type,
+        )
+        .unwrap();
+    let output_key =
+        XOnlyPublicKey::from_slice(&output.txout.script_pubkey.as_bytes()[2..]).unwrap();
+    secp.verify_schnorr(
+        &signature.signature,
+        &Message::from_digest(sighash.to_byte_array()),
+        &output_key,
+    )
+    .unwrap();
+
+    // the output is spent once the transaction is applied, even without change
+    assert_eq!(spend_tx.output.len(), 1);
+    receiver.apply_unconfirmed_txs([(spend_tx, 200)]);
+    assert_eq!(receiver.list_silent_payment_unspent().count(), 0);
+    assert_eq!(receiver.silent_payment_balance().total(), Amount::ZERO);
+}
+
+#[test]
+fn test_finalize_only_silent_payment_inputs() {
+    let secp = Secp256k1::new();
+    let scan_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let spend_key = SecretKey::from_slice(&[2; 32]).unwrap();
+    let (mut wallet, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    wallet.scan_silent_payments(scan_key, spend_key.public_key(&secp));
+
+    // a foreign taproot output, not received with silent payments
+    let output_key = SecretKey::from_slice(&[3; 32])
+        .unwrap()
+        .x_only_public_key(&secp)
+        .0;
+    let outpoint = OutPoint::new(Txid::all_zeros(), 0);
+    let psbt_input = psbt::Input {
+        witness_utxo: Some(TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: ScriptBuf::new_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(
+                output_key,
+            )),
+        }),
+        ..Default::default()
+    };
+    let recipient = wallet
+        .reveal_next_address(KeychainKind::External)
+        .script_pubkey();
+    let mut builder = wallet.build_tx();
+    builder
+        .only_witness_utxo()
+        .add_foreign_utxo(outpoint, psbt_input, Weight::from_wu(66))
+        .unwrap()
+        .add_recipient(recipient, Amount::from_sat(5_000));
+    let mut psbt = builder.finish().unwrap();
+    let index = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|txin| txin.previous_output == outpoint)
+        .unwrap();
+
+    // a key path signature doesn't finalize it
+    psbt.inputs[index].tap_key_sig = Some(taproot::Signature {
+        signature: schnorr::Signature::from_slice(&[1; 64]).unwrap(),
+        sighash_type: TapSighashType::Default,
+    });
+    assert!(!wallet
+        .finalize_psbt(&mut psbt, SignOptions::default())
+        .unwrap());
+    assert!(psbt.inputs[index].final_script_witness.is_none());
+}
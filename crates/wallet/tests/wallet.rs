@@ -1,18 +1,32 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::Context;
 use assert_matches::assert_matches;
+use bdk_chain::tx_graph::CalculateFeeError;
 use bdk_chain::{BlockId, ChainPosition, ConfirmationBlockTime};
 use bdk_wallet::coin_selection::{self, LargestFirstCoinSelection};
+use bdk_wallet::descriptor::policy::{Condition, PkOrF, Satisfaction, SpendingPath};
 use bdk_wallet::descriptor::{calc_checksum, DescriptorError, IntoWalletDescriptor};
-use bdk_wallet::error::CreateTxError;
+use bdk_wallet::error::{
+    BuildCpfpError, BuildFeeBumpError, BuildTimelockRefreshError, CreateTxError,
+};
+use bdk_wallet::labels::{Label, LabelError, LabelRef, LabelType};
+use bdk_wallet::message::{verify_message, MessageError, MessageSignature, SignatureFormat};
 use bdk_wallet::psbt::PsbtUtils;
-use bdk_wallet::signer::{SignOptions, SignerError};
+use bdk_wallet::signer::musig::{MusigError, MusigKey, MusigSigner};
+use bdk_wallet::signer::policy::{
+    MaxFee, MaxFeeRate, RejectForeignChange, SigningPolicy, SigningPolicyError,
+};
+use bdk_wallet::signer::{SignOptions, SignerError, SignerOrdering};
 use bdk_wallet::test_utils::*;
 use bdk_wallet::tx_builder::AddForeignUtxoError;
-use bdk_wallet::{AddressInfo, Balance, ChangeSet, Update, Wallet, WalletPersister, WalletTx};
+use bdk_wallet::{
+    AddressInfo, Balance, ChangeSet, LegacyChangeSet, Update, UtxoLock, Wallet, WalletPersister,
+    WalletTx,
+};
 use bdk_wallet::{KeychainKind, LoadError, LoadMismatch, LoadWithPersistError};
 use bitcoin::constants::{ChainHash, COINBASE_MATURITY};
 use bitcoin::hashes::Hash;
@@ -127,6 +141,358 @@ fn wallet_is_persisted() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn wallet_custom_keychains_are_persisted() -> anyhow::Result<()> {
+    fn run<Db, CreateDb, OpenDb>(
+        filename: &str,
+        create_db: CreateDb,
+        open_db: OpenDb,
+    ) -> anyhow::Result<()>
+    where
+        CreateDb: Fn(&Path) -> anyhow::Result<Db>,
+        OpenDb: Fn(&Path) -> anyhow::Result<Db>,
+        Db: WalletPersister + std::fmt::Debug,
+        Db::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let temp_dir = tempfile::tempdir().expect("must create tempdir");
+        let file_path = temp_dir.path().join(filename);
+        let (external_desc, internal_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+
+        // create new wallet with one custom keychain and add another one later
+        let addr = {
+            let mut db = create_db(&file_path)?;
+            let mut wallet = Wallet::create(external_desc, internal_desc)
+                .keychain(7, CUSTOM_DESC_A)
+                .network(Network::Testnet)
+                .create_wallet(&mut db)?;
+            assert!(wallet.add_keychain(8, CUSTOM_DESC_B)?);
+            let addr = wallet.reveal_next_address(KeychainKind::Custom(8));
+            assert!(wallet.persist(&mut db)?, "must write");
+            addr
+        };
+
+        // recover wallet
+        {
+            let mut db = open_db(&file_path).context("failed to recover db")?;
+            let mut wallet = Wallet::load()
+                .descriptor(KeychainKind::Custom(7), Some(CUSTOM_DESC_A))
+                .descriptor(KeychainKind::Custom(8), Some(CUSTOM_DESC_B))
+                .extract_keys()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(wallet.keychains().count(), 4);
+            assert_eq!(
+                wallet.derivation_index(KeychainKind::Custom(8)),
+                Some(addr.index)
+            );
+            assert!(!wallet
+                .get_signers(KeychainKind::Custom(7))
+                .signers()
+                .is_empty());
+            // adding the same keychain again is a no-op
+            assert!(!wallet.add_keychain(7, CUSTOM_DESC_A)?);
+        }
+
+        // loading with a different custom descriptor must fail
+        assert_matches!(
+            Wallet::load()
+                .descriptor(KeychainKind::Custom(7), Some(CUSTOM_DESC_B))
+                .load_wallet(&mut open_db(&file_path)?),
+            Err(LoadWithPersistError::InvalidChangeSet(LoadError::Mismatch(
+                LoadMismatch::Descriptor {
+                    keychain: KeychainKind::Custom(7),
+                    ..
+                }
+            )))
+        );
+        // loading with an unknown custom keychain must fail
+        assert_matches!(
+            Wallet::load()
+                .descriptor(KeychainKind::Custom(9), Some(CUSTOM_DESC_B))
+                .load_wallet(&mut open_db(&file_path)?),
+            Err(LoadWithPersistError::InvalidChangeSet(LoadError::Mismatch(
+                LoadMismatch::Descriptor {
+                    keychain: KeychainKind::Custom(9),
+                    loaded: None,
+                    ..
+                }
+            )))
+        );
+
+        Ok(())
+    }
+
+    run(
+        "store.db",
+        |path| Ok(bdk_file_store::Store::create_new(DB_MAGIC, path)?),
+        |path| Ok(bdk_file_store::Store::open(DB_MAGIC, path)?),
+    )?;
+    run::<bdk_chain::rusqlite::Connection, _, _>(
+        "store.sqlite",
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn wallet_labels_are_persisted() -> anyhow::Result<()> {
+    fn run<Db, CreateDb, OpenDb>(
+        filename: &str,
+        create_db: CreateDb,
+        open_db: OpenDb,
+    ) -> anyhow::Result<()>
+    where
+        CreateDb: Fn(&Path) -> anyhow::Result<Db>,
+        OpenDb: Fn(&Path) -> anyhow::Result<Db>,
+        Db: WalletPersister + std::fmt::Debug,
+        Db::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let temp_dir = tempfile::tempdir().expect("must create tempdir");
+        let file_path = temp_dir.path().join(filename);
+        let (external_desc, internal_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+        let txid = Txid::all_zeros();
+        let tx_ref = LabelRef::tx(txid);
+        let output_ref = LabelRef::output(OutPoint::new(txid, 0));
+        let input_ref = LabelRef::input(OutPoint::new(txid, 1));
+
+        // create new wallet and label some items
+        let addr_ref = {
+            let mut db = create_db(&file_path)?;
+            let mut wallet = Wallet::create(external_desc, internal_desc)
+                .network(Network::Testnet)
+                .create_wallet(&mut db)?;
+            let addr_ref = LabelRef::addr(&wallet.reveal_next_address(KeychainKind::External));
+            wallet.set_label(addr_ref.clone(), "donations");
+            wallet.set_label(tx_ref.clone(), "rent");
+            wallet.set_label(input_ref.clone(), "to be removed");
+            assert!(wallet.persist(&mut db)?, "must write");
+            addr_ref
+        };
+
+        // update, add and remove labels
+        {
+            let mut db = open_db(&file_path)?;
+            let mut wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(wallet.labels().count(), 3);
+            wallet.set_label(tx_ref.clone(), "rent for march");
+            wallet.set_label(
+                output_ref.clone(),
+                Label {
+                    label: "change".to_string(),
+                    origin: None,
+                    spendable: Some(false),
+                },
+            );
+            assert_eq!(
+                wallet.remove_label(&input_ref),
+                Some(Label::from("to be removed"))
+            );
+            assert_eq!(wallet.remove_label(&input_ref), None);
+            assert!(wallet.persist(&mut db)?, "must write");
+        }
+
+        // recover wallet
+        {
+            let mut db = open_db(&file_path).context("failed to recover db")?;
+            let wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(
+                wallet.labels().collect::<Vec<_>>(),
+                vec![
+                    (&tx_ref, &Label::from("rent for march")),
+                    (&addr_ref, &Label::from("donations")),
+                    (
+                        &output_ref,
+                        &Label {
+                            label: "change".to_string(),
+                            origin: None,
+                            spendable: Some(false),
+                        }
+                    ),
+                ]
+            );
+            assert_eq!(wallet.get_label(&input_ref), None);
+        }
+
+        Ok(())
+    }
+
+    run(
+        "store.db",
+        |path| Ok(bdk_file_store::Store::create_new(DB_MAGIC, path)?),
+        |path| Ok(bdk_file_store::Store::open(DB_MAGIC, path)?),
+    )?;
+    run::<bdk_chain::rusqlite::Connection, _, _>(
+        "store.sqlite",
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn wallet_utxo_locks_are_persisted() -> anyhow::Result<()> {
+    fn run<Db, CreateDb, OpenDb>(
+        filename: &str,
+        create_db: CreateDb,
+        open_db: OpenDb,
+    ) -> anyhow::Result<()>
+    where
+        CreateDb: Fn(&Path) -> anyhow::Result<Db>,
+        OpenDb: Fn(&Path) -> anyhow::Result<Db>,
+        Db: WalletPersister + std::fmt::Debug,
+        Db::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let temp_dir = tempfile::tempdir().expect("must create tempdir");
+        let file_path = temp_dir.path().join(filename);
+        let (external_desc, internal_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+        let outpoint_a = OutPoint::new(Txid::all_zeros(), 0);
+        let outpoint_b = OutPoint::new(Txid::all_zeros(), 1);
+
+        // create new wallet and lock some utxos
+        {
+            let mut db = create_db(&file_path)?;
+            let mut wallet = Wallet::create(external_desc, internal_desc)
+                .network(Network::Testnet)
+                .create_wallet(&mut db)?;
+            wallet.lock_utxo(outpoint_a, "dust attack", None);
+            wallet.lock_utxo(outpoint_b, "compliance review", Some(100));
+            assert!(wallet.persist(&mut db)?, "must write");
+        }
+
+        // update and remove locks
+        {
+            let mut db = open_db(&file_path)?;
+            let mut wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(wallet.locked_utxos().count(), 2);
+            wallet.lock_utxo(outpoint_b, "compliance review", Some(200));
+            assert!(wallet.unlock_utxo(outpoint_a).is_some());
+            assert!(wallet.persist(&mut db)?, "must write");
+        }
+
+        // recover wallet
+        {
+            let mut db = open_db(&file_path).context("failed to recover db")?;
+            let wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(
+                wallet.locked_utxos().collect::<Vec<_>>(),
+                vec![(
+                    outpoint_b,
+                    &UtxoLock {
+                        reason: "compliance review".to_string(),
+                        until_height: Some(200),
+                    }
+                )]
+            );
+        }
+
+        Ok(())
+    }
+
+    run(
+        "store.db",
+        |path| Ok(bdk_file_store::Store::create_new(DB_MAGIC, path)?),
+        |path| Ok(bdk_file_store::Store::open(DB_MAGIC, path)?),
+    )?;
+    run::<bdk_chain::rusqlite::Connection, _, _>(
+        "store.sqlite",
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn wallet_pending_spends_are_persisted() -> anyhow::Result<()> {
+    fn run<Db, CreateDb, OpenDb>(
+        filename: &str,
+        create_db: CreateDb,
+        open_db: OpenDb,
+    ) -> anyhow::Result<()>
+    where
+        CreateDb: Fn(&Path) -> anyhow::Result<Db>,
+        OpenDb: Fn(&Path) -> anyhow::Result<Db>,
+        Db: WalletPersister + std::fmt::Debug,
+        Db::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let temp_dir = tempfile::tempdir().expect("must create tempdir");
+        let file_path = temp_dir.path().join(filename);
+        let (external_desc, internal_desc) = get_test_wpkh_and_change_desc();
+        let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+            .unwrap()
+            .assume_checked();
+
+        // create new wallet and reserve the inputs of two psbts
+        let (txid_a, txid_b) = {
+            let mut db = create_db(&file_path)?;
+            let mut wallet = Wallet::create(external_desc, internal_desc)
+                .network(Network::Testnet)
+                .create_wallet(&mut db)?;
+            receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
+            receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
+            let mut txids = vec![];
+            for _ in 0..2 {
+                let mut builder = wallet.build_tx();
+                builder.add_recipient(addr.script_pubkey(), Amount::from_sat(10_000));
+                let psbt = builder.finish()?;
+                txids.push(wallet.insert_pending_spend(&psbt, Some(100)));
+            }
+            assert!(wallet.persist(&mut db)?, "must write");
+            (txids[0], txids[1])
+        };
+
+        // release one of them
+        {
+            let mut db = open_db(&file_path)?;
+            let mut wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            assert_eq!(wallet.pending_spends().count(), 2);
+            assert!(wallet.release_pending_spend(txid_a).is_some());
+            assert!(wallet.persist(&mut db)?, "must write");
+        }
+
+        // recover wallet
+        {
+            let mut db = open_db(&file_path).context("failed to recover db")?;
+            let wallet = Wallet::load()
+                .load_wallet(&mut db)?
+                .expect("wallet must exist");
+            let pending_spends = wallet.pending_spends().collect::<Vec<_>>();
+            assert_eq!(pending_spends.len(), 1);
+            assert_eq!(pending_spends[0].0, txid_b);
+            assert_eq!(pending_spends[0].1.tx.compute_txid(), txid_b);
+            assert_eq!(pending_spends[0].1.until_height, Some(100));
+        }
+
+        Ok(())
+    }
+
+    run(
+        "store.db",
+        |path| Ok(bdk_file_store::Store::create_new(DB_MAGIC, path)?),
+        |path| Ok(bdk_file_store::Store::open(DB_MAGIC, path)?),
+    )?;
+    run::<bdk_chain::rusqlite::Connection, _, _>(
+        "store.sqlite",
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+        |path| Ok(bdk_chain::rusqlite::Connection::open(path)?),
+    )?;
+
+    Ok(())
+}
+
 #[test]
 fn wallet_load_checks() -> anyhow::Result<()> {
     fn run<Db, CreateDb, OpenDb>(
@@ -223,6 +589,43 @@ fn wallet_load_checks() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[test]
+fn wallet_load_legacy_file_store() -> anyhow::Result<()> {
+    let temp_dir = tempfile::tempdir().expect("must create tempdir");
+    let file_path = temp_dir.path().join("store.db");
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+
+    // a store written before the custom keychains, labels, locks and pending spends
+    let (mut wallet, _) = get_funded_wallet(desc, change_desc);
+    let changeset = wallet.take_staged().expect("wallet has changes");
+    let legacy = LegacyChangeSet {
+        descriptor: changeset.descriptor,
+        change_descriptor: changeset.change_descriptor,
+        network: changeset.network,
+        local_chain: changeset.local_chain,
+        tx_graph: changeset.tx_graph,
+        indexer: changeset.indexer,
+    };
+    let mut db = bdk_file_store::Store::<LegacyChangeSet>::create_new(DB_MAGIC, &file_path)?;
+    db.append_changeset(&legacy)?;
+    drop(db);
+
+    // it can't be read with the current changeset, but can be migrated
+    let mut db = bdk_file_store::Store::<ChangeSet>::open(DB_MAGIC, &file_path)?;
+    assert!(db.aggregate_changesets().is_err());
+    let mut db = bdk_file_store::Store::<LegacyChangeSet>::open(DB_MAGIC, &file_path)?;
+    let changeset = ChangeSet::from(db.aggregate_changesets()?.expect("store has changes"));
+    let loaded = Wallet::load()
+        .load_wallet_no_persist(changeset)?
+        .expect("wallet was persisted");
+    assert_eq!(loaded.balance(), wallet.balance());
+    assert_eq!(
+        loaded.derivation_index(KeychainKind::External),
+        wallet.derivation_index(KeychainKind::External)
+    );
+    Ok(())
+}
+
 #[test]
 fn wallet_should_persist_anchors_and_recover() {
     use bdk_chain::rusqlite;
@@ -355,14 +758,123 @@ fn test_error_external_and_internal_are_the_same() {
     );
 }
 
+const CUSTOM_DESC_A: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/2/*)";
+const CUSTOM_DESC_B: &str = "tr(tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/3/*)";
+
 #[test]
-fn test_descriptor_checksum() {
-    let (wallet, _) = get_funded_wallet_wpkh();
-    let checksum = wallet.descriptor_checksum(KeychainKind::External);
-    assert_eq!(checksum.len(), 8);
+#[should_panic(expected = "keychain must exist")]
+fn test_unknown_custom_keychain_panics() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    wallet.reveal_next_address(KeychainKind::Custom(7));
+}
 
-    let raw_descriptor = wallet
-        .keychains()
+#[test]
+fn test_custom_keychains() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+    let mut wallet = Wallet::create(desc, change_desc)
+        .keychain(7, CUSTOM_DESC_A)
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap();
+    assert!(wallet.add_keychain(8, CUSTOM_DESC_B).unwrap());
+    assert!(!wallet.add_keychain(8, CUSTOM_DESC_B).unwrap());
+    assert_eq!(wallet.keychains().count(), 4);
+
+    // every keychain derives its own addresses
+    let addr_a = wallet.reveal_next_address(KeychainKind::Custom(7));
+    let addr_b = wallet.reveal_next_address(KeychainKind::Custom(8));
+    let addr_ext = wallet.reveal_next_address(KeychainKind::External);
+    assert_eq!(addr_a.keychain, KeychainKind::Custom(7));
+    assert_eq!(addr_a.index, 0);
+    assert_eq!(addr_b.index, 0);
+    assert_ne!(addr_a.address, addr_b.address);
+    assert_ne!(addr_a.address, addr_ext.address);
+    assert_ne!(
+        KeychainKind::Custom(7).to_bytes(),
+        KeychainKind::Custom(8).to_bytes()
+    );
+
+    let anchor = ConfirmationBlockTime {
+        block_id: wallet.latest_checkpoint().block_id(),
+        confirmation_time: 0,
+    };
+    insert_checkpoint(
+        &mut wallet,
+        BlockId {
+            height: 1_000,
+            hash: BlockHash::all_zeros(),
+        },
+    );
+    receive_output_to_address(&mut wallet, addr_a.address, 30_000, anchor);
+    receive_output_to_address(&mut wallet, addr_b.address, 20_000, anchor);
+    receive_output_to_address(&mut wallet, addr_ext.address, 10_000, anchor);
+
+    assert_eq!(
+        wallet.keychain_balance(KeychainKind::Custom(7)).total(),
+        Amount::from_sat(30_000)
+    );
+    assert_eq!(
+        wallet.keychain_balance(KeychainKind::Custom(8)).total(),
+        Amount::from_sat(20_000)
+    );
+    assert_eq!(wallet.balance().total(), Amount::from_sat(60_000));
+
+    // spend coins from several keychains, change goes to the internal keychain
+    let addr = Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx().coin_selection(LargestFirstCoinSelection);
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(45_000));
+    let mut psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    let change_spk = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .find(|txout| txout.script_pubkey != addr.script_pubkey())
+        .map(|txout| txout.script_pubkey.clone())
+        .expect("must have change");
+    assert_matches!(
+        wallet.derivation_of_spk(change_spk),
+        Some((KeychainKind::Internal, 0))
+    );
+
+    // custom keychains are signed with their own keys
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+}
+
+#[test]
+fn test_custom_keychain_errors() {
+    let (desc, change_desc) = get_test_tr_single_sig_xprv_and_change_desc();
+    let mut wallet = Wallet::create(desc, change_desc)
+        .keychain(7, CUSTOM_DESC_A)
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap();
+    assert_matches!(
+        wallet.add_keychain(8, CUSTOM_DESC_A),
+        Err(DescriptorError::DescriptorAlreadyInUse)
+    );
+    assert_matches!(
+        wallet.add_keychain(9, desc),
+        Err(DescriptorError::DescriptorAlreadyInUse)
+    );
+    assert_matches!(
+        wallet.add_keychain(7, CUSTOM_DESC_B),
+        Err(DescriptorError::KeychainAlreadyAssigned)
+    );
+    assert_eq!(wallet.keychains().count(), 3);
+}
+
+#[test]
+fn test_descriptor_checksum() {
+    let (wallet, _) = get_funded_wallet_wpkh();
+    let checksum = wallet.descriptor_checksum(KeychainKind::External);
+    assert_eq!(checksum.len(), 8);
+
+    let raw_descriptor = wallet
+        .keychains()
         .next()
         .unwrap()
         .1
@@ -890,6 +1402,106 @@ fn test_create_tx_custom_fee_rate() {
     assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), FeeRate::from_sat_per_vb_unchecked(5), @add_signature);
 }
 
+#[test]
+fn test_create_tx_fee_target() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    let estimates: BTreeMap<u16, FeeRate> = [
+        (1, FeeRate::from_sat_per_vb_u32(20)),
+        (6, FeeRate::from_sat_per_vb_u32(5)),
+        (144, FeeRate::from_sat_per_kwu(100)),
+    ]
+    .into();
+
+    let cases = [
+        (3, FeeRate::from_sat_per_vb_u32(20)),
+        (6, FeeRate::from_sat_per_vb_u32(5)),
+        // below the minimum relay fee rate
+        (1008, FeeRate::BROADCAST_MIN),
+        // no estimate
+        (0, FeeRate::BROADCAST_MIN),
+    ];
+    for (target, expected) in cases {
+        let mut builder = wallet.build_tx();
+        builder
+            .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+            .fee_target(target, &estimates)
+            .unwrap();
+        let psbt = builder.finish().unwrap();
+        let fee = check_fee!(wallet, psbt);
+        assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), expected, @add_signature);
+    }
+}
+
+#[test]
+fn test_create_tx_silent_payment() {
+    use bdk_wallet::silent_payments::{self, SilentPaymentAddress, SilentPaymentError};
+    use bitcoin::key::{Keypair, TapTweak};
+    use bitcoin::secp256k1::{Parity, SecretKey};
+    use bitcoin::{NetworkKind, PrivateKey};
+
+    let secp = Secp256k1::new();
+    let scan_key = SecretKey::from_slice(&[1; 32]).unwrap().public_key(&secp);
+    let spend_key = SecretKey::from_slice(&[2; 32]).unwrap().public_key(&secp);
+    let address = SilentPaymentAddress::new(scan_key, spend_key, NetworkKind::Test);
+
+    let wpkh_key = PrivateKey::from_wif("cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW")
+        .unwrap()
+        .inner;
+    let tr_key = {
+        let key = PrivateKey::from_wif("cNJmN3fH9DDbDt131fQNkVakkpzawJBSeybCUNmP1BovpmGQ45xG")
+            .unwrap()
+            .inner;
+        let keypair = Keypair::from_secret_key(&secp, &key)
+            .tap_tweak(&secp, None)
+            .to_keypair();
+        match keypair.x_only_public_key().1 {
+            Parity::Odd => keypair.secret_key().negate(),
+            Parity::Even => keypair.secret_key(),
+        }
+    };
+
+    for (desc, input_key) in [
+        (get_test_wpkh(), wpkh_key),
+        (get_test_tr_single_sig(), tr_key),
+    ] {
+        let (mut wallet, _) = get_funded_wallet_single(desc);
+        let mut builder = wallet.build_tx();
+        builder.add_silent_payment_recipient(address, Amount::from_sat(25_000));
+        let psbt = builder.finish().unwrap();
+
+        let outpoints = psbt
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output);
+        let output_key =
+            silent_payments::create_outputs(&secp, outpoints, &[input_key], &[address]).unwrap()[0];
+        let output = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .find(|txout| txout.value == Amount::from_sat(25_000))
+            .unwrap();
+        assert_eq!(
+            output.script_pubkey,
+            ScriptBuf::new_p2tr_tweaked(output_key.dangerous_assume_tweaked())
+        );
+    }
+
+    // the address must be for the network of the wallet
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let address = SilentPaymentAddress::new(scan_key, spend_key, NetworkKind::Main);
+    let mut builder = wallet.build_tx();
+    builder.add_silent_payment_recipient(address, Amount::from_sat(25_000));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::SilentPayment(
+            SilentPaymentError::WrongNetwork(_)
+        ))
+    );
+}
+
 #[test]
 fn test_create_tx_absolute_fee() {
     let (mut wallet, _) = get_funded_wallet_wpkh();
@@ -1395,6 +2007,293 @@ fn test_create_tx_policy_path_ignored_subtree_with_csv() {
     assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFD));
 }
 
+// The public descriptor key of a WIF private key
+fn wif_public_key(wif: &str) -> DescriptorPublicKey {
+    let secp = Secp256k1::new();
+    let public_key = bitcoin::PrivateKey::from_wif(wif)
+        .unwrap()
+        .public_key(&secp);
+    DescriptorPublicKey::from_str(&public_key.to_string()).unwrap()
+}
+
+#[test]
+fn test_create_tx_assets_use_csv() {
+    use miniscript::plan::Assets;
+
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    // only the key "B" is available, so the csv path is the only satisfiable one
+    let assets = Assets::new()
+        .add(wif_public_key(
+            "cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8",
+        ))
+        .older(bitcoin::relative::LockTime::from_height(144));
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(5))
+        .assets(assets);
+    let mut psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(144));
+    assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
+
+    // the csv isn't satisfied yet
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(!finalized);
+
+    let finalized = wallet
+        .sign(
+            &mut psbt,
+            SignOptions {
+                assume_height: Some(2_000 + 144),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(finalized);
+
+    // the input is satisfied with the key "B" even though the wallet also signed with "A"
+    let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+    assert_eq!(witness.len(), 3);
+    assert!(witness.nth(1).unwrap().is_empty());
+
+    // coin selection used the exact satisfaction weight
+    let fee = check_fee!(wallet, psbt).unwrap();
+    let tx = psbt.clone().extract_tx().unwrap();
+    let expected_fee = FeeRate::from_sat_per_vb_u32(5) * tx.weight();
+    assert!(fee.to_sat().abs_diff(expected_fee.to_sat()) <= 5);
+}
+
+#[test]
+fn test_create_tx_assets_cheapest_path() {
+    use miniscript::plan::Assets;
+
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    let assets = Assets::new()
+        .add(vec![
+            wif_public_key("cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu"),
+            wif_public_key("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8"),
+        ])
+        .older(bitcoin::relative::LockTime::from_height(144));
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .assets(assets);
+    let mut psbt = builder.finish().unwrap();
+
+    // the key "A" alone is cheaper than the key "B" and the csv
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFD));
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+    let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+    assert_eq!(witness.len(), 2);
+}
+
+#[test]
+fn test_create_tx_assets_unsatisfiable() {
+    use miniscript::plan::Assets;
+
+    let (mut wallet, txid) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    // the key "B" without the csv can't satisfy the descriptor
+    let key_b = wif_public_key("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8");
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .assets(Assets::new().add(key_b.clone()));
+    assert_matches!(builder.finish(), Err(CreateTxError::CoinSelection(_)));
+
+    let outpoint = OutPoint { txid, vout: 0 };
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(30_000))
+        .add_utxo(outpoint)
+        .unwrap()
+        .assets(Assets::new().add(key_b));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::UnsatisfiableUtxo(op)) if op == outpoint
+    );
+}
+
+#[test]
+fn test_create_tx_assets_cltv() {
+    use miniscript::plan::Assets;
+
+    let (mut wallet, _) = get_funded_wallet_single(get_test_single_sig_cltv());
+    let assets = Assets::new()
+        .add(wif_public_key(
+            "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW",
+        ))
+        .after(absolute::LockTime::from_height(100_000).unwrap());
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .assets(assets);
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.lock_time.to_consensus_u32(), 100_000);
+    assert_eq!(psbt.unsigned_tx.input[0].sequence, Sequence(0xFFFFFFFD));
+
+    // a nLockTime lower than the one required by the plan is rejected
+    let assets = Assets::new()
+        .add(wif_public_key(
+            "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW",
+        ))
+        .after(absolute::LockTime::from_height(100_000).unwrap());
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .nlocktime(absolute::LockTime::from_height(50_000).unwrap())
+        .assets(assets);
+    assert_matches!(builder.finish(), Err(CreateTxError::LockTime { .. }));
+}
+
+#[test]
+fn test_create_tx_assets_taproot_script_path() {
+    use bitcoin::key::XOnlyPublicKey;
+    use miniscript::plan::Assets;
+
+    let (mut wallet, _) = get_funded_wallet_single(get_test_tr_with_taptree_both_priv());
+    // the internal key is not available, only the key of the second leaf
+    let key = wif_public_key("cNaQCDwmmh4dS9LzCgVtyy1e1xjCJ21GUDHe9K98nzb689JvinGV");
+    let x_only_key = match &key {
+        DescriptorPublicKey::Single(single) => match single.key {
+            miniscript::descriptor::SinglePubKey::FullKey(pk) => XOnlyPublicKey::from(pk.inner),
+            miniscript::descriptor::SinglePubKey::XOnly(pk) => pk,
+        },
+        _ => unreachable!(),
+    };
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(5))
+        .assets(Assets::new().add(key));
+    let mut psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.inputs[0].tap_internal_key, None);
+    assert_eq!(psbt.inputs[0].tap_scripts.len(), 1);
+    let (leaf_hashes, _) = &psbt.inputs[0].tap_key_origins[&x_only_key];
+    assert_eq!(leaf_hashes.len(), 1);
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+    // signature, script and control block
+    let witness = psbt.inputs[0].final_script_witness.as_ref().unwrap();
+    assert_eq!(witness.len(), 3);
+
+    let fee = check_fee!(wallet, psbt).unwrap();
+    let tx = psbt.clone().extract_tx().unwrap();
+    let expected_fee = FeeRate::from_sat_per_vb_u32(5) * tx.weight();
+    assert!(fee.to_sat().abs_diff(expected_fee.to_sat()) <= 5);
+}
+
+#[test]
+fn test_list_spendability() {
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    let key_a = PkOrF::Pubkey(
+        bitcoin::PrivateKey::from_wif("cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu")
+            .unwrap()
+            .public_key(&Secp256k1::new()),
+    );
+    let unconfirmed = receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(100));
+
+    let utxos = wallet.list_spendability().unwrap();
+    assert_eq!(utxos.len(), 2);
+    for utxo in &utxos {
+        assert_eq!(utxo.paths.len(), 2);
+        let (key_path, csv_path): (Vec<_>, Vec<_>) = utxo
+            .paths
+            .iter()
+            .partition(|path| path.path.is_signed_by(core::slice::from_ref(&key_a)));
+        assert_eq!(key_path.len(), 1);
+        assert_eq!(key_path[0].spendable_at, Some(Default::default()));
+        assert!(key_path[0].is_spendable(0, 0));
+        assert_eq!(csv_path[0].path.condition.csv, Some(Sequence(144)));
+
+        if utxo.utxo.outpoint == unconfirmed {
+            // the relative timelock can't be projected before the confirmation
+            assert_eq!(csv_path[0].spendable_at, None);
+        } else {
+            assert_eq!(csv_path[0].spendable_at.unwrap().height, Some(2_000 + 144));
+            assert!(!csv_path[0].is_spendable(2_143, 0));
+            assert!(csv_path[0].is_spendable(2_144, 0));
+        }
+    }
+
+    let (wallet, _) = get_funded_wallet_single(get_test_single_sig_cltv());
+    let utxos = wallet.list_spendability().unwrap();
+    assert_eq!(utxos[0].paths.len(), 1);
+    assert_eq!(
+        utxos[0].paths[0].spendable_at.unwrap().height,
+        Some(100_000)
+    );
+
+    // relative time-based timelocks start from the median time past of the block before the
+    // confirmation, which the wallet doesn't know
+    let (wallet, _) = get_funded_wallet_single("wsh(or_d(pk(cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu),and_v(v:pk(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(4194305))))");
+    let utxos = wallet.list_spendability().unwrap();
+    let (key_path, csv_path): (Vec<_>, Vec<_>) = utxos[0]
+        .paths
+        .iter()
+        .partition(|path| path.path.is_signed_by(core::slice::from_ref(&key_a)));
+    assert_eq!(key_path[0].spendable_at, Some(Default::default()));
+    assert_eq!(csv_path[0].spendable_at, None);
+    assert!(!csv_path[0].is_spendable(u32::MAX, u64::MAX));
+}
+
+#[test]
+fn test_projected_balance() {
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    let funded = wallet.balance().total();
+    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(100));
+    let key_b = PkOrF::Pubkey(
+        bitcoin::PrivateKey::from_wif("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8")
+            .unwrap()
+            .public_key(&Secp256k1::new()),
+    );
+
+    // every output can be spent with any path
+    let balance = wallet.projected_balance(0, 0, |_| true).unwrap();
+    assert_eq!(balance.spendable, funded + Amount::from_sat(25_000));
+    assert_eq!(balance.locked, Amount::ZERO);
+
+    // the recovery key "B" must wait for the confirmation and the csv
+    let by_key_b = |path: &SpendingPath| path.is_signed_by(core::slice::from_ref(&key_b));
+    let balance = wallet.projected_balance(2_143, 0, by_key_b).unwrap();
+    assert_eq!(balance.spendable, Amount::ZERO);
+    assert_eq!(balance.locked, funded + Amount::from_sat(25_000));
+    let balance = wallet.projected_balance(2_144, 0, by_key_b).unwrap();
+    assert_eq!(balance.spendable, funded);
+    assert_eq!(balance.locked, Amount::from_sat(25_000));
+
+    // no path can be signed by no key
+    let balance = wallet
+        .projected_balance(u32::MAX, u64::MAX, |path| path.is_signed_by(&[]))
+        .unwrap();
+    assert_eq!(balance.total(), Amount::ZERO);
+}
+
 #[test]
 fn test_create_tx_global_xpubs_with_origin() {
     use bitcoin::bip32;
@@ -1609,8 +2508,7 @@ fn test_add_foreign_utxo() {
 }
 
 #[test]
-fn test_calculate_fee_with_missing_foreign_utxo() {
-    use bdk_chain::tx_graph::CalculateFeeError;
+fn test_add_foreign_utxo_with_descriptor() {
     let (mut wallet1, _) = get_funded_wallet_wpkh();
     let (wallet2, _) =
         get_funded_wallet_single("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
@@ -1619,26 +2517,164 @@ fn test_calculate_fee_with_missing_foreign_utxo() {
         .unwrap()
         .assume_checked();
     let utxo = wallet2.list_unspent().next().expect("must take!");
-    let foreign_utxo_satisfaction = wallet2
-        .public_descriptor(KeychainKind::External)
-        .max_weight_to_satisfy()
-        .unwrap();
-
     let psbt_input = psbt::Input {
         witness_utxo: Some(utxo.txout.clone()),
         ..Default::default()
     };
 
+    // the descriptor must match the script of the utxo
+    let wrong_descriptor = wallet1.public_descriptor(KeychainKind::External).clone();
+    let mut builder = wallet1.build_tx();
+    assert_matches!(
+        builder.add_foreign_utxo_with_descriptor(
+            utxo.outpoint,
+            psbt_input.clone(),
+            &wrong_descriptor,
+            0,
+            None,
+        ),
+        Err(AddForeignUtxoError::DescriptorMismatch(outpoint)) if outpoint == utxo.outpoint
+    );
+
+    let foreign_descriptor = wallet2.public_descriptor(KeychainKind::External).clone();
     let mut builder = wallet1.build_tx();
     builder
         .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
         .only_witness_utxo()
-        .add_foreign_utxo(utxo.outpoint, psbt_input, foreign_utxo_satisfaction)
+        .add_foreign_utxo_with_descriptor(
+            utxo.outpoint,
+            psbt_input,
+            &foreign_descriptor,
+            utxo.derivation_index,
+            None,
+        )
         .unwrap();
-    let psbt = builder.finish().unwrap();
-    let tx = psbt.extract_tx().expect("failed to extract tx");
-    let res = wallet1.calculate_fee(&tx);
-    assert!(
+    let mut psbt = builder.finish().unwrap();
+
+    let foreign_index = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|input| input.previous_output == utxo.outpoint)
+        .expect("foreign_utxo should be in there");
+    assert_eq!(psbt.inputs[foreign_index].bip32_derivation.len(), 1);
+
+    let sign_options = SignOptions {
+        trust_witness_utxo: true,
+        ..Default::default()
+    };
+    assert!(!wallet1.sign(&mut psbt, sign_options.clone()).unwrap());
+    assert!(wallet2.sign(&mut psbt, sign_options).unwrap());
+}
+
+#[test]
+fn test_add_foreign_utxo_with_descriptor_and_assets() {
+    use miniscript::plan::Assets;
+
+    let (mut wallet1, _) = get_funded_wallet_wpkh();
+    let (wallet2, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet2.list_unspent().next().expect("must take!");
+    let psbt_input = psbt::Input {
+        witness_utxo: Some(utxo.txout.clone()),
+        ..Default::default()
+    };
+    let foreign_descriptor = wallet2.public_descriptor(KeychainKind::External).clone();
+
+    // the key "B" alone can't satisfy the descriptor
+    let key_b = wif_public_key("cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8");
+    let mut builder = wallet1.build_tx();
+    assert_matches!(
+        builder.add_foreign_utxo_with_descriptor(
+            utxo.outpoint,
+            psbt_input.clone(),
+            &foreign_descriptor,
+            utxo.derivation_index,
+            Some(&Assets::new().add(key_b.clone())),
+        ),
+        Err(AddForeignUtxoError::Unsatisfiable(_))
+    );
+
+    let assets = Assets::new()
+        .add(key_b)
+        .older(bitcoin::relative::LockTime::from_height(144));
+    let mut builder = wallet1.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
+        .only_witness_utxo()
+        .add_foreign_utxo_with_descriptor(
+            utxo.outpoint,
+            psbt_input,
+            &foreign_descriptor,
+            utxo.derivation_index,
+            Some(&assets),
+        )
+        .unwrap();
+    let mut psbt = builder.finish().unwrap();
+
+    let foreign_index = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|input| input.previous_output == utxo.outpoint)
+        .expect("foreign_utxo should be in there");
+    assert_eq!(
+        psbt.unsigned_tx.input[foreign_index].sequence,
+        Sequence(144)
+    );
+    assert!(psbt.inputs[foreign_index].witness_script.is_some());
+    assert_eq!(psbt.inputs[foreign_index].bip32_derivation.len(), 1);
+
+    let sign_options = SignOptions {
+        trust_witness_utxo: true,
+        assume_height: Some(2_000 + 144),
+        ..Default::default()
+    };
+    assert!(!wallet1.sign(&mut psbt, sign_options.clone()).unwrap());
+    assert!(wallet2.sign(&mut psbt, sign_options).unwrap());
+
+    // the foreign input is satisfied with the planned path
+    let witness = psbt.inputs[foreign_index]
+        .final_script_witness
+        .as_ref()
+        .unwrap();
+    assert_eq!(witness.len(), 3);
+}
+
+#[test]
+fn test_calculate_fee_with_missing_foreign_utxo() {
+    use bdk_chain::tx_graph::CalculateFeeError;
+    let (mut wallet1, _) = get_funded_wallet_wpkh();
+    let (wallet2, _) =
+        get_funded_wallet_single("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet2.list_unspent().next().expect("must take!");
+    let foreign_utxo_satisfaction = wallet2
+        .public_descriptor(KeychainKind::External)
+        .max_weight_to_satisfy()
+        .unwrap();
+
+    let psbt_input = psbt::Input {
+        witness_utxo: Some(utxo.txout.clone()),
+        ..Default::default()
+    };
+
+    let mut builder = wallet1.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(60_000))
+        .only_witness_utxo()
+        .add_foreign_utxo(utxo.outpoint, psbt_input, foreign_utxo_satisfaction)
+        .unwrap();
+    let psbt = builder.finish().unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let res = wallet1.calculate_fee(&tx);
+    assert!(
         matches!(res, Err(CalculateFeeError::MissingTxOut(outpoints)) if outpoints[0] == utxo.outpoint)
     );
 }
@@ -2616,56 +3652,1204 @@ fn test_bump_fee_unconfirmed_inputs_only() {
         .unwrap()
         .assume_checked();
     let mut builder = wallet.build_tx();
-    builder.drain_wallet().drain_to(addr.script_pubkey());
+    builder.drain_wallet().drain_to(addr.script_pubkey());
+    let psbt = builder.finish().unwrap();
+    // Now we receive one transaction with 0 confirmations. We won't be able to use that for
+    // fee bumping, as it's still unconfirmed!
+    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder.fee_rate(FeeRate::from_sat_per_vb_unchecked(25));
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_bump_fee_unconfirmed_input() {
+    // We create a tx draining the wallet and spending one confirmed
+    // and one unconfirmed UTXO. We check that we can fee bump normally
+    // (BIP125 rule 2 only apply to newly added unconfirmed input, you can
+    // always fee bump with an unconfirmed input if it was included in the
+    // original transaction)
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    // We receive a tx with 0 confirmations, which will be used as an input
+    // in the drain tx.
+    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
+    let mut builder = wallet.build_tx();
+    builder.drain_wallet().drain_to(addr.script_pubkey());
+    let psbt = builder.finish().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
+        // remove original tx drain_to address and amount
+        .set_recipients(Vec::new())
+        // set back original drain_to address
+        .drain_to(addr.script_pubkey())
+        // drain wallet output amount will be re-calculated with new fee rate
+        .drain_wallet();
+    builder.finish().unwrap();
+}
+
+#[test]
+fn test_bump_fee_smaller_replacement_pays_original_fee() {
+    // The original tx carries a large OP_RETURN output that the replacement drops, so a fee rate
+    // just above the original one isn't enough to satisfy BIP125 rules 3 and 4.
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let data = PushBytesBuf::try_from(vec![0; 80]).unwrap();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_data(&data)
+        .fee_rate(FeeRate::from_sat_per_vb_u32(10));
+    let psbt = builder.finish().unwrap();
+    let original_fee = psbt.fee().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+
+    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    builder
+        .set_recipients(vec![(addr.script_pubkey(), Amount::from_sat(25_000))])
+        .fee_rate(FeeRate::from_sat_per_vb_u32(11));
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    assert!(FeeRate::from_sat_per_vb_u32(11) * tx.weight() < original_fee);
+    assert!(fee >= original_fee + FeeRate::BROADCAST_MIN * tx.weight());
+}
+
+#[test]
+fn test_batch_replacement() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let new_addr = Address::from_str("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt")
+        .unwrap()
+        .assume_checked();
+    let feerate = FeeRate::from_sat_per_vb_u32(5);
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(feerate);
+    let psbt = builder.finish().unwrap();
+    let original_fee = psbt.fee().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx.clone());
+
+    let mut builder = wallet.build_batch_replacement(txid).unwrap();
+    builder.add_recipient(new_addr.script_pubkey(), Amount::from_sat(10_000));
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee().unwrap();
+    let new_tx = psbt.clone().extract_tx().expect("failed to extract tx");
+
+    // all the original inputs are spent and all the original payments are kept
+    assert_eq!(new_tx.input.len(), tx.input.len());
+    assert_eq!(new_tx.input[0].previous_output, tx.input[0].previous_output);
+    assert_eq!(new_tx.output.len(), 3);
+    let value_to = |spk: ScriptBuf| {
+        new_tx
+            .output
+            .iter()
+            .find(|txout| txout.script_pubkey == spk)
+            .map(|txout| txout.value)
+    };
+    assert_eq!(
+        value_to(addr.script_pubkey()),
+        Some(Amount::from_sat(25_000))
+    );
+    assert_eq!(
+        value_to(new_addr.script_pubkey()),
+        Some(Amount::from_sat(10_000))
+    );
+
+    // BIP125 rules 3 and 4
+    let mut signed_tx = new_tx.clone();
+    for txin in &mut signed_tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    assert!(fee >= original_fee + FeeRate::BROADCAST_MIN * signed_tx.weight());
+    assert_fee_rate!(psbt, fee, FeeRate::from_sat_per_vb_u32(6), @add_signature);
+}
+
+#[test]
+fn test_batch_replacement_add_input() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let incoming_op = receive_output_in_latest_block(&mut wallet, 30_000);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx().coin_selection(LargestFirstCoinSelection);
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+    let original_fee = psbt.fee().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    assert_eq!(tx.input.len(), 1);
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+
+    // the change of the original tx can't cover the new payment
+    let new_addr = Address::from_str("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet
+        .build_batch_replacement(txid)
+        .unwrap()
+        .coin_selection(LargestFirstCoinSelection);
+    builder.add_recipient(new_addr.script_pubkey(), Amount::from_sat(40_000));
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    assert!(psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .any(|txin| txin.previous_output == incoming_op));
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    assert!(fee >= original_fee + FeeRate::BROADCAST_MIN * tx.weight());
+}
+
+#[test]
+fn test_batch_replacement_set_recipients() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let new_addr = Address::from_str("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+
+    // the original payment can't be removed
+    let mut builder = wallet.build_batch_replacement(txid).unwrap();
+    builder.set_recipients(vec![(new_addr.script_pubkey(), Amount::from_sat(10_000))]);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::RemovedOutput(spk)) if spk == addr.script_pubkey()
+    );
+
+    // nor changed
+    let mut builder = wallet.build_batch_replacement(txid).unwrap();
+    builder.set_recipients(vec![
+        (addr.script_pubkey(), Amount::from_sat(20_000)),
+        (new_addr.script_pubkey(), Amount::from_sat(10_000)),
+    ]);
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::RemovedOutput(spk)) if spk == addr.script_pubkey()
+    );
+
+    // but it can be listed again
+    let mut builder = wallet.build_batch_replacement(txid).unwrap();
+    builder.set_recipients(vec![
+        (new_addr.script_pubkey(), Amount::from_sat(10_000)),
+        (addr.script_pubkey(), Amount::from_sat(25_000)),
+    ]);
+    let psbt = builder.finish().unwrap();
+    assert_eq!(psbt.unsigned_tx.output.len(), 3);
+}
+
+#[test]
+fn test_batch_replacement_confirmed_tx() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    assert_matches!(
+        wallet.build_batch_replacement(txid),
+        Err(BuildFeeBumpError::TransactionConfirmed(id)) if id == txid
+    );
+}
+
+#[test]
+fn test_build_cancellation() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(5));
+    let psbt = builder.finish().unwrap();
+    let original_fee = psbt.fee().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    let original_fee_rate = original_fee / tx.weight();
+    insert_tx(&mut wallet, tx.clone());
+    let balance_before = wallet.balance().total();
+
+    // the change address is only revealed when the transaction is created
+    let change_index = wallet.derivation_index(KeychainKind::Internal);
+    let _ = wallet
+        .build_cancellation(txid, FeeRate::BROADCAST_MIN)
+        .unwrap();
+    assert_eq!(
+        wallet.derivation_index(KeychainKind::Internal),
+        change_index
+    );
+
+    let psbt = wallet
+        .build_cancellation(txid, FeeRate::BROADCAST_MIN)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let fee = psbt.fee().unwrap();
+    let mut cancellation = psbt.extract_tx().expect("failed to extract tx");
+    for txin in &mut cancellation.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+
+    // it double-spends the inputs of the original tx back to the change keychain
+    assert_eq!(
+        cancellation
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>(),
+        tx.input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(cancellation.output.len(), 1);
+    assert_matches!(
+        wallet.derivation_of_spk(cancellation.output[0].script_pubkey.clone()),
+        Some((KeychainKind::Internal, _))
+    );
+
+    // it pays the minimum fee satisfying the replacement rules
+    let min_fee = original_fee + FeeRate::BROADCAST_MIN * cancellation.weight();
+    assert!(fee >= min_fee);
+    assert!(fee - min_fee < Amount::from_sat(10), "{} {}", fee, min_fee);
+    assert!(fee / cancellation.weight() > original_fee_rate);
+
+    // once applied, the original tx is conflicted
+    let cancellation_txid = cancellation.compute_txid();
+    let last_seen = wallet
+        .tx_graph()
+        .get_tx_node(txid)
+        .and_then(|node| node.last_seen_unconfirmed)
+        .unwrap();
+    insert_tx(&mut wallet, cancellation);
+    insert_seen_at(&mut wallet, cancellation_txid, last_seen + 1);
+    assert!(wallet.get_tx(txid).is_none());
+    assert!(wallet.get_tx(cancellation_txid).is_some());
+    assert!(wallet.transactions().all(|tx| tx.tx_node.txid != txid));
+    assert_eq!(
+        wallet.balance().total(),
+        balance_before + Amount::from_sat(25_000) + original_fee - fee
+    );
+}
+
+#[test]
+fn test_build_cancellation_min_fee_input_count() {
+    use miniscript::plan::Assets;
+
+    // the fee required by the replacement rules covers the input count taking three bytes
+    let (mut wallet, _) = get_funded_wallet_single(get_test_tr_single_sig_xprv());
+    for _ in 0..260 {
+        receive_output_in_latest_block(&mut wallet, 5_000);
+    }
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.drain_wallet();
+    for _ in 0..600 {
+        builder.add_recipient(addr.script_pubkey(), Amount::from_sat(1_000));
+    }
+    let psbt = builder.finish().unwrap();
+    let original_fee = psbt.fee().unwrap();
+    let tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    insert_tx(&mut wallet, tx);
+
+    // the planned satisfaction weights are exact
+    let key = match wallet.public_descriptor(KeychainKind::External) {
+        Descriptor::Tr(tr) => tr.internal_key().clone(),
+        _ => unreachable!(),
+    };
+    let mut builder = wallet
+        .build_cancellation(txid, FeeRate::BROADCAST_MIN)
+        .unwrap();
+    builder.assets(Assets::new().add(key));
+    let mut psbt = builder.finish().unwrap();
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let fee = psbt.fee().unwrap();
+    let cancellation = psbt.extract_tx().expect("failed to extract tx");
+    assert!(cancellation.input.len() > 252);
+
+    assert!(fee >= original_fee + FeeRate::BROADCAST_MIN * cancellation.weight());
+}
+
+#[test]
+fn test_build_cancellation_fee_rate() {
+    // a high fee rate is used as is
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+    let mut tx = psbt.extract_tx().expect("failed to extract tx");
+    let txid = tx.compute_txid();
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    insert_tx(&mut wallet, tx);
+
+    let fee_rate = FeeRate::from_sat_per_vb_u32(50);
+    let psbt = wallet
+        .build_cancellation(txid, fee_rate)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let fee = psbt.fee().unwrap();
+    assert_fee_rate!(psbt, fee, fee_rate, @add_signature);
+}
+
+#[test]
+fn test_build_cancellation_foreign_inputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let foreign_op = OutPoint::new(
+        Txid::from_str("a4a6a7bd4d8d2e0c5e5b3b2e7c9e2ba4f1cd4c1d1a8ec7d6a6db2e0f84e2a9a1").unwrap(),
+        0,
+    );
+    wallet.insert_txout(
+        foreign_op,
+        TxOut {
+            value: Amount::from_sat(30_200),
+            script_pubkey: ScriptBuf::new(),
+        },
+    );
+    let tx = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: foreign_op,
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(30_000),
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .script_pubkey(),
+        }],
+    };
+    let txid = tx.compute_txid();
+    insert_tx(&mut wallet, tx);
+    insert_seen_at(&mut wallet, txid, 100);
+
+    assert_matches!(
+        wallet.build_cancellation(txid, FeeRate::BROADCAST_MIN),
+        Err(BuildFeeBumpError::UnknownUtxo(outpoint)) if outpoint == foreign_op
+    );
+}
+
+/// Insert an unconfirmed tx that spends a foreign output worth `input_value` and pays
+/// `output_value` to the wallet.
+fn receive_unconfirmed_parent(wallet: &mut Wallet, input_value: u64, output_value: u64) -> Txid {
+    let foreign_op = OutPoint::new(
+        Txid::from_str("a4a6a7bd4d8d2e0c5e5b3b2e7c9e2ba4f1cd4c1d1a8ec7d6a6db2e0f84e2a9a1").unwrap(),
+        0,
+    );
+    wallet.insert_txout(
+        foreign_op,
+        TxOut {
+            value: Amount::from_sat(input_value),
+            script_pubkey: ScriptBuf::new(),
+        },
+    );
+    let mut witness = bitcoin::Witness::new();
+    witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]);
+    let parent = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: foreign_op,
+            witness,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(output_value),
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .script_pubkey(),
+        }],
+    };
+    let txid = parent.compute_txid();
+    insert_tx(wallet, parent);
+    insert_seen_at(wallet, txid, 100);
+    txid
+}
+
+#[test]
+fn test_lowest_waste_coin_selection() {
+    let mut rng: StdRng = SeedableRng::from_seed([0; 32]);
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    for _ in 0..3 {
+        receive_output_in_latest_block(&mut wallet, 10_000);
+    }
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+    let low_fee_rate = FeeRate::from_sat_per_vb_u32(1);
+    let high_fee_rate = FeeRate::from_sat_per_vb_u32(50);
+
+    // consolidate when fees are lower than expected in the long term
+    let mut builder = wallet
+        .build_tx()
+        .coin_selection(coin_selection::LowestWasteCoinSelection::default());
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(5_000))
+        .fee_rate(low_fee_rate)
+        .long_term_fee_rate(high_fee_rate);
+    let psbt = builder.finish_with_aux_rand(&mut rng).unwrap();
+    assert!(psbt.unsigned_tx.input.len() > 1);
+
+    // spend as few inputs as possible otherwise
+    let mut builder = wallet
+        .build_tx()
+        .coin_selection(coin_selection::LowestWasteCoinSelection::default());
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(5_000))
+        .fee_rate(high_fee_rate)
+        .long_term_fee_rate(low_fee_rate);
+    let psbt = builder.finish_with_aux_rand(&mut rng).unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    let fee = check_fee!(wallet, psbt);
+    assert_fee_rate!(psbt, fee.unwrap_or(Amount::ZERO), high_fee_rate, @add_signature);
+}
+
+#[test]
+fn test_lock_utxo() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+    let lock = UtxoLock {
+        reason: "dust attack".to_string(),
+        until_height: None,
+    };
+
+    wallet.lock_utxo(outpoint, "dust attack", None);
+    assert_eq!(wallet.get_utxo(outpoint).unwrap().lock, Some(lock.clone()));
+    assert_eq!(
+        wallet.list_unspent().next().unwrap().lock,
+        Some(lock.clone())
+    );
+
+    // locked utxos are not selected
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    assert_matches!(
+        builder.finish(),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::InsufficientFunds { .. }
+        ))
+    );
+
+    // unless they are added manually
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .add_utxo(outpoint)
+        .unwrap();
+    assert!(builder.finish().is_ok());
+
+    assert_eq!(wallet.unlock_utxo(outpoint), Some(lock));
+    assert_eq!(wallet.unlock_utxo(outpoint), None);
+    assert_eq!(wallet.get_utxo(outpoint).unwrap().lock, None);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    assert!(builder.finish().is_ok());
+}
+
+#[test]
+fn test_lock_utxo_expires() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let outpoint = wallet.list_unspent().next().unwrap().outpoint;
+    let height = wallet.latest_checkpoint().height();
+
+    wallet.lock_utxo(outpoint, "compliance review", Some(height + 1));
+    assert!(wallet.get_utxo(outpoint).unwrap().lock.is_some());
+    assert_eq!(wallet.locked_utxos().count(), 1);
+
+    insert_checkpoint(
+        &mut wallet,
+        BlockId {
+            height: height + 1,
+            hash: BlockHash::all_zeros(),
+        },
+    );
+    assert_eq!(wallet.get_utxo(outpoint).unwrap().lock, None);
+    assert_eq!(wallet.locked_utxos().count(), 0);
+
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    assert!(builder.finish().is_ok());
+}
+
+#[test]
+fn test_pending_spends_reserve_inputs() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    receive_output_in_latest_block(&mut wallet, 25_000);
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+    let build = |wallet: &mut Wallet| {
+        let mut builder = wallet.build_tx().coin_selection(LargestFirstCoinSelection);
+        builder.add_recipient(addr.script_pubkey(), Amount::from_sat(20_000));
+        builder.finish()
+    };
+
+    let psbt1 = build(&mut wallet).unwrap();
+    let txid1 = wallet.insert_pending_spend(&psbt1, None);
+    assert_eq!(txid1, psbt1.unsigned_tx.compute_txid());
+
+    // the second psbt can't use the inputs of the first one
+    let psbt2 = build(&mut wallet).unwrap();
+    let inputs1 = psbt1
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| txin.previous_output);
+    for outpoint in inputs1 {
+        assert!(!psbt2
+            .unsigned_tx
+            .input
+            .iter()
+            .any(|txin| txin.previous_output == outpoint));
+    }
+    let txid2 = wallet.insert_pending_spend(&psbt2, None);
+    assert_eq!(
+        wallet
+            .pending_spends()
+            .map(|(txid, _)| txid)
+            .collect::<std::collections::HashSet<_>>(),
+        [txid1, txid2].into()
+    );
+    assert_matches!(
+        build(&mut wallet),
+        Err(CreateTxError::CoinSelection(
+            coin_selection::InsufficientFunds { .. }
+        ))
+    );
+
+    // releasing a pending spend makes its inputs available again
+    assert_eq!(
+        wallet.release_pending_spend(txid1).map(|p| p.tx),
+        Some(psbt1.unsigned_tx.clone())
+    );
+    assert_eq!(wallet.release_pending_spend(txid1), None);
+    let psbt3 = build(&mut wallet).unwrap();
+    assert_eq!(psbt3.unsigned_tx.input, psbt1.unsigned_tx.input);
+
+    // so does cancelling it
+    wallet.cancel_tx(&psbt2.unsigned_tx);
+    assert_eq!(wallet.pending_spends().count(), 0);
+}
+
+#[test]
+fn test_pending_spend_ends() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    let height = wallet.latest_checkpoint().height();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(20_000));
+    let psbt = builder.finish().unwrap();
+
+    // the reservation expires
+    wallet.insert_pending_spend(&psbt, Some(height + 1));
+    assert_eq!(wallet.pending_spends().count(), 1);
+    insert_checkpoint(
+        &mut wallet,
+        BlockId {
+            height: height + 1,
+            hash: BlockHash::all_zeros(),
+        },
+    );
+    assert_eq!(wallet.pending_spends().count(), 0);
+
+    // the reservation ends once the transaction is broadcast
+    wallet.insert_pending_spend(&psbt, None);
+    assert_eq!(wallet.pending_spends().count(), 1);
+    wallet.apply_unconfirmed_txs([(psbt.unsigned_tx.clone(), 100)]);
+    assert_eq!(wallet.pending_spends().count(), 0);
+}
+
+#[test]
+fn test_pending_spend_ends_when_inputs_are_spent() {
+    // the txid of a transaction spending a P2SH-P2WPKH input changes once it's signed
+    let (mut wallet, _) =
+        get_funded_wallet_single("sh(wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW))");
+    let addr = Address::from_str("2N4eQYCbKUHCCTUjBJeHcJp9ok6J2GZsTDt")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .reserve_inputs(None);
+    let mut psbt = builder.finish().unwrap();
+    let unsigned_txid = psbt.unsigned_tx.compute_txid();
+    assert_eq!(
+        wallet
+            .pending_spends()
+            .map(|(txid, _)| txid)
+            .collect::<Vec<_>>(),
+        vec![unsigned_txid]
+    );
+    let _ = wallet.take_staged();
+
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx().unwrap();
+    assert_ne!(tx.compute_txid(), unsigned_txid);
+    wallet.apply_unconfirmed_txs([(tx, 100)]);
+    assert_eq!(wallet.pending_spends().count(), 0);
+    // the reservation is removed
+    assert_eq!(
+        wallet.staged().unwrap().pending_spends,
+        [(unsigned_txid, None)].into()
+    );
+    assert!(wallet.release_pending_spend(unsigned_txid).is_none());
+
+    // a replacement of the pending spend ends it too
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .reserve_inputs(None);
+    let psbt = builder.finish().unwrap();
+    let mut replacement = psbt.unsigned_tx.clone();
+    replacement.output[0].value -= Amount::from_sat(1_000);
+    wallet.apply_unconfirmed_txs([(replacement, 100)]);
+    assert_eq!(wallet.pending_spends().count(), 0);
+}
+
+#[test]
+fn test_import_export_labels() {
+    let (mut wallet, txid) = get_funded_wallet_wpkh();
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    wallet.set_label(LabelRef::tx(txid), "salary");
+    wallet.set_label(LabelRef::addr(&addr), "invoice #1");
+
+    let exported = wallet.export_labels();
+    assert_eq!(exported.lines().count(), 2);
+
+    let (mut other, _) = get_funded_wallet_wpkh();
+    other.set_label(LabelRef::tx(txid), "old label");
+    assert_eq!(other.import_labels(&exported), Ok(2));
+    assert_eq!(other.export_labels(), exported);
+    assert_eq!(
+        other.get_label(&LabelRef::tx(txid)),
+        Some(&Label::from("salary"))
+    );
+
+    // a failed import leaves the labels untouched
+    let invalid = format!(
+        "{}\n{}",
+        r#"{"type":"tx","ref":"0000000000000000000000000000000000000000000000000000000000000000","label":"x"}"#,
+        r#"{"type":"addr","ref":"bc1q34aq5drpuwy3wgl9lhup9892qp6svr8ldzyy7c","label":"mainnet"}"#,
+    );
+    assert_matches!(
+        other.import_labels(&invalid),
+        Err(LabelError::InvalidRef {
+            label_type: LabelType::Addr,
+            ..
+        })
+    );
+    assert_eq!(other.export_labels(), exported);
+}
+
+#[test]
+fn test_build_cpfp() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let parent_txid = receive_unconfirmed_parent(&mut wallet, 30_200, 30_000);
+    let parent = wallet.get_tx(parent_txid).unwrap().tx_node.tx;
+    let parent_fee = Amount::from_sat(200);
+    assert_eq!(wallet.calculate_fee(&parent).unwrap(), parent_fee);
+
+    let target = FeeRate::from_sat_per_vb_u32(10);
+    // the change address is only revealed when the transaction is created
+    let change_index = wallet.derivation_index(KeychainKind::Internal);
+    let _ = wallet.build_cpfp(parent_txid, target).unwrap();
+    assert_eq!(
+        wallet.derivation_index(KeychainKind::Internal),
+        change_index
+    );
+
+    let psbt = wallet
+        .build_cpfp(parent_txid, target)
+        .unwrap()
+        .coin_selection(LargestFirstCoinSelection)
+        .finish()
+        .unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(
+        psbt.unsigned_tx.input[0].previous_output,
+        OutPoint::new(parent_txid, 0)
+    );
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert_matches!(
+        wallet.derivation_of_spk(psbt.unsigned_tx.output[0].script_pubkey.clone()),
+        Some((KeychainKind::Internal, _))
+    );
+
+    let child_fee = psbt.fee().unwrap();
+    let mut child = psbt.extract_tx().expect("failed to extract tx");
+    for txin in &mut child.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    // the child alone pays more than the target to make up for the parent
+    assert!(child_fee / child.weight() > target);
+    let package_fee_rate = (parent_fee + child_fee) / (parent.weight() + child.weight());
+    let half_default = FeeRate::BROADCAST_MIN.checked_div(2).unwrap();
+    assert!(package_fee_rate >= target, "{}", package_fee_rate);
+    assert!(
+        package_fee_rate.to_sat_per_kwu() - target.to_sat_per_kwu() < half_default.to_sat_per_kwu(),
+        "{}",
+        package_fee_rate
+    );
+}
+
+#[test]
+fn test_build_cpfp_parent_pays_enough() {
+    // the parent already pays more than the target, so the child only pays for itself
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let parent_txid = receive_unconfirmed_parent(&mut wallet, 40_000, 30_000);
+
+    let target = FeeRate::from_sat_per_vb_u32(5);
+    let psbt = wallet
+        .build_cpfp(parent_txid, target)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let fee = psbt.fee().unwrap();
+    assert_fee_rate!(psbt, fee, target, @add_signature);
+}
+
+#[test]
+fn test_build_cpfp_errors() {
+    let (mut wallet, funding_txid) = get_funded_wallet_wpkh();
+    let target = FeeRate::from_sat_per_vb_u32(10);
+
+    let unknown_txid = Txid::all_zeros();
+    assert_matches!(
+        wallet.build_cpfp(unknown_txid, target),
+        Err(BuildCpfpError::TransactionNotFound(txid)) if txid == unknown_txid
+    );
+    assert_matches!(
+        wallet.build_cpfp(funding_txid, target),
+        Err(BuildCpfpError::TransactionConfirmed(txid)) if txid == funding_txid
+    );
+
+    // the prevout of the parent's input is unknown
+    let parent = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 1),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(30_000),
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .script_pubkey(),
+        }],
+    };
+    let parent_txid = parent.compute_txid();
+    insert_tx(&mut wallet, parent);
+    insert_seen_at(&mut wallet, parent_txid, 100);
+    assert_matches!(
+        wallet.build_cpfp(parent_txid, target),
+        Err(BuildCpfpError::CalculateFee(CalculateFeeError::MissingTxOut(outpoints)))
+            if outpoints == vec![OutPoint::new(Txid::all_zeros(), 1)]
+    );
+
+    // the parent doesn't pay to the wallet
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_utxo(OutPoint::new(funding_txid, 0))
+        .unwrap()
+        .manually_selected_only()
+        .drain_to(addr.script_pubkey());
+    let tx = builder.finish().unwrap().extract_tx().unwrap();
+    let txid = tx.compute_txid();
+    insert_tx(&mut wallet, tx);
+    insert_seen_at(&mut wallet, txid, 200);
+    assert_matches!(
+        wallet.build_cpfp(txid, target),
+        Err(BuildCpfpError::NoSpendableOutput(id)) if id == txid
+    );
+}
+
+#[test]
+fn test_build_timelock_refresh() {
+    // the recovery key "B" can spend the funded output from height 2000 + 144
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    let funded = wallet.list_unspent().next().unwrap();
+    // the timelock of unconfirmed outputs didn't start yet
+    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(100));
+
+    assert!(wallet.list_expiring_utxos(143).unwrap().is_empty());
+    let expiring = wallet.list_expiring_utxos(144).unwrap();
+    assert_eq!(expiring.len(), 1);
+    assert_eq!(expiring[0].utxo, funded);
+
+    let fee_rate = FeeRate::from_sat_per_vb_u32(5);
+    let mut psbt = wallet
+        .build_timelock_refresh(144, fee_rate)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, funded.outpoint);
+    // the path of the key "A" doesn't need the relative timelock
+    assert!(!psbt.unsigned_tx.input[0].sequence.is_relative_lock_time());
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    assert!(wallet.is_mine(psbt.unsigned_tx.output[0].script_pubkey.clone()));
+
+    let finalized = wallet.sign(&mut psbt, SignOptions::default()).unwrap();
+    assert!(finalized);
+    let fee = psbt.fee().unwrap();
+    let tx = psbt.extract_tx().unwrap();
+    assert!(fee / tx.weight() >= fee_rate);
+    assert_eq!(
+        tx.output[0].value + fee,
+        funded.txout.value,
+        "the whole output is refreshed"
+    );
+}
+
+#[test]
+fn test_build_timelock_refresh_two_keychains() {
+    let desc = |keychain| {
+        format!(
+            "wsh(or_d(pk(tprv8ZgxMBicQKsPdy6LMhUtFHAgpocR8GC6QmwMSFpZs7h6Eziw3SpThFfczTDh5rW2krkqffa11UpX3XkeTTB2FvzZKWXqPY54Y6Rq4AQ5R8L/{}/*),and_v(v:pk(cMnkdebixpXMPfkcNEjjGin7s94hiehAH4mLbYkZoh9KSiNNmqC8),older(144))))",
+            keychain
+        )
+    };
+    let (mut wallet, _) = get_funded_wallet(&desc(0), &desc(1));
+    let fee_rate = FeeRate::from_sat_per_vb_u32(5);
+
+    // the change address is only revealed when the transaction is created
+    let change_index = wallet.derivation_index(KeychainKind::Internal);
+    let _ = wallet.build_timelock_refresh(144, fee_rate).unwrap();
+    assert_eq!(
+        wallet.derivation_index(KeychainKind::Internal),
+        change_index
+    );
+
+    // the change keychain doesn't have expiring utxos, and doesn't need a policy path
+    let psbt = wallet
+        .build_timelock_refresh(144, fee_rate)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(psbt.unsigned_tx.output.len(), 1);
+    let (keychain, index) = wallet
+        .derivation_of_spk(psbt.unsigned_tx.output[0].script_pubkey.clone())
+        .unwrap();
+    assert_eq!(keychain, KeychainKind::Internal);
+    assert_eq!(wallet.derivation_index(KeychainKind::Internal), Some(index));
+}
+
+#[test]
+fn test_build_timelock_refresh_errors() {
+    let fee_rate = FeeRate::from_sat_per_vb_u32(5);
+
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    assert_matches!(
+        wallet.build_timelock_refresh(100, fee_rate),
+        Err(BuildTimelockRefreshError::NoExpiringUtxos)
+    );
+
+    // absolute timelocks don't expire
+    let (mut wallet, _) = get_funded_wallet_single(get_test_single_sig_cltv());
+    assert_matches!(
+        wallet.build_timelock_refresh(u32::MAX, fee_rate),
+        Err(BuildTimelockRefreshError::NoExpiringUtxos)
+    );
+
+    // and(pk(A),older(6)) can only be spent with the relative timelock
+    let (mut wallet, _) = get_funded_wallet_single(get_test_single_sig_csv());
+    assert_matches!(
+        wallet.build_timelock_refresh(6, fee_rate),
+        Err(BuildTimelockRefreshError::NoPrimaryPath(
+            KeychainKind::External
+        ))
+    );
+}
+
+#[test]
+fn test_build_timelock_refresh_skips_locked_and_reserved() {
+    let fee_rate = FeeRate::from_sat_per_vb_u32(5);
+    let (mut wallet, _) = get_funded_wallet_single(get_test_a_or_b_plus_csv());
+    let funded = wallet.list_unspent().next().unwrap();
+    let anchor = match funded.chain_position {
+        ChainPosition::Confirmed { anchor, .. } => anchor,
+        _ => panic!("the funding tx is confirmed"),
+    };
+    let outpoint = receive_output(&mut wallet, 25_000, anchor);
+    assert_eq!(wallet.list_expiring_utxos(144).unwrap().len(), 2);
+
+    // the locked utxo isn't refreshed
+    wallet.lock_utxo(funded.outpoint, "cold storage", None);
+    let psbt = wallet
+        .build_timelock_refresh(144, fee_rate)
+        .unwrap()
+        .finish()
+        .unwrap();
+    assert_eq!(psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(psbt.unsigned_tx.input[0].previous_output, outpoint);
+
+    // neither is the utxo reserved by the refresh transaction itself
+    wallet.insert_pending_spend(&psbt, None);
+    assert_matches!(
+        wallet.build_timelock_refresh(144, fee_rate),
+        Err(BuildTimelockRefreshError::NoExpiringUtxos)
+    );
+}
+
+/// Asserts that `psbt` and its unconfirmed `ancestors` pay `target` fee rate as a package.
+fn assert_package_fee_rate(psbt: &psbt::Psbt, ancestors: &[(Amount, Weight)], target: FeeRate) {
+    let fee = psbt.fee().unwrap();
+    let mut tx = psbt.clone().extract_tx().expect("failed to extract tx");
+    for txin in &mut tx.input {
+        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
+    }
+    let (package_fee, package_weight) = ancestors.iter().fold(
+        (fee, tx.weight()),
+        |(fee, weight), (anc_fee, anc_weight)| (fee + *anc_fee, weight + *anc_weight),
+    );
+    let package_fee_rate = (package_fee / package_weight).to_sat_per_kwu();
+    let half_default = FeeRate::BROADCAST_MIN
+        .checked_div(2)
+        .unwrap()
+        .to_sat_per_kwu();
+    assert!(
+        package_fee_rate >= target.to_sat_per_kwu()
+            && package_fee_rate - target.to_sat_per_kwu() < half_default,
+        "Expected package fee rate of {:?}, the package has {:?}",
+        target.to_sat_per_kwu(),
+        package_fee_rate
+    );
+}
+
+#[test]
+fn test_create_tx_bumps_unconfirmed_parent() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let parent_txid = receive_unconfirmed_parent(&mut wallet, 30_200, 30_000);
+    let parent = wallet.get_tx(parent_txid).unwrap().tx_node.tx;
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    let target = FeeRate::from_sat_per_vb_u32(10);
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .add_utxo(OutPoint::new(parent_txid, 0))
+        .unwrap()
+        .manually_selected_only()
+        .fee_rate(target);
+    let psbt = builder.finish().unwrap();
+
+    let fee = psbt.fee().unwrap();
+    let weight = psbt.clone().extract_tx().unwrap().weight();
+    assert!(fee > target * weight, "must pay for the parent too");
+    assert_package_fee_rate(&psbt, &[(Amount::from_sat(200), parent.weight())], target);
+}
+
+#[test]
+fn test_create_tx_bumps_unconfirmed_ancestors() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let grandparent_txid = receive_unconfirmed_parent(&mut wallet, 30_200, 30_000);
+    let grandparent = wallet.get_tx(grandparent_txid).unwrap().tx_node.tx;
+
+    // the parent spends the output of the grandparent back to the wallet, paying a low fee
+    let mut witness = bitcoin::Witness::new();
+    witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]);
+    let parent = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(grandparent_txid, 0),
+            witness,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(29_900),
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .script_pubkey(),
+        }],
+    };
+    let parent_txid = parent.compute_txid();
+    let parent_weight = parent.weight();
+    insert_tx(&mut wallet, parent);
+    insert_seen_at(&mut wallet, parent_txid, 200);
+
+    let target = FeeRate::from_sat_per_vb_u32(10);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .add_utxo(OutPoint::new(parent_txid, 0))
+        .unwrap()
+        .manually_selected_only()
+        .fee_rate(target);
+    let psbt = builder.finish().unwrap();
+
+    assert_package_fee_rate(
+        &psbt,
+        &[
+            (Amount::from_sat(200), grandparent.weight()),
+            (Amount::from_sat(100), parent_weight),
+        ],
+        target,
+    );
+}
+
+#[test]
+fn test_create_tx_no_bump_for_parent_with_unknown_fee() {
+    // the prevout of the parent's input is unknown, like for most incoming payments, so the
+    // parent's fee can't be known and only the new tx is paid for
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let mut witness = bitcoin::Witness::new();
+    witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]);
+    let parent = Transaction {
+        version: transaction::Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::all_zeros(), 1),
+            witness,
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(30_000),
+            script_pubkey: wallet
+                .next_unused_address(KeychainKind::External)
+                .script_pubkey(),
+        }],
+    };
+    let parent_txid = parent.compute_txid();
+    insert_tx(&mut wallet, parent);
+    insert_seen_at(&mut wallet, parent_txid, 100);
+
+    let target = FeeRate::from_sat_per_vb_u32(10);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .add_utxo(OutPoint::new(parent_txid, 0))
+        .unwrap()
+        .manually_selected_only()
+        .fee_rate(target);
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee().unwrap();
+    assert_fee_rate!(psbt, fee, target, @add_signature);
+}
+
+#[test]
+fn test_create_tx_no_bump_for_high_fee_parent() {
+    // the parent pays more than the target fee rate, so only the new tx is paid for
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let parent_txid = receive_unconfirmed_parent(&mut wallet, 40_000, 30_000);
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+
+    let target = FeeRate::from_sat_per_vb_u32(10);
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(20_000))
+        .add_utxo(OutPoint::new(parent_txid, 0))
+        .unwrap()
+        .manually_selected_only()
+        .fee_rate(target);
     let psbt = builder.finish().unwrap();
-    // Now we receive one transaction with 0 confirmations. We won't be able to use that for
-    // fee bumping, as it's still unconfirmed!
-    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
-    let mut tx = psbt.extract_tx().expect("failed to extract tx");
-    let txid = tx.compute_txid();
-    for txin in &mut tx.input {
-        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
-    }
-    insert_tx(&mut wallet, tx);
-    let mut builder = wallet.build_fee_bump(txid).unwrap();
-    builder.fee_rate(FeeRate::from_sat_per_vb_unchecked(25));
-    builder.finish().unwrap();
+    let fee = psbt.fee().unwrap();
+    assert_fee_rate!(psbt, fee, target, @add_signature);
 }
 
 #[test]
-fn test_bump_fee_unconfirmed_input() {
-    // We create a tx draining the wallet and spending one confirmed
-    // and one unconfirmed UTXO. We check that we can fee bump normally
-    // (BIP125 rule 2 only apply to newly added unconfirmed input, you can
-    // always fee bump with an unconfirmed input if it was included in the
-    // original transaction)
+fn test_create_tx_bump_fee_selects_more_coins() {
+    // the parent output alone can't pay for the recipient and the bump fee of the parent
     let (mut wallet, _) = get_funded_wallet_wpkh();
+    let parent_txid = receive_unconfirmed_parent(&mut wallet, 30_010, 30_000);
+    let parent = wallet.get_tx(parent_txid).unwrap().tx_node.tx;
     let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
         .unwrap()
         .assume_checked();
-    // We receive a tx with 0 confirmations, which will be used as an input
-    // in the drain tx.
-    receive_output(&mut wallet, 25_000, ReceiveTo::Mempool(0));
-    let mut builder = wallet.build_tx();
-    builder.drain_wallet().drain_to(addr.script_pubkey());
-    let psbt = builder.finish().unwrap();
-    let mut tx = psbt.extract_tx().expect("failed to extract tx");
-    let txid = tx.compute_txid();
-    for txin in &mut tx.input {
-        txin.witness.push([0x00; P2WPKH_FAKE_WITNESS_SIZE]); // fake signature
-    }
-    insert_tx(&mut wallet, tx);
 
-    let mut builder = wallet.build_fee_bump(txid).unwrap();
+    let target = FeeRate::from_sat_per_vb_u32(20);
+    let mut builder = wallet.build_tx().coin_selection(LargestFirstCoinSelection);
     builder
-        .fee_rate(FeeRate::from_sat_per_vb_unchecked(15))
-        // remove original tx drain_to address and amount
-        .set_recipients(Vec::new())
-        // set back original drain_to address
-        .drain_to(addr.script_pubkey())
-        // drain wallet output amount will be re-calculated with new fee rate
-        .drain_wallet();
-    builder.finish().unwrap();
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(29_000))
+        .add_utxo(OutPoint::new(parent_txid, 0))
+        .unwrap()
+        .fee_rate(target);
+    let psbt = builder.finish().unwrap();
+
+    assert_eq!(psbt.unsigned_tx.input.len(), 2);
+    assert_package_fee_rate(&psbt, &[(Amount::from_sat(10), parent.weight())], target);
 }
 
 #[test]
@@ -3752,6 +5936,487 @@ fn test_taproot_sign_derive_index_from_psbt() {
     );
 }
 
+#[test]
+fn test_sign_musig() {
+    use bitcoin::secp256k1::SecretKey;
+
+    let secp = Secp256k1::new();
+    let alice_key = SecretKey::from_slice(&[1; 32]).unwrap();
+    let bob_key = SecretKey::from_slice(&[2; 32]).unwrap();
+    let key = MusigKey::new(
+        &secp,
+        vec![alice_key.public_key(&secp), bob_key.public_key(&secp)],
+    )
+    .unwrap();
+    let descriptor = format!("tr({}/0/*)", key);
+    let change_descriptor = format!("tr({}/1/*)", key);
+
+    let (mut alice, _) = get_funded_wallet(&descriptor, &change_descriptor);
+    alice.add_signer(
+        KeychainKind::External,
+        SignerOrdering(200),
+        Arc::new(MusigSigner::new(&secp, alice_key, key.clone()).unwrap()),
+    );
+    let mut bob = Wallet::create(descriptor, change_descriptor)
+        .network(Network::Regtest)
+        .create_wallet_no_persist()
+        .unwrap();
+    bob.add_signer(
+        KeychainKind::External,
+        SignerOrdering(200),
+        Arc::new(MusigSigner::new(&secp, bob_key, key.clone()).unwrap()),
+    );
+
+    let addr = alice.next_unused_address(KeychainKind::External);
+    let mut builder = alice.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+    let mut stale = psbt.clone();
+    assert!(!alice.sign(&mut stale, SignOptions::default()).unwrap());
+
+    // alice adds a nonce, then bob adds a nonce and a partial signature
+    assert!(!alice.sign(&mut psbt, SignOptions::default()).unwrap());
+    assert!(psbt.inputs[0].tap_key_sig.is_none());
+    // the nonce is keyed by the derived key, not by the aggregate key
+    let internal_key = psbt.inputs[0].tap_internal_key.unwrap();
+    assert_ne!(internal_key, key.aggregate_key().x_only_public_key().0);
+    let nonce_field = psbt.inputs[0]
+        .unknown
+        .keys()
+        .find(|field| field.type_value == 0x1b)
+        .unwrap();
+    assert_eq!(
+        nonce_field.key[..33],
+        alice_key.public_key(&secp).serialize()
+    );
+    assert_eq!(nonce_field.key[34..], internal_key.serialize());
+    assert!(!bob.sign(&mut psbt, SignOptions::default()).unwrap());
+    assert!(psbt.inputs[0].tap_key_sig.is_none());
+    let second_round = psbt.clone();
+
+    // alice adds a partial signature and aggregates all of them
+    assert!(alice.sign(&mut psbt, SignOptions::default()).unwrap());
+    let tx = psbt.extract_tx().unwrap();
+    assert_eq!(tx.input[0].witness.len(), 1);
+
+    // the nonce of alice can't be used a second time
+    let mut psbt = second_round;
+    assert_matches!(
+        alice.sign(&mut psbt, SignOptions::default()),
+        Err(SignerError::Musig(MusigError::MissingSecretNonce))
+    );
+
+    // the first nonce of alice was dropped when a new one was generated for the same input
+    assert!(!bob.sign(&mut stale, SignOptions::default()).unwrap());
+    assert_matches!(
+        alice.sign(&mut stale, SignOptions::default()),
+        Err(SignerError::Musig(MusigError::MissingSecretNonce))
+    );
+}
+
+#[test]
+fn test_sign_message() {
+    let foreign_address = Address::from_str("bcrt1qc6fweuf4xjvz4x3gx3t9e0fh4hvqyu2qw4wvxm")
+        .unwrap()
+        .assume_checked();
+    let multisig = "wsh(multi(2,cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW,cRjo6jqfVNP33HhSS76UhXETZsGTZYx8FMFvR9kpbtCSV1PmdZdu))";
+    for descriptor in [
+        get_test_wpkh(),
+        get_test_tr_single_sig_xprv(),
+        get_test_tr_with_taptree_both_priv(),
+        multisig,
+    ] {
+        let (wallet, _) = get_funded_wallet_single(descriptor);
+        let address = wallet.peek_address(KeychainKind::External, 0).address;
+        for format in [SignatureFormat::Simple, SignatureFormat::Full] {
+            let signature = wallet
+                .sign_message(&address, "Hello World", format)
+                .unwrap();
+            assert_eq!(signature.format(), format);
+
+            // the signature can be verified by anyone from its encoding
+            let signature = MessageSignature::from_str(&signature.to_string()).unwrap();
+            let secp = Secp256k1::verification_only();
+            assert!(verify_message(&secp, &address, "Hello World", &signature).unwrap());
+            assert!(!wallet
+                .verify_message(&address, "Hello", &signature)
+                .unwrap());
+            assert!(!wallet
+                .verify_message(&foreign_address, "Hello World", &signature)
+                .unwrap());
+        }
+        assert_matches!(
+            wallet.sign_message(&address, "Hello World", SignatureFormat::Legacy),
+            Err(MessageError::UnsupportedFormat)
+        );
+    }
+
+    // the wallet can only sign for its own addresses
+    let (wallet, _) = get_funded_wallet_single(get_test_tr_single_sig());
+    assert_matches!(
+        wallet.sign_message(&foreign_address, "Hello World", SignatureFormat::Simple),
+        Err(MessageError::UnknownAddress(_))
+    );
+
+    // a watch-only wallet can't complete the signature
+    let (wallet, _) = get_funded_wallet_single("wpkh(tpubD6NzVbkrYhZ4Xferm7Pz4VnjdcDPFyjVu5K4iZXQ4pVN8Cks4pHVowTBXBKRhX64pkRyJZJN5xAKj4UDNnLPb5p2sSKXhewoYx5GbTdUFWq/*)");
+    let address = wallet.peek_address(KeychainKind::External, 0).address;
+    assert_matches!(
+        wallet.sign_message(&address, "Hello World", SignatureFormat::Full),
+        Err(MessageError::Incomplete)
+    );
+}
+
+#[test]
+fn test_sign_message_legacy() {
+    let (wallet, _) = get_funded_wallet_single(
+        "pkh(tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/44'/1'/0'/0/*)",
+    );
+    let address = wallet.peek_address(KeychainKind::External, 0).address;
+    for format in [SignatureFormat::Legacy, SignatureFormat::Full] {
+        let signature = wallet
+            .sign_message(&address, "Hello World", format)
+            .unwrap();
+        let signature = MessageSignature::from_str(&signature.to_string()).unwrap();
+        assert_eq!(signature.format(), format);
+        assert!(wallet
+            .verify_message(&address, "Hello World", &signature)
+            .unwrap());
+        assert!(!wallet
+            .verify_message(&address, "Hello", &signature)
+            .unwrap());
+    }
+    assert_matches!(
+        wallet.sign_message(&address, "Hello World", SignatureFormat::Simple),
+        Err(MessageError::UnsupportedFormat)
+    );
+
+    // legacy signatures are only for P2PKH addresses
+    let signature = wallet
+        .sign_message(&address, "Hello World", SignatureFormat::Legacy)
+        .unwrap();
+    let (wallet, _) = get_funded_wallet_wpkh();
+    let address = wallet.peek_address(KeychainKind::External, 0).address;
+    assert_matches!(
+        wallet.verify_message(&address, "Hello World", &signature),
+        Err(MessageError::UnsupportedFormat)
+    );
+}
+
+#[test]
+fn test_update_psbt() {
+    let multisig = "wsh(multi(2,[d34db33f/48h/1h/0h/2h]tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/0/*,[c0ffee00/48h/1h/0h/2h]tprv8ZgxMBicQKsPdDArR4xSAECuVxeX1jwwSXR4ApKbkYgZiziDc4LdBy2WvJeGDfUSE4UT4hHhbgEwbdq8ajjUHiKDegkwrNU6V55CxcxonVN/0/*))";
+    for descriptor in [multisig, get_test_tr_single_sig_xprv()] {
+        let (mut wallet, _) = get_funded_wallet_single(descriptor);
+        let addr = wallet.next_unused_address(KeychainKind::External);
+        let mut builder = wallet.build_tx();
+        builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+        let psbt = builder.finish().unwrap();
+
+        // the PSBT we get from the coordinator lacks all the wallet data
+        let mut foreign_psbt = psbt::Psbt::from_unsigned_tx(psbt.unsigned_tx.clone()).unwrap();
+        assert_matches!(
+            wallet.sign(&mut foreign_psbt.clone(), SignOptions::default()),
+            Ok(false) | Err(SignerError::MissingNonWitnessUtxo)
+        );
+
+        wallet.update_psbt(&mut foreign_psbt).unwrap();
+        assert_eq!(foreign_psbt, psbt);
+        assert!(wallet
+            .sign(&mut foreign_psbt, SignOptions::default())
+            .unwrap());
+    }
+}
+
+#[test]
+fn test_update_psbt_foreign_input() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let (wallet2, _) =
+        get_funded_wallet_single("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let utxo = wallet2.list_unspent().next().expect("must take!");
+    let foreign_outpoint = utxo.outpoint;
+    let foreign_input = psbt::Input {
+        witness_utxo: Some(utxo.txout.clone()),
+        ..Default::default()
+    };
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_foreign_utxo(utxo.outpoint, foreign_input.clone(), Weight::from_wu(108))
+        .unwrap()
+        .only_witness_utxo()
+        .drain_to(addr.script_pubkey())
+        .drain_wallet();
+    let psbt = builder.finish().unwrap();
+    let mut foreign_psbt = psbt::Psbt::from_unsigned_tx(psbt.unsigned_tx.clone()).unwrap();
+    let foreign_index = foreign_psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .position(|txin| txin.previous_output == foreign_outpoint)
+        .unwrap();
+    foreign_psbt.inputs[foreign_index] = foreign_input;
+
+    // inputs and outputs that don't belong to the wallet are left untouched
+    wallet.update_psbt(&mut foreign_psbt).unwrap();
+    assert_eq!(
+        foreign_psbt.inputs[foreign_index],
+        psbt.inputs[foreign_index]
+    );
+    assert_eq!(foreign_psbt.outputs, psbt.outputs);
+    let own_index = 1 - foreign_index;
+    assert_eq!(
+        foreign_psbt.inputs[own_index].bip32_derivation,
+        psbt.inputs[own_index].bip32_derivation
+    );
+    assert!(foreign_psbt.inputs[own_index].witness_utxo.is_some());
+    assert!(foreign_psbt.inputs[own_index].non_witness_utxo.is_some());
+}
+
+#[test]
+fn test_analyze_psbt() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+
+    let analysis = wallet.analyze_psbt(&psbt).unwrap();
+    assert!(!analysis.is_final());
+    let input = &analysis.inputs[0];
+    assert_eq!(input.keychain, Some(KeychainKind::External));
+    assert!(input.has_utxo);
+    assert!(input.signed_by.is_empty());
+    assert!(!input.is_satisfied());
+    assert!(input.pending_timelocks.is_empty());
+    assert_eq!(analysis.fee, psbt.fee_amount());
+    let estimated_weight = analysis.estimated_weight.unwrap();
+
+    assert!(wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let analysis = wallet.analyze_psbt(&psbt).unwrap();
+    assert!(analysis.is_final());
+    assert_eq!(analysis.inputs[0].policy, None);
+    let tx = psbt.clone().extract_tx().unwrap();
+    assert_eq!(analysis.estimated_weight, Some(tx.weight()));
+    assert_eq!(analysis.estimated_fee_rate, psbt.fee_rate());
+    // the estimation assumes the largest possible signature
+    assert!(estimated_weight >= tx.weight());
+    assert!(estimated_weight - tx.weight() <= Weight::from_wu(2));
+}
+
+#[test]
+fn test_analyze_psbt_multisig() {
+    use bitcoin::bip32::Fingerprint;
+
+    let (mut wallet, _) = get_funded_wallet_single("wsh(multi(2,tprv8ZgxMBicQKsPd3EupYiPRhaMooHKUHJxNsTfYuScep13go8QFfHdtkG9nRkFGb7busX4isf6X9dURGCoKgitaApQ6MupRhZMcELAxTBRJgS/0/*,tpubD6NzVbkrYhZ4WgCeJid2Zds24zATB58r1q1qTLMuApUxZUxzETADNTeP6SvZKSsXs4qhvFAC21GFjXHwgxAcDtZqzzj8JMpsFDgqyjSJHGa/0/*))");
+    let addr = wallet.next_unused_address(KeychainKind::External);
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let mut psbt = builder.finish().unwrap();
+
+    let analysis = wallet.analyze_psbt(&psbt).unwrap();
+    let policy = analysis.inputs[0].policy.as_ref().unwrap();
+    assert_matches!(&policy.satisfaction, Satisfaction::Partial { items, m: 2, .. } if items.is_empty());
+    // the wallet can only provide one of the signatures
+    assert_matches!(&policy.contribution, Satisfaction::Partial { items, .. } if items == &[0]);
+
+    assert!(!wallet.sign(&mut psbt, SignOptions::default()).unwrap());
+    let analysis = wallet.analyze_psbt(&psbt).unwrap();
+    let input = &analysis.inputs[0];
+    assert_eq!(
+        input.signed_by,
+        vec![PkOrF::Fingerprint(
+            Fingerprint::from_str("b6dff990").unwrap()
+        )]
+    );
+    assert!(!input.is_satisfied());
+    assert_matches!(&input.policy.as_ref().unwrap().satisfaction, Satisfaction::Partial { items, .. } if items == &[0]);
+    assert!(analysis.estimated_weight.is_some());
+}
+
+#[test]
+fn test_analyze_psbt_timelocks() {
+    for (descriptor, timelock) in [
+        (
+            get_test_single_sig_cltv(),
+            Condition {
+                timelock: Some(absolute::LockTime::from_height(100_000).unwrap()),
+                csv: None,
+            },
+        ),
+        (
+            get_test_single_sig_csv(),
+            Condition {
+                timelock: None,
+                csv: Some(Sequence(6)),
+            },
+        ),
+    ] {
+        let (mut wallet, _) = get_funded_wallet_single(descriptor);
+        let addr = wallet.next_unused_address(KeychainKind::External);
+        let mut builder = wallet.build_tx();
+        builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+        let mut psbt = builder.finish().unwrap();
+        let sign_options = SignOptions {
+            try_finalize: false,
+            ..Default::default()
+        };
+        wallet.sign(&mut psbt, sign_options).unwrap();
+
+        // the signature is there but the timelock isn't expired yet
+        let analysis = wallet.analyze_psbt(&psbt).unwrap();
+        let input = &analysis.inputs[0];
+        assert_eq!(input.signed_by.len(), 1);
+        assert!(!input.is_satisfied());
+        assert_eq!(input.pending_timelocks, vec![timelock]);
+
+        insert_checkpoint(
+            &mut wallet,
+            BlockId {
+                height: 100_000,
+                hash: BlockHash::all_zeros(),
+            },
+        );
+        let analysis = wallet.analyze_psbt(&psbt).unwrap();
+        assert!(analysis.inputs[0].is_satisfied());
+        assert!(analysis.inputs[0].pending_timelocks.is_empty());
+    }
+}
+
+#[test]
+fn test_signing_policy_fee() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(10));
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee_amount().unwrap();
+
+    let policy_wallet = |policy: Arc<dyn SigningPolicy>| {
+        let (mut wallet, _) = get_funded_wallet_wpkh();
+        wallet.add_signing_policy(policy);
+        let mut psbt = psbt.clone();
+        let res = wallet.sign(&mut psbt, SignOptions::default());
+        if res.is_err() {
+            // the PSBT is rejected before signing
+            assert!(psbt.inputs[0].partial_sigs.is_empty());
+            assert!(psbt.inputs[0].final_script_witness.is_none());
+        }
+        res
+    };
+
+    assert_matches!(
+        policy_wallet(Arc::new(MaxFee(fee - Amount::from_sat(1)))),
+        Err(SignerError::Policy(SigningPolicyError::FeeTooHigh { fee: f, .. })) if f == fee
+    );
+    assert!(policy_wallet(Arc::new(MaxFee(fee))).unwrap());
+    assert_matches!(
+        policy_wallet(Arc::new(MaxFeeRate(FeeRate::from_sat_per_vb_u32(9)))),
+        Err(SignerError::Policy(
+            SigningPolicyError::FeeRateTooHigh { .. }
+        ))
+    );
+    assert!(policy_wallet(Arc::new(MaxFeeRate(FeeRate::from_sat_per_vb_u32(11)))).unwrap());
+
+    // the fee can't be computed if the previous outputs are unknown
+    let (mut wallet, _) =
+        get_funded_wallet_single("wpkh(cVbZ8ovhye9AoAHFsqobCf7LxbXDAECy9Kb8TZdfsDYMZGBUyCnm)");
+    wallet.add_signing_policy(Arc::new(MaxFee(fee)));
+    let mut foreign_psbt = psbt::Psbt::from_unsigned_tx(psbt.unsigned_tx.clone()).unwrap();
+    assert_matches!(
+        wallet.sign(&mut foreign_psbt, SignOptions::default()),
+        Err(SignerError::Policy(SigningPolicyError::UnknownFee))
+    );
+}
+
+#[test]
+fn test_signing_policy_forged_witness_utxo() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(addr.script_pubkey(), Amount::from_sat(25_000))
+        .fee_rate(FeeRate::from_sat_per_vb_u32(10));
+    let psbt = builder.finish().unwrap();
+    let fee = psbt.fee_amount().unwrap();
+    wallet.add_signing_policy(Arc::new(MaxFee(fee)));
+
+    // the coordinator understates the value of the wallet's input to hide a higher fee
+    let mut forged_psbt = psbt.clone();
+    let change_index = psbt
+        .outputs
+        .iter()
+        .position(|output| !output.bip32_derivation.is_empty())
+        .unwrap();
+    let stolen = Amount::from_sat(10_000);
+    forged_psbt.unsigned_tx.output[change_index].value -= stolen;
+    let witness_utxo = forged_psbt.inputs[0].witness_utxo.as_mut().unwrap();
+    witness_utxo.value -= stolen;
+    assert_eq!(forged_psbt.fee_amount().unwrap(), fee);
+    assert_eq!(
+        wallet.analyze_psbt(&forged_psbt).unwrap().fee,
+        Some(fee + stolen)
+    );
+    assert_matches!(
+        wallet.sign(&mut forged_psbt, SignOptions::default()),
+        Err(SignerError::Policy(SigningPolicyError::InconsistentUtxo(0)))
+    );
+    assert!(forged_psbt.inputs[0].partial_sigs.is_empty());
+
+    // without the `non_witness_utxo`, the value is still taken from the wallet's graph
+    forged_psbt.inputs[0].non_witness_utxo = None;
+    assert_matches!(
+        wallet.sign(&mut forged_psbt, SignOptions::default()),
+        Err(SignerError::Policy(SigningPolicyError::InconsistentUtxo(0)))
+    );
+}
+
+#[test]
+fn test_signing_policy_foreign_change() {
+    let (mut wallet, _) = get_funded_wallet_wpkh();
+    let addr = Address::from_str("2N1Ffz3WaNzbeLFBb51xyFMHYSEUXcbiSoX")
+        .unwrap()
+        .assume_checked();
+    let mut builder = wallet.build_tx();
+    builder.add_recipient(addr.script_pubkey(), Amount::from_sat(25_000));
+    let psbt = builder.finish().unwrap();
+    let change_index = psbt
+        .outputs
+        .iter()
+        .position(|output| !output.bip32_derivation.is_empty())
+        .unwrap();
+
+    // the coordinator replaced the change script, but kept its key origin
+    let mut foreign_psbt = psbt.clone();
+    foreign_psbt.unsigned_tx.output[change_index].script_pubkey = addr.script_pubkey();
+
+    wallet.add_signing_policy(Arc::new(RejectForeignChange));
+    assert!(wallet
+        .sign(&mut psbt.clone(), SignOptions::default())
+        .unwrap());
+    assert_matches!(
+        wallet.sign(&mut foreign_psbt, SignOptions::default()),
+        Err(SignerError::Policy(SigningPolicyError::ForeignChange(index))) if index == change_index
+    );
+
+    // the policy only checks the consistency of the key origins, outputs without them pass
+    foreign_psbt.outputs[change_index].bip32_derivation.clear();
+    assert!(wallet
+        .sign(&mut foreign_psbt, SignOptions::default())
+        .unwrap());
+}
+
 #[test]
 fn test_taproot_sign_explicit_sighash_all() {
     let (mut wallet, _) = get_funded_wallet_single(get_test_tr_single_sig());